@@ -0,0 +1,241 @@
+//! Compile time and search throughput of the regex engine.
+//!
+//! The corpora are generated from a fixed seed, so every run searches the
+//! same bytes: a server log, where most lines look alike and matches are
+//! rare, and a prose text with some non-ASCII words. Each pattern is run
+//! over every line like the CLI does, once for whether a line matches and
+//! once for the spans of every match.
+//!
+//! Run with `cargo bench`, or `cargo bench -- literal` for one group.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use grep_clone::regex::{Cursor, RegexNFA, RegexOptions};
+
+/// Lines in each generated corpus.
+const CORPUS_LINES: usize = 10_000;
+
+/// A xorshift generator, so the corpora don't depend on a random crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+/// Lines like `2024-03-07 14:02:11 INFO [worker-3] GET /api/users/812 200
+/// 13ms from 10.0.4.17`, with the odd error among them.
+fn log_corpus() -> Vec<String> {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    let methods = ["GET", "GET", "GET", "POST", "PUT", "DELETE"];
+    let paths = [
+        "/api/users",
+        "/api/orders",
+        "/static/app.js",
+        "/health",
+        "/login",
+    ];
+    (0..CORPUS_LINES)
+        .map(|i| {
+            let level = match rng.below(100) {
+                0 => "ERROR",
+                1..=3 => "WARN",
+                _ => "INFO",
+            };
+            let mut line = format!(
+                "2024-03-{:02} {:02}:{:02}:{:02} {} [worker-{}] {} {}/{} {} {}ms from 10.0.{}.{}",
+                1 + i * 30 / CORPUS_LINES,
+                rng.below(24),
+                rng.below(60),
+                rng.below(60),
+                level,
+                rng.below(8),
+                rng.pick(&methods),
+                rng.pick(&paths),
+                rng.below(1000),
+                if level == "ERROR" { 503 } else { 200 },
+                rng.below(500),
+                rng.below(256),
+                rng.below(256),
+            );
+            if level == "ERROR" {
+                line.push_str(" error code 503: upstream timed out");
+            }
+            line
+        })
+        .collect()
+}
+
+/// Sentences of common words, a few of them with accents or in Greek.
+fn prose_corpus() -> Vec<String> {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let words: Vec<&str> = concat!(
+        "the of and to in a is that for it as was with be by on not he this are or his ",
+        "from at which but have an had they you were their one all we can her has there ",
+        "been if more when will would who so no café naïve résumé Zürich λόγος Sherlock ",
+        "Holmes Watson",
+    )
+    .split(' ')
+    .collect();
+    (0..CORPUS_LINES)
+        .map(|_| {
+            let len = 6 + rng.below(10);
+            let sentence: Vec<&str> = (0..len).map(|_| rng.pick(&words)).collect();
+            format!("{}.", sentence.join(" "))
+        })
+        .collect()
+}
+
+fn corpus_bytes(lines: &[String]) -> u64 {
+    lines.iter().map(|line| line.len() as u64 + 1).sum()
+}
+
+/// Lines matching `regex`, the way the CLI decides what to print.
+fn count_matching(regex: &RegexNFA, lines: &[String]) -> usize {
+    lines.iter().filter(|line| regex.matches(line)).count()
+}
+
+/// Matches in all of `lines`, finding the span of each like `-o` would.
+fn count_spans(regex: &RegexNFA, lines: &[String]) -> usize {
+    let mut found = 0;
+    for line in lines {
+        let mut cursor = Cursor::new(0);
+        while regex.next_match(line, &mut cursor).is_some() {
+            found += 1;
+        }
+    }
+    found
+}
+
+/// Benchmarks every pattern of `patterns` over `lines` as group `name`.
+fn search_group(c: &mut Criterion, name: &str, patterns: &[&str], lines: &[String]) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(corpus_bytes(lines)));
+    for &pattern in patterns {
+        let regex = RegexNFA::new(pattern.to_string());
+        group.bench_with_input(BenchmarkId::new("matches", pattern), lines, |b, lines| {
+            b.iter(|| count_matching(&regex, black_box(lines)))
+        });
+        group.bench_with_input(BenchmarkId::new("spans", pattern), lines, |b, lines| {
+            b.iter(|| count_spans(&regex, black_box(lines)))
+        });
+    }
+    group.finish();
+}
+
+fn compile(c: &mut Criterion) {
+    let words: Vec<String> = (0..500).map(|i| format!("word{}", i)).collect();
+    let alternation = words.join("|");
+    let patterns = [
+        "error code",
+        r"(\w+)@(\w+)\.com",
+        r"^\d{4}-\d{2}-\d{2} (\d{2}):(\d{2})",
+        r"[A-Za-z]{2,10}\d{1,5}(?:-[a-z]+)*",
+        r"(\w{10}){50}",
+        &alternation,
+    ];
+    let mut group = c.benchmark_group("compile");
+    for pattern in patterns {
+        let name = if pattern.len() > 40 {
+            "500 words"
+        } else {
+            pattern
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| RegexNFA::new(black_box(pattern).to_string()))
+        });
+    }
+    group.finish();
+}
+
+fn literal(c: &mut Criterion) {
+    let log = log_corpus();
+    search_group(
+        c,
+        "literal",
+        &["error code", "upstream timed out", "zzz"],
+        &log,
+    );
+}
+
+fn class_heavy(c: &mut Criterion) {
+    let log = log_corpus();
+    let patterns = [
+        r"\d+\.\d+\.\d+\.\d+",
+        r"[A-Z]{4,5} \[worker-\d\]",
+        r" (\d+)ms from",
+        r"^\d{4}-\d{2}-\d{2} 2\d:",
+    ];
+    search_group(c, "class-heavy", &patterns, &log);
+
+    let prose = prose_corpus();
+    search_group(c, "class-heavy", &[r"\w+é\w*", "[α-ω]+"], &prose);
+}
+
+fn alternation(c: &mut Criterion) {
+    let log = log_corpus();
+    search_group(
+        c,
+        "alternation",
+        &["ERROR|WARN|FATAL", "(GET|PUT) /api/(users|orders)"],
+        &log,
+    );
+
+    let prose = prose_corpus();
+    search_group(
+        c,
+        "alternation",
+        &["Sherlock|Holmes|Watson|Zürich|λόγος"],
+        &prose,
+    );
+}
+
+fn pathological(c: &mut Criterion) {
+    let lines: Vec<String> = (1..=40).map(|len| "a".repeat(len)).collect();
+    // Ambiguous, but linear for the simulations and the memoized backtracker
+    search_group(
+        c,
+        "pathological",
+        &["(a|a)*b", "(a*)*b", "(a|aa)+$"],
+        &lines,
+    );
+
+    // Backreferences need the plain backtracker, which is exponential here
+    let options = RegexOptions::new().step_limit(Some(100_000));
+    let regex = RegexNFA::with_options("(a|aa)*\\1c".to_string(), options);
+    let mut group = c.benchmark_group("pathological");
+    group.throughput(Throughput::Bytes(corpus_bytes(&lines)));
+    group.bench_function("step limit/(a|aa)*\\1c", |b| {
+        b.iter(|| {
+            black_box(&lines)
+                .iter()
+                .filter(|line| regex.try_matches(line).is_err())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    compile,
+    literal,
+    class_heavy,
+    alternation,
+    pathological
+);
+criterion_main!(benches);
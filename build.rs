@@ -0,0 +1,99 @@
+//! Generates the Unicode tables of the regex engine from the Unicode data
+//! of the compiler's standard library, so `\w`, `\d`, `\s` and case folding
+//! don't have to go through all of Unicode every time a pattern is compiled.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mut tables = String::from("// Generated by build.rs, do not edit.\n\n");
+    write_ranges(&mut tables, "WORD", |c| c.is_alphanumeric() || c == '_');
+    write_ranges(&mut tables, "DIGIT", char::is_numeric);
+    write_ranges(&mut tables, "WHITESPACE", char::is_whitespace);
+    write_case_variants(&mut tables);
+
+    let out_dir = env::var("OUT_DIR").expect("cargo sets OUT_DIR");
+    fs::write(Path::new(&out_dir).join("unicode_tables.rs"), tables)
+        .expect("failed to write the Unicode tables");
+}
+
+/// Writes the chars satisfying `predicate` as a sorted slice of inclusive
+/// ranges named `name`.
+fn write_ranges(out: &mut String, name: &str, predicate: impl Fn(char) -> bool) {
+    let mut ranges: Vec<(char, char)> = Vec::new();
+    for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+        if !predicate(c) {
+            continue;
+        }
+        match ranges.last_mut() {
+            // The surrogate gap doesn't break a range
+            Some((_, end)) if next_char(*end) == Some(c) => *end = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+    let _ = writeln!(out, "pub const {}: &[(char, char)] = &[", name);
+    for (start, end) in ranges {
+        let _ = writeln!(out, "    ('{}', '{}'),", escape(start), escape(end));
+    }
+    out.push_str("];\n\n");
+}
+
+/// Writes every char that case folding equates with others, paired with
+/// each of them, sorted. Chars fold together when one is the single-char
+/// lowercase or uppercase form of the other, transitively, so that `K`, `k`
+/// and the Kelvin sign `K` all end up together, as do `σ`, `ς` and `Σ`.
+fn write_case_variants(out: &mut String) {
+    let mut orbits: BTreeMap<char, BTreeSet<char>> = BTreeMap::new();
+    for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+        // The dotted and dotless i only fold together in Turkic languages
+        if matches!(c, '\u{130}' | '\u{131}') {
+            continue;
+        }
+        for mapped in [
+            c.to_lowercase().collect::<Vec<_>>(),
+            c.to_uppercase().collect(),
+        ] {
+            if let [single] = mapped[..] {
+                if single != c {
+                    join(&mut orbits, c, single);
+                }
+            }
+        }
+    }
+    out.push_str("pub const CASE_VARIANTS: &[(char, char)] = &[\n");
+    for (c, orbit) in &orbits {
+        for variant in orbit.iter().filter(|&variant| variant != c) {
+            let _ = writeln!(out, "    ('{}', '{}'),", escape(*c), escape(*variant));
+        }
+    }
+    out.push_str("];\n");
+}
+
+/// Puts `a`, `b` and everything either folds with in the same orbit.
+fn join(orbits: &mut BTreeMap<char, BTreeSet<char>>, a: char, b: char) {
+    let mut orbit: BTreeSet<char> = [a, b].into();
+    for c in [a, b] {
+        if let Some(other) = orbits.get(&c) {
+            orbit.extend(other.iter().copied());
+        }
+    }
+    for &c in &orbit {
+        orbits.insert(c, orbit.clone());
+    }
+}
+
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn escape(c: char) -> String {
+    format!("\\u{{{:X}}}", c as u32)
+}
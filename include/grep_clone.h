@@ -0,0 +1,55 @@
+/*
+ * C interface to the grep-clone regex engine.
+ *
+ * Build the library with the `ffi` feature:
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * and link against target/release/libgrep_clone.so (.dylib, .dll).
+ */
+
+#ifndef GREP_CLONE_H
+#define GREP_CLONE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A compiled pattern. Only ever used through a pointer. */
+typedef struct grep_regex grep_regex;
+
+/*
+ * Compiles the NUL-terminated UTF-8 pattern. Returns NULL if it can't be
+ * compiled and, when error isn't NULL, sets *error to a message saying why,
+ * to be released with grep_error_free. *error is NULL on success.
+ */
+grep_regex *grep_regex_new(const char *pattern, char **error);
+
+/*
+ * Whether the len bytes at haystack contain a match. Bytes of invalid UTF-8
+ * each read as one U+FFFD character.
+ */
+bool grep_regex_is_match(const grep_regex *regex, const uint8_t *haystack, size_t len);
+
+/*
+ * Finds the leftmost match in the len bytes at haystack. Returns whether
+ * there is one and, if so, stores its byte offsets in *start and *end.
+ */
+bool grep_regex_find(const grep_regex *regex, const uint8_t *haystack, size_t len,
+                     size_t *start, size_t *end);
+
+/* Releases a pattern. Does nothing for NULL. */
+void grep_regex_free(grep_regex *regex);
+
+/* Releases an error message from grep_regex_new. Does nothing for NULL. */
+void grep_error_free(char *error);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GREP_CLONE_H */
@@ -0,0 +1,167 @@
+//! The command line: the flags the binary takes, parsed with clap. `--help`
+//! prints the usage from the message catalog, so it follows the user's
+//! language like everything else the binary prints.
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use grep_clone::messages::{self, Message};
+use grep_clone::regex::Backend;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "grep-clone",
+    override_usage = "grep-clone [OPTIONS] <PATTERN> [PATH]...\n       \
+                      grep-clone [OPTIONS] -e <PATTERN>... [PATH]...",
+    override_help = messages::text(Message::Usage),
+    disable_version_flag = true,
+    arg_required_else_help = true
+)]
+pub struct Args {
+    /// Patterns to search for, from `-e` or `-E`, or else the first
+    /// positional argument.
+    #[arg(
+        short = 'e',
+        short_alias = 'E',
+        long = "regexp",
+        value_name = "PATTERN"
+    )]
+    pub patterns: Vec<String>,
+    /// Files to read more patterns from, one per line.
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    pub pattern_files: Vec<String>,
+    /// Files, or directories with `-r`, to search instead of stdin.
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<String>,
+    #[arg(short = 'r', long)]
+    pub recursive: bool,
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+    #[arg(short = 'i', long)]
+    pub ignore_case: bool,
+    /// Only match whole words.
+    #[arg(short = 'w', long)]
+    pub word_regexp: bool,
+    /// Only match whole lines.
+    #[arg(short = 'x', long)]
+    pub line_regexp: bool,
+    /// Print the lines that don't match instead.
+    #[arg(short = 'v', long)]
+    pub invert_match: bool,
+    #[arg(short = 'n', long)]
+    pub line_number: bool,
+    #[arg(short = 'o', long)]
+    pub only_matching: bool,
+    /// Lines of context to print after each match.
+    #[arg(short = 'A', long, value_name = "NUM")]
+    pub after_context: Option<usize>,
+    /// Lines of context to print before each match.
+    #[arg(short = 'B', long, value_name = "NUM")]
+    pub before_context: Option<usize>,
+    /// Lines of context on both sides, where `-A` or `-B` don't say.
+    #[arg(short = 'C', long, value_name = "NUM")]
+    pub context: Option<usize>,
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+    #[arg(short = 'l', long)]
+    pub files_with_matches: bool,
+    #[arg(short = 'L', long, conflicts_with = "files_with_matches")]
+    pub files_without_match: bool,
+    #[arg(short = 'c', long)]
+    pub count: bool,
+    /// Print matching lines of binary input as they are.
+    #[arg(short = 'a', long)]
+    pub text: bool,
+    #[arg(short = 'Z', long)]
+    pub decompress: bool,
+    #[arg(long)]
+    pub stats: bool,
+    #[arg(long, value_enum)]
+    pub engine: Option<Engine>,
+    #[arg(long)]
+    pub show_spans: bool,
+    #[arg(long)]
+    pub trace: bool,
+    #[arg(short = 'V', long)]
+    pub version: bool,
+    #[arg(long)]
+    pub json: bool,
+    /// Replace every match in the files given with this, rewriting them in
+    /// place, instead of printing anything.
+    #[arg(long, value_name = "REPLACEMENT")]
+    pub write: Option<String>,
+    /// Keep the original of each file `--write` rewrites at its path plus
+    /// this suffix.
+    #[arg(long, value_name = "SUFFIX", requires = "write")]
+    pub backup_suffix: Option<String>,
+}
+
+/// The names `--engine` takes for the regex backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    Auto,
+    Backtrack,
+    #[value(name = "pikevm")]
+    PikeVm,
+    #[value(name = "lazydfa")]
+    LazyDfa,
+}
+
+impl From<Engine> for Backend {
+    fn from(engine: Engine) -> Backend {
+        match engine {
+            Engine::Auto => Backend::Auto,
+            Engine::Backtrack => Backend::Backtrack,
+            Engine::PikeVm => Backend::PikeVm,
+            Engine::LazyDfa => Backend::LazyDfa,
+        }
+    }
+}
+
+impl Args {
+    /// The arguments of the process. Without `-e`, `-E` or `-f`, the first
+    /// positional argument is the pattern and the rest are paths; `-r`
+    /// without paths searches the current directory. Exits with a usage
+    /// error, status 2, when they don't make sense.
+    pub fn from_env() -> Args {
+        let mut args = Args::parse();
+        if args.version {
+            return args;
+        }
+        if args.patterns.is_empty() && args.pattern_files.is_empty() {
+            if args.paths.is_empty() {
+                Args::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        messages::text(Message::MissingPattern),
+                    )
+                    .exit();
+            }
+            let pattern = args.paths.remove(0);
+            args.patterns.push(pattern);
+        }
+        if args.write.is_some() {
+            // Groups in the replacement need to know which pattern they are of
+            if args.patterns.len() != 1 || !args.pattern_files.is_empty() {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        messages::text(Message::WriteNeedsOnePattern),
+                    )
+                    .exit();
+            }
+            // Not even with -r: rewriting the current directory has to be asked for
+            if args.paths.is_empty() {
+                Args::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        messages::text(Message::WriteNeedsPaths),
+                    )
+                    .exit();
+            }
+        }
+        if args.recursive && args.paths.is_empty() {
+            args.paths.push(".".to_string());
+        }
+        args
+    }
+}
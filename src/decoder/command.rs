@@ -0,0 +1,118 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+
+use super::{Decoded, Decoder};
+
+/// Pipes the input through an external program (e.g. `xz -dc`) and reads its
+/// output as UTF-8 text. Claims files by extension.
+pub struct CommandDecoder {
+    program: String,
+    args: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl CommandDecoder {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        CommandDecoder {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// File extensions (without the dot) this decoder is used for.
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self
+    }
+}
+
+impl Decoder for CommandDecoder {
+    fn detect(&self, path: &Path, _prefix: &[u8]) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.extensions.iter().any(|x| x == e))
+    }
+
+    fn decode<'a>(&self, mut input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        // Feed the child from a separate thread so neither side can block the
+        // other on a full pipe. The input may borrow, so buffer it first.
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        thread::spawn(move || io::copy(&mut data.as_slice(), &mut stdin));
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Box::new(BufReader::new(ChildOutput {
+            stdout,
+            child: Some(child),
+        })))
+    }
+}
+
+struct ChildOutput {
+    stdout: ChildStdout,
+    // Taken once the output is exhausted and the child has been reaped
+    child: Option<Child>,
+}
+
+impl Read for ChildOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read > 0 || buf.is_empty() {
+            return Ok(read);
+        }
+        // Reap the child; a failing converter shows up as an error at the end
+        match self
+            .child
+            .take()
+            .map(|mut child| child.wait())
+            .transpose()?
+        {
+            Some(status) if !status.success() => {
+                Err(io::Error::other(format!("decoder exited with {}", status)))
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_command_decoder() {
+        let decoder = CommandDecoder::new("tr", &["a-z", "A-Z"]).extensions(&["up"]);
+        assert!(decoder.detect(Path::new("notes.up"), b""));
+        assert!(!decoder.detect(Path::new("notes.txt"), b""));
+
+        let mut text = String::new();
+        decoder
+            .decode(Box::new(Cursor::new(b"one\ntwo\n".to_vec())))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "ONE\nTWO\n");
+    }
+
+    #[test]
+    fn test_failing_command() {
+        let decoder = CommandDecoder::new("false", &[]);
+        let mut text = String::new();
+        let read = decoder
+            .decode(Box::new(Cursor::new(Vec::new())))
+            .unwrap()
+            .read_to_string(&mut text);
+        assert!(read.is_err());
+    }
+}
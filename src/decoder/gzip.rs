@@ -0,0 +1,47 @@
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use flate2::bufread::MultiGzDecoder;
+
+use super::{Decoded, Decoder};
+
+const MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// gzip-compressed UTF-8 text, recognized by its magic bytes.
+pub struct GzipDecoder;
+
+impl Decoder for GzipDecoder {
+    fn detect(&self, _path: &Path, prefix: &[u8]) -> bool {
+        prefix.starts_with(&MAGIC)
+    }
+
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>> {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(input))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"first\nsecond\n").unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert!(GzipDecoder.detect(Path::new("log.gz"), &data));
+        assert!(!GzipDecoder.detect(Path::new("log.gz"), b"plain"));
+
+        let mut text = String::new();
+        GzipDecoder
+            .decode(Box::new(Cursor::new(data)))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "first\nsecond\n");
+    }
+}
@@ -0,0 +1,174 @@
+//! Turning raw input bytes into text records for the searcher.
+//!
+//! Each input format is handled by a `Decoder`. A `DecoderRegistry` peeks at
+//! the start of a file, picks the first decoder that recognizes it and hands
+//! the searcher its contents as UTF-8, so the search itself never has to know
+//! about compression, encodings or external converters. The searcher splits
+//! them into lines as it does any reader; `open` and `decode` split them at
+//! `\n` for callers that want the lines.
+
+mod command;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod plain;
+mod utf16;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::searcher::line_text;
+
+pub use command::CommandDecoder;
+#[cfg(feature = "gzip")]
+pub use gzip::GzipDecoder;
+pub use plain::PlainDecoder;
+pub use utf16::Utf16Decoder;
+
+/// The decoded contents of an input, UTF-8 text still to be split into
+/// lines.
+pub type Decoded<'a> = Box<dyn BufRead + 'a>;
+
+/// The decoded records of an input, usually its lines.
+pub type Records<'a> = Box<dyn Iterator<Item = io::Result<String>> + 'a>;
+
+pub trait Decoder {
+    /// Whether this decoder handles the input at `path`, given the first
+    /// block of its bytes.
+    fn detect(&self, path: &Path, prefix: &[u8]) -> bool;
+
+    /// Decodes `input` into UTF-8 text.
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>>;
+}
+
+/// The lines of UTF-8 text, decoded the way the searcher decodes a reader:
+/// bytes that aren't valid UTF-8 become U+FFFD and a `\r` before the `\n` is
+/// dropped.
+fn text_lines<'a, R: BufRead + 'a>(input: R) -> Records<'a> {
+    Box::new(
+        input
+            .split(b'\n')
+            .map(|line| line.map(|line| line_text(line, b'\n'))),
+    )
+}
+
+/// An ordered set of decoders. The first one detecting an input decodes it;
+/// anything no decoder claims is read as plain text.
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn Decoder>>,
+}
+
+impl DecoderRegistry {
+    /// Only plain text, UTF-8 or UTF-16 with a byte order mark.
+    pub fn new() -> Self {
+        DecoderRegistry {
+            decoders: vec![Box::new(Utf16Decoder)],
+        }
+    }
+
+    /// Registers `decoder` ahead of all previously registered ones.
+    pub fn register(&mut self, decoder: Box<dyn Decoder>) {
+        self.decoders.insert(0, decoder);
+    }
+
+    /// Opens the file at `path` and decodes it into lines with the matching
+    /// decoder.
+    pub fn open(&self, path: &Path) -> io::Result<Records<'static>> {
+        Ok(text_lines(self.open_reader(path)?))
+    }
+
+    /// Decodes `input`, read from `path`, into lines with the matching
+    /// decoder.
+    pub fn decode<'a>(&self, path: &Path, input: Box<dyn BufRead + 'a>) -> io::Result<Records<'a>> {
+        Ok(text_lines(self.decode_reader(path, input)?))
+    }
+
+    /// Opens the file at `path` and decodes it with the matching decoder,
+    /// leaving the text to split.
+    pub fn open_reader(&self, path: &Path) -> io::Result<Decoded<'static>> {
+        let file = File::open(path)?;
+        self.decode_reader(path, Box::new(BufReader::new(file)))
+    }
+
+    /// Decodes `input`, read from `path`, with the matching decoder, leaving
+    /// the text to split.
+    pub fn decode_reader<'a>(
+        &self,
+        path: &Path,
+        mut input: Box<dyn BufRead + 'a>,
+    ) -> io::Result<Decoded<'a>> {
+        let prefix = input.fill_buf()?;
+        match self.decoders.iter().find(|d| d.detect(path, prefix)) {
+            Some(decoder) => decoder.decode(input),
+            None => PlainDecoder.decode(input),
+        }
+    }
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        DecoderRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn records(registry: &DecoderRegistry, path: &str, data: &[u8]) -> Vec<String> {
+        let input = Box::new(Cursor::new(data.to_vec()));
+        registry
+            .decode(Path::new(path), input)
+            .unwrap()
+            .collect::<io::Result<Vec<String>>>()
+            .unwrap()
+    }
+
+    struct Upper;
+
+    impl Decoder for Upper {
+        fn detect(&self, path: &Path, _prefix: &[u8]) -> bool {
+            path.extension().is_some_and(|e| e == "up")
+        }
+
+        fn decode<'a>(&self, mut input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>> {
+            let mut text = String::new();
+            input.read_to_string(&mut text)?;
+            Ok(Box::new(Cursor::new(text.to_uppercase().into_bytes())))
+        }
+    }
+
+    #[test]
+    fn test_plain_fallback() {
+        let registry = DecoderRegistry::new();
+        assert_eq!(
+            records(&registry, "a.txt", b"one\ntwo\n"),
+            vec!["one", "two"]
+        );
+    }
+
+    #[test]
+    fn test_plain_invalid_utf8_is_lossy() {
+        let registry = DecoderRegistry::new();
+        assert_eq!(
+            records(&registry, "a.txt", b"caf\xe9\r\nok"),
+            vec!["caf\u{FFFD}", "ok"]
+        );
+    }
+
+    #[test]
+    fn test_utf16_detected_by_default() {
+        let registry = DecoderRegistry::new();
+        let data = [0xFF, 0xFE, b'h', 0, b'i', 0, b'\n', 0];
+        assert_eq!(records(&registry, "a.txt", &data), vec!["hi"]);
+    }
+
+    #[test]
+    fn test_registered_decoder_takes_priority() {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(Upper));
+        assert_eq!(records(&registry, "a.up", b"one\n"), vec!["ONE"]);
+        assert_eq!(records(&registry, "a.txt", b"one\n"), vec!["one"]);
+    }
+}
@@ -0,0 +1,18 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use super::{Decoded, Decoder};
+
+/// UTF-8 text, read as it is. Invalid bytes are replaced when it is split
+/// into lines rather than failing the read.
+pub struct PlainDecoder;
+
+impl Decoder for PlainDecoder {
+    fn detect(&self, _path: &Path, _prefix: &[u8]) -> bool {
+        true
+    }
+
+    fn decode<'a>(&self, input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>> {
+        Ok(input)
+    }
+}
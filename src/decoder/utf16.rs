@@ -0,0 +1,145 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use super::{Decoded, Decoder};
+
+/// UTF-16 text with a byte order mark, read as UTF-8. Unpaired surrogates
+/// become U+FFFD.
+pub struct Utf16Decoder;
+
+impl Decoder for Utf16Decoder {
+    fn detect(&self, _path: &Path, prefix: &[u8]) -> bool {
+        prefix.starts_with(&[0xFF, 0xFE]) || prefix.starts_with(&[0xFE, 0xFF])
+    }
+
+    fn decode<'a>(&self, mut input: Box<dyn BufRead + 'a>) -> io::Result<Decoded<'a>> {
+        let mut bom = [0; 2];
+        input.read_exact(&mut bom)?;
+        Ok(Box::new(BufReader::new(Utf16Reader {
+            input,
+            big_endian: bom == [0xFE, 0xFF],
+            next: None,
+            encoded: [0; 4],
+            unread: 0..0,
+        })))
+    }
+}
+
+struct Utf16Reader<'a> {
+    input: Box<dyn BufRead + 'a>,
+    big_endian: bool,
+    // A unit read after a high surrogate that didn't pair with it
+    next: Option<u16>,
+    // The last char decoded, as UTF-8, and the part of it not read yet
+    encoded: [u8; 4],
+    unread: Range<usize>,
+}
+
+impl Utf16Reader<'_> {
+    fn next_unit(&mut self) -> io::Result<Option<u16>> {
+        if let Some(unit) = self.next.take() {
+            return Ok(Some(unit));
+        }
+        let mut unit = [0; 2];
+        match self.input.read_exact(&mut unit) {
+            Ok(()) if self.big_endian => Ok(Some(u16::from_be_bytes(unit))),
+            Ok(()) => Ok(Some(u16::from_le_bytes(unit))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn next_char(&mut self) -> io::Result<Option<char>> {
+        let Some(unit) = self.next_unit()? else {
+            return Ok(None);
+        };
+        let mut units = vec![unit];
+        if (0xD800..0xDC00).contains(&unit) {
+            match self.next_unit()? {
+                Some(low) if (0xDC00..0xE000).contains(&low) => units.push(low),
+                other => self.next = other,
+            }
+        }
+        Ok(char::decode_utf16(units)
+            .next()
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+    }
+}
+
+impl Read for Utf16Reader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            if self.unread.is_empty() {
+                match self.next_char()? {
+                    Some(c) => self.unread = 0..c.encode_utf8(&mut self.encoded).len(),
+                    None => break,
+                }
+            }
+            let count = self.unread.len().min(buf.len() - written);
+            let start = self.unread.start;
+            buf[written..written + count].copy_from_slice(&self.encoded[start..start + count]);
+            self.unread.start += count;
+            written += count;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encode(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut data = if big_endian {
+            vec![0xFE, 0xFF]
+        } else {
+            vec![0xFF, 0xFE]
+        };
+        for unit in text.encode_utf16() {
+            if big_endian {
+                data.extend(unit.to_be_bytes());
+            } else {
+                data.extend(unit.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    fn decode(data: Vec<u8>) -> String {
+        let mut text = String::new();
+        Utf16Decoder
+            .decode(Box::new(Cursor::new(data)))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_little_endian() {
+        assert_eq!(decode(encode("héllo\r\nwörld", false)), "héllo\r\nwörld");
+    }
+
+    #[test]
+    fn test_big_endian_with_surrogates() {
+        assert_eq!(decode(encode("a😀\nb\n", true)), "a😀\nb\n");
+    }
+
+    #[test]
+    fn test_unpaired_surrogate() {
+        let mut data = vec![0xFF, 0xFE];
+        for unit in [0xD83D, u16::from(b'x'), 0xDE00] {
+            data.extend(unit.to_le_bytes());
+        }
+        assert_eq!(decode(data), "\u{FFFD}x\u{FFFD}");
+    }
+
+    #[test]
+    fn test_detect() {
+        assert!(Utf16Decoder.detect(Path::new("x"), &[0xFF, 0xFE, 0x41]));
+        assert!(!Utf16Decoder.detect(Path::new("x"), b"AB"));
+    }
+}
@@ -0,0 +1,109 @@
+//! `Error`, what can go wrong compiling a pattern or searching with it.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::regex::{CompileError, SyntaxError};
+
+/// A pattern that can't be used, or an input that can't be read.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The pattern doesn't parse.
+    #[error(transparent)]
+    Syntax(SyntaxError),
+    /// The pattern parses but can't be compiled: it is malformed, like the
+    /// unclosed group in `(a`, or is too large for the size limit.
+    #[error(transparent)]
+    Compile(CompileError),
+    /// Reading an input failed; `path` is the file, if it was one.
+    #[error("{}", io_message(.path.as_deref(), .source))]
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// An error reading the file at `path`.
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    /// The file the error is about, for IO errors reading one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(error: CompileError) -> Self {
+        match error {
+            CompileError::Syntax(error) => Error::Syntax(error),
+            error => Error::Compile(error),
+        }
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(error: SyntaxError) -> Self {
+        Error::Syntax(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+fn io_message(path: Option<&Path>, source: &io::Error) -> String {
+    match path {
+        Some(path) => format!("{}: {}", path.display(), source),
+        None => source.to_string(),
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+
+    #[test]
+    fn test_pattern_errors() {
+        let error = Error::from(RegexNFA::try_new("a|*".to_string()).err().unwrap());
+        assert!(matches!(
+            error,
+            Error::Syntax(SyntaxError::NothingToRepeat(2))
+        ));
+        assert_eq!(error.to_string(), "nothing to repeat at position 2");
+
+        let error = Error::from(RegexNFA::try_new("(a".to_string()).err().unwrap());
+        assert!(matches!(
+            error,
+            Error::Compile(CompileError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_io_errors_name_the_path() {
+        let source = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let error = Error::io("dir/a.txt", source);
+        assert_eq!(error.path(), Some(Path::new("dir/a.txt")));
+        assert_eq!(error.to_string(), "dir/a.txt: gone");
+        assert!(std::error::Error::source(&error).is_some());
+
+        let error = Error::from(io::Error::other("broken pipe"));
+        assert_eq!(error.path(), None);
+        assert_eq!(error.to_string(), "broken pipe");
+    }
+}
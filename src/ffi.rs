@@ -0,0 +1,236 @@
+//! C bindings to the regex engine, declared in `include/grep_clone.h`.
+//!
+//! A compiled pattern is handed out as an opaque `grep_regex` pointer that
+//! only these functions look into. No Rust panic crosses into C: a pattern
+//! that can't be compiled comes back as an error message, and a panic while
+//! matching is reported as no match.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::regex::RegexNFA;
+
+/// A compiled pattern, as C code holds it.
+#[allow(non_camel_case_types)]
+pub struct grep_regex {
+    regex: RegexNFA,
+}
+
+/// Compiles the NUL-terminated UTF-8 `pattern`. Returns null if it can't be
+/// compiled and, when `error` isn't null, points `*error` to a message
+/// saying why, to be released with `grep_error_free`.
+///
+/// # Safety
+///
+/// `pattern` must point to a NUL-terminated string and `error` be null or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn grep_regex_new(
+    pattern: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut grep_regex {
+    if !error.is_null() {
+        *error = ptr::null_mut();
+    }
+    if pattern.is_null() {
+        set_error(error, "the pattern is null");
+        return ptr::null_mut();
+    }
+    let pattern = match CStr::from_ptr(pattern).to_str() {
+        Ok(pattern) => pattern.to_string(),
+        Err(_) => {
+            set_error(error, "the pattern is not valid UTF-8");
+            return ptr::null_mut();
+        }
+    };
+    match catch_unwind(|| RegexNFA::try_new(pattern)) {
+        Ok(Ok(regex)) => Box::into_raw(Box::new(grep_regex { regex })),
+        Ok(Err(compile_error)) => {
+            set_error(error, &compile_error.to_string());
+            ptr::null_mut()
+        }
+        Err(_) => {
+            set_error(error, "the pattern could not be compiled");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Whether the `len` bytes at `haystack` contain a match. Invalid UTF-8
+/// reads as in `RegexNFA::is_match_bytes`.
+///
+/// # Safety
+///
+/// `regex` must come from `grep_regex_new` and not be freed yet, and
+/// `haystack` point to `len` readable bytes (or be anything when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn grep_regex_is_match(
+    regex: *const grep_regex,
+    haystack: *const u8,
+    len: usize,
+) -> bool {
+    let Some(regex) = regex.as_ref() else {
+        return false;
+    };
+    let haystack = bytes(haystack, len);
+    catch_unwind(AssertUnwindSafe(|| regex.regex.is_match_bytes(haystack))).unwrap_or(false)
+}
+
+/// Finds the leftmost match in the `len` bytes at `haystack`. Returns whether
+/// there is one and, if so, stores its byte offsets in `*start` and `*end`.
+///
+/// # Safety
+///
+/// As for `grep_regex_is_match`; `start` and `end` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn grep_regex_find(
+    regex: *const grep_regex,
+    haystack: *const u8,
+    len: usize,
+    start: *mut usize,
+    end: *mut usize,
+) -> bool {
+    let Some(regex) = regex.as_ref() else {
+        return false;
+    };
+    let haystack = bytes(haystack, len);
+    let found = catch_unwind(AssertUnwindSafe(|| {
+        regex.regex.find_bytes(haystack).map(|found| found.range())
+    }));
+    match found {
+        Ok(Some(range)) => {
+            *start = range.start;
+            *end = range.end;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Releases a pattern from `grep_regex_new`. Does nothing for null.
+///
+/// # Safety
+///
+/// `regex` must be null or come from `grep_regex_new` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn grep_regex_free(regex: *mut grep_regex) {
+    if !regex.is_null() {
+        drop(Box::from_raw(regex));
+    }
+}
+
+/// Releases an error message from `grep_regex_new`. Does nothing for null.
+///
+/// # Safety
+///
+/// `error` must be null or a message from `grep_regex_new` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn grep_error_free(error: *mut c_char) {
+    if !error.is_null() {
+        drop(CString::from_raw(error));
+    }
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: &str) {
+    if error.is_null() {
+        return;
+    }
+    // Messages don't contain NULs, but cut one short rather than lose it
+    let message = message.split('\0').next().unwrap_or_default();
+    *error = CString::new(message).unwrap_or_default().into_raw();
+}
+
+unsafe fn bytes<'h>(haystack: *const u8, len: usize) -> &'h [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(haystack, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(pattern: &str) -> Result<*mut grep_regex, String> {
+        let pattern = CString::new(pattern).unwrap();
+        let mut error = ptr::null_mut();
+        unsafe {
+            let regex = grep_regex_new(pattern.as_ptr(), &mut error);
+            if regex.is_null() {
+                let message = CStr::from_ptr(error).to_str().unwrap().to_string();
+                grep_error_free(error);
+                return Err(message);
+            }
+            assert!(error.is_null());
+            Ok(regex)
+        }
+    }
+
+    #[test]
+    fn test_match_and_find() {
+        let regex = compile(r"\d+").unwrap();
+        let haystack = b"abc 123 def";
+        let (mut start, mut end) = (0, 0);
+        unsafe {
+            assert!(grep_regex_is_match(
+                regex,
+                haystack.as_ptr(),
+                haystack.len()
+            ));
+            assert!(!grep_regex_is_match(regex, haystack.as_ptr(), 3));
+            assert!(grep_regex_find(
+                regex,
+                haystack.as_ptr(),
+                haystack.len(),
+                &mut start,
+                &mut end
+            ));
+            assert_eq!((start, end), (4, 7));
+            assert!(!grep_regex_find(
+                regex,
+                ptr::null(),
+                0,
+                &mut start,
+                &mut end
+            ));
+            grep_regex_free(regex);
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_haystack() {
+        let regex = compile("b").unwrap();
+        let haystack = b"a\xffb";
+        let (mut start, mut end) = (0, 0);
+        unsafe {
+            assert!(grep_regex_find(
+                regex,
+                haystack.as_ptr(),
+                haystack.len(),
+                &mut start,
+                &mut end
+            ));
+            grep_regex_free(regex);
+        }
+        assert_eq!((start, end), (2, 3));
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(
+            compile("a|*").err().unwrap(),
+            "nothing to repeat at position 2"
+        );
+        unsafe {
+            let pattern = b"\xff\0";
+            let regex = grep_regex_new(pattern.as_ptr().cast(), ptr::null_mut());
+            assert!(regex.is_null());
+            assert!(grep_regex_new(ptr::null(), ptr::null_mut()).is_null());
+            grep_regex_free(ptr::null_mut());
+            grep_error_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! Shell globs such as `*.rs` or `src/**/test_*.txt`, translated to regexes
+//! and compiled, for choosing which files to search.
+//!
+//! A glob matches a whole path, with `/` between its components:
+//!
+//! - `*` matches any run of chars but `/`, and `?` any one char but `/`.
+//! - `**` as a whole component matches any number of components: `**/a`
+//!   matches `a` in any directory, `a/**/b` matches `a/b` and `a/x/y/b`,
+//!   and `a/**` everything under `a`. Elsewhere it is the same as `*`.
+//! - `[abc]`, `[a-z]` and `[!abc]` (or `[^abc]`) match one char of the
+//!   class, or not in it; a negated class doesn't match `/` either. A `]`
+//!   right after the opening bracket is part of the class.
+//! - `{a,b}` matches either alternative, which may hold any of the above,
+//!   nested alternations included.
+//! - `\` makes the char after it match itself.
+//!
+//! A glob without a `/` matches the last component only, so `*.rs` matches
+//! `main.rs` and `src/lib.rs` alike. One with a `/` matches from the start
+//! of the path; a leading `/` only says so and is dropped.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use thiserror::Error;
+
+use crate::regex::{escape, CompileError, RegexNFA, RegexOptions, RegexSet};
+
+/// A glob that can't be translated. Positions are char indices into it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GlobError {
+    #[error("unclosed character class at position {0}")]
+    UnclosedClass(usize),
+    #[error("invalid range {range} in the character class at position {position}")]
+    InvalidRange { range: String, position: usize },
+    #[error("unclosed alternation at position {0}")]
+    UnclosedAlternation(usize),
+    #[error("trailing backslash at position {0}")]
+    TrailingBackslash(usize),
+    /// The translated regex didn't compile, which only a glob past the
+    /// regex size limit can cause.
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+}
+
+/// How globs are matched.
+#[derive(Debug, Clone, Default)]
+pub struct GlobOptions {
+    /// Ignore case, as on case-insensitive file systems.
+    pub case_insensitive: bool,
+}
+
+impl GlobOptions {
+    pub fn new() -> Self {
+        GlobOptions::default()
+    }
+
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    fn regex_options(&self) -> RegexOptions {
+        // A path may hold any char, line breaks included
+        RegexOptions::new()
+            .case_insensitive(self.case_insensitive)
+            .dot_matches_new_line(true)
+    }
+}
+
+/// A compiled glob.
+pub struct Glob {
+    glob: String,
+    regex: RegexNFA,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Glob, GlobError> {
+        Glob::with_options(glob, &GlobOptions::default())
+    }
+
+    pub fn with_options(glob: &str, options: &GlobOptions) -> Result<Glob, GlobError> {
+        let pattern = glob_to_regex(glob)?;
+        let regex = RegexNFA::try_with_options(pattern, options.regex_options())?;
+        Ok(Glob {
+            glob: glob.to_string(),
+            regex,
+        })
+    }
+
+    /// The glob as it was given.
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+
+    /// The regex the glob was translated to.
+    pub fn regex(&self) -> &RegexNFA {
+        &self.regex
+    }
+
+    /// Whether the glob matches `path`, written with `/` between its
+    /// components. A leading `./` is ignored.
+    pub fn is_match(&self, path: &str) -> bool {
+        self.regex.matches(normalize(path))
+    }
+
+    /// Whether the glob matches `path`, with the separators of the platform.
+    #[cfg(feature = "std")]
+    pub fn is_match_path(&self, path: &std::path::Path) -> bool {
+        self.is_match(&path_text(path))
+    }
+}
+
+/// Several globs matched together, as for a list of `--include` globs.
+pub struct GlobSet {
+    set: RegexSet,
+}
+
+impl GlobSet {
+    /// Compiles every glob. Fails on the first invalid one.
+    pub fn new<I, S>(globs: I) -> Result<GlobSet, GlobError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        GlobSet::with_options(globs, &GlobOptions::default())
+    }
+
+    pub fn with_options<I, S>(globs: I, options: &GlobOptions) -> Result<GlobSet, GlobError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = globs
+            .into_iter()
+            .map(|glob| glob_to_regex(glob.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let set = RegexSet::with_options(patterns, options.regex_options())?;
+        Ok(GlobSet { set })
+    }
+
+    /// Number of globs.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Whether any of the globs matches `path`, as `Glob::is_match` does.
+    pub fn is_match(&self, path: &str) -> bool {
+        self.set.is_match(normalize(path))
+    }
+
+    /// Indices of the globs matching `path`, in increasing order.
+    pub fn matches(&self, path: &str) -> Vec<usize> {
+        self.set.matches(normalize(path))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn is_match_path(&self, path: &std::path::Path) -> bool {
+        self.is_match(&path_text(path))
+    }
+}
+
+/// `path` without the `./` it may start with.
+fn normalize(path: &str) -> &str {
+    path.strip_prefix("./").unwrap_or(path)
+}
+
+/// `path` with `/` between its components.
+#[cfg(feature = "std")]
+fn path_text(path: &std::path::Path) -> String {
+    let text = path.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '/' {
+        text.into_owned()
+    } else {
+        text.replace(std::path::MAIN_SEPARATOR, "/")
+    }
+}
+
+/// Translates `glob` into a regex matching the same paths, anchored at both
+/// ends.
+pub fn glob_to_regex(glob: &str) -> Result<String, GlobError> {
+    let chars: Vec<char> = glob.chars().collect();
+    let anchored = chars.contains(&'/');
+    let mut i = usize::from(chars.first() == Some(&'/'));
+    let mut out = String::from("^");
+    if !anchored {
+        out.push_str("(?:.*/)?");
+    }
+    // The alternations open around `i`: where each started, the
+    // alternatives it has so far, and the regex of the one being read
+    let mut alternations: Vec<(usize, Vec<String>, String)> = Vec::new();
+    while i < chars.len() {
+        let c = chars[i];
+        let open = !alternations.is_empty();
+        let regex = match alternations.last_mut() {
+            Some((_, _, current)) => current,
+            None => &mut out,
+        };
+        match c {
+            '*' => {
+                let start = i;
+                while chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+                let whole_component = (start == 0 || chars[start - 1] == '/')
+                    && (i + 1 == chars.len() || chars[i + 1] == '/');
+                if i > start && whole_component {
+                    if i + 1 < chars.len() {
+                        // Its `/` goes with it, so no component at all
+                        // matches as well
+                        regex.push_str("(?:.*/)?");
+                        i += 1;
+                    } else {
+                        regex.push_str(".*");
+                    }
+                } else {
+                    regex.push_str("[^/]*");
+                }
+            }
+            '?' => regex.push_str("[^/]"),
+            '[' => i = class(&chars, i, regex)?,
+            '\\' => {
+                let escaped = chars.get(i + 1).ok_or(GlobError::TrailingBackslash(i))?;
+                regex.push_str(&escape(escaped.encode_utf8(&mut [0; 4])));
+                i += 1;
+            }
+            '{' => alternations.push((i, Vec::new(), String::new())),
+            ',' if open => {
+                let (_, alternatives, current) = alternations.last_mut().unwrap();
+                alternatives.push(core::mem::take(current));
+            }
+            '}' if open => {
+                let (_, mut alternatives, current) = alternations.pop().unwrap();
+                alternatives.push(current);
+                let regex = match alternations.last_mut() {
+                    Some((_, _, current)) => current,
+                    None => &mut out,
+                };
+                push_alternation(regex, alternatives);
+            }
+            c => regex.push_str(&escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        i += 1;
+    }
+    if let Some((start, _, _)) = alternations.first() {
+        return Err(GlobError::UnclosedAlternation(*start));
+    }
+    out.push('$');
+    Ok(out)
+}
+
+/// Writes the regex of the class starting at `chars[start]`, a `[`,
+/// returning where its `]` is.
+fn class(chars: &[char], start: usize, out: &mut String) -> Result<usize, GlobError> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let first = i;
+    let mut ranges = Vec::new();
+    loop {
+        let c = *chars.get(i).ok_or(GlobError::UnclosedClass(start))?;
+        if c == ']' && i > first {
+            break;
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                if end < c {
+                    return Err(GlobError::InvalidRange {
+                        range: [c, '-', end].iter().collect(),
+                        position: start,
+                    });
+                }
+                ranges.push((c, end));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+    if negated {
+        // Nor does it match `/`
+        ranges.push(('/', '/'));
+        ranges.sort_unstable();
+        ranges = complement(&ranges);
+    }
+    push_char_set(out, &ranges);
+    Ok(i)
+}
+
+/// Every char not in `ranges`, which are sorted.
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut complement = Vec::new();
+    let mut next = Some('\0');
+    for &(start, end) in ranges {
+        if let Some(from) = next.filter(|&from| from < start) {
+            complement.push((from, previous(start)));
+        }
+        if next.is_some_and(|from| from <= end) {
+            next = following(end);
+        }
+    }
+    if let Some(from) = next {
+        complement.push((from, char::MAX));
+    }
+    complement
+}
+
+/// Writes a regex matching one char of `ranges`. Classes of this engine
+/// have no escapes, so the chars with a meaning in them never end a range
+/// there: they are matched outside the class instead.
+fn push_char_set(out: &mut String, ranges: &[(char, char)]) {
+    let special = |c: char| matches!(c, '[' | ']' | '\\' | '^' | '-' | '&');
+    let mut class = String::new();
+    let mut literals = Vec::new();
+    for &(mut start, mut end) in ranges {
+        while special(start) && start <= end {
+            literals.push(escape(start.encode_utf8(&mut [0; 4])));
+            match following(start) {
+                Some(c) if start < end => start = c,
+                _ => break,
+            }
+        }
+        if special(start) {
+            continue;
+        }
+        while special(end) && start < end {
+            literals.push(escape(end.encode_utf8(&mut [0; 4])));
+            end = previous(end);
+        }
+        class.push(start);
+        if start < end {
+            class.push('-');
+            class.push(end);
+        }
+    }
+    if !class.is_empty() {
+        literals.insert(0, ["[", &class, "]"].concat());
+    }
+    match literals.len() {
+        1 => out.push_str(&literals[0]),
+        _ => push_alternation(out, literals),
+    }
+}
+
+/// The char right after `c`, skipping surrogates.
+fn following(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// The char right before `c`, which isn't `\0`, skipping surrogates.
+fn previous(c: char) -> char {
+    match c {
+        '\u{E000}' => '\u{D7FF}',
+        c => char::from_u32(c as u32 - 1).unwrap(),
+    }
+}
+
+/// Writes a group matching any of `alternatives`. The engine has no empty
+/// alternatives, so an empty one makes the group optional instead.
+fn push_alternation(out: &mut String, alternatives: Vec<String>) {
+    let optional = alternatives.iter().any(String::is_empty);
+    let alternatives: Vec<String> = alternatives.into_iter().filter(|a| !a.is_empty()).collect();
+    if alternatives.is_empty() {
+        return;
+    }
+    out.push_str("(?:");
+    out.push_str(&alternatives.join("|"));
+    out.push(')');
+    if optional {
+        out.push('?');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn test_stars() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/lib.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(matches("src/*.rs", "./src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/regex/mod.rs"));
+        assert!(!matches("src/*.rs", "lib/src/lib.rs"));
+        assert!(matches("/src/?.rs", "src/a.rs"));
+        assert!(!matches("?.rs", "ab.rs"));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(matches("src/**/test_*.txt", "src/test_a.txt"));
+        assert!(matches("src/**/test_*.txt", "src/a/b/test_a.txt"));
+        assert!(!matches("src/**/test_*.txt", "src/a/btest_a.txt"));
+        assert!(matches("**/target", "target"));
+        assert!(matches("**/target", "a/b/target"));
+        assert!(matches("target/**", "target/debug/build"));
+        assert!(!matches("target/**", "targets/debug"));
+        // Not a whole component, so only `*`
+        assert!(matches("a**b/c", "axyb/c"));
+        assert!(!matches("a**b/c", "ax/yb/c"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[!0-9]", "file7"));
+        assert!(matches("file[^0-9]", "filex"));
+        assert!(!matches("a[!x]b/c", "a/b/c"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("[a&-]", "-"));
+        assert!(matches("[a&-]", "&"));
+        assert!(matches("[\\^]", "^"));
+        assert!(matches("[\\^]", "\\"));
+        assert!(matches("[Z-b]", "]"));
+        assert!(!matches("[!]]", "]"));
+        assert!(matches("[!]]", "["));
+        assert!(matches("[!a]", "\u{10FFFF}"));
+    }
+
+    #[test]
+    fn test_alternations() {
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(matches("*.{rs,toml}", "src/main.rs"));
+        assert!(!matches("*.{rs,toml}", "README.md"));
+        assert!(matches("{src,tests}/**/*.rs", "tests/common/mod.rs"));
+        assert!(matches("a{,.bak}", "a"));
+        assert!(matches("a{,.bak}", "a.bak"));
+        assert!(matches("{a,b{c,d}}", "bd"));
+        assert!(matches("x,y}", "x,y}"));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert!(matches("a+b(1).txt", "a+b(1).txt"));
+        assert!(!matches("a.txt", "abtxt"));
+        assert!(matches("\\*.txt", "*.txt"));
+        assert!(!matches("\\*.txt", "a.txt"));
+        assert!(matches("é*", "été"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(glob_to_regex("a[bc"), Err(GlobError::UnclosedClass(1)));
+        assert_eq!(glob_to_regex("[]"), Err(GlobError::UnclosedClass(0)));
+        assert_eq!(
+            glob_to_regex("x[z-a]"),
+            Err(GlobError::InvalidRange {
+                range: "z-a".to_string(),
+                position: 1
+            })
+        );
+        assert_eq!(
+            glob_to_regex("{a,{b}"),
+            Err(GlobError::UnclosedAlternation(0))
+        );
+        assert_eq!(glob_to_regex("a\\"), Err(GlobError::TrailingBackslash(1)));
+    }
+
+    #[test]
+    fn test_options_and_sets() {
+        let options = GlobOptions::new().case_insensitive(true);
+        assert!(Glob::with_options("*.RS", &options)
+            .unwrap()
+            .is_match("a.rs"));
+        assert!(!matches("*.RS", "a.rs"));
+        let set = GlobSet::new(["*.rs", "docs/**", "*.md"]).unwrap();
+        assert_eq!(set.matches("docs/intro.md"), [1, 2]);
+        assert!(set.is_match("main.rs"));
+        assert!(!set.is_match("Cargo.toml"));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn test_translation() {
+        assert_eq!(glob_to_regex("*.rs").unwrap(), r"^(?:.*/)?[^/]*\.rs$");
+        assert_eq!(
+            glob_to_regex("src/**/[a-c]?").unwrap(),
+            "^src/(?:.*/)?[a-c][^/]$"
+        );
+        assert_eq!(glob_to_regex("[]x-]").unwrap(), r"^(?:.*/)?(?:[x]|\]|-)$");
+    }
+}
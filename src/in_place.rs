@@ -0,0 +1,280 @@
+//! Crash-safe replacement of a file's contents.
+//!
+//! New contents are staged in a temporary file next to the original, flushed
+//! to disk and then renamed over it, so readers only ever see the old or the
+//! new file. The staged file takes over the original's permissions, ownership
+//! and extended attributes where the platform and our privileges allow it, and
+//! so does a backup.
+//!
+//! `replace_in_file` runs `RegexNFA::replace_all` over a file this way, the
+//! basis of a sed-like substitution mode; `write_in_place` writes contents
+//! computed any other way.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::error::{Error, Result};
+use crate::messages::{self, Message};
+use crate::regex::{Captures, RegexNFA, Replacer};
+
+/// Replaces every match of `regex` in the file at `path` with what
+/// `replacer` makes of it, as `RegexNFA::replace_all` does, and writes the
+/// result back with `write_in_place`, backing the original up with
+/// `backup_suffix` if given. Returns the number of replacements. A file
+/// without a match is left alone, backup and all.
+///
+/// The regex sees the whole file at once: `^` and `$` match at its start and
+/// end only, unless it was compiled with `RegexOptions::multi_line`. The
+/// file must be valid UTF-8.
+pub fn replace_in_file<R: Replacer>(
+    path: &Path,
+    regex: &RegexNFA,
+    mut replacer: R,
+    backup_suffix: Option<&str>,
+) -> Result<usize> {
+    let contents = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+    let mut replacements = 0;
+    let replaced = regex.replace_all(&contents, |caps: &Captures<'_>| {
+        replacements += 1;
+        let mut replacement = String::new();
+        replacer.replace_append(caps, &mut replacement);
+        replacement
+    });
+    if replacements > 0 {
+        write_in_place(path, replaced.as_bytes(), backup_suffix)
+            .map_err(|error| Error::io(path, error))?;
+    }
+    Ok(replacements)
+}
+
+/// Replaces the contents of `path` with `contents`.
+///
+/// With a `backup_suffix` (e.g. `.bak`), the original file is first copied to
+/// `path` + suffix. Fails without touching the file if its directory isn't
+/// writable: the rename needs it, and writing over the file in place
+/// wouldn't be atomic. Once renamed, the directory is synced too, so the
+/// replacement survives a crash.
+pub fn write_in_place(path: &Path, contents: &[u8], backup_suffix: Option<&str>) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+
+    if let Some(suffix) = backup_suffix {
+        let backup = with_suffix(path, suffix);
+        fs::copy(path, &backup)?;
+        copy_ownership(&backup, &metadata);
+        copy_xattrs(path, &backup);
+        File::open(&backup)?.sync_all()?;
+    }
+
+    let (staged, mut file) = create_staging_file(path)?;
+    let result = (|| {
+        file.write_all(contents)?;
+        file.set_permissions(metadata.permissions())?;
+        copy_ownership(&staged, &metadata);
+        copy_xattrs(path, &staged);
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&staged, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+        return result;
+    }
+    sync_parent(path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Creates the staging file next to `path` so the final rename stays on the
+/// same filesystem.
+fn create_staging_file(path: &Path) -> io::Result<(PathBuf, File)> {
+    let staged = sibling_path(path, "grep-tmp")?;
+    match create_new(&staged) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(io::Error::new(
+            e.kind(),
+            messages::text(Message::DirectoryNotWritable),
+        )),
+        result => result.map(|file| (staged, file)),
+    }
+}
+
+/// A hidden path next to `path` for a temporary file, marked with `tag`
+/// and the process ID.
+fn sibling_path(path: &Path, tag: &str) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path is not a file"))?;
+    let mut sibling_name = OsString::from(".");
+    sibling_name.push(name);
+    sibling_name.push(format!(".{}-{}", tag, process::id()));
+
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    Ok(dir.unwrap_or(Path::new(".")).join(sibling_name))
+}
+
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Syncs the directory holding `path`, making a rename into it durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    File::open(dir.unwrap_or(Path::new(".")))?.sync_all()
+}
+
+// Directories can't be opened as files to sync them elsewhere
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_ownership(staged: &Path, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{chown, MetadataExt};
+    // Only root can give files away; everyone else keeps their own ownership
+    let _ = chown(staged, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_staged: &Path, _metadata: &fs::Metadata) {}
+
+#[cfg(unix)]
+fn copy_xattrs(path: &Path, staged: &Path) {
+    if let Ok(names) = xattr::list(path) {
+        for name in names {
+            if let Ok(Some(value)) = xattr::get(path, &name) {
+                let _ = xattr::set(staged, &name, &value);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_path: &Path, _staged: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// A directory of its own holding `notes.txt`.
+    fn fixture(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("in-place-{}", name));
+        dir.file("notes.txt", "old contents\n");
+        dir
+    }
+
+    #[test]
+    fn test_replaces_contents() {
+        let dir = fixture("replace");
+        let file = dir.path().join("notes.txt");
+        write_in_place(&file, b"new contents\n", None).unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new contents\n");
+        let leftovers = fs::read_dir(file.parent().unwrap()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn test_backup_suffix() {
+        let dir = fixture("backup");
+        let file = dir.path().join("notes.txt");
+        write_in_place(&file, b"new contents\n", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new contents\n");
+        let backup = file.with_file_name("notes.txt.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old contents\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = fixture("permissions");
+        let file = dir.path().join("notes.txt");
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        write_in_place(&file, b"new contents\n", None).unwrap();
+
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_replace_in_file() {
+        let dir = fixture("replace-all");
+        let file = dir.path().join("notes.txt");
+        let regex = RegexNFA::new(r"(\w+) contents".to_string());
+        let count = replace_in_file(&file, &regex, "$1 stuff", Some(".orig")).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old stuff\n");
+        let backup = file.with_file_name("notes.txt.orig");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old contents\n");
+    }
+
+    #[test]
+    fn test_replace_without_match() {
+        let dir = fixture("no-match");
+        let file = dir.path().join("notes.txt");
+        let regex = RegexNFA::new("new".to_string());
+        let count = replace_in_file(&file, &regex, "x", Some(".bak")).unwrap();
+
+        assert_eq!(count, 0);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old contents\n");
+        // Nothing was written, so there is nothing to back up
+        assert_eq!(fs::read_dir(file.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unwritable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = fixture("unwritable");
+        let file = dir.path().join("notes.txt");
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555)).unwrap();
+        let result = write_in_place(&file, b"new contents\n", None);
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        // Root writes anyway
+        let Err(error) = result else {
+            return;
+        };
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old contents\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_backup_keeps_xattrs() {
+        let dir = fixture("backup-xattrs");
+        let file = dir.path().join("notes.txt");
+        // Not every filesystem takes user attributes
+        if xattr::set(&file, "user.grep-test", b"kept").is_err() {
+            return;
+        }
+        write_in_place(&file, b"new contents\n", Some(".bak")).unwrap();
+
+        let backup = file.with_file_name("notes.txt.bak");
+        let value = xattr::get(&backup, "user.grep-test").unwrap();
+        assert_eq!(value.as_deref(), Some(&b"kept"[..]));
+        let value = xattr::get(&file, "user.grep-test").unwrap();
+        assert_eq!(value.as_deref(), Some(&b"kept"[..]));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = fixture("missing");
+        let file = dir.path().join("absent.txt");
+        assert!(write_in_place(&file, b"x", None).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! A grep clone: a regex engine written from scratch, and the pieces for
+//! searching files with it.
+//!
+//! `regex` compiles patterns and matches them against text, `searcher` feeds
+//! it the lines of files, directories and streams, and `decoder` turns the
+//! bytes of an input into those lines. `printer` writes out what a search
+//! finds. Those two take any `matcher::Matcher`, the trait the regex engine
+//! implements, rather than the engine itself; `literal` implements it too,
+//! for fixed strings. `globs` compiles shell globs for picking which files
+//! to search, and `in_place` rewrites files with the matches replaced. The
+//! `grep-clone` binary is a thin command-line frontend over these modules.
+//! With the `ffi` feature, `ffi` exposes the regex engine to C, and with
+//! `python` it is importable from Python as `grep_rs`.
+//!
+//! Only `regex`, `matcher`, `literal` and `globs` are left without the
+//! default `std` feature: the crate is then `no_std` and needs nothing but
+//! an allocator.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod decoder;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod globs;
+#[cfg(feature = "std")]
+pub mod in_place;
+pub mod literal;
+pub mod matcher;
+#[cfg(feature = "std")]
+pub mod messages;
+#[cfg(feature = "std")]
+pub mod printer;
+#[cfg(feature = "python")]
+mod python;
+pub mod regex;
+#[cfg(feature = "std")]
+pub mod searcher;
+#[cfg(all(test, feature = "std"))]
+mod test_util;
+
+#[cfg(feature = "std")]
+pub use error::{Error, Result};
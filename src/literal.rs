@@ -0,0 +1,136 @@
+//! `LiteralMatcher`: fixed strings searched for as they are, without going
+//! through the regex engine, for `-F` and for patterns that are plain text.
+//!
+//! There is nothing to parse or compile: a single string is looked for with
+//! `memchr::memmem`, several at once with an Aho-Corasick automaton. Like
+//! the alternatives of a regex, the leftmost occurrence wins and, of those
+//! starting at the same place, the string given first.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use memchr::memmem::Finder;
+
+use crate::matcher::Matcher;
+use crate::regex::{escape, CompileError};
+
+/// A `Matcher` for any of a set of fixed strings.
+#[derive(Debug, Clone)]
+pub struct LiteralMatcher {
+    kind: Kind,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    One(Box<Finder<'static>>),
+    /// Also used for no strings at all, which never match.
+    Many(AhoCorasick),
+}
+
+impl LiteralMatcher {
+    /// A matcher for `literals`, bytes being matched as they are. An empty
+    /// string matches everywhere. Fails only when there are too many strings
+    /// for the automaton.
+    pub fn new<I, S>(literals: I) -> Result<LiteralMatcher, CompileError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        let literals: Vec<S> = literals.into_iter().collect();
+        let kind = match &literals[..] {
+            [literal] => Kind::One(Box::new(Finder::new(literal.as_ref()).into_owned())),
+            literals => AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .build(literals)
+                .map(Kind::Many)
+                .map_err(|error| CompileError::Unsupported(error.to_string()))?,
+        };
+        Ok(LiteralMatcher {
+            kind,
+            len: literals.len(),
+        })
+    }
+
+    /// Number of strings.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Matcher for LiteralMatcher {
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        match &self.kind {
+            Kind::One(finder) => {
+                let start = at + finder.find(haystack.get(at..)?)?;
+                Some(start..start + finder.needle().len())
+            }
+            Kind::Many(automaton) => {
+                if at > haystack.len() {
+                    return None;
+                }
+                let found = automaton.find(Input::new(haystack).range(at..))?;
+                Some(found.range())
+            }
+        }
+    }
+}
+
+/// Whether `pattern` only matches its own text, having no char with a
+/// meaning in patterns, so that a `LiteralMatcher` can stand in for it.
+pub fn is_literal(pattern: &str) -> bool {
+    escape(pattern) == pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::FindIter;
+
+    fn all(matcher: &LiteralMatcher, haystack: &str) -> Vec<Range<usize>> {
+        FindIter::new(matcher, haystack.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_one_literal() {
+        let matcher = LiteralMatcher::new(["a.b"]).unwrap();
+        assert_eq!(all(&matcher, "a.b axb a.b"), [0..3, 8..11]);
+        assert_eq!(matcher.find_at(b"a.b a.b", 1), Some(4..7));
+        assert_eq!(matcher.find_at(b"a.b", 4), None);
+        assert!(!matcher.is_match(b"ab"));
+    }
+
+    #[test]
+    fn test_many_literals() {
+        let matcher = LiteralMatcher::new(["ab", "a", "bc"]).unwrap();
+        // The leftmost one, then the one given first
+        assert_eq!(all(&matcher, "abc bc"), [0..2, 4..6]);
+        assert_eq!(matcher.find_at(b"abc", 1), Some(1..3));
+        assert_eq!(matcher.find_at(b"abc", 4), None);
+        assert_eq!(matcher.len(), 3);
+    }
+
+    #[test]
+    fn test_empty() {
+        let matcher = LiteralMatcher::new(Vec::<&str>::new()).unwrap();
+        assert!(matcher.is_empty());
+        assert!(!matcher.is_match(b"anything"));
+        let matcher = LiteralMatcher::new([""]).unwrap();
+        assert_eq!(all(&matcher, "ab"), [0..0, 1..1, 2..2]);
+    }
+
+    #[test]
+    fn test_is_literal() {
+        assert!(is_literal("hello world"));
+        assert!(is_literal("é-"));
+        assert!(!is_literal("a.b"));
+        assert!(!is_literal("(?i)x"));
+    }
+}
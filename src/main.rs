@@ -1,199 +1,472 @@
-use std::env;
-use std::fs::{read_dir, File};
+use std::fs;
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::IsTerminal;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::process;
 
-mod regex;
+mod args;
+mod version;
 
-use regex::RegexNFA;
+use grep_clone::decoder::DecoderRegistry;
+#[cfg(feature = "gzip")]
+use grep_clone::decoder::GzipDecoder;
+use grep_clone::in_place;
+use grep_clone::literal::{self, LiteralMatcher};
+use grep_clone::matcher::Matcher;
+use grep_clone::messages::{self, Message};
+use grep_clone::printer::{Json, Standard, StandardOptions, Summary, SummaryKind, SummaryOptions};
+use grep_clone::regex::{self, Backend, CompileError, MultiPatternBuilder, RegexNFA, RegexOptions};
+use grep_clone::searcher::{self, BinaryDetection, SearchSummary, Searcher, Sink, SinkMatch};
+use grep_clone::Error;
 
-fn match_pattern(input_line: &str, pattern: &str) -> bool {
-    let regex_nfa = RegexNFA::new(pattern.to_string());
-    regex_nfa.matches(input_line)
+use args::Args;
+
+/// What is printed for the inputs that match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// The matching lines.
+    Lines,
+    /// An object per event as JSON Lines (`--json`).
+    Json,
+    /// Nothing (`-q`): the exit status tells whether anything matched.
+    Quiet,
+    /// The path of each input with a match (`-l`).
+    FilesWithMatches,
+    /// The path of each input without a match (`-L`).
+    FilesWithoutMatch,
+    /// The number of matching lines of each input (`-c`).
+    Count,
 }
 
-fn process_file(file_path: &str, pattern: &str, multiple: bool) -> io::Result<()> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut found_match = false;
-
-    for (_, line) in reader.lines().enumerate() {
-        let line = line?;
-        if match_pattern(&line, pattern) {
-            found_match = true;
-            if multiple {
-                println!("{}:{}", file_path, line);
-            } else {
-                println!("{}", line);
+/// What the inputs are searched with, set up once from the command line.
+struct Search<'a> {
+    searcher: Searcher,
+    matcher: &'a dyn Matcher,
+    decoders: DecoderRegistry,
+    output: Output,
+    /// The printer of matching lines.
+    standard: Standard<io::Stdout>,
+    /// The printer of `--json` events.
+    json: Json<io::Stdout>,
+    /// The printer of counts and paths, for `-c`, `-l` and `-L`.
+    summary: Summary<io::Stdout>,
+    /// What `--stats` collects, when given.
+    stats: Option<SearchSummary>,
+}
+
+/// Runs `search` with `sink` or, with `--stats`, with `sink` wrapped to add
+/// the search to `stats`.
+fn search_into<S, F>(
+    stats: &mut Option<SearchSummary>,
+    matcher: &dyn Matcher,
+    sink: &mut S,
+    search: F,
+) -> grep_clone::Result<()>
+where
+    S: Sink,
+    F: FnOnce(&mut dyn Sink) -> grep_clone::Result<()>,
+{
+    match stats {
+        Some(stats) => search(&mut stats.sink(matcher, sink)),
+        None => search(sink),
+    }
+}
+
+impl Search<'_> {
+    /// Searches an input with `search` and prints what `output` asks for,
+    /// returning whether the input counts as a match. `path` is that of a
+    /// file, `None` for standard input, and is printed in front of its
+    /// lines and counts when `multiple`.
+    fn search<F>(
+        &mut self,
+        path: Option<&Path>,
+        multiple: bool,
+        search: F,
+    ) -> grep_clone::Result<bool>
+    where
+        F: FnOnce(&Searcher, &DecoderRegistry, &mut dyn Sink) -> grep_clone::Result<()>,
+    {
+        let Search {
+            searcher,
+            matcher,
+            decoders,
+            output,
+            standard,
+            json,
+            summary,
+            stats,
+        } = self;
+        let matcher = *matcher;
+        let search = |sink: &mut dyn Sink| search(searcher, decoders, sink);
+        let named = path.filter(|_| multiple);
+        match output {
+            Output::Lines => {
+                let mut sink = match (named, path) {
+                    (Some(path), _) => standard.sink_with_path(matcher, path),
+                    (None, Some(path)) => standard.sink_named(matcher, path),
+                    (None, None) => standard.sink(matcher),
+                };
+                search_into(stats, matcher, &mut sink, search)?;
+                Ok(sink.has_match())
+            }
+            Output::Json => {
+                let mut sink = match path {
+                    Some(path) => json.sink_with_path(matcher, path),
+                    None => json.sink(matcher),
+                };
+                search_into(stats, matcher, &mut sink, search)?;
+                Ok(sink.has_match())
+            }
+            Output::Quiet => {
+                // Stop at the first match, which is all there is to know
+                let mut found = false;
+                let mut first_match = |_: &SinkMatch<'_>| {
+                    found = true;
+                    Ok(false)
+                };
+                search_into(stats, matcher, &mut first_match, search)?;
+                Ok(found)
+            }
+            Output::Count | Output::FilesWithMatches | Output::FilesWithoutMatch => {
+                // A count only has a path in front when there are several
+                let path = if *output == Output::Count {
+                    named
+                } else {
+                    path
+                };
+                let mut sink = match path {
+                    Some(path) => summary.sink_with_path(path),
+                    None => summary.sink(),
+                };
+                search_into(stats, matcher, &mut sink, search)?;
+                // Listing the input is what counts as a match
+                Ok(sink.has_match() != (*output == Output::FilesWithoutMatch))
             }
         }
     }
 
-    if !found_match {
-        return Err(io::Error::new(io::ErrorKind::Other, "No matches found"));
+    fn process_file(&mut self, path: &Path, multiple: bool) -> grep_clone::Result<bool> {
+        let matcher = self.matcher;
+        self.search(Some(path), multiple, |searcher, decoders, sink| {
+            searcher.search_file(matcher, path, decoders, sink)
+        })
     }
-    Ok(())
-}
 
-fn process_directory_recursive(dir_path: &str, pattern: &str) -> io::Result<()> {
-    let path = Path::new(dir_path);
-    if !path.is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Path is not a directory",
-        ));
-    }
-
-    let mut found_match = false;
-
-    for entry in read_dir(path)? {
-        let entry = entry?;
-        let entry_path = entry.path();
-
-        if entry_path.is_file() {
-            // Process file
-            if let Ok(file) = File::open(&entry_path) {
-                let reader = BufReader::new(file);
-
-                for (_, line) in reader.lines().enumerate() {
-                    if let Ok(line) = line {
-                        if match_pattern(&line, pattern) {
-                            println!("{}:{}", entry_path.display(), line);
-                            found_match = true;
-                        }
-                    }
-                }
-            }
-        } else if entry_path.is_dir() {
-            // Recursively process subdirectory
-            if let Some(dir_name) = entry_path.file_name() {
-                if let Some(dir_name_str) = dir_name.to_str() {
-                    // Skip hidden directories (starting with .)
-                    if !dir_name_str.starts_with('.') {
-                        match process_directory_recursive(entry_path.to_str().unwrap(), pattern) {
-                            Ok(_) => found_match = true,
-                            Err(_) => {}
-                        }
-                    }
+    /// Searches every file under `dir_path`, each like a file of several.
+    /// Files and directories that can't be read are reported on stderr,
+    /// setting `failed`, and the others are still searched.
+    fn process_directory_recursive(
+        &mut self,
+        dir_path: &str,
+        failed: &mut bool,
+    ) -> grep_clone::Result<bool> {
+        let mut found_match = false;
+        for file in searcher::walk_files(Path::new(dir_path))? {
+            match file.and_then(|file| self.process_file(&file, true)) {
+                // There is no need to walk any further
+                Ok(true) if self.output == Output::Quiet => return Ok(true),
+                Ok(found) => found_match |= found,
+                Err(e) => {
+                    eprintln!("{}", processing_error(dir_path, &e));
+                    *failed = true;
                 }
             }
         }
+        Ok(found_match)
     }
 
-    if !found_match {
-        return Err(io::Error::new(io::ErrorKind::Other, "No matches found"));
+    fn process_stdin(&mut self) -> grep_clone::Result<bool> {
+        let matcher = self.matcher;
+        self.search(None, false, |searcher, _, sink| {
+            searcher.search_reader(matcher, io::stdin().lock(), sink)
+        })
     }
-    Ok(())
 }
 
-fn process_stdin(pattern: &str) -> io::Result<()> {
-    let stdin = io::stdin();
-    let reader = stdin.lock();
-    let mut found_match = false;
+/// The exit status after an error, whether the pattern can't be used or an
+/// input can't be read, as in grep: 1 only ever means nothing matched.
+const ERROR_EXIT_CODE: i32 = 2;
+
+/// What to print for `error`, which came up searching `path`. An error
+/// reading a file names that file, which may be one inside `path`.
+fn processing_error(path: &str, error: &Error) -> String {
+    match error {
+        Error::Io {
+            path: Some(file),
+            source,
+        } => messages::format(Message::ErrorProcessing, &[&file.display(), source]),
+        _ => messages::format(Message::ErrorProcessing, &[&path, error]),
+    }
+}
+
+/// Prints what `--stats` collected, after the results.
+fn print_stats(stats: &Option<SearchSummary>) {
+    let Some(stats) = stats else {
+        return;
+    };
+    let seconds = format!("{:.6}", stats.elapsed.as_secs_f64());
+    let text = messages::format(
+        Message::Stats,
+        &[
+            &stats.matches,
+            &stats.lines_matched,
+            &stats.files_with_matches,
+            &stats.files_searched,
+            &stats.bytes_searched,
+            &seconds,
+        ],
+    );
+    println!("\n{}", text);
+}
+
+/// The matcher for `patterns`. Fixed strings, with `-F` or when every
+/// pattern is plain text, skip the regex engine, unless it is to be traced,
+/// `--engine` picked one of its backends, or case is to be ignored or words
+/// or lines matched whole: only the engine folds case beyond ASCII and
+/// anchors matches, so `-F` with `-i`, `-w` or `-x` gives it the escaped
+/// strings.
+fn build_matcher(patterns: Vec<String>, args: &Args) -> Result<Box<dyn Matcher>, CompileError> {
+    let plain_text = |pattern: &String| literal::is_literal(pattern);
+    let engine_only = args.ignore_case || args.word_regexp || args.line_regexp;
+    let regex_wanted = args.trace || args.engine.is_some() || engine_only;
+    if (args.fixed_strings && !engine_only) || (!regex_wanted && patterns.iter().all(plain_text)) {
+        return Ok(Box::new(LiteralMatcher::new(&patterns)?));
+    }
+    let mut builder = MultiPatternBuilder::with_options(regex_options(args));
+    for pattern in patterns {
+        if args.fixed_strings {
+            builder.add(regex::escape(&pattern));
+        } else {
+            builder.add(pattern);
+        }
+    }
+    Ok(Box::new(builder.build()?))
+}
+
+/// The regex options the command line asks for.
+fn regex_options(args: &Args) -> RegexOptions {
+    RegexOptions::new()
+        .backend(args.engine.map_or(Backend::Auto, Backend::from))
+        .case_insensitive(args.ignore_case)
+        .word(args.word_regexp)
+        .whole_line(args.line_regexp)
+}
+
+/// The regex `--write` replaces the matches of. It sees each file whole,
+/// so `^` and `$` match at the start and end of every line of it.
+fn build_write_regex(pattern: &str, args: &Args) -> Result<RegexNFA, CompileError> {
+    let pattern = if args.fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    RegexNFA::try_with_options(pattern, regex_options(args).multi_line(true))
+}
 
-    for (_, line) in reader.lines().enumerate() {
-        let line = line?;
-        if match_pattern(&line, pattern) {
-            found_match = true;
-            println!("{}", line);
+/// Replaces every match of `regex` in the files at `paths`, or under them
+/// with `-r`, with `replacement`, for `--write`. Returns the exit status:
+/// 0 when anything was replaced, 1 when nothing was and 2 after an error.
+fn rewrite(paths: &[String], args: &Args, regex: &RegexNFA, replacement: &str) -> i32 {
+    let backup_suffix = args.backup_suffix.as_deref();
+    let mut replaced = false;
+    let mut failed = false;
+    for path in paths {
+        let files: Box<dyn Iterator<Item = grep_clone::Result<PathBuf>>> = if args.recursive {
+            match searcher::walk_files(Path::new(path)) {
+                Ok(files) => Box::new(files),
+                Err(e) => {
+                    eprintln!("{}", processing_error(path, &e));
+                    failed = true;
+                    continue;
+                }
+            }
+        } else {
+            Box::new(iter::once(Ok(PathBuf::from(path))))
+        };
+        for file in files {
+            let replacing = file.and_then(|file| {
+                in_place::replace_in_file(&file, regex, replacement, backup_suffix)
+            });
+            match replacing {
+                Ok(count) => replaced |= count > 0,
+                // The other files are still rewritten
+                Err(e) => {
+                    eprintln!("{}", processing_error(path, &e));
+                    failed = true;
+                }
+            }
         }
     }
+    if failed {
+        ERROR_EXIT_CODE
+    } else if replaced {
+        0
+    } else {
+        1
+    }
+}
 
-    if !found_match {
-        return Err(io::Error::new(io::ErrorKind::Other, "No matches found"));
+/// The patterns in the file at `path`, one per line, for `-f`.
+fn read_patterns(path: &str) -> io::Result<Vec<String>> {
+    let patterns = fs::read_to_string(path)?;
+    Ok(patterns.lines().map(str::to_string).collect())
+}
+
+/// The decoders files are read with; `-Z` adds gzip when it is compiled in.
+#[cfg_attr(not(feature = "gzip"), allow(unused_mut, unused_variables))]
+fn decoder_registry(decompress: bool) -> DecoderRegistry {
+    let mut decoders = DecoderRegistry::new();
+    #[cfg(feature = "gzip")]
+    if decompress {
+        decoders.register(Box::new(GzipDecoder));
     }
-    Ok(())
+    decoders
 }
 
-// Usage:
-// echo <input_text> | myprogram -E <pattern>
-// myprogram -E <pattern> <filepath1> [filepath2] [filepath3] ...
-// myprogram -r -E <pattern> <directory1> [directory2] [directory3] ...
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 3 {
-        println!("Usage: myprogram -E <pattern> [filepath1] [filepath2] ...");
-        println!("       myprogram -r -E <pattern> <directory1> [directory2] ...");
-        println!("  If no filepath is provided, reads from stdin");
-        process::exit(1);
-    }
-
-    let mut recursive = false;
-    let mut pattern_index = 0;
-    let mut path_start_index = 0;
-
-    // Parse arguments
-    for (i, arg) in args.iter().enumerate() {
-        match arg.as_str() {
-            "-r" => recursive = true,
-            "-E" => {
-                pattern_index = i + 1;
-                path_start_index = i + 2;
+    let args = Args::from_env();
+
+    if args.version {
+        let info = version::VersionInfo::current();
+        if args.json {
+            println!("{}", info.to_json());
+        } else {
+            println!("{}", info.to_text());
+        }
+        process::exit(0);
+    }
+
+    let output = if args.quiet {
+        Output::Quiet
+    } else if args.files_with_matches {
+        Output::FilesWithMatches
+    } else if args.files_without_match {
+        Output::FilesWithoutMatch
+    } else if args.count {
+        Output::Count
+    } else if args.json {
+        Output::Json
+    } else {
+        Output::Lines
+    };
+    // Every pattern from `-e` or the command line, then those from `-f`
+    let mut patterns = args.patterns.clone();
+    for file in &args.pattern_files {
+        match read_patterns(file) {
+            Ok(lines) => patterns.extend(lines),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    messages::format(Message::ErrorReadingPatterns, &[file, &e])
+                );
+                process::exit(2);
             }
-            _ => {}
         }
     }
 
-    if pattern_index == 0 {
-        println!("Expected '-E' flag");
-        process::exit(1);
+    if args.trace {
+        regex::set_tracer(Some(Box::new(|event| eprintln!("trace: {}", event))));
     }
 
-    if pattern_index >= args.len() {
-        println!("Missing pattern after -E");
-        process::exit(1);
+    if let Some(replacement) = &args.write {
+        let regex = match build_write_regex(&patterns[0], &args) {
+            Ok(regex) => regex,
+            Err(e) => {
+                let e = Error::from(e);
+                eprintln!("{}", messages::format(Message::InvalidPattern, &[&e]));
+                process::exit(ERROR_EXIT_CODE);
+            }
+        };
+        process::exit(rewrite(&args.paths, &args, &regex, replacement));
     }
 
-    let pattern = &args[pattern_index];
+    let matcher = match build_matcher(patterns, &args) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            let e = Error::from(e);
+            eprintln!("{}", messages::format(Message::InvalidPattern, &[&e]));
+            process::exit(ERROR_EXIT_CODE);
+        }
+    };
+
+    let before_context = args.before_context.or(args.context).unwrap_or(0);
+    let after_context = args.after_context.or(args.context).unwrap_or(0);
+    let standard = StandardOptions::new()
+        .line_number(args.line_number)
+        .only_matching(args.only_matching)
+        .show_spans(args.show_spans)
+        .separate_searches(before_context > 0 || after_context > 0);
+    let summary = SummaryOptions::new().kind(match output {
+        Output::FilesWithMatches => SummaryKind::PathWithMatch,
+        Output::FilesWithoutMatch => SummaryKind::PathWithoutMatch,
+        _ => SummaryKind::Count,
+    });
+    // Only a terminal needs protecting from binary lines, of files and
+    // standard input alike
+    let binary_detection = if !args.text && output == Output::Lines && io::stdout().is_terminal() {
+        BinaryDetection::Report
+    } else {
+        BinaryDetection::None
+    };
+    let mut search = Search {
+        searcher: Searcher::new()
+            .invert_match(args.invert_match)
+            .before_context(before_context)
+            .after_context(after_context)
+            .binary_detection(binary_detection),
+        matcher: &*matcher,
+        decoders: decoder_registry(args.decompress),
+        output,
+        standard: Standard::with_options(io::stdout(), standard),
+        json: Json::new(io::stdout()),
+        summary: Summary::with_options(io::stdout(), summary),
+        stats: args.stats.then(SearchSummary::new),
+    };
 
     // Check if paths are provided
-    if path_start_index < args.len() {
-        let paths = &args[path_start_index..];
+    if !args.paths.is_empty() {
+        let paths = &args.paths;
         let mut found_match_anywhere = false;
-        let mut errors = Vec::new();
+        let mut failed = false;
 
         for path in paths {
-            let path_result = if recursive {
+            let path_result = if args.recursive {
                 // Recursive directory search
-                process_directory_recursive(path, pattern)
+                search.process_directory_recursive(path, &mut failed)
             } else {
                 // Single file search
-                process_file(path, pattern, paths.len() > 1)
+                search.process_file(Path::new(path), paths.len() > 1)
             };
 
             match path_result {
-                Ok(_) => {
-                    found_match_anywhere = true;
-                }
+                // Any match settles the exit status, so -q is done
+                Ok(true) if output == Output::Quiet => process::exit(0),
+                Ok(found) => found_match_anywhere |= found,
+                // The other inputs are still searched
                 Err(e) => {
-                    let error_msg = format!("Error processing '{}': {}", path, e);
-                    errors.push(error_msg);
+                    eprintln!("{}", processing_error(path, &e));
+                    failed = true;
                 }
             }
         }
 
-        // Exit with appropriate code
-        if found_match_anywhere {
-            process::exit(0);
-        } else {
-            if !found_match_anywhere && !errors.is_empty() {
-                for error in errors {
-                    eprintln!("{}", error);
-                }
-            }
-            // No matches found in any file
-            process::exit(1);
+        print_stats(&search.stats);
+        // An error wins over any match
+        if failed {
+            process::exit(ERROR_EXIT_CODE);
         }
+        process::exit(if found_match_anywhere { 0 } else { 1 });
     } else {
         // No path provided, read from stdin
-        match process_stdin(pattern) {
-            Ok(_) => process::exit(0),
+        let result = search.process_stdin();
+        print_stats(&search.stats);
+        match result {
+            Ok(true) => process::exit(0),
+            Ok(false) => process::exit(1),
             Err(e) => {
-                eprintln!("Error reading from stdin: {}", e);
-                process::exit(1);
+                eprintln!("{}", messages::format(Message::ErrorReadingStdin, &[&e]));
+                process::exit(ERROR_EXIT_CODE);
             }
         }
     }
@@ -0,0 +1,184 @@
+//! `Matcher`: what the search pipeline needs from a pattern engine.
+//!
+//! The searcher, `search_path` and the printers only ever ask whether a line
+//! matches, where its matches are and, for output that wants them, the
+//! groups of a match. `Matcher` is those questions over bytes, so any engine
+//! answering them can be searched with: `RegexNFA` implements it, and a
+//! backend added later only has to implement it too. Offsets are byte
+//! offsets into the haystack. On valid UTF-8 they fall on char boundaries,
+//! so text searched as `&str` can be sliced with them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A compiled pattern that finds matches in bytes.
+///
+/// Only `find_at` has to be written; the rest follow from it, and are worth
+/// overriding where an engine can answer them more cheaply. The trait is
+/// object safe, so a `&dyn Matcher` can stand for whichever backend was
+/// picked at run time.
+pub trait Matcher {
+    /// The leftmost match starting at byte offset `at` or later. The bytes
+    /// before `at` still count as context for anchors and look-behind.
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>>;
+
+    /// The leftmost match in `haystack`.
+    fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Whether `haystack` contains a match.
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// The spans of the leftmost match, then of each of its groups in order,
+    /// `None` for a group that didn't take part. Engines without groups
+    /// give just the match.
+    fn captures(&self, haystack: &[u8]) -> Option<Vec<Option<Range<usize>>>> {
+        self.find(haystack).map(|found| vec![Some(found)])
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        (**self).find_at(haystack, at)
+    }
+
+    fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        (**self).find(haystack)
+    }
+
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        (**self).is_match(haystack)
+    }
+
+    fn captures(&self, haystack: &[u8]) -> Option<Vec<Option<Range<usize>>>> {
+        (**self).captures(haystack)
+    }
+}
+
+/// Every match of a `Matcher` in a haystack, left to right. Matches never
+/// overlap, and an empty match right where the previous one ended is
+/// skipped, as with `RegexNFA::find_iter`.
+pub struct FindIter<'m, 'h, M: ?Sized> {
+    matcher: &'m M,
+    haystack: &'h [u8],
+    // Byte offset the next search starts at
+    at: usize,
+    // Byte offset where the previous match ended
+    last_end: Option<usize>,
+}
+
+impl<'m, 'h, M: Matcher + ?Sized> FindIter<'m, 'h, M> {
+    pub fn new(matcher: &'m M, haystack: &'h [u8]) -> Self {
+        FindIter {
+            matcher,
+            haystack,
+            at: 0,
+            last_end: None,
+        }
+    }
+}
+
+impl<M: Matcher + ?Sized> Iterator for FindIter<'_, '_, M> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        loop {
+            let found = self.matcher.find_at(self.haystack, self.at)?;
+            if found.is_empty() && self.last_end == Some(found.end) {
+                if found.start >= self.haystack.len() {
+                    return None;
+                }
+                self.at = found.start + char_len(&self.haystack[found.start..]);
+                continue;
+            }
+            self.at = found.end;
+            self.last_end = Some(found.end);
+            return Some(found);
+        }
+    }
+}
+
+/// The length of the char `bytes` starts with, 1 for a byte that doesn't
+/// start a valid UTF-8 sequence.
+fn char_len(bytes: &[u8]) -> usize {
+    match bytes.utf8_chunks().next() {
+        Some(chunk) => chunk.valid().chars().next().map_or(1, char::len_utf8),
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use alloc::string::ToString;
+
+    /// Matches `needle` only, the least a backend can be.
+    struct Literal(&'static [u8]);
+
+    impl Matcher for Literal {
+        fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+            let start = haystack
+                .get(at..)?
+                .windows(self.0.len())
+                .position(|window| window == self.0)?;
+            Some(at + start..at + start + self.0.len())
+        }
+    }
+
+    fn all<M: Matcher + ?Sized>(matcher: &M, haystack: &[u8]) -> Vec<Range<usize>> {
+        FindIter::new(matcher, haystack).collect()
+    }
+
+    #[test]
+    fn test_provided_methods() {
+        let literal = Literal(b"ab");
+        assert_eq!(literal.find(b"xxabab"), Some(2..4));
+        assert!(literal.is_match(b"cab"));
+        assert!(!literal.is_match(b"ba"));
+        assert_eq!(literal.captures(b"ab"), Some(vec![Some(0..2)]));
+        assert_eq!(all(&literal, b"abxab"), [0..2, 3..5]);
+    }
+
+    #[test]
+    fn test_regex() {
+        let regex = RegexNFA::new(r"(\w)(\d)?".to_string());
+        let matcher: &dyn Matcher = &regex;
+        assert_eq!(matcher.find(b"  ab"), Some(2..3));
+        assert_eq!(matcher.find_at(b"a1 b", 2), Some(3..4));
+        assert_eq!(
+            matcher.captures(b" a1"),
+            Some(vec![Some(1..3), Some(1..2), Some(2..3)])
+        );
+        assert_eq!(
+            matcher.captures(b"-b"),
+            Some(vec![Some(1..2), Some(1..2), None])
+        );
+        assert_eq!(all(matcher, b"a1 b"), [0..2, 3..4]);
+    }
+
+    #[test]
+    fn test_regex_invalid_utf8() {
+        let regex = RegexNFA::new("b+".to_string());
+        assert!(regex.is_match(b"\xff\xfeb".as_slice()));
+        assert_eq!(Matcher::find(&regex, b"a\xffbb"), Some(2..4));
+        assert_eq!(all(&regex, b"b\xffb\xe2\x82b"), [0..1, 2..3, 5..6]);
+        let regex = RegexNFA::new("(.)b".to_string());
+        assert_eq!(
+            Matcher::captures(&regex, b"\xffb"),
+            Some(vec![Some(0..2), Some(0..1)])
+        );
+    }
+
+    #[test]
+    fn test_empty_matches() {
+        let regex = RegexNFA::new("a*".to_string());
+        assert_eq!(all(&regex, b"baaa"), [0..0, 1..4]);
+        assert_eq!(all(&regex, "é".as_bytes()), [0..0, 2..2]);
+        assert_eq!(all(&regex, b"\xff"), [0..0, 1..1]);
+    }
+}
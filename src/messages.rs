@@ -0,0 +1,301 @@
+//! User-facing CLI messages.
+//!
+//! Every string the binary prints to the user lives in a per-language table
+//! here, so a translation only needs a new table and an entry in `CATALOGS`.
+//! The language is picked from `LC_ALL`, `LC_MESSAGES` or `LANG` (in that
+//! order), falling back to English for unknown languages or missing entries.
+
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    Usage,
+    MissingPattern,
+    /// `{0}` is the path, `{1}` the error
+    ErrorProcessing,
+    /// `{0}` is the error
+    ErrorReadingStdin,
+    NotADirectory,
+    DirectoryNotWritable,
+    SpanMatch,
+    /// `{0}` is the group number
+    SpanGroup,
+    BinaryStdinMatches,
+    /// `{0}` is the path
+    BinaryFileMatches,
+    StandardInput,
+    /// `{0}` is the program name, `{1}` its version, `{2}` the enabled
+    /// features, `{3}` the regex dialects and `{4}` the thread count
+    Version,
+    NoFeatures,
+    /// `{0}` is the syntax error
+    InvalidPattern,
+    /// `{0}` is the path of the pattern file, `{1}` the error
+    ErrorReadingPatterns,
+    /// `{0}` is the number of matches, `{1}` of matching lines, `{2}` of
+    /// files with a match, `{3}` of files searched, `{4}` of bytes searched
+    /// and `{5}` the seconds the searches took
+    Stats,
+    WriteNeedsOnePattern,
+    WriteNeedsPaths,
+}
+
+struct Catalog {
+    language: &'static str,
+    lookup: fn(Message) -> Option<&'static str>,
+}
+
+const CATALOGS: &[Catalog] = &[
+    Catalog {
+        language: "en",
+        lookup: english,
+    },
+    Catalog {
+        language: "es",
+        lookup: spanish,
+    },
+];
+
+fn english(message: Message) -> Option<&'static str> {
+    Some(match message {
+        Message::Usage => {
+            "Usage: grep-clone [options] <pattern> [path...]\n       \
+             grep-clone [options] -e <pattern>... [path...]\n  \
+             If no path is provided, reads from stdin, or with -r the current directory\n  \
+             -e, -E, --regexp <pattern>  a pattern to search for; repeat it to search for several\n  \
+             -f, --file <file>           search for the patterns in a file, one per line\n  \
+             -F, --fixed-strings         take the patterns as fixed strings, not regexes\n  \
+             -i, --ignore-case           match letters of either case\n  \
+             -w, --word-regexp           only match whole words, with no letter, digit or _ around them\n  \
+             -x, --line-regexp           only match whole lines\n  \
+             -v, --invert-match          print the lines that don't match instead\n  \
+             -n, --line-number           print the line number before each line\n  \
+             -o, --only-matching         print only the matches, each on a line of its own\n  \
+             -A, --after-context <num>   print num lines of context after each match\n  \
+             -B, --before-context <num>  print num lines of context before each match\n  \
+             -C, --context <num>         print num lines of context before and after each match\n  \
+             -r, --recursive             search the directories given and everything in them\n  \
+             -q, --quiet                 print nothing; exit with 0 at the first match\n  \
+             -l, --files-with-matches    print only the paths of the files that match\n  \
+             -L, --files-without-match   print only the paths of the files that don't match\n  \
+             -c, --count                 print only the number of matching lines of each input\n  \
+             -a, --text                  print matching lines of binary input as text\n  \
+             -Z, --decompress            search inside gzip-compressed files\n      \
+             --stats                 print how much was searched and found, and how long it took\n      \
+             --engine <engine>       run the regexes on one engine: auto, backtrack, pikevm or lazydfa\n      \
+             --show-spans            underline the match and each capture group\n      \
+             --json                  print each match, context line, and the start and end of each input as JSON Lines\n      \
+             --write <text>          replace every match in the files with text ($1 for group 1), rewriting them\n      \
+             --backup-suffix <suf>   with --write, keep each original file at its path plus suf\n      \
+             --trace                 print what the regex engine does to stderr\n  \
+             -h, --help                  print this help\n  \
+             -V, --version               print the version and capabilities (as JSON with --json)"
+        }
+        Message::MissingPattern => "no pattern given: pass one, or use -e or -f",
+        Message::ErrorProcessing => "Error processing '{0}': {1}",
+        Message::ErrorReadingStdin => "Error reading from stdin: {0}",
+        Message::NotADirectory => "Path is not a directory",
+        Message::DirectoryNotWritable => {
+            "Can't create a temporary file in its directory to replace it safely"
+        }
+        Message::SpanMatch => "match",
+        Message::SpanGroup => "group {0}",
+        Message::BinaryStdinMatches => "binary file (standard input) matches",
+        Message::BinaryFileMatches => "binary file {0} matches",
+        Message::StandardInput => "(standard input)",
+        Message::Version => "{0} {1}\nfeatures: {2}\ndialects: {3}\nthreads: {4}",
+        Message::NoFeatures => "none",
+        Message::InvalidPattern => "Invalid pattern: {0}",
+        Message::ErrorReadingPatterns => "Error reading patterns from '{0}': {1}",
+        Message::Stats => {
+            "{0} matches\n{1} matched lines\n{2} files contained matches\n\
+             {3} files searched\n{4} bytes searched\n{5} seconds spent searching"
+        }
+        Message::WriteNeedsOnePattern => "--write takes a single pattern",
+        Message::WriteNeedsPaths => "--write needs the files to rewrite",
+    })
+}
+
+fn spanish(message: Message) -> Option<&'static str> {
+    Some(match message {
+        Message::Usage => {
+            "Uso: grep-clone [opciones] <patrón> [ruta...]\n     \
+             grep-clone [opciones] -e <patrón>... [ruta...]\n  \
+             Si no se indica ninguna ruta, se lee de la entrada estándar, o con -r del directorio actual\n  \
+             -e, -E, --regexp <patrón>   un patrón a buscar; repítela para buscar varios\n  \
+             -f, --file <fichero>        busca los patrones de un fichero, uno por línea\n  \
+             -F, --fixed-strings         toma los patrones como cadenas fijas, no como regex\n  \
+             -i, --ignore-case           no distingue mayúsculas de minúsculas\n  \
+             -w, --word-regexp           solo coincide con palabras enteras, sin letras, dígitos ni _ alrededor\n  \
+             -x, --line-regexp           solo coincide con líneas enteras\n  \
+             -v, --invert-match          muestra en cambio las líneas que no coinciden\n  \
+             -n, --line-number           muestra el número de línea delante de cada línea\n  \
+             -o, --only-matching         muestra solo las coincidencias, cada una en su línea\n  \
+             -A, --after-context <núm>   muestra núm líneas de contexto tras cada coincidencia\n  \
+             -B, --before-context <núm>  muestra núm líneas de contexto antes de cada coincidencia\n  \
+             -C, --context <núm>         muestra núm líneas de contexto antes y después de cada coincidencia\n  \
+             -r, --recursive             busca en los directorios indicados y todo lo que contienen\n  \
+             -q, --quiet                 no muestra nada; termina con 0 en la primera coincidencia\n  \
+             -l, --files-with-matches    muestra solo las rutas de los ficheros que coinciden\n  \
+             -L, --files-without-match   muestra solo las rutas de los ficheros que no coinciden\n  \
+             -c, --count                 muestra solo el número de líneas coincidentes de cada entrada\n  \
+             -a, --text                  muestra como texto las líneas coincidentes de entradas binarias\n  \
+             -Z, --decompress            busca dentro de archivos comprimidos con gzip\n      \
+             --stats                 muestra cuánto se buscó y encontró, y cuánto tardó\n      \
+             --engine <motor>        ejecuta las regex con un solo motor: auto, backtrack, pikevm o lazydfa\n      \
+             --show-spans            subraya la coincidencia y cada grupo de captura\n      \
+             --json                  muestra cada coincidencia, línea de contexto, y el inicio y fin de cada entrada como JSON Lines\n      \
+             --write <texto>         sustituye cada coincidencia en los ficheros por texto ($1 para el grupo 1), reescribiéndolos\n      \
+             --backup-suffix <suf>   con --write, guarda cada fichero original en su ruta más suf\n      \
+             --trace                 muestra en stderr lo que hace el motor de regex\n  \
+             -h, --help                  muestra esta ayuda\n  \
+             -V, --version               muestra la versión y las capacidades (en JSON con --json)"
+        }
+        Message::MissingPattern => "no se indicó ningún patrón: pasa uno, o usa -e o -f",
+        Message::ErrorProcessing => "Error al procesar '{0}': {1}",
+        Message::ErrorReadingStdin => "Error al leer de la entrada estándar: {0}",
+        Message::NotADirectory => "La ruta no es un directorio",
+        Message::DirectoryNotWritable => {
+            "No se puede crear un fichero temporal en su directorio para reemplazarlo de forma segura"
+        }
+        Message::SpanMatch => "coincidencia",
+        Message::SpanGroup => "grupo {0}",
+        Message::BinaryStdinMatches => "el fichero binario (entrada estándar) coincide",
+        Message::BinaryFileMatches => "el fichero binario {0} coincide",
+        Message::StandardInput => "(entrada estándar)",
+        Message::Version => "{0} {1}\ncaracterísticas: {2}\ndialectos: {3}\nhilos: {4}",
+        Message::NoFeatures => "ninguna",
+        Message::InvalidPattern => "Patrón no válido: {0}",
+        Message::ErrorReadingPatterns => "Error al leer los patrones de '{0}': {1}",
+        Message::Stats => {
+            "{0} coincidencias\n{1} líneas coincidentes\n{2} ficheros con coincidencias\n\
+             {3} ficheros buscados\n{4} bytes buscados\n{5} segundos de búsqueda"
+        }
+        Message::WriteNeedsOnePattern => "--write admite un solo patrón",
+        Message::WriteNeedsPaths => "--write necesita los ficheros a reescribir",
+    })
+}
+
+/// Extracts the language code from a locale string like `es_ES.UTF-8`.
+/// `C` and `POSIX` mean "no translation".
+fn language_of(locale: &str) -> Option<&str> {
+    let language = locale.split(['_', '.', '@']).next().unwrap_or("");
+    match language {
+        "" | "C" | "POSIX" => None,
+        _ => Some(language),
+    }
+}
+
+fn catalog_for(language: Option<&str>) -> &'static Catalog {
+    language
+        .and_then(|language| CATALOGS.iter().find(|c| c.language == language))
+        .unwrap_or(&CATALOGS[0])
+}
+
+fn current_catalog() -> &'static Catalog {
+    static CURRENT: OnceLock<&'static Catalog> = OnceLock::new();
+    CURRENT.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        catalog_for(language_of(&locale))
+    })
+}
+
+fn lookup(catalog: &Catalog, message: Message) -> &'static str {
+    (catalog.lookup)(message)
+        .or_else(|| english(message))
+        .expect("English catalog covers every message")
+}
+
+/// The message text in the user's language.
+pub fn text(message: Message) -> &'static str {
+    lookup(current_catalog(), message)
+}
+
+/// The message text with the `{0}`, `{1}`, ... placeholders filled in.
+pub fn format(message: Message, args: &[&dyn Display]) -> String {
+    fill(text(message), args)
+}
+
+/// `template` with each `{n}` replaced by `args[n]`, in a single pass so
+/// that an argument containing a placeholder is left as it is. A brace not
+/// starting a placeholder for one of `args` is copied through.
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let arg = after.find('}').and_then(|close| {
+            let index: usize = after[..close].parse().ok()?;
+            Some((args.get(index)?, close))
+        });
+        match arg {
+            Some((arg, close)) => {
+                result.push_str(&arg.to_string());
+                rest = &after[close + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_of() {
+        assert_eq!(language_of("es_ES.UTF-8"), Some("es"));
+        assert_eq!(language_of("en"), Some("en"));
+        assert_eq!(language_of("de_DE@euro"), Some("de"));
+        assert_eq!(language_of("C"), None);
+        assert_eq!(language_of("POSIX"), None);
+        assert_eq!(language_of(""), None);
+    }
+
+    #[test]
+    fn test_fallback_to_english() {
+        assert_eq!(catalog_for(Some("xx")).language, "en");
+        assert_eq!(catalog_for(None).language, "en");
+        assert_eq!(catalog_for(Some("es")).language, "es");
+    }
+
+    #[test]
+    fn test_lookup() {
+        let spanish = catalog_for(Some("es"));
+        assert_eq!(
+            lookup(spanish, Message::MissingPattern),
+            "no se indicó ningún patrón: pasa uno, o usa -e o -f"
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        let template = english(Message::ErrorProcessing).unwrap();
+        assert_eq!(
+            fill(template, &[&"a.txt", &"No matches found"]),
+            "Error processing 'a.txt': No matches found"
+        );
+    }
+
+    #[test]
+    fn test_fill_arguments_are_not_templates() {
+        let template = english(Message::ErrorProcessing).unwrap();
+        assert_eq!(
+            fill(template, &[&"{1}.txt", &"not found"]),
+            "Error processing '{1}.txt': not found"
+        );
+        assert_eq!(fill("{0} {2} {x} {", &[&"{0}"]), "{0} {2} {x} {");
+    }
+}
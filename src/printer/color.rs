@@ -0,0 +1,48 @@
+//! Terminal colors for the printers.
+
+use std::path::Path;
+
+/// The ANSI SGR parameters each part of the output is colored with, like
+/// `1;31` for bold red. An empty one leaves that part uncolored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorSpecs {
+    pub path: String,
+    pub line_number: String,
+    /// The matched text inside a line.
+    pub matched: String,
+    /// The separators after paths and line numbers.
+    pub separator: String,
+}
+
+impl Default for ColorSpecs {
+    /// Magenta paths, green line numbers and bold red matches, as ripgrep
+    /// and GNU grep print them.
+    fn default() -> Self {
+        ColorSpecs {
+            path: "35".to_string(),
+            line_number: "32".to_string(),
+            matched: "1;31".to_string(),
+            separator: String::new(),
+        }
+    }
+}
+
+/// Appends `text` to `out` in the colors of `spec`, or as it is for `None`.
+pub(super) fn paint(out: &mut Vec<u8>, spec: Option<&str>, text: &[u8]) {
+    match spec {
+        Some(spec) if !spec.is_empty() && !text.is_empty() => {
+            out.extend_from_slice(b"\x1b[");
+            out.extend_from_slice(spec.as_bytes());
+            out.push(b'm');
+            out.extend_from_slice(text);
+            out.extend_from_slice(b"\x1b[0m");
+        }
+        _ => out.extend_from_slice(text),
+    }
+}
+
+/// The bytes of `path` as the OS has them, so a name that isn't valid UTF-8
+/// is written as it is rather than with U+FFFD in it.
+pub(super) fn path_bytes(path: &Path) -> Vec<u8> {
+    path.as_os_str().as_encoded_bytes().to_vec()
+}
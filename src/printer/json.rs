@@ -0,0 +1,244 @@
+//! `Json`: what a search finds as JSON Lines, one object per event.
+//!
+//! Every object has a `type` and the `data` of the event:
+//!
+//! ```text
+//! {"type":"begin","data":{"path":"src/a.rs"}}
+//! {"type":"match","data":{"path":"src/a.rs","line_number":3,"line":"let x = 1;","submatches":[{"match":"x","start":4,"end":5}]}}
+//! {"type":"context","data":{"path":"src/a.rs","line_number":4,"line":"}"}}
+//! {"type":"end","data":{"path":"src/a.rs","binary":false,"lines_searched":9,"lines_matched":1}}
+//! ```
+//!
+//! `path` is `null` for an input without one. Submatch offsets are bytes
+//! into the line, which is text: invalid UTF-8 in the input is replaced
+//! with U+FFFD. Field names and their meaning are stable.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::matcher::{FindIter, Matcher};
+use crate::searcher::{Sink, SinkContext, SinkFinish, SinkMatch};
+
+/// What a `Json` printer prints.
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    /// Print `begin` and `end` for inputs without any match too, rather
+    /// than only around the lines of those with some.
+    pub always_begin_end: bool,
+}
+
+impl JsonOptions {
+    pub fn new() -> Self {
+        JsonOptions::default()
+    }
+
+    pub fn always_begin_end(mut self, yes: bool) -> Self {
+        self.always_begin_end = yes;
+        self
+    }
+}
+
+/// Prints what searches find to a writer as JSON Lines.
+pub struct Json<W> {
+    writer: W,
+    options: JsonOptions,
+}
+
+impl<W: Write> Json<W> {
+    pub fn new(writer: W) -> Self {
+        Json::with_options(writer, JsonOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: JsonOptions) -> Self {
+        Json { writer, options }
+    }
+
+    /// A sink printing the events of a search with `matcher`, which finds the
+    /// submatches of each matching line.
+    pub fn sink<'p, 'm>(&'p mut self, matcher: &'m dyn Matcher) -> JsonSink<'p, 'm, W> {
+        JsonSink {
+            printer: self,
+            matcher,
+            path: None,
+            begun: false,
+            match_count: 0,
+        }
+    }
+
+    /// Like `sink`, saying that the events are about the file at `path`.
+    pub fn sink_with_path<'p, 'm>(
+        &'p mut self,
+        matcher: &'m dyn Matcher,
+        path: &Path,
+    ) -> JsonSink<'p, 'm, W> {
+        let mut sink = self.sink(matcher);
+        sink.path = Some(path.display().to_string());
+        sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// The sink of a `Json` printer for one search.
+pub struct JsonSink<'p, 'm, W> {
+    printer: &'p mut Json<W>,
+    matcher: &'m dyn Matcher,
+    path: Option<String>,
+    begun: bool,
+    match_count: u64,
+}
+
+impl<W: Write> JsonSink<'_, '_, W> {
+    pub fn has_match(&self) -> bool {
+        self.match_count > 0
+    }
+
+    /// Matching lines so far.
+    pub fn match_count(&self) -> u64 {
+        self.match_count
+    }
+
+    /// Writes an event of type `kind`, after the `begin` one if it is still
+    /// due. `data` is the members of its data object after `path`.
+    fn write_event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let path = self.path.as_deref();
+        let mut out = String::new();
+        if !self.begun {
+            self.begun = true;
+            push_event(&mut out, "begin", path, "");
+        }
+        push_event(&mut out, kind, path, data);
+        self.printer.writer.write_all(out.as_bytes())
+    }
+}
+
+impl<W: Write> Sink for JsonSink<'_, '_, W> {
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+        self.match_count += 1;
+        let mut data = line_members(found.line_number(), found.line());
+        data.push_str(",\"submatches\":[");
+        let line = found.line();
+        for (i, submatch) in FindIter::new(self.matcher, line.as_bytes()).enumerate() {
+            if i > 0 {
+                data.push(',');
+            }
+            data.push_str("{\"match\":");
+            push_json_string(&mut data, &line[submatch.clone()]);
+            data.push_str(&format!(
+                ",\"start\":{},\"end\":{}}}",
+                submatch.start, submatch.end
+            ));
+        }
+        data.push(']');
+        self.write_event("match", &data)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, context: &SinkContext<'_>) -> io::Result<bool> {
+        let data = line_members(context.line_number(), context.line());
+        self.write_event("context", &data)?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, finish: &SinkFinish) -> io::Result<()> {
+        if !self.begun && !self.printer.options.always_begin_end {
+            return Ok(());
+        }
+        let data = format!(
+            ",\"binary\":{},\"lines_searched\":{},\"lines_matched\":{}",
+            finish.binary, finish.lines_searched, finish.lines_matched
+        );
+        self.write_event("end", &data)
+    }
+}
+
+fn push_event(out: &mut String, kind: &str, path: Option<&str>, data: &str) {
+    out.push_str("{\"type\":\"");
+    out.push_str(kind);
+    out.push_str("\",\"data\":{\"path\":");
+    match path {
+        Some(path) => push_json_string(out, path),
+        None => out.push_str("null"),
+    }
+    out.push_str(data);
+    out.push_str("}}\n");
+}
+
+/// The data members shared by `match` and `context` events.
+fn line_members(line_number: u64, line: &str) -> String {
+    let mut data = format!(",\"line_number\":{},\"line\":", line_number);
+    push_json_string(&mut data, line);
+    data
+}
+
+fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::searcher::Searcher;
+    use std::io::Cursor;
+
+    fn print(options: JsonOptions, searcher: &Searcher, pattern: &str, input: &str) -> String {
+        let regex = RegexNFA::new(pattern.to_string());
+        let mut printer = Json::with_options(Vec::new(), options);
+        let mut sink = printer.sink_with_path(&regex, Path::new("f"));
+        searcher
+            .search_reader(&regex, Cursor::new(input), &mut sink)
+            .unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_events() {
+        let searcher = Searcher::new().after_context(1);
+        assert_eq!(
+            print(JsonOptions::new(), &searcher, "b", "abcb\n\"x\"\n"),
+            concat!(
+                "{\"type\":\"begin\",\"data\":{\"path\":\"f\"}}\n",
+                "{\"type\":\"match\",\"data\":{\"path\":\"f\",\"line_number\":1,\"line\":\"abcb\",",
+                "\"submatches\":[{\"match\":\"b\",\"start\":1,\"end\":2},{\"match\":\"b\",\"start\":3,\"end\":4}]}}\n",
+                "{\"type\":\"context\",\"data\":{\"path\":\"f\",\"line_number\":2,\"line\":\"\\\"x\\\"\"}}\n",
+                "{\"type\":\"end\",\"data\":{\"path\":\"f\",\"binary\":false,\"lines_searched\":2,\"lines_matched\":1}}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_begin_end_only_with_matches() {
+        let searcher = Searcher::new();
+        assert_eq!(print(JsonOptions::new(), &searcher, "z", "a\n"), "");
+        assert_eq!(
+            print(JsonOptions::new().always_begin_end(true), &searcher, "z", "a\n"),
+            concat!(
+                "{\"type\":\"begin\",\"data\":{\"path\":\"f\"}}\n",
+                "{\"type\":\"end\",\"data\":{\"path\":\"f\",\"binary\":false,\"lines_searched\":1,\"lines_matched\":0}}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        let mut out = String::new();
+        push_json_string(&mut out, "a\"\\\t\u{1}é");
+        assert_eq!(out, "\"a\\\"\\\\\\u0009\\u0001é\"");
+    }
+}
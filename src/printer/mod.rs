@@ -0,0 +1,18 @@
+//! Printing what a `Searcher` finds.
+//!
+//! Each printer is a set of `Sink`s writing to one writer: `Standard` prints
+//! matching lines the way grep does, `Summary` only counts or paths, and
+//! `Json` a JSON object per event for other programs to read. A printer
+//! keeps its writer and options and hands out a sink per search, with
+//! `sink` or, to say which file the lines come from, `sink_with_path`.
+
+mod color;
+mod json;
+mod spans;
+mod standard;
+mod summary;
+
+pub use color::ColorSpecs;
+pub use json::{Json, JsonOptions, JsonSink};
+pub use standard::{Standard, StandardOptions, StandardSink};
+pub use summary::{Summary, SummaryKind, SummaryOptions, SummarySink};
@@ -0,0 +1,126 @@
+//! Rendering for `--show-spans`: a matched line followed by marker lines that
+//! underline the whole match and each capture group.
+//!
+//! ```text
+//! key=value
+//! ^^^^^^^^^ match
+//! ^^^       group 1
+//!     ^^^^^ group 2
+//! ```
+
+use crate::messages::{self, Message};
+
+/// Renders `line` (printed after `prefix`, e.g. `path:`) and the marker lines
+/// for `spans`, where entry 0 is the whole match and entry n is group n, as
+/// byte ranges into `line`. Groups that didn't participate are skipped.
+pub(super) fn render(prefix: &str, line: &str, spans: &[Option<(usize, usize)>]) -> String {
+    let mut output = format!("{}{}", prefix, line);
+
+    for (group, span) in spans.iter().enumerate() {
+        let Some((start, end)) = *span else {
+            continue;
+        };
+        let label = if group == 0 {
+            messages::text(Message::SpanMatch).to_string()
+        } else {
+            messages::format(Message::SpanGroup, &[&group])
+        };
+        output.push('\n');
+        output.push_str(&marker_line(prefix, line, start, end));
+        output.push(' ');
+        output.push_str(&label);
+    }
+
+    output
+}
+
+/// Carets under the bytes `start..end` of `line` (a single one for an empty
+/// span), padded to the width of the line. Each char takes as many columns
+/// as a terminal gives it, two for wide ones like `漢`, and tabs in the line
+/// are kept so the carets stay aligned.
+fn marker_line(prefix: &str, line: &str, start: usize, end: usize) -> String {
+    let mut marker = " ".repeat(prefix.chars().map(width).sum());
+    for (offset, c) in line.char_indices() {
+        let columns = width(c);
+        if (start..end).contains(&offset) {
+            marker.push_str(&"^".repeat(columns));
+        } else if offset == start {
+            marker.push('^');
+            marker.push_str(&" ".repeat(columns.saturating_sub(1)));
+        } else if c == '\t' {
+            marker.push('\t');
+        } else {
+            marker.push_str(&" ".repeat(columns));
+        }
+    }
+    if start == line.len() {
+        marker.push('^');
+    }
+    marker
+}
+
+/// Columns `c` takes on a terminal: none for combining marks and other
+/// zero-width chars, two for East Asian wide and fullwidth chars and most
+/// emoji, one for the rest.
+fn width(c: char) -> usize {
+    match c {
+        '\u{0300}'..='\u{036F}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}' => 0,
+        '\u{1100}'..='\u{115F}'
+        | '\u{2E80}'..='\u{303E}'
+        | '\u{3041}'..='\u{33FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F900}'..='\u{1F9FF}'
+        | '\u{20000}'..='\u{2FFFD}'
+        | '\u{30000}'..='\u{3FFFD}' => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_match_and_groups() {
+        let spans = vec![Some((4, 9)), Some((4, 5)), None, Some((6, 9))];
+        let rendered = render("", "set a=bcd", &spans);
+        assert_eq!(
+            rendered,
+            "set a=bcd\n    ^^^^^ match\n    ^     group 1\n      ^^^ group 3"
+        );
+    }
+
+    #[test]
+    fn test_render_with_prefix_and_tabs() {
+        let rendered = render("f.txt:", "\tab", &[Some((1, 2))]);
+        assert_eq!(rendered, "f.txt:\tab\n      \t^  match");
+    }
+
+    #[test]
+    fn test_render_empty_span() {
+        let rendered = render("", "ab", &[Some((2, 2))]);
+        assert_eq!(rendered, "ab\n  ^ match");
+    }
+
+    #[test]
+    fn test_render_non_ascii() {
+        // Byte offsets: `é` takes two bytes and one column, `漢` and `字`
+        // three bytes and two columns each
+        let rendered = render("", "é漢字abc", &[Some((5, 9)), Some((8, 9))]);
+        assert_eq!(rendered, "é漢字abc\n   ^^^   match\n     ^   group 1");
+        let rendered = render("漢:", "字x", &[Some((3, 4)), Some((0, 0))]);
+        assert_eq!(rendered, "漢:字x\n     ^ match\n   ^   group 1");
+    }
+}
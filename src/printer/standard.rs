@@ -0,0 +1,530 @@
+//! `Standard`: matching lines, the way grep prints them.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::matcher::{FindIter, Matcher};
+use crate::messages::{self, Message};
+use crate::printer::color::{paint, path_bytes, ColorSpecs};
+use crate::printer::spans;
+use crate::searcher::{Sink, SinkContext, SinkMatch};
+
+/// How a `Standard` printer lays out its lines.
+#[derive(Debug, Clone)]
+pub struct StandardOptions {
+    /// Color paths, line numbers and matches with `colors`.
+    pub color: bool,
+    pub colors: ColorSpecs,
+    /// Put the line number in front of each line.
+    pub line_number: bool,
+    /// Print the path once, on a line of its own above the lines of the
+    /// file, instead of in front of each of them. Files are set apart by an
+    /// empty line.
+    pub heading: bool,
+    /// What follows the path and line number of a matching line.
+    pub match_separator: String,
+    /// What follows the path and line number of a context line.
+    pub context_separator: String,
+    /// The line between groups of lines that aren't next to each other,
+    /// when the searcher reports context; `None` for nothing.
+    pub group_separator: Option<String>,
+    /// Print the group separator between the lines of one search and those
+    /// of the next too, as grep does when it prints context.
+    pub separate_searches: bool,
+    /// Print each non-empty match on a line of its own in place of the line
+    /// it is on, and no context lines, as `grep -o` does.
+    pub only_matching: bool,
+    /// Follow each matching line with caret lines marking the match and
+    /// every capture group that took part in it. These lines aren't colored.
+    pub show_spans: bool,
+}
+
+impl Default for StandardOptions {
+    fn default() -> Self {
+        StandardOptions {
+            color: false,
+            colors: ColorSpecs::default(),
+            line_number: false,
+            heading: false,
+            match_separator: ":".to_string(),
+            context_separator: "-".to_string(),
+            group_separator: Some("--".to_string()),
+            separate_searches: false,
+            only_matching: false,
+            show_spans: false,
+        }
+    }
+}
+
+impl StandardOptions {
+    pub fn new() -> Self {
+        StandardOptions::default()
+    }
+
+    pub fn color(mut self, yes: bool) -> Self {
+        self.color = yes;
+        self
+    }
+
+    pub fn colors(mut self, colors: ColorSpecs) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn line_number(mut self, yes: bool) -> Self {
+        self.line_number = yes;
+        self
+    }
+
+    pub fn heading(mut self, yes: bool) -> Self {
+        self.heading = yes;
+        self
+    }
+
+    pub fn match_separator(mut self, separator: &str) -> Self {
+        self.match_separator = separator.to_string();
+        self
+    }
+
+    pub fn context_separator(mut self, separator: &str) -> Self {
+        self.context_separator = separator.to_string();
+        self
+    }
+
+    pub fn group_separator(mut self, separator: Option<&str>) -> Self {
+        self.group_separator = separator.map(str::to_string);
+        self
+    }
+
+    pub fn separate_searches(mut self, yes: bool) -> Self {
+        self.separate_searches = yes;
+        self
+    }
+
+    pub fn only_matching(mut self, yes: bool) -> Self {
+        self.only_matching = yes;
+        self
+    }
+
+    pub fn show_spans(mut self, yes: bool) -> Self {
+        self.show_spans = yes;
+        self
+    }
+}
+
+/// Prints matching lines, and any context, to a writer. Lines and paths are
+/// written as they were read, whether or not they are valid UTF-8. The lines
+/// of a binary input aren't printed: a note that it matches is, in their
+/// place.
+pub struct Standard<W> {
+    writer: W,
+    options: StandardOptions,
+    // Whether a heading was printed yet, so the next one is set apart
+    headed: bool,
+    // Whether any line was printed yet, for `separate_searches`
+    printed: bool,
+}
+
+impl<W: Write> Standard<W> {
+    pub fn new(writer: W) -> Self {
+        Standard::with_options(writer, StandardOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: StandardOptions) -> Self {
+        Standard {
+            writer,
+            options,
+            headed: false,
+            printed: false,
+        }
+    }
+
+    /// A sink printing the lines of a search with `matcher`, which colors the
+    /// matches in them.
+    pub fn sink<'p, 'm>(&'p mut self, matcher: &'m dyn Matcher) -> StandardSink<'p, 'm, W> {
+        StandardSink {
+            printer: self,
+            matcher,
+            path: None,
+            name: None,
+            binary: false,
+            match_count: 0,
+            started: false,
+        }
+    }
+
+    /// Like `sink`, saying that the lines come from the file at `path`.
+    pub fn sink_with_path<'p, 'm>(
+        &'p mut self,
+        matcher: &'m dyn Matcher,
+        path: &Path,
+    ) -> StandardSink<'p, 'm, W> {
+        let mut sink = self.sink_named(matcher, path);
+        sink.path = Some(path_bytes(path));
+        sink
+    }
+
+    /// Like `sink`, for the lines of the file at `path` without it in front
+    /// of them: it only shows in the note on binary input.
+    pub fn sink_named<'p, 'm>(
+        &'p mut self,
+        matcher: &'m dyn Matcher,
+        path: &Path,
+    ) -> StandardSink<'p, 'm, W> {
+        let mut sink = self.sink(matcher);
+        sink.name = Some(path.display().to_string());
+        sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// The sink of a `Standard` printer for one search.
+pub struct StandardSink<'p, 'm, W> {
+    printer: &'p mut Standard<W>,
+    matcher: &'m dyn Matcher,
+    // Printed in front of the lines, and naming the input in notes
+    path: Option<Vec<u8>>,
+    name: Option<String>,
+    binary: bool,
+    match_count: u64,
+    // Whether this search printed a line yet
+    started: bool,
+}
+
+impl<W: Write> StandardSink<'_, '_, W> {
+    pub fn has_match(&self) -> bool {
+        self.match_count > 0
+    }
+
+    /// Matching lines so far.
+    pub fn match_count(&self) -> u64 {
+        self.match_count
+    }
+
+    /// Writes what comes before the first line of the search: the group
+    /// separator after the lines of an earlier one, and the heading.
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        let options = &self.printer.options;
+        let mut out = Vec::new();
+        if let Some(separator) = &options.group_separator {
+            if options.separate_searches && self.printer.printed {
+                out.extend_from_slice(separator.as_bytes());
+                out.push(b'\n');
+            }
+        }
+        if let (Some(path), true) = (&self.path, options.heading) {
+            if self.printer.headed {
+                out.push(b'\n');
+            }
+            let colors = options.color.then_some(&options.colors);
+            paint(&mut out, colors.map(|c| c.path.as_str()), path);
+            out.push(b'\n');
+            self.printer.headed = true;
+        }
+        self.printer.printed = true;
+        self.printer.writer.write_all(&out)
+    }
+
+    /// What goes in front of line `line_number`: its path, unless in a
+    /// heading, and number, each followed by the separator of a matching
+    /// line or of a context one. Uncolored when `plain` is set.
+    fn prefix(&self, line_number: u64, matched: bool, plain: bool) -> Vec<u8> {
+        let options = &self.printer.options;
+        let colors = (options.color && !plain).then_some(&options.colors);
+        let separator = if matched {
+            options.match_separator.as_bytes()
+        } else {
+            options.context_separator.as_bytes()
+        };
+        let mut out = Vec::new();
+        if let (Some(path), false) = (&self.path, options.heading) {
+            paint(&mut out, colors.map(|c| c.path.as_str()), path);
+            paint(&mut out, colors.map(|c| c.separator.as_str()), separator);
+        }
+        if options.line_number {
+            let number = line_number.to_string();
+            paint(
+                &mut out,
+                colors.map(|c| c.line_number.as_str()),
+                number.as_bytes(),
+            );
+            paint(&mut out, colors.map(|c| c.separator.as_str()), separator);
+        }
+        out
+    }
+
+    fn write_line(&mut self, line_number: u64, line: &[u8], matched: bool) -> io::Result<()> {
+        self.start()?;
+        let options = &self.printer.options;
+        if matched && options.show_spans {
+            // The carets line up under chars, so the line and its prefix
+            // are shown as the text they were searched as
+            let line = String::from_utf8_lossy(line);
+            let spans: Vec<_> = self
+                .matcher
+                .captures(line.as_bytes())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|span| span.map(|span| (span.start, span.end)))
+                .collect();
+            let prefix = self.prefix(line_number, matched, true);
+            let out = spans::render(&String::from_utf8_lossy(&prefix), &line, &spans);
+            return writeln!(self.printer.writer, "{}", out);
+        }
+        let mut out = self.prefix(line_number, matched, false);
+        let colors = options.color.then_some(&options.colors);
+        match colors {
+            Some(colors) if matched => {
+                // Byte offset up to which `line` is in `out`
+                let mut copied = 0;
+                for found in FindIter::new(self.matcher, line) {
+                    out.extend_from_slice(&line[copied..found.start]);
+                    paint(&mut out, Some(&colors.matched), &line[found.clone()]);
+                    copied = found.end;
+                }
+                out.extend_from_slice(&line[copied..]);
+            }
+            _ => out.extend_from_slice(line),
+        }
+        out.push(b'\n');
+        self.printer.writer.write_all(&out)
+    }
+
+    /// Writes each non-empty match on `line` on a line of its own.
+    fn write_matches(&mut self, line_number: u64, line: &[u8]) -> io::Result<()> {
+        let prefix = self.prefix(line_number, true, false);
+        let options = &self.printer.options;
+        let color = options.color.then_some(options.colors.matched.as_str());
+        let mut out = Vec::new();
+        for found in FindIter::new(self.matcher, line).filter(|span| !span.is_empty()) {
+            out.extend_from_slice(&prefix);
+            paint(&mut out, color, &line[found]);
+            out.push(b'\n');
+        }
+        if !out.is_empty() {
+            self.start()?;
+        }
+        self.printer.writer.write_all(&out)
+    }
+}
+
+impl<W: Write> Sink for StandardSink<'_, '_, W> {
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+        self.match_count += 1;
+        if self.binary {
+            let note = match &self.name {
+                Some(path) => messages::format(Message::BinaryFileMatches, &[path]),
+                None => messages::text(Message::BinaryStdinMatches).to_string(),
+            };
+            writeln!(self.printer.writer, "{}", note)?;
+            return Ok(false);
+        }
+        if self.printer.options.only_matching {
+            self.write_matches(found.line_number(), found.bytes())?;
+        } else {
+            self.write_line(found.line_number(), found.bytes(), true)?;
+        }
+        Ok(true)
+    }
+
+    fn context(&mut self, context: &SinkContext<'_>) -> io::Result<bool> {
+        // With `only_matching` there is no match on them to print
+        if !self.binary && !self.printer.options.only_matching {
+            self.write_line(context.line_number(), context.bytes(), false)?;
+        }
+        Ok(true)
+    }
+
+    fn context_break(&mut self) -> io::Result<bool> {
+        if self.binary {
+            return Ok(true);
+        }
+        if let Some(separator) = &self.printer.options.group_separator {
+            writeln!(self.printer.writer, "{}", separator)?;
+        }
+        Ok(true)
+    }
+
+    fn binary_data(&mut self) -> io::Result<bool> {
+        self.binary = true;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::searcher::{BinaryDetection, Searcher};
+    use std::io::Cursor;
+
+    fn print(
+        printer: &mut Standard<Vec<u8>>,
+        searcher: &Searcher,
+        pattern: &str,
+        path: Option<&str>,
+        input: &str,
+    ) -> String {
+        let regex = RegexNFA::new(pattern.to_string());
+        let mut sink = match path {
+            Some(path) => printer.sink_with_path(&regex, Path::new(path)),
+            None => printer.sink(&regex),
+        };
+        searcher
+            .search_reader(&regex, Cursor::new(input), &mut sink)
+            .unwrap();
+        String::from_utf8(std::mem::take(printer.get_mut())).unwrap()
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut printer = Standard::new(Vec::new());
+        let searcher = Searcher::new();
+        assert_eq!(
+            print(&mut printer, &searcher, "a", None, "ab\ncd\nxa\n"),
+            "ab\nxa\n"
+        );
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("f.txt"), "ab\ncd\n"),
+            "f.txt:ab\n"
+        );
+    }
+
+    #[test]
+    fn test_line_numbers_and_context() {
+        let options = StandardOptions::new().line_number(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        let searcher = Searcher::new().before_context(1);
+        assert_eq!(
+            print(&mut printer, &searcher, "x", Some("f"), "1\nx2\n3\n4\nx5\n"),
+            "f-1-1\nf:2:x2\n--\nf-4-4\nf:5:x5\n"
+        );
+
+        let options = StandardOptions::new()
+            .match_separator("=")
+            .group_separator(None);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        assert_eq!(
+            print(&mut printer, &searcher, "x", Some("f"), "1\nx2\n3\n4\nx5\n"),
+            "f-1\nf=x2\nf-4\nf=x5\n"
+        );
+    }
+
+    #[test]
+    fn test_headings() {
+        let options = StandardOptions::new().heading(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        let searcher = Searcher::new();
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("f"), "a1\na2\n"),
+            "f\na1\na2\n"
+        );
+        assert_eq!(print(&mut printer, &searcher, "a", Some("g"), "b\n"), "");
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("h"), "a3\n"),
+            "\nh\na3\n"
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let options = StandardOptions::new().color(true).line_number(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        assert_eq!(
+            print(&mut printer, &Searcher::new(), "b+", Some("f"), "abba b\n"),
+            "\x1b[35mf\x1b[0m:\x1b[32m1\x1b[0m:a\x1b[1;31mbb\x1b[0ma \x1b[1;31mb\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let options = StandardOptions::new().color(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        let regex = RegexNFA::new("f.".to_string());
+        let mut sink = printer.sink(&regex);
+        Searcher::new()
+            .search_reader(&regex, Cursor::new(b"caf\xe9\n"), &mut sink)
+            .unwrap();
+        // The byte that isn't UTF-8 is matched as a char, and kept
+        assert_eq!(printer.into_inner(), b"ca\x1b[1;31mf\xe9\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_only_matching() {
+        let options = StandardOptions::new().only_matching(true).line_number(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        let searcher = Searcher::new().after_context(1);
+        assert_eq!(
+            print(&mut printer, &searcher, "b*", Some("f"), "abcbb\nx\n"),
+            "f:1:b\nf:1:bb\n"
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let options = StandardOptions::new().show_spans(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        assert_eq!(
+            print(&mut printer, &Searcher::new(), "(a)b", Some("f"), "xab\n"),
+            format!(
+                "f:xab\n   ^^ {}\n   ^  {}\n",
+                messages::text(Message::SpanMatch),
+                messages::format(Message::SpanGroup, &[&1])
+            )
+        );
+    }
+
+    #[test]
+    fn test_separate_searches() {
+        let options = StandardOptions::new().separate_searches(true);
+        let mut printer = Standard::with_options(Vec::new(), options);
+        let searcher = Searcher::new().before_context(1);
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("f"), "a\n"),
+            "f:a\n"
+        );
+        assert_eq!(print(&mut printer, &searcher, "a", Some("g"), "b\n"), "");
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("h"), "c\na\n"),
+            "--\nh-c\nh:a\n"
+        );
+    }
+
+    #[test]
+    fn test_binary_input() {
+        let mut printer = Standard::new(Vec::new());
+        let searcher = Searcher::new().binary_detection(BinaryDetection::Report);
+        assert_eq!(
+            print(&mut printer, &searcher, "a", Some("f"), "a\0\na\n"),
+            format!(
+                "{}\n",
+                messages::format(Message::BinaryFileMatches, &[&"f"])
+            )
+        );
+
+        // A file named only in the note
+        let regex = RegexNFA::new("a".to_string());
+        let mut sink = printer.sink_named(&regex, Path::new("g"));
+        searcher
+            .search_reader(&regex, Cursor::new("a\0\n"), &mut sink)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(std::mem::take(printer.get_mut())).unwrap(),
+            format!(
+                "{}\n",
+                messages::format(Message::BinaryFileMatches, &[&"g"])
+            )
+        );
+    }
+}
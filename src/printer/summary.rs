@@ -0,0 +1,192 @@
+//! `Summary`: a line per input with its count of matching lines, or its
+//! path, in place of the lines themselves.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::messages::{self, Message};
+use crate::printer::color::{paint, path_bytes, ColorSpecs};
+use crate::searcher::{Sink, SinkFinish, SinkMatch};
+
+/// What a `Summary` printer prints for each input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SummaryKind {
+    /// The number of matching lines, after the path when there is one, as
+    /// `grep -c` does.
+    #[default]
+    Count,
+    /// The path of inputs with a match, as `grep -l` does. The search stops
+    /// at the first match.
+    PathWithMatch,
+    /// The path of inputs without a match, as `grep -L` does. The search
+    /// stops at the first match.
+    PathWithoutMatch,
+}
+
+/// What a `Summary` printer prints and how.
+#[derive(Debug, Clone, Default)]
+pub struct SummaryOptions {
+    pub kind: SummaryKind,
+    /// Color paths with `colors`.
+    pub color: bool,
+    pub colors: ColorSpecs,
+}
+
+impl SummaryOptions {
+    pub fn new() -> Self {
+        SummaryOptions::default()
+    }
+
+    pub fn kind(mut self, kind: SummaryKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn color(mut self, yes: bool) -> Self {
+        self.color = yes;
+        self
+    }
+
+    pub fn colors(mut self, colors: ColorSpecs) -> Self {
+        self.colors = colors;
+        self
+    }
+}
+
+/// Prints a summary of each search to a writer.
+pub struct Summary<W> {
+    writer: W,
+    options: SummaryOptions,
+}
+
+impl<W: Write> Summary<W> {
+    pub fn new(writer: W) -> Self {
+        Summary::with_options(writer, SummaryOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: SummaryOptions) -> Self {
+        Summary { writer, options }
+    }
+
+    /// A sink summing up a search of standard input, or any input without
+    /// a path.
+    pub fn sink(&mut self) -> SummarySink<'_, W> {
+        SummarySink {
+            printer: self,
+            path: None,
+            match_count: 0,
+        }
+    }
+
+    /// A sink summing up a search of the file at `path`.
+    pub fn sink_with_path(&mut self, path: &Path) -> SummarySink<'_, W> {
+        let mut sink = self.sink();
+        sink.path = Some(path_bytes(path));
+        sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// The sink of a `Summary` printer for one search, which prints once the
+/// search is over.
+pub struct SummarySink<'p, W> {
+    printer: &'p mut Summary<W>,
+    path: Option<Vec<u8>>,
+    match_count: u64,
+}
+
+impl<W: Write> SummarySink<'_, W> {
+    pub fn has_match(&self) -> bool {
+        self.match_count > 0
+    }
+
+    /// Matching lines so far; at most 1 for the path kinds, which stop at
+    /// the first.
+    pub fn match_count(&self) -> u64 {
+        self.match_count
+    }
+}
+
+impl<W: Write> Sink for SummarySink<'_, W> {
+    fn matched(&mut self, _: &SinkMatch<'_>) -> io::Result<bool> {
+        self.match_count += 1;
+        Ok(self.printer.options.kind == SummaryKind::Count)
+    }
+
+    fn finish(&mut self, _: &SinkFinish) -> io::Result<()> {
+        let options = &self.printer.options;
+        let path_color = options.color.then_some(options.colors.path.as_str());
+        let path = match &self.path {
+            Some(path) => path.as_slice(),
+            None => messages::text(Message::StandardInput).as_bytes(),
+        };
+        let mut out = Vec::new();
+        match options.kind {
+            SummaryKind::Count => {
+                if let Some(path) = &self.path {
+                    paint(&mut out, path_color, path);
+                    let separator_color =
+                        options.color.then_some(options.colors.separator.as_str());
+                    paint(&mut out, separator_color, b":");
+                }
+                out.extend_from_slice(self.match_count.to_string().as_bytes());
+            }
+            SummaryKind::PathWithMatch if self.has_match() => paint(&mut out, path_color, path),
+            SummaryKind::PathWithoutMatch if !self.has_match() => paint(&mut out, path_color, path),
+            _ => return Ok(()),
+        }
+        out.push(b'\n');
+        self.printer.writer.write_all(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::searcher::Searcher;
+    use std::io::Cursor;
+
+    fn summarize(kind: SummaryKind, pattern: &str, inputs: &[(Option<&str>, &str)]) -> String {
+        let regex = RegexNFA::new(pattern.to_string());
+        let mut printer = Summary::with_options(Vec::new(), SummaryOptions::new().kind(kind));
+        for (path, input) in inputs {
+            let mut sink = match path {
+                Some(path) => printer.sink_with_path(Path::new(path)),
+                None => printer.sink(),
+            };
+            Searcher::new()
+                .search_reader(&regex, Cursor::new(input), &mut sink)
+                .unwrap();
+        }
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_count() {
+        let inputs = [(Some("f"), "a\nb\na\n"), (Some("g"), "b\n")];
+        assert_eq!(summarize(SummaryKind::Count, "a", &inputs), "f:2\ng:0\n");
+        assert_eq!(summarize(SummaryKind::Count, "a", &[(None, "a\n")]), "1\n");
+    }
+
+    #[test]
+    fn test_paths() {
+        let inputs = [(Some("f"), "a\nb\na\n"), (Some("g"), "b\n")];
+        assert_eq!(summarize(SummaryKind::PathWithMatch, "a", &inputs), "f\n");
+        assert_eq!(
+            summarize(SummaryKind::PathWithoutMatch, "a", &inputs),
+            "g\n"
+        );
+        assert_eq!(
+            summarize(SummaryKind::PathWithMatch, "a", &[(None, "a\n")]),
+            format!("{}\n", messages::text(Message::StandardInput))
+        );
+    }
+}
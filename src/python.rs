@@ -0,0 +1,259 @@
+//! The `grep_rs` Python module: the regex engine from Python, with the same
+//! pattern syntax and matches as the command line.
+//!
+//! ```python
+//! import grep_rs
+//!
+//! regex = grep_rs.compile(r"(?P<key>\w+)=(\d+)")
+//! regex.find("set x=1").span()      # (4, 7)
+//! regex.captures("set x=1")["key"]  # 'x'
+//! ```
+//!
+//! Offsets are char indices into the Python string, as with `re`, rather
+//! than the byte offsets the Rust API reports.
+
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::regex::{Captures, Match, RegexNFA};
+
+/// A compiled pattern.
+#[pyclass(name = "Regex", module = "grep_rs", frozen)]
+struct PyRegex {
+    regex: RegexNFA,
+    pattern: String,
+}
+
+/// A match: where it is in the string searched and its text.
+#[pyclass(name = "Match", module = "grep_rs", frozen, skip_from_py_object)]
+#[derive(Clone)]
+struct PyMatch {
+    #[pyo3(get)]
+    start: usize,
+    #[pyo3(get)]
+    end: usize,
+    #[pyo3(get)]
+    text: String,
+}
+
+/// The groups of a match, by index (0 being the whole match) or by name.
+#[pyclass(name = "Captures", module = "grep_rs", frozen)]
+struct PyCaptures {
+    groups: Vec<Option<PyMatch>>,
+    names: Vec<(String, usize)>,
+}
+
+#[derive(FromPyObject)]
+enum Group {
+    Index(usize),
+    Name(String),
+}
+
+#[pymethods]
+impl PyRegex {
+    #[new]
+    fn new(pattern: String) -> PyResult<Self> {
+        let regex = RegexNFA::try_new(pattern.clone())
+            .map_err(|error| PyValueError::new_err(error.to_string()))?;
+        Ok(PyRegex { regex, pattern })
+    }
+
+    #[getter]
+    fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether `text` contains a match.
+    fn is_match(&self, text: &str) -> bool {
+        self.regex.matches(text)
+    }
+
+    /// The leftmost match in `text`, or `None`.
+    fn find(&self, text: &str) -> Option<PyMatch> {
+        let found = self.regex.find(text)?;
+        Some(PyMatch::new(&mut CharOffsets::new(text), found))
+    }
+
+    /// Every non-overlapping match in `text`, in order.
+    fn find_all(&self, text: &str) -> Vec<PyMatch> {
+        let mut offsets = CharOffsets::new(text);
+        self.regex
+            .find_iter(text)
+            .map(|found| PyMatch::new(&mut offsets, found))
+            .collect()
+    }
+
+    /// The groups of the leftmost match in `text`, or `None`.
+    fn captures(&self, text: &str) -> Option<PyCaptures> {
+        let caps = self.regex.captures(text)?;
+        Some(PyCaptures::new(&self.regex, text, &caps))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("grep_rs.compile({:?})", self.pattern)
+    }
+}
+
+impl PyMatch {
+    fn new(offsets: &mut CharOffsets<'_>, found: Match<'_>) -> Self {
+        PyMatch {
+            start: offsets.at(found.start()),
+            end: offsets.at(found.end()),
+            text: found.as_str().to_string(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyMatch {
+    /// `(start, end)`.
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<grep_rs.Match span=({}, {}) text={:?}>",
+            self.start, self.end, self.text
+        )
+    }
+}
+
+impl PyCaptures {
+    fn new(regex: &RegexNFA, text: &str, caps: &Captures<'_>) -> Self {
+        let mut offsets = CharOffsets::new(text);
+        PyCaptures {
+            groups: caps
+                .iter()
+                .map(|group| group.map(|found| PyMatch::new(&mut offsets, found)))
+                .collect(),
+            names: regex
+                .group_names()
+                .map(|(name, index)| (name.to_string(), index))
+                .collect(),
+        }
+    }
+
+    /// The group `key` refers to, `Ok(None)` if it didn't take part in the
+    /// match. With a name given to several groups in a branch reset, the
+    /// first of them that took part.
+    fn group(&self, key: Group) -> PyResult<Option<&PyMatch>> {
+        match key {
+            Group::Index(index) => match self.groups.get(index) {
+                Some(group) => Ok(group.as_ref()),
+                None => Err(PyIndexError::new_err(format!("no group {}", index))),
+            },
+            Group::Name(name) => {
+                let mut indices = self
+                    .names
+                    .iter()
+                    .filter(|(group_name, _)| *group_name == name)
+                    .map(|&(_, index)| index)
+                    .peekable();
+                if indices.peek().is_none() {
+                    return Err(PyIndexError::new_err(format!("no group named {}", name)));
+                }
+                Ok(indices.find_map(|index| self.groups[index].as_ref()))
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl PyCaptures {
+    /// The text of a group, `None` if it didn't take part in the match.
+    /// Raises `IndexError` for a group the pattern doesn't have.
+    fn __getitem__(&self, key: Group) -> PyResult<Option<String>> {
+        Ok(self.group(key)?.map(|found| found.text.clone()))
+    }
+
+    /// The `Match` of a group, like `captures[key]`.
+    fn get(&self, key: Group) -> PyResult<Option<PyMatch>> {
+        Ok(self.group(key)?.cloned())
+    }
+
+    fn __len__(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+/// Compiles `pattern`, raising `ValueError` if it is invalid.
+#[pyfunction]
+fn compile(pattern: String) -> PyResult<PyRegex> {
+    PyRegex::new(pattern)
+}
+
+#[pymodule]
+fn grep_rs(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(compile, module)?)?;
+    module.add_class::<PyRegex>()?;
+    module.add_class::<PyMatch>()?;
+    module.add_class::<PyCaptures>()?;
+    Ok(())
+}
+
+/// Turns byte offsets into a string into char offsets, counting on from the
+/// last offset asked for so the matches of a whole string cost one pass.
+struct CharOffsets<'t> {
+    text: &'t str,
+    byte: usize,
+    char: usize,
+}
+
+impl<'t> CharOffsets<'t> {
+    fn new(text: &'t str) -> Self {
+        CharOffsets {
+            text,
+            byte: 0,
+            char: 0,
+        }
+    }
+
+    fn at(&mut self, byte: usize) -> usize {
+        if byte < self.byte {
+            self.byte = 0;
+            self.char = 0;
+        }
+        self.char += self.text[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.char
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_offsets() {
+        let text = "naïve café";
+        let mut offsets = CharOffsets::new(text);
+        assert_eq!(offsets.at(0), 0);
+        assert_eq!(offsets.at(4), 3);
+        assert_eq!(offsets.at(text.len()), 10);
+        assert_eq!(offsets.at(2), 2);
+    }
+
+    #[test]
+    fn test_find_all_in_chars() {
+        let regex = PyRegex::new(r"\w+".to_string()).unwrap();
+        let spans: Vec<(usize, usize)> = regex
+            .find_all("é, ça va")
+            .iter()
+            .map(PyMatch::span)
+            .collect();
+        assert_eq!(spans, [(0, 1), (3, 5), (6, 8)]);
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = PyRegex::new(r"(?P<key>\w+)=(\d+)?".to_string()).unwrap();
+        let caps = regex.captures("set clé=").unwrap();
+        assert_eq!(caps.__len__(), 3);
+        let key = caps.get(Group::Name("key".to_string())).unwrap().unwrap();
+        assert_eq!((key.span(), key.text.as_str()), ((4, 7), "clé"));
+        assert_eq!(caps.__getitem__(Group::Index(2)).unwrap(), None);
+        assert!(caps.group(Group::Index(3)).is_err());
+        assert!(caps.group(Group::Name("value".to_string())).is_err());
+    }
+}
@@ -0,0 +1,588 @@
+//! The syntax tree of a pattern: `parse` and `Ast`.
+//!
+//! Tools that lint, pretty-print or rewrite patterns work on the tree
+//! instead of the text, and turn it back into a pattern with `to_string`
+//! to compile it. The tree is built from the same parse `RegexNFA` compiles,
+//! so it reflects exactly how the engine reads a pattern: non-capturing
+//! groups only group, `a{1,}` and `a+` are the same repetition, and inline
+//! flags are carried by the chars and classes they apply to.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::regex::error::CompileError;
+use crate::regex::escape::push_literal;
+use crate::regex::parser::{postfix_with_names, Flags, Token};
+
+/// A parsed pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    /// Matches the empty string: a missing branch of a conditional.
+    Empty,
+    /// A single char.
+    Literal(char, Flags),
+    /// `.`
+    Dot(Flags),
+    /// `\d`, `\w` or `\s`, or `\D`, `\W` or `\S`, by its letter.
+    ClassEscape(char, Flags),
+    /// A bracketed class, as written, like `[a-z&&[^aeiou]]`.
+    Class(String, Flags),
+    /// A `^` anchoring the whole pattern to the start of the line.
+    Start,
+    /// A `$` anchoring the whole pattern to the end of the line.
+    End,
+    /// A `^` matched where it stands, as in `(?:^|,)field`.
+    LineStart,
+    /// A `$` matched where it stands, as in `field(?:,|$)`.
+    LineEnd,
+    /// `\b`, or `\B` when negated.
+    WordBoundary(bool, Flags),
+    /// `\G`
+    SearchStart,
+    /// `\X`
+    Grapheme,
+    /// `\R`
+    Linebreak,
+    /// `\1` or `\k<name>`.
+    Backref {
+        index: usize,
+        name: Option<String>,
+    },
+    /// `(?R)` for group 0, `(?1)` or `(?&name)`.
+    Call {
+        index: usize,
+        name: Option<String>,
+    },
+    /// A capture group.
+    Group {
+        index: usize,
+        name: Option<String>,
+        ast: Box<Ast>,
+    },
+    /// `(?(group)then|otherwise)`
+    Conditional {
+        group: usize,
+        then: Box<Ast>,
+        otherwise: Box<Ast>,
+    },
+    /// `*`, `+`, `?` or a counted repetition, `max` being `None` when
+    /// unbounded.
+    Repetition {
+        ast: Box<Ast>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+    Concat(Vec<Ast>),
+    Alternation(Vec<Ast>),
+}
+
+/// Parses `pattern` without compiling it. Fails on the patterns compiling
+/// fails on for their syntax or for a missing part, like `a|` or `()`.
+pub fn parse(pattern: &str) -> Result<Ast, CompileError> {
+    let (tokens, group_names) = postfix_with_names(pattern, Flags::default())?;
+    let name = |index: usize| {
+        group_names
+            .iter()
+            .find(|(_, group)| *group == index)
+            .map(|(name, _)| name.clone())
+    };
+
+    let mut stack: Vec<Ast> = Vec::new();
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        let ast = match token {
+            Token::Literal(c, flags) => Ast::Literal(c, flags),
+            Token::ComplexLiteral(s, flags) => match s.as_str() {
+                "." => Ast::Dot(flags),
+                "d" | "w" | "s" | "D" | "W" | "S" => {
+                    Ast::ClassEscape(s.chars().next().unwrap(), flags)
+                }
+                _ => Ast::Class(s, flags),
+            },
+            Token::StartRef => Ast::Start,
+            Token::EndRef => Ast::End,
+            Token::LineStart => Ast::LineStart,
+            Token::LineEnd => Ast::LineEnd,
+            Token::WordBoundary(negated, flags) => Ast::WordBoundary(negated, flags),
+            Token::SearchStart => Ast::SearchStart,
+            Token::Grapheme => Ast::Grapheme,
+            Token::Linebreak => Ast::Linebreak,
+            Token::Empty => Ast::Empty,
+            Token::Backref(index) => Ast::Backref {
+                index,
+                name: name(index),
+            },
+            Token::Call(index) => Ast::Call {
+                index,
+                name: name(index),
+            },
+            Token::Group(index) => Ast::Group {
+                index,
+                name: name(index),
+                ast: Box::new(operand(&mut stack, "empty group")?),
+            },
+            Token::Conditional(group) => {
+                let otherwise = operand(&mut stack, "conditional without a branch")?;
+                let then = operand(&mut stack, "conditional without a branch")?;
+                Ast::Conditional {
+                    group,
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                }
+            }
+            Token::Star | Token::Plus | Token::Question | Token::Repeat(..) => {
+                let (min, max) = match token {
+                    Token::Star => (0, None),
+                    Token::Plus => (1, None),
+                    Token::Question => (0, Some(1)),
+                    Token::Repeat(min, max) => (min, max),
+                    _ => unreachable!(),
+                };
+                let greedy = iter.next_if_eq(&Token::Lazy).is_none();
+                Ast::Repetition {
+                    ast: Box::new(operand(&mut stack, "quantifier with nothing to repeat")?),
+                    min,
+                    max,
+                    greedy,
+                }
+            }
+            Token::Concat => {
+                let right = operand(&mut stack, "concatenation missing a part")?;
+                let left = operand(&mut stack, "concatenation missing a part")?;
+                Ast::Concat(flatten(left, right, Ast::into_concat))
+            }
+            Token::Or => {
+                let right = operand(&mut stack, "empty alternative")?;
+                let left = operand(&mut stack, "empty alternative")?;
+                Ast::Alternation(flatten(left, right, Ast::into_alternation))
+            }
+            Token::LBracket | Token::RBracket => {
+                return Err(CompileError::Unsupported(
+                    "unbalanced parentheses".to_string(),
+                ))
+            }
+            token => return Err(CompileError::Unsupported(format!("unexpected {:?}", token))),
+        };
+        stack.push(ast);
+    }
+
+    // The anchors stand apart from the rest in the postfix tokens
+    let start = stack.first() == Some(&Ast::Start);
+    let end = stack.last() == Some(&Ast::End);
+    let body_range = start as usize..stack.len() - end as usize;
+    let mut body: Vec<Ast> = stack.drain(body_range).collect();
+    if body.len() > 1 {
+        return Err(CompileError::Unsupported(
+            "parts of the pattern left unconnected".to_string(),
+        ));
+    }
+    let Some(body) = body.pop() else {
+        return Err(CompileError::Unsupported("nothing to match".to_string()));
+    };
+    if !start && !end {
+        return Ok(body);
+    }
+    let mut parts = Vec::new();
+    if start {
+        parts.push(Ast::Start);
+    }
+    parts.extend(body.into_concat());
+    if end {
+        parts.push(Ast::End);
+    }
+    Ok(Ast::Concat(parts))
+}
+
+fn operand(stack: &mut Vec<Ast>, missing: &str) -> Result<Ast, CompileError> {
+    stack
+        .pop()
+        .ok_or_else(|| CompileError::Unsupported(missing.to_string()))
+}
+
+/// The parts of `left` and `right` as one list, each split by `parts` if it
+/// is already a list of the same kind.
+fn flatten(left: Ast, right: Ast, parts: fn(Ast) -> Vec<Ast>) -> Vec<Ast> {
+    let mut all = parts(left);
+    all.extend(parts(right));
+    all
+}
+
+impl Ast {
+    fn into_concat(self) -> Vec<Ast> {
+        match self {
+            Ast::Concat(parts) => parts,
+            ast => Vec::from([ast]),
+        }
+    }
+
+    fn into_alternation(self) -> Vec<Ast> {
+        match self {
+            Ast::Alternation(alternatives) => alternatives,
+            ast => Vec::from([ast]),
+        }
+    }
+
+    /// The flags of a char, class or word boundary.
+    fn flags(&self) -> Option<Flags> {
+        match self {
+            Ast::Literal(_, flags)
+            | Ast::Dot(flags)
+            | Ast::ClassEscape(_, flags)
+            | Ast::Class(_, flags)
+            | Ast::WordBoundary(_, flags) => Some(*flags),
+            _ => None,
+        }
+    }
+
+    /// Whether a quantifier can follow this node as written.
+    fn is_atom(&self) -> bool {
+        !matches!(
+            self,
+            Ast::Empty
+                | Ast::Start
+                | Ast::End
+                | Ast::LineStart
+                | Ast::LineEnd
+                | Ast::WordBoundary(..)
+                | Ast::Repetition { .. }
+                | Ast::Concat(_)
+                | Ast::Alternation(_)
+        )
+    }
+
+    /// Pushes the indices of the capture groups in this node to `groups`.
+    fn groups(&self, groups: &mut Vec<usize>) {
+        match self {
+            Ast::Group { index, ast, .. } => {
+                groups.push(*index);
+                ast.groups(groups);
+            }
+            Ast::Conditional {
+                then, otherwise, ..
+            } => {
+                then.groups(groups);
+                otherwise.groups(groups);
+            }
+            Ast::Repetition { ast, .. } => ast.groups(groups),
+            Ast::Concat(parts) | Ast::Alternation(parts) => {
+                for part in parts {
+                    part.groups(groups);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Ast::Empty => {}
+            Ast::Literal(..)
+            | Ast::Dot(_)
+            | Ast::ClassEscape(..)
+            | Ast::Class(..)
+            | Ast::WordBoundary(..) => write_with_flags(out, core::slice::from_ref(self)),
+            // At the very start a `^` would anchor the whole pattern
+            Ast::LineStart if out.is_empty() => out.push_str("(?:^)"),
+            Ast::Start | Ast::LineStart => out.push('^'),
+            Ast::End | Ast::LineEnd => out.push('$'),
+            Ast::SearchStart => out.push_str("\\G"),
+            Ast::Grapheme => out.push_str("\\X"),
+            Ast::Linebreak => out.push_str("\\R"),
+            Ast::Backref {
+                name: Some(name), ..
+            } => {
+                out.push_str("\\k<");
+                out.push_str(name);
+                out.push('>');
+            }
+            Ast::Backref { index, .. } => {
+                out.push('\\');
+                out.push_str(&index.to_string());
+            }
+            Ast::Call { index: 0, .. } => out.push_str("(?R)"),
+            Ast::Call {
+                name: Some(name), ..
+            } => {
+                out.push_str("(?&");
+                out.push_str(name);
+                out.push(')');
+            }
+            Ast::Call { index, .. } => {
+                out.push_str("(?");
+                out.push_str(&index.to_string());
+                out.push(')');
+            }
+            Ast::Group { name, ast, .. } => {
+                out.push('(');
+                if let Some(name) = name {
+                    out.push_str("?P<");
+                    out.push_str(name);
+                    out.push('>');
+                }
+                ast.write(out);
+                out.push(')');
+            }
+            Ast::Conditional {
+                group,
+                then,
+                otherwise,
+            } => {
+                out.push_str("(?(");
+                out.push_str(&group.to_string());
+                out.push(')');
+                // A `|` in a conditional separates its branches
+                write_grouped(out, then, matches!(**then, Ast::Alternation(_)));
+                if **otherwise != Ast::Empty {
+                    out.push('|');
+                    write_grouped(out, otherwise, matches!(**otherwise, Ast::Alternation(_)));
+                }
+                out.push(')');
+            }
+            Ast::Repetition {
+                ast,
+                min,
+                max,
+                greedy,
+            } => {
+                write_grouped(out, ast, !ast.is_atom());
+                match (min, max) {
+                    (0, None) => out.push('*'),
+                    (1, None) => out.push('+'),
+                    (0, Some(1)) => out.push('?'),
+                    (min, Some(max)) if min == max => {
+                        out.push('{');
+                        out.push_str(&min.to_string());
+                        out.push('}');
+                    }
+                    (min, max) => {
+                        out.push('{');
+                        out.push_str(&min.to_string());
+                        out.push(',');
+                        if let Some(max) = max {
+                            out.push_str(&max.to_string());
+                        }
+                        out.push('}');
+                    }
+                }
+                if !greedy {
+                    out.push('?');
+                }
+            }
+            Ast::Concat(parts) => {
+                let mut rest = parts.as_slice();
+                while let Some(first) = rest.first() {
+                    // Chars and classes with the same flags share one group
+                    let run = match first.flags() {
+                        Some(flags) => rest
+                            .iter()
+                            .take_while(|part| part.flags() == Some(flags))
+                            .count(),
+                        None => 0,
+                    };
+                    if run > 0 {
+                        write_with_flags(out, &rest[..run]);
+                        rest = &rest[run..];
+                    } else {
+                        write_grouped(out, first, matches!(first, Ast::Alternation(_)));
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            Ast::Alternation(alternatives) => {
+                // Alternatives numbering their groups from the same index
+                // were in a branch reset group
+                let mut seen = Vec::new();
+                let branch_reset = alternatives.iter().any(|alternative| {
+                    let mut groups = Vec::new();
+                    alternative.groups(&mut groups);
+                    let reused = groups.iter().any(|group| seen.contains(group));
+                    seen.extend(groups);
+                    reused
+                });
+                if branch_reset {
+                    out.push_str("(?|");
+                }
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        out.push('|');
+                    }
+                    alternative.write(out);
+                }
+                if branch_reset {
+                    out.push(')');
+                }
+            }
+        }
+    }
+}
+
+/// Writes `ast`, in a non-capturing group if `group`.
+fn write_grouped(out: &mut String, ast: &Ast, group: bool) {
+    if group {
+        out.push_str("(?:");
+        ast.write(out);
+        out.push(')');
+    } else {
+        ast.write(out);
+    }
+}
+
+/// Writes the chars and classes `atoms`, all with the same flags, in a
+/// `(?flags:...)` group unless those are the default ones.
+fn write_with_flags(out: &mut String, atoms: &[Ast]) {
+    let flags = atoms[0].flags().unwrap_or_default();
+    let scoped = flags != Flags::default();
+    if scoped {
+        out.push_str("(?");
+        if flags.case_insensitive {
+            out.push('i');
+        }
+        if flags.dot_all {
+            out.push('s');
+        }
+        if flags.ascii {
+            out.push_str("-u");
+        }
+        out.push(':');
+    }
+    for atom in atoms {
+        match atom {
+            Ast::Literal(c, _) => push_literal(out, *c),
+            Ast::Dot(_) => out.push('.'),
+            Ast::ClassEscape(c, _) => {
+                out.push('\\');
+                out.push(*c);
+            }
+            Ast::Class(class, _) => out.push_str(class),
+            Ast::WordBoundary(false, _) => out.push_str("\\b"),
+            Ast::WordBoundary(true, _) => out.push_str("\\B"),
+            _ => atom.write(out),
+        }
+    }
+    if scoped {
+        out.push(')');
+    }
+}
+
+impl fmt::Display for Ast {
+    /// Writes the tree as a pattern that parses back to it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out);
+        f.write_str(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::{RegexNFA, SyntaxError};
+    use alloc::vec;
+
+    fn literals(text: &str) -> Vec<Ast> {
+        text.chars()
+            .map(|c| Ast::Literal(c, Flags::default()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r"^(?P<key>\w+)=\d*?$").unwrap(),
+            Ast::Concat(vec![
+                Ast::Start,
+                Ast::Group {
+                    index: 1,
+                    name: Some("key".to_string()),
+                    ast: Box::new(Ast::Repetition {
+                        ast: Box::new(Ast::ClassEscape('w', Flags::default())),
+                        min: 1,
+                        max: None,
+                        greedy: true,
+                    }),
+                },
+                Ast::Literal('=', Flags::default()),
+                Ast::Repetition {
+                    ast: Box::new(Ast::ClassEscape('d', Flags::default())),
+                    min: 0,
+                    max: None,
+                    greedy: false,
+                },
+                Ast::End,
+            ])
+        );
+        assert_eq!(
+            parse("ab|c|(?:de)").unwrap(),
+            Ast::Alternation(vec![
+                Ast::Concat(literals("ab")),
+                Ast::Literal('c', Flags::default()),
+                Ast::Concat(literals("de")),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            parse("a|*"),
+            Err(CompileError::Syntax(SyntaxError::NothingToRepeat(2)))
+        ));
+        assert!(matches!(parse("(a"), Err(CompileError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for pattern in [
+            "abc",
+            r"^(?P<key>\w+)=(\d+)?$",
+            "a|b(c|d)e",
+            r"(?i)ab(?-i)c[a-z&&[^aeiou]]",
+            r"(?s-u:.\w)x",
+            "(?:ab)+?c{2,5}d{3}e{1,}",
+            r"a\.b\*c\(d\)\[e\]\{f\}\|\^\$\\",
+            "(?:^|,)field",
+            r"\G\X\R",
+            r"(a)(?<n>b)\1\k<n>",
+            r"^(\((?1)?\))$",
+            r"(a)?(?(1)b|c)(?(1)d)",
+            r"(?|(a)|(b)x)(c)",
+            "(?:a+)*",
+            "x\ty\n",
+            r"\bfoo\B\D\W\S",
+            r"a|b$",
+            r"[\d\]\x41[:alpha:]]",
+            "",
+            "a||b|",
+            "(|a)()",
+        ] {
+            let ast = parse(pattern).unwrap();
+            let printed = ast.to_string();
+            assert_eq!(
+                parse(&printed).unwrap(),
+                ast,
+                "{} printed as {}",
+                pattern,
+                printed
+            );
+        }
+        assert_eq!(
+            parse("(?:ab)+?c{2,5}").unwrap().to_string(),
+            "(?:ab)+?c{2,5}"
+        );
+        assert_eq!(parse("(?i)abc").unwrap().to_string(), "(?i:abc)");
+    }
+
+    #[test]
+    fn test_rewrite() {
+        // Anchoring a pattern that may be an alternation
+        let ast = parse("cat|dog").unwrap();
+        let anchored = Ast::Concat(vec![Ast::Start, ast, Ast::End]);
+        let regex = RegexNFA::new(anchored.to_string());
+        assert!(regex.matches("dog"));
+        assert!(!regex.matches("hotdog"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Skipping ahead to the next byte of a class.
+//!
+//! A pattern like `[0-9]+:` has no fixed text for the literal prefilters to
+//! look for, but every match still starts with one of a few bytes. Scanning
+//! for those 16 bytes at a time with SSE2 is much faster than starting an
+//! engine at every offset: a byte is in a range `lo..=hi` when `byte - lo`,
+//! wrapping, is at most `hi - lo`, which takes a subtraction, an unsigned
+//! minimum and a comparison per range. Classes with too many ranges, other
+//! targets and the last few bytes of the input use a lookup table instead.
+//!
+//! Non-ASCII chars are looked for by the first byte of their UTF-8 encoding,
+//! so every offset found is a char boundary, though not always a char of the
+//! class.
+
+use alloc::vec::Vec;
+
+/// Byte ranges compared per 16-byte block before the table is faster.
+const MAX_SIMD_RANGES: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct ByteClass {
+    /// Sorted, disjoint and not adjacent.
+    ranges: Vec<(u8, u8)>,
+    table: [bool; 256],
+}
+
+impl ByteClass {
+    /// The bytes chars in `ranges` (inclusive) can start with.
+    pub fn from_char_ranges(ranges: &[(char, char)]) -> Self {
+        let mut bytes: Vec<(u8, u8)> = Vec::new();
+        for &(start, end) in ranges {
+            if start > end {
+                continue;
+            }
+            if start.is_ascii() {
+                bytes.push((start as u8, end.min('\x7F') as u8));
+            }
+            if !end.is_ascii() {
+                let start = start.max('\u{80}');
+                bytes.push((first_byte(start), first_byte(end)));
+            }
+        }
+        bytes.sort_unstable();
+        let mut merged: Vec<(u8, u8)> = Vec::new();
+        for (start, end) in bytes {
+            match merged.last_mut() {
+                Some(last) if start as usize <= last.1 as usize + 1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        let mut table = [false; 256];
+        for &(start, end) in &merged {
+            for byte in start..=end {
+                table[byte as usize] = true;
+            }
+        }
+        ByteClass {
+            ranges: merged,
+            table,
+        }
+    }
+
+    /// How many of the 256 byte values are in the class.
+    pub fn len(&self) -> usize {
+        self.table.iter().filter(|&&member| member).count()
+    }
+
+    pub fn contains(&self, byte: u8) -> bool {
+        self.table[byte as usize]
+    }
+
+    /// Offset of the first byte of the class in `haystack` at or after
+    /// `from`.
+    pub fn find(&self, haystack: &[u8], from: usize) -> Option<usize> {
+        #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+        if self.ranges.len() <= MAX_SIMD_RANGES {
+            // SAFETY: the build targets SSE2, see the `cfg`
+            return unsafe { self.find_sse2(haystack, from) };
+        }
+        self.find_scalar(haystack, from)
+    }
+
+    fn find_scalar(&self, haystack: &[u8], from: usize) -> Option<usize> {
+        haystack[from..]
+            .iter()
+            .position(|&byte| self.contains(byte))
+            .map(|found| from + found)
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+    #[target_feature(enable = "sse2")]
+    fn find_sse2(&self, haystack: &[u8], from: usize) -> Option<usize> {
+        use core::arch::x86_64::*;
+
+        let ranges: Vec<(__m128i, __m128i)> = self
+            .ranges
+            .iter()
+            .map(|&(start, end)| {
+                (
+                    _mm_set1_epi8(start as i8),
+                    _mm_set1_epi8(end.wrapping_sub(start) as i8),
+                )
+            })
+            .collect();
+        let mut at = from;
+        while at + 16 <= haystack.len() {
+            // SAFETY: the 16 bytes from `at` are in bounds, and an unaligned
+            // load has no other requirement
+            let block = unsafe { _mm_loadu_si128(haystack[at..].as_ptr() as *const __m128i) };
+            let mut found = _mm_setzero_si128();
+            for &(start, width) in &ranges {
+                let shifted = _mm_sub_epi8(block, start);
+                let in_range = _mm_cmpeq_epi8(_mm_min_epu8(shifted, width), shifted);
+                found = _mm_or_si128(found, in_range);
+            }
+            let mask = _mm_movemask_epi8(found);
+            if mask != 0 {
+                return Some(at + mask.trailing_zeros() as usize);
+            }
+            at += 16;
+        }
+        self.find_scalar(haystack, at)
+    }
+}
+
+/// The first byte of the UTF-8 encoding of `c`.
+fn first_byte(c: char) -> u8 {
+    let mut buffer = [0; 4];
+    c.encode_utf8(&mut buffer).as_bytes()[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_char_ranges() {
+        let class = ByteClass::from_char_ranges(&[('a', 'z'), ('0', '9'), ('A', 'A'), ('B', 'F')]);
+        assert_eq!(class.ranges, [(b'0', b'9'), (b'A', b'F'), (b'a', b'z')]);
+        assert_eq!(class.len(), 10 + 6 + 26);
+
+        // é and ö both start with 0xC3, € with 0xE2
+        let class = ByteClass::from_char_ranges(&[('é', 'é'), ('ö', 'ö'), ('x', '€')]);
+        assert_eq!(class.ranges, [(b'x', 0x7F), (0xC2, 0xE2)]);
+        assert!(!class.contains(0xA9));
+    }
+
+    #[test]
+    fn test_find_agrees_with_scalar() {
+        let haystack = "the quick brown fox: 42 jumps over é 7 lazy dogs...".repeat(3);
+        let haystack = haystack.as_bytes();
+        let classes = [
+            ByteClass::from_char_ranges(&[('0', '9')]),
+            ByteClass::from_char_ranges(&[(':', ':'), ('é', 'é')]),
+            ByteClass::from_char_ranges(&[('Q', 'Q')]),
+            // Too many ranges for the SIMD loop
+            ByteClass::from_char_ranges(&[
+                ('a', 'a'),
+                ('c', 'c'),
+                ('e', 'e'),
+                ('g', 'g'),
+                ('i', 'i'),
+            ]),
+        ];
+        for class in &classes {
+            for from in 0..=haystack.len() {
+                assert_eq!(
+                    class.find(haystack, from),
+                    class.find_scalar(haystack, from),
+                    "{:?} from {}",
+                    class.ranges,
+                    from
+                );
+            }
+        }
+        assert_eq!(classes[0].find(haystack, 0), Some(21));
+        assert_eq!(classes[2].find(haystack, 0), None);
+    }
+}
@@ -0,0 +1,235 @@
+//! Searching bytes that may not be valid UTF-8: `RegexNFA::is_match_bytes`,
+//! `find_bytes` and `find_bytes_iter`.
+//!
+//! The engines read chars, so a haystack that isn't valid UTF-8 is decoded
+//! first, every byte of an invalid sequence becoming a U+FFFD replacement
+//! char. An invalid byte is thus a single char that `.` and negated classes
+//! match and `\w` or any literal other than U+FFFD don't; a log line with a
+//! stray Latin-1 byte still matches `error: .*` the way it would after
+//! `String::from_utf8_lossy`. Classes follow the pattern's flags as for text,
+//! so `RegexOptions::ascii` restricts `\w` and `\d` to ASCII here too.
+//! Matches are reported as byte offsets into the original haystack; valid
+//! UTF-8 is searched in place without decoding. The same reading is what
+//! `RegexNFA` gives as a `Matcher`.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::matcher::Matcher;
+use crate::regex::find::ByteCursor;
+use crate::regex::nfa_regex::RegexNFA;
+
+/// Where a match is in a byte haystack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BytesMatch<'h> {
+    haystack: &'h [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'h> BytesMatch<'h> {
+    /// Byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The byte range of the match, to slice the haystack with.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The matched bytes.
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.range()]
+    }
+}
+
+/// A byte haystack as text, with what it takes to map offsets back.
+pub(super) struct Decoded<'h> {
+    pub(super) text: Cow<'h, str>,
+    // Offsets into `text` of the replacement chars standing for invalid bytes
+    replaced: Vec<usize>,
+}
+
+impl<'h> Decoded<'h> {
+    pub(super) fn new(haystack: &'h [u8]) -> Self {
+        if let Ok(text) = core::str::from_utf8(haystack) {
+            return Decoded {
+                text: Cow::Borrowed(text),
+                replaced: Vec::new(),
+            };
+        }
+        let mut text = String::with_capacity(haystack.len() + 8);
+        let mut replaced = Vec::new();
+        for chunk in haystack.utf8_chunks() {
+            text.push_str(chunk.valid());
+            for _ in chunk.invalid() {
+                replaced.push(text.len());
+                text.push(char::REPLACEMENT_CHARACTER);
+            }
+        }
+        Decoded {
+            text: Cow::Owned(text),
+            replaced,
+        }
+    }
+
+    /// The offset in the haystack of offset `index` into `text`, which is
+    /// never inside a replacement char.
+    fn original(&self, index: usize) -> usize {
+        // Each replacement takes three bytes for the one it stands for
+        let before = self.replaced.partition_point(|&at| at < index);
+        index - 2 * before
+    }
+
+    /// The offset into `text` of offset `at` in the haystack.
+    fn decoded(&self, at: usize) -> usize {
+        // Replacements before `at` in the haystack, found by bisection since
+        // `replaced[i] - 2 * i` is the haystack offset of the i-th one
+        let (mut low, mut high) = (0, self.replaced.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.replaced[middle] - 2 * middle < at {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        at + 2 * low
+    }
+
+    fn original_range(&self, start: usize, end: usize) -> Range<usize> {
+        self.original(start)..self.original(end)
+    }
+}
+
+/// The successive non-overlapping matches in a byte haystack, see
+/// `RegexNFA::find_bytes_iter`.
+pub struct BytesMatches<'r, 'h> {
+    regex: &'r RegexNFA,
+    haystack: &'h [u8],
+    decoded: Decoded<'h>,
+    cursor: ByteCursor,
+}
+
+impl<'h> Iterator for BytesMatches<'_, 'h> {
+    type Item = BytesMatch<'h>;
+
+    fn next(&mut self) -> Option<BytesMatch<'h>> {
+        let (start, end, _) = self.cursor.next(self.regex, &self.decoded.text)?;
+        Some(BytesMatch {
+            haystack: self.haystack,
+            start: self.decoded.original(start),
+            end: self.decoded.original(end),
+        })
+    }
+}
+
+impl RegexNFA {
+    /// Whether `haystack` contains a match, reading invalid UTF-8 as in the
+    /// module documentation.
+    pub fn is_match_bytes(&self, haystack: &[u8]) -> bool {
+        self.matches(&Decoded::new(haystack).text)
+    }
+
+    /// The leftmost match in `haystack`, as byte offsets into it.
+    pub fn find_bytes<'h>(&self, haystack: &'h [u8]) -> Option<BytesMatch<'h>> {
+        self.find_bytes_iter(haystack).next()
+    }
+
+    /// Every match in `haystack`, as `find_iter` finds them in text.
+    pub fn find_bytes_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> BytesMatches<'r, 'h> {
+        BytesMatches {
+            regex: self,
+            haystack,
+            decoded: Decoded::new(haystack),
+            cursor: ByteCursor::default(),
+        }
+    }
+}
+
+impl Matcher for RegexNFA {
+    /// Panics if `at` is inside a valid UTF-8 sequence of `haystack`.
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        let decoded = Decoded::new(haystack);
+        let found = self.find_at(&decoded.text, decoded.decoded(at))?;
+        Some(decoded.original_range(found.start(), found.end()))
+    }
+
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        self.is_match_bytes(haystack)
+    }
+
+    fn captures(&self, haystack: &[u8]) -> Option<Vec<Option<Range<usize>>>> {
+        let decoded = Decoded::new(haystack);
+        let caps = self.captures(&decoded.text)?;
+        let spans = caps
+            .iter()
+            .map(|group| group.map(|found| decoded.original_range(found.start(), found.end())))
+            .collect();
+        Some(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexOptions;
+    use alloc::string::ToString;
+
+    fn spans(pattern: &str, haystack: &[u8]) -> Vec<(usize, usize)> {
+        let regex = RegexNFA::new(pattern.to_string());
+        regex
+            .find_bytes_iter(haystack)
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_utf8() {
+        let regex = RegexNFA::new("\\w+".to_string());
+        assert!(regex.is_match_bytes("élan".as_bytes()));
+        assert!(!regex.is_match_bytes(b"  "));
+        let found = regex.find_bytes("- élan".as_bytes()).unwrap();
+        assert_eq!(found.range(), 2..7);
+        assert_eq!(found.as_bytes(), "élan".as_bytes());
+    }
+
+    #[test]
+    fn test_invalid_bytes_keep_their_offsets() {
+        let haystack = b"caf\xe9 ok \xff\xfe err";
+        assert_eq!(spans("\\w+", haystack), [(0, 3), (5, 7), (11, 14)]);
+        assert_eq!(spans("ok .. e", haystack), [(5, 12)]);
+        let regex = RegexNFA::new("error: .*$".to_string());
+        let found = regex.find_bytes(b"error: \xc3(\x28 end").unwrap();
+        assert_eq!(found.range(), 0..14);
+    }
+
+    #[test]
+    fn test_invalid_bytes_are_single_chars() {
+        assert_eq!(spans("^.$", b"\x80"), [(0, 1)]);
+        assert_eq!(spans("^..$", b"\xe2\x82"), [(0, 2)]);
+        assert_eq!(spans("[^a]", b"a\xffa"), [(1, 2)]);
+        assert!(!RegexNFA::new("\\w".to_string()).is_match_bytes(b"\xff"));
+        // A truncated sequence doesn't swallow the byte after it
+        assert_eq!(spans("a", b"\xe2\x82a"), [(2, 3)]);
+    }
+
+    #[test]
+    fn test_ascii_option() {
+        let options = RegexOptions::new().ascii(true);
+        let regex = RegexNFA::with_options("\\w+".to_string(), options);
+        let found: Vec<_> = regex
+            .find_bytes_iter(b"na\xc3\xafve\xff")
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(found, [0..2, 4..6]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Search state that can't be shared between threads.
+//!
+//! Searching never changes a compiled `RegexNFA`, so one regex can serve
+//! every thread searching with it. What a search does change, the DFA states
+//! built lazily as the input asks for them and the scratch space of the
+//! backtracking search, lives in a `Cache` instead: a
+//! thread gets its own from `RegexNFA::create_cache` and passes it to the
+//! `_with` searches. The other searches take one from a pool kept in the
+//! regex and put it back afterwards, so each thread still reuses the states
+//! built by earlier searches, and a new cache is only made when every pooled
+//! one is in use. Without std there is no lock to guard the pool with, so
+//! those searches start from a new cache every time.
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::regex::engine::SearchContext;
+use crate::regex::lazy_dfa::LazyDfa;
+
+#[derive(Debug)]
+pub struct Cache {
+    /// For `matches`, if the pattern can run as a lazy DFA.
+    pub(super) dfa: Option<LazyDfa>,
+    /// For the reverse DFA finding where matches start.
+    pub(super) reverse: Option<LazyDfa>,
+    /// Stacks and buffers of the backtracking search, kept at the size the
+    /// earlier searches grew them to.
+    pub(super) backtrack: SearchContext,
+}
+
+/// Caches not in use by any search.
+#[derive(Debug, Default)]
+pub(super) struct Pool {
+    #[cfg(feature = "std")]
+    caches: Mutex<Vec<Cache>>,
+}
+
+impl Pool {
+    /// Runs `search` with a pooled cache, or with one from `create` if there
+    /// is none left.
+    #[cfg(feature = "std")]
+    pub fn with<T>(
+        &self,
+        create: impl FnOnce() -> Cache,
+        search: impl FnOnce(&mut Cache) -> T,
+    ) -> T {
+        let cache = self.caches().pop();
+        let mut cache = cache.unwrap_or_else(create);
+        let found = search(&mut cache);
+        self.caches().push(cache);
+        found
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn with<T>(
+        &self,
+        create: impl FnOnce() -> Cache,
+        search: impl FnOnce(&mut Cache) -> T,
+    ) -> T {
+        search(&mut create())
+    }
+
+    #[cfg(feature = "std")]
+    fn caches(&self) -> MutexGuard<'_, Vec<Cache>> {
+        // The lock is never held while searching, so nothing can panic while
+        // the list is half updated
+        self.caches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
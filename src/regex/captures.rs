@@ -0,0 +1,202 @@
+//! Capture groups as values: `RegexNFA::captures` and `captures_iter`.
+//!
+//! A `Captures` holds the match and every group of it as byte offsets, like
+//! `Match`, and looks groups up by index or by name. Indexing with `[]` gives
+//! the text and panics for a group that didn't take part; `get` and `name`
+//! return `None` instead.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Index;
+
+use crate::regex::engine::Slots;
+use crate::regex::find::{ByteCursor, Match};
+use crate::regex::nfa_regex::RegexNFA;
+use crate::regex::parser::GroupName;
+
+/// The match and capture groups of one match.
+#[derive(Debug, Clone)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    // Byte span of the whole match, then of every group in order
+    spans: Vec<Option<(usize, usize)>>,
+    names: Arc<[GroupName]>,
+}
+
+impl<'h> Captures<'h> {
+    fn new(regex: &RegexNFA, haystack: &'h str, start: usize, end: usize, slots: Slots) -> Self {
+        let mut spans = vec![Some((start, end))];
+        for group in 1..=regex.groups {
+            let span = match (slots.get(2 * group), slots.get(2 * group + 1)) {
+                (Some(Some(s)), Some(Some(e))) => Some((*s, *e)),
+                _ => None,
+            };
+            spans.push(span);
+        }
+        Captures {
+            haystack,
+            spans,
+            names: Arc::clone(&regex.group_names),
+        }
+    }
+
+    /// Group `index`, 0 being the whole match. `None` if the group didn't
+    /// take part in the match or there is no such group.
+    pub fn get(&self, index: usize) -> Option<Match<'h>> {
+        let (start, end) = (*self.spans.get(index)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    /// The group named `name`, like `get`. With a name given to several
+    /// groups in a branch reset, the first of them that took part.
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        self.names
+            .iter()
+            .filter(|(group_name, _)| group_name == name)
+            .find_map(|&(_, index)| self.get(index))
+    }
+
+    /// The whole match.
+    pub fn get_match(&self) -> Match<'h> {
+        self.get(0).expect("the whole match always has a span")
+    }
+
+    /// Every group in order, starting with the whole match.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.spans.len()).map(|index| self.get(index))
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    /// Panics if group `index` didn't take part in the match.
+    fn index(&self, index: usize) -> &str {
+        self.get(index)
+            .unwrap_or_else(|| panic!("no group at index {}", index))
+            .as_str()
+    }
+}
+
+impl Index<&str> for Captures<'_> {
+    type Output = str;
+
+    /// Panics if no group named `name` took part in the match.
+    fn index(&self, name: &str) -> &str {
+        self.name(name)
+            .unwrap_or_else(|| panic!("no group named {}", name))
+            .as_str()
+    }
+}
+
+/// The groups of the successive non-overlapping matches in a haystack, see
+/// `RegexNFA::captures_iter`.
+pub struct CaptureMatches<'r, 'h> {
+    regex: &'r RegexNFA,
+    haystack: &'h str,
+    cursor: ByteCursor,
+}
+
+impl<'h> Iterator for CaptureMatches<'_, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Captures<'h>> {
+        let (start, end, slots) = self.cursor.next(self.regex, self.haystack)?;
+        Some(Captures::new(self.regex, self.haystack, start, end, slots))
+    }
+}
+
+impl RegexNFA {
+    /// The groups of the leftmost match in `haystack`. A search going over
+    /// the step limit finds nothing.
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        let (start, end, slots) = self.search_at(haystack, 0)?;
+        Some(Captures::new(self, haystack, start, end, slots))
+    }
+
+    /// The groups of every match in `haystack`, the matches being those
+    /// `find_iter` finds.
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CaptureMatches<'r, 'h> {
+        CaptureMatches {
+            regex: self,
+            haystack,
+            cursor: ByteCursor::default(),
+        }
+    }
+
+    /// The named groups of the pattern, in order, each with its index. A
+    /// name given to several groups in a branch reset comes once per group.
+    pub fn group_names(&self) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.group_names
+            .iter()
+            .map(|(name, index)| (name.as_str(), *index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    #[test]
+    fn test_captures_by_index() {
+        let regex = RegexNFA::new("(\\w+)=(\\d+)?".to_string());
+        let caps = regex.captures("set clé=42").unwrap();
+        assert_eq!(&caps[0], "clé=42");
+        assert_eq!(&caps[1], "clé");
+        assert_eq!(caps.get(2).map(|m| m.range()), Some(9..11));
+        assert_eq!(caps.get(3), None);
+        assert_eq!(caps.iter().len(), 3);
+
+        let caps = regex.captures("key=").unwrap();
+        assert_eq!(caps.get(2), None);
+        assert_eq!(caps.get_match().as_str(), "key=");
+        assert!(regex.captures("nothing").is_none());
+    }
+
+    #[test]
+    fn test_captures_by_name() {
+        let regex = RegexNFA::new("(?P<year>\\d{4})-(?<month>\\d{2})".to_string());
+        let caps = regex.captures("on 2024-03-07").unwrap();
+        assert_eq!(&caps["year"], "2024");
+        assert_eq!(&caps["month"], "03");
+        assert_eq!(caps.name("day"), None);
+        assert_eq!(
+            regex.group_names().collect::<Vec<_>>(),
+            [("year", 1), ("month", 2)]
+        );
+
+        let regex = RegexNFA::new("(?|(?<n>a)|(?<n>b)x)".to_string());
+        assert_eq!(&regex.captures("bx").unwrap()["n"], "b");
+    }
+
+    #[test]
+    #[should_panic(expected = "no group named day")]
+    fn test_index_missing_name() {
+        let regex = RegexNFA::new("(?<year>\\d+)".to_string());
+        let _ = &regex.captures("2024").unwrap()["day"];
+    }
+
+    #[test]
+    fn test_captures_iter() {
+        let regex = RegexNFA::new("(\\w)(\\d)?".to_string());
+        let groups: Vec<(String, Option<String>)> = regex
+            .captures_iter("a1 b c3")
+            .map(|caps| {
+                (
+                    caps[1].to_string(),
+                    caps.get(2).map(|m| m.as_str().to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("a".to_string(), Some("1".to_string())),
+                ("b".to_string(), None),
+                ("c".to_string(), Some("3".to_string())),
+            ]
+        );
+    }
+}
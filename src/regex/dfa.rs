@@ -0,0 +1,494 @@
+//! A DFA built ahead of time from the NFA and minimized.
+//!
+//! Where `lazy_dfa` builds states while searching, `Engine::determinize`
+//! runs the whole subset construction up front and merges equivalent states,
+//! leaving a plain transition table. Chars are first grouped into classes
+//! that every transition treats alike, so the table has one column per class
+//! rather than per char. The result can be written out with `Dfa::to_bytes`
+//! and loaded back with `Dfa::from_bytes`, skipping compilation altogether
+//! when the same pattern is searched run after run.
+//!
+//! Like the lazy DFA, only NFAs made of plain char transitions can be
+//! determinized; groups are accepted but their spans aren't tracked.
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::Engine;
+use crate::regex::error::DfaError;
+use crate::regex::lazy_dfa::LazyDfa;
+use crate::regex::nfa_regex::trailing_terminator_len;
+use crate::regex::Map;
+
+/// Subset construction gives up past this many states.
+const MAX_DFA_STATES: usize = 10_000;
+
+/// Sorted, disjoint char intervals and the class of their chars.
+type Intervals = Vec<(char, char, usize)>;
+
+const MAGIC: &[u8; 4] = b"GDFA";
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dfa {
+    anchored_start: bool,
+    anchored_end: bool,
+    // Chars outside every interval are class 0
+    intervals: Intervals,
+    class_count: usize,
+    start: usize,
+    // `class_count` targets per state
+    table: Vec<usize>,
+    is_match: Vec<bool>,
+    // A state no input leads out of, if any
+    dead: Option<usize>,
+}
+
+impl Engine {
+    /// A minimized DFA matching what this NFA matches. Unless `anchored`,
+    /// matches may start anywhere in the input. `None` if the NFA uses
+    /// anything besides plain char transitions (backreferences, `\G`, `\X`,
+    /// ...) or the DFA would be too large.
+    pub fn determinize(&self, anchored: bool) -> Option<Dfa> {
+        if !LazyDfa::supports(self) {
+            return None;
+        }
+        // Distinct matchers, and every state's consuming transitions as
+        // (matcher, target) pairs
+        let mut matchers: Vec<&Matcher> = Vec::new();
+        let mut consuming = vec![Vec::new(); self.states.len()];
+        for state in &self.states {
+            for (matcher, next) in &state.transitions {
+                if matcher.is_epsilon() {
+                    continue;
+                }
+                let index = match matchers.iter().position(|m| *m == matcher) {
+                    Some(index) => index,
+                    None => {
+                        matchers.push(matcher);
+                        matchers.len() - 1
+                    }
+                };
+                consuming[state.id].push((index, *next));
+            }
+        }
+        let (intervals, classes) = char_classes(&matchers);
+
+        let start = self.dfa_closure(&[self.start_state]);
+        let mut ids = Map::from([(start.clone(), 0)]);
+        let mut sets = vec![start];
+        let mut queue = VecDeque::from([0]);
+        let mut table = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            table.resize((current + 1) * classes.len(), 0);
+            for (class, signature) in classes.iter().enumerate() {
+                let mut targets: Vec<usize> = sets[current]
+                    .iter()
+                    .flat_map(|&id| &consuming[id])
+                    .filter(|(matcher, _)| signature[*matcher])
+                    .map(|(_, next)| *next)
+                    .collect();
+                if !anchored {
+                    // A new match attempt may start at every position
+                    targets.push(self.start_state);
+                }
+                let next_set = self.dfa_closure(&targets);
+                let next = match ids.get(&next_set) {
+                    Some(&next) => next,
+                    None => {
+                        if sets.len() == MAX_DFA_STATES {
+                            return None;
+                        }
+                        let next = sets.len();
+                        ids.insert(next_set.clone(), next);
+                        sets.push(next_set);
+                        queue.push_back(next);
+                        next
+                    }
+                };
+                table[current * classes.len() + class] = next;
+            }
+        }
+
+        let is_match = sets
+            .iter()
+            .map(|set| set.binary_search(&self.end_state).is_ok())
+            .collect();
+        let mut dfa = Dfa {
+            anchored_start: anchored,
+            anchored_end: false,
+            intervals,
+            class_count: classes.len(),
+            start: 0,
+            table,
+            is_match,
+            dead: None,
+        };
+        dfa.minimize();
+        Some(dfa)
+    }
+}
+
+/// Groups chars by which of `matchers` accept them. Returns the intervals of
+/// every class but 0, which holds the chars no matcher names, and for every
+/// class whether each matcher accepts it.
+fn char_classes(matchers: &[&Matcher]) -> (Intervals, Vec<Vec<bool>>) {
+    let signature = |c: char| -> Vec<bool> { matchers.iter().map(|m| m.matches(c)).collect() };
+
+    // Every matcher treats all chars between two consecutive boundaries alike
+    let mut boundaries = BTreeSet::from([0]);
+    for matcher in matchers {
+        match matcher {
+            Matcher::Range(ranges, _) => {
+                for &(start, end) in ranges {
+                    boundaries.insert(start as u32);
+                    boundaries.insert(end as u32 + 1);
+                }
+            }
+            Matcher::Any { .. } => {
+                boundaries.extend(['\n' as u32, '\n' as u32 + 1, '\r' as u32, '\r' as u32 + 1])
+            }
+            _ => {}
+        }
+    }
+    let ends: Vec<u32> = boundaries
+        .iter()
+        .skip(1)
+        .map(|b| b - 1)
+        .chain([char::MAX as u32])
+        .collect();
+
+    let unnamed = signature('\u{10FFFF}');
+    let mut classes = vec![unnamed.clone()];
+    let mut class_ids = Map::from([(unnamed, 0)]);
+    let mut intervals: Intervals = Vec::new();
+    for (start, end) in boundaries.into_iter().zip(ends) {
+        // Segments are never split by the surrogate gap, but may start or
+        // end inside it
+        let (Some(start), Some(end)) = (
+            char::from_u32(start).or(char::from_u32(start.max(0xE000))),
+            char::from_u32(end).or(char::from_u32(end.min(0xD7FF))),
+        ) else {
+            continue;
+        };
+        if start > end {
+            continue;
+        }
+        let signature = signature(start);
+        let class = *class_ids.entry(signature.clone()).or_insert_with(|| {
+            classes.push(signature);
+            classes.len() - 1
+        });
+        if class == 0 {
+            continue;
+        }
+        match intervals.last_mut() {
+            Some((_, last_end, last)) if *last == class && *last_end as u32 + 1 == start as u32 => {
+                *last_end = end
+            }
+            _ => intervals.push((start, end, class)),
+        }
+    }
+    (intervals, classes)
+}
+
+impl Dfa {
+    /// Only accepts matches ending at the end of the input, or right before
+    /// a final line terminator, like a pattern ending in `$`.
+    pub fn anchored_end(mut self, anchored_end: bool) -> Self {
+        self.anchored_end = anchored_end;
+        self
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.is_match.len()
+    }
+
+    /// Whether `input` contains a match.
+    pub fn matches(&self, input: &str) -> bool {
+        let terminator = trailing_terminator_len(input);
+        self.is_match(input, |index| {
+            !self.anchored_end || index == input.len() || index + terminator == input.len()
+        })
+    }
+
+    /// Whether a match ends at a byte offset accepted by `accept`.
+    pub fn is_match<F>(&self, input: &str, accept: F) -> bool
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut current = self.start;
+        let mut index = 0;
+        for c in input.chars() {
+            if self.is_match[current] && accept(index) {
+                return true;
+            }
+            if self.dead == Some(current) {
+                return false;
+            }
+            current = self.table[current * self.class_count + self.class_of(c)];
+            index += c.len_utf8();
+        }
+        self.is_match[current] && accept(index)
+    }
+
+    fn class_of(&self, c: char) -> usize {
+        let after = self.intervals.partition_point(|&(start, _, _)| start <= c);
+        match after.checked_sub(1).map(|i| self.intervals[i]) {
+            Some((_, end, class)) if c <= end => class,
+            _ => 0,
+        }
+    }
+
+    /// Merges states no input can tell apart (Moore's partition refinement).
+    fn minimize(&mut self) {
+        let state_count = self.state_count();
+        let mut blocks: Vec<usize> = self.is_match.iter().map(|&m| m as usize).collect();
+        let mut block_count = 0;
+        loop {
+            let mut ids: Map<Vec<usize>, usize> = Map::new();
+            let refined: Vec<usize> = (0..state_count)
+                .map(|state| {
+                    let row = &self.table[state * self.class_count..(state + 1) * self.class_count];
+                    let mut key = vec![blocks[state]];
+                    key.extend(row.iter().map(|&next| blocks[next]));
+                    let next_id = ids.len();
+                    *ids.entry(key).or_insert(next_id)
+                })
+                .collect();
+            blocks = refined;
+            if ids.len() == block_count {
+                break;
+            }
+            block_count = ids.len();
+        }
+
+        let mut table = vec![0; block_count * self.class_count];
+        let mut is_match = vec![false; block_count];
+        for state in 0..state_count {
+            let block = blocks[state];
+            is_match[block] = self.is_match[state];
+            for class in 0..self.class_count {
+                table[block * self.class_count + class] =
+                    blocks[self.table[state * self.class_count + class]];
+            }
+        }
+        self.start = blocks[self.start];
+        self.table = table;
+        self.is_match = is_match;
+        self.dead = self.find_dead();
+    }
+
+    fn find_dead(&self) -> Option<usize> {
+        (0..self.state_count()).find(|&state| {
+            !self.is_match[state]
+                && self.table[state * self.class_count..(state + 1) * self.class_count]
+                    .iter()
+                    .all(|&next| next == state)
+        })
+    }
+
+    /// The DFA in a compact binary form that `from_bytes` reads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.anchored_start as u8 | (self.anchored_end as u8) << 1);
+        let mut push = |value: usize| bytes.extend_from_slice(&(value as u32).to_le_bytes());
+        push(self.intervals.len());
+        for &(start, end, class) in &self.intervals {
+            push(start as usize);
+            push(end as usize);
+            push(class);
+        }
+        push(self.class_count);
+        push(self.state_count());
+        push(self.start);
+        for (state, &is_match) in self.is_match.iter().enumerate() {
+            push(is_match as usize);
+            for class in 0..self.class_count {
+                push(self.table[state * self.class_count + class]);
+            }
+        }
+        bytes
+    }
+
+    /// Loads a DFA written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Dfa, DfaError> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(DfaError::NotADfa);
+        };
+        let [version, flags, rest @ ..] = rest else {
+            return Err(DfaError::Corrupt);
+        };
+        if *version != FORMAT_VERSION {
+            return Err(DfaError::UnsupportedVersion(*version));
+        }
+        let mut words = rest.chunks(4).map(|chunk| {
+            <[u8; 4]>::try_from(chunk)
+                .map(|word| u32::from_le_bytes(word) as usize)
+                .map_err(|_| DfaError::Corrupt)
+        });
+        let mut next = || words.next().unwrap_or(Err(DfaError::Corrupt));
+        let to_char = |value: usize| char::from_u32(value as u32).ok_or(DfaError::Corrupt);
+
+        let interval_count = next()?;
+        let mut intervals = Vec::new();
+        for _ in 0..interval_count {
+            intervals.push((to_char(next()?)?, to_char(next()?)?, next()?));
+        }
+        let class_count = next()?;
+        let state_count = next()?;
+        let start = next()?;
+        let mut table = Vec::new();
+        let mut is_match = Vec::new();
+        for _ in 0..state_count {
+            is_match.push(next()? != 0);
+            for _ in 0..class_count {
+                table.push(next()?);
+            }
+        }
+        if next().is_ok() {
+            return Err(DfaError::Corrupt);
+        }
+
+        let sorted = intervals.windows(2).all(|pair| pair[0].1 < pair[1].0);
+        if class_count == 0
+            || start >= state_count
+            || !sorted
+            || intervals
+                .iter()
+                .any(|&(start, end, class)| start > end || class >= class_count)
+            || table.iter().any(|&next| next >= state_count)
+        {
+            return Err(DfaError::Corrupt);
+        }
+        let mut dfa = Dfa {
+            anchored_start: flags & 1 != 0,
+            anchored_end: flags & 2 != 0,
+            intervals,
+            class_count,
+            start,
+            table,
+            is_match,
+            dead: None,
+        };
+        dfa.dead = dfa.find_dead();
+        Ok(dfa)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use alloc::string::ToString;
+
+    const INPUTS: [&str; 12] = [
+        "", "a", "ab", "aab", "abbb", "ba", "cd\n", "xyzw", "12", "HeLLo", "a\nb", "zzz",
+    ];
+
+    #[test]
+    fn test_agrees_with_nfa() {
+        let patterns = [
+            "a+b",
+            "^ab*$",
+            "c?d$",
+            "(x|yz)+w",
+            "\\d{2,3}",
+            "(?i)hello",
+            "[^a]b",
+            "q*",
+            "a.b",
+            "(?s)a.b",
+            "[a-c&&[^b]]+",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let dfa = regex_nfa.determinize().unwrap();
+            for input in INPUTS {
+                assert_eq!(
+                    dfa.matches(input),
+                    regex_nfa.matches(input),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(RegexNFA::new("(a)\\1".to_string()).determinize().is_none());
+        assert!(RegexNFA::new("\\Ga".to_string()).determinize().is_none());
+        // Needs a state per combination of the last 16 chars
+        assert!(RegexNFA::new("(a|b)*a(a|b){16}".to_string())
+            .determinize()
+            .is_none());
+    }
+
+    #[test]
+    fn test_minimized() {
+        let count = |pattern: &str| {
+            RegexNFA::new(pattern.to_string())
+                .determinize()
+                .unwrap()
+                .state_count()
+        };
+        assert_eq!(count("(a|b)*c"), count("[ab]*c"));
+        // Start, after `a` and after `ab` (which already accepts)
+        assert_eq!(count("^ab"), 4);
+        // The unanchored search: either `a` was just seen or it wasn't
+        assert_eq!(count("a"), 2);
+    }
+
+    #[test]
+    fn test_char_classes() {
+        let regex_nfa = RegexNFA::new("[a-f]x|[d-z]".to_string());
+        let dfa = regex_nfa.determinize().unwrap();
+        // Besides the chars never named: a-c, d-f, g-w with y-z, and x
+        assert_eq!(dfa.class_count, 5);
+        assert_eq!(dfa.class_of('a'), dfa.class_of('c'));
+        assert_eq!(dfa.class_of('g'), dfa.class_of('z'));
+        assert_ne!(dfa.class_of('a'), dfa.class_of('d'));
+        assert_eq!(dfa.class_of('!'), 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for pattern in ["^ab*$", "(?i)hello", "\\w+@\\w+", "[^a]b"] {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let dfa = regex_nfa.determinize().unwrap();
+            let loaded = Dfa::from_bytes(&dfa.to_bytes()).unwrap();
+            assert_eq!(loaded, dfa);
+            for input in INPUTS {
+                assert_eq!(loaded.matches(input), regex_nfa.matches(input));
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let bytes = RegexNFA::new("ab".to_string())
+            .determinize()
+            .unwrap()
+            .to_bytes();
+        assert_eq!(Dfa::from_bytes(b"nope"), Err(DfaError::NotADfa));
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert_eq!(
+            Dfa::from_bytes(&newer),
+            Err(DfaError::UnsupportedVersion(9))
+        );
+        assert_eq!(
+            Dfa::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DfaError::Corrupt)
+        );
+        let mut wrong_target = bytes.clone();
+        let last = wrong_target.len() - 4;
+        wrong_target[last..].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(Dfa::from_bytes(&wrong_target), Err(DfaError::Corrupt));
+    }
+}
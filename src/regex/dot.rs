@@ -0,0 +1,147 @@
+//! Graphviz output of the compiled NFA, for seeing why a pattern matches
+//! what it does: `dot -Tsvg nfa.dot > nfa.svg`.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::Engine;
+
+/// Ranges shown in a class label before the rest is summarized.
+const MAX_LABEL_RANGES: usize = 6;
+
+impl Engine {
+    /// The NFA as a DOT graph. The start state has an arrow coming in from
+    /// nowhere and the end state is circled twice. When a state has several
+    /// transitions, their labels are numbered in the order they are tried.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph nfa {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=circle];\n");
+        dot.push_str("    start [shape=point];\n");
+        let _ = writeln!(dot, "    start -> {};", self.start_state);
+        let _ = writeln!(dot, "    {} [shape=doublecircle];", self.end_state);
+        for state in &self.states {
+            let numbered = state.transitions.len() > 1;
+            for (priority, (matcher, next)) in state.transitions.iter().enumerate() {
+                let mut label = label(matcher);
+                if numbered {
+                    label = format!("{}: {}", priority + 1, label);
+                }
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {} [label=\"{}\"];",
+                    state.id,
+                    next,
+                    escape(&label)
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A short description of the transition, in regex syntax where possible.
+fn label(matcher: &Matcher) -> String {
+    match matcher {
+        Matcher::Range(ranges, negated) => {
+            if let [(start, end)] = ranges.as_slice() {
+                if start == end && !negated {
+                    return char_label(*start);
+                }
+            }
+            let mut label = String::from(if *negated { "[^" } else { "[" });
+            for &(start, end) in ranges.iter().take(MAX_LABEL_RANGES) {
+                label.push_str(&char_label(start));
+                if end != start {
+                    label.push('-');
+                    label.push_str(&char_label(end));
+                }
+            }
+            if ranges.len() > MAX_LABEL_RANGES {
+                let _ = write!(label, "…+{}", ranges.len() - MAX_LABEL_RANGES);
+            }
+            label.push(']');
+            label
+        }
+        Matcher::Literal(literal) => literal.chars().map(char_label).collect(),
+        Matcher::Any {
+            except_newline: true,
+        } => ".".to_string(),
+        Matcher::Any {
+            except_newline: false,
+        } => "(?s).".to_string(),
+        Matcher::GroupStart(group) => format!("({}", group),
+        Matcher::GroupEnd(group) => format!("{})", group),
+        Matcher::Backref(group) => format!("\\{}", group),
+        Matcher::IfGroup(group, true) => format!("(?({}))", group),
+        Matcher::IfGroup(group, false) => format!("(?(!{}))", group),
+        Matcher::Call(0) => "(?R)".to_string(),
+        Matcher::Call(group) => format!("(?{})", group),
+        Matcher::CountReset(slot) => format!("#{} = 0", slot),
+        Matcher::CountUp(slot) => format!("#{} + 1", slot),
+        Matcher::IfCount { slot, min, max } => format!("#{} in {}..={}", slot, min, max),
+        Matcher::SearchStart => "\\G".to_string(),
+        Matcher::LineStart => "^".to_string(),
+        Matcher::Grapheme => "\\X".to_string(),
+        Matcher::Linebreak => "\\R".to_string(),
+        Matcher::WordStart { .. } => "(?<!\\w)".to_string(),
+        Matcher::WordEnd { .. } => "(?!\\w)".to_string(),
+        Matcher::LineEnd => "$".to_string(),
+        Matcher::WordBoundary { negated: false, .. } => "\\b".to_string(),
+        Matcher::WordBoundary { negated: true, .. } => "\\B".to_string(),
+        Matcher::Epsilon => "ε".to_string(),
+    }
+}
+
+/// Control and invisible chars as escapes, the rest as is.
+fn char_label(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ' ' => "' '".to_string(),
+        c if c.is_control() || c.is_whitespace() => format!("\\u{{{:X}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// Escapes a label for a double-quoted DOT string.
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::regex::RegexNFA;
+    use alloc::format;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_to_dot() {
+        let regex_nfa = RegexNFA::new("(a|[^0-9\"])\\1".to_string());
+        let dot = regex_nfa.engine.to_dot();
+        assert!(dot.starts_with("digraph nfa {\n"));
+        assert!(dot.ends_with("}\n"));
+        let engine = &regex_nfa.engine;
+        assert!(dot.contains(&format!("start -> {};", engine.start_state)));
+        assert!(dot.contains(&format!("{} [shape=doublecircle];", engine.end_state)));
+        assert!(dot.contains("[label=\"a\"]"));
+        assert!(dot.contains("[label=\"[^\\\"0-9]\"]"));
+        assert!(dot.contains("[label=\"\\\\1\"]"));
+        assert!(dot.contains("[label=\"(1\"]"));
+        assert!(dot.contains("[label=\"1: ε\"]"));
+        assert!(dot.contains("[label=\"2: ε\"]"));
+    }
+
+    #[test]
+    fn test_long_classes_are_summarized() {
+        let regex_nfa = RegexNFA::new("\\w".to_string());
+        let dot = regex_nfa.engine.to_dot();
+        let label = dot.lines().find(|line| line.contains("label=\"[")).unwrap();
+        assert!(label.contains("[0-9A-Z_a-z"), "{}", label);
+        assert!(label.contains("…+"), "{}", label);
+    }
+}
@@ -1,43 +1,107 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::panic;
 
+use crate::regex::elements::unicode;
+use crate::regex::error::SyntaxError;
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Matcher {
-    Range(Vec<char>, bool), // Range of characters, e.g., 'a' to 'z', and if is negated
+    Range(Ranges, bool), // Intervals of characters, e.g., 'a' to 'z', and if is negated
+    Any { except_newline: bool }, // Any character, optionally except \n and \r
+    Literal(Box<str>),   // A run of at least two chars, compared all at once
+    GroupStart(usize),   // Records where capture group n starts, consumes nothing
+    GroupEnd(usize),     // Records where capture group n ends, consumes nothing
+    Backref(usize),      // The text last captured by group n
+    IfGroup(usize, bool), // Whether group n has (or hasn't) captured, consumes nothing
+    Call(usize),         // Matches group n (0 for the whole pattern) as a subroutine
+    CountReset(usize),   // Sets the repetition counter in slot n to 0, consumes nothing
+    CountUp(usize),      // Adds one to the repetition counter in slot n, consumes nothing
+    IfCount { slot: usize, min: usize, max: usize }, // Whether the counter is in min..=max
+    SearchStart,         // Only where the search started (\G), consumes nothing
+    LineStart,           // `^` within the pattern, at the start of the input or a line
+    LineEnd,             // `$` within the pattern, at the end of the input or a line
+    Grapheme,            // One extended grapheme cluster (\X), however many chars
+    Linebreak,           // Any line ending (\R), \r\n being a single one
+    WordStart { ascii: bool }, // No word char right before, consumes nothing
+    WordEnd { ascii: bool }, // No word char right after, consumes nothing
+    WordBoundary { ascii: bool, negated: bool }, // `\b` (or `\B`), consumes nothing
     Epsilon,
 }
 
+/// Sorted codepoint intervals, inclusive on both ends. They never overlap or
+/// touch: `normalize` merges those.
+pub type Ranges = Vec<(char, char)>;
+
 impl Matcher {
+    /// Whether the transition is taken without consuming input. Group
+    /// markers count too: they only record the current position.
     pub fn is_epsilon(&self) -> bool {
-        matches!(self, Matcher::Epsilon)
+        matches!(
+            self,
+            Matcher::Epsilon | Matcher::GroupStart(_) | Matcher::GroupEnd(_)
+        )
     }
 
     pub fn matches(&self, c: char) -> bool {
         match self {
-            Matcher::Range(chars, negated) => {
-                let contains = chars.contains(&c);
-                if *negated {
-                    !contains
-                } else {
-                    contains
-                }
-            }
+            Matcher::Range(ranges, negated) => contains(ranges, c) != *negated,
+            Matcher::Any { except_newline } => !(*except_newline && (c == '\n' || c == '\r')),
+            // Handled by the engine, which knows the input and the captures
+            Matcher::GroupStart(_)
+            | Matcher::GroupEnd(_)
+            | Matcher::Backref(_)
+            | Matcher::IfGroup(..)
+            | Matcher::Call(_)
+            | Matcher::CountReset(_)
+            | Matcher::CountUp(_)
+            | Matcher::IfCount { .. }
+            | Matcher::SearchStart
+            | Matcher::LineStart
+            | Matcher::LineEnd
+            | Matcher::Grapheme
+            | Matcher::Linebreak
+            | Matcher::WordStart { .. }
+            | Matcher::WordEnd { .. }
+            | Matcher::WordBoundary { .. }
+            | Matcher::Literal(_) => false,
             Matcher::Epsilon => true, // Epsilon matches all charcters
         }
     }
 
-    fn create_alphanumeric() -> Matcher {
-        Matcher::Range(
-            ('a'..='z')
-                .chain('A'..='Z')
-                .chain('0'..='9')
-                .chain(std::iter::once('_'))
-                .collect(),
-            false,
-        )
+    fn create_alphanumeric(ascii: bool) -> Matcher {
+        if ascii {
+            return Matcher::Range(
+                normalize(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+                false,
+            );
+        }
+        Matcher::Range(unicode::WORD.to_vec(), false)
     }
 
-    fn create_digit() -> Matcher {
-        Matcher::Range(('0'..='9').collect(), false)
+    /// Whether `c` is a word char, one `\w` matches.
+    pub fn is_word_char(c: char, ascii: bool) -> bool {
+        if ascii {
+            return c.is_ascii_alphanumeric() || c == '_';
+        }
+        contains(unicode::WORD, c)
+    }
+
+    fn create_digit(ascii: bool) -> Matcher {
+        if ascii {
+            return Matcher::Range(vec![('0', '9')], false);
+        }
+        Matcher::Range(unicode::DIGIT.to_vec(), false)
+    }
+
+    fn create_whitespace(ascii: bool) -> Matcher {
+        if ascii {
+            return Matcher::Range(vec![('\t', '\r'), (' ', ' ')], false);
+        }
+        Matcher::Range(unicode::WHITESPACE.to_vec(), false)
     }
 
     fn create_blank(negated: bool) -> Matcher {
@@ -45,94 +109,427 @@ impl Matcher {
     }
 
     fn append_literal(mut matcher: Matcher, c: char) -> Matcher {
-        if let Matcher::Range(ref mut chars, negated) = matcher {
-            chars.push(c);
-            Matcher::Range(chars.clone(), negated)
+        if let Matcher::Range(ref mut ranges, negated) = matcher {
+            ranges.push((c, c));
+            Matcher::Range(normalize(ranges.clone()), negated)
         } else {
             matcher
         }
     }
 
-    fn create_dot() -> Matcher {
-        // Matches any character except \n and \r
+    fn create_dot(ascii: bool, dot_all: bool) -> Matcher {
+        // Matches any character except \n and \r, unless dot_all is set
+        if !ascii {
+            return Matcher::Any {
+                except_newline: !dot_all,
+            };
+        }
         Matcher::Range(
-            ('\u{0000}'..='\u{10FFFF}')
-                .filter(|&c| c != '\n' && c != '\r')
-                .collect(),
+            ranges_of(('\u{0000}'..='\u{007F}').filter(|&c| dot_all || (c != '\n' && c != '\r'))),
             false,
         )
     }
 
-    pub fn create_complex_matcher(input: &str) -> Matcher {
+    /// The matcher for `\d`, `\w` or `\s`, or their negations `\D`, `\W`
+    /// and `\S`, by its letter.
+    fn create_class_escape(letter: char, ascii: bool) -> Option<Matcher> {
+        let matcher = match letter.to_ascii_lowercase() {
+            'd' => Matcher::create_digit(ascii),
+            'w' => Matcher::create_alphanumeric(ascii),
+            's' => Matcher::create_whitespace(ascii),
+            _ => return None,
+        };
+        match matcher {
+            Matcher::Range(ranges, _) => Some(Matcher::Range(ranges, letter.is_uppercase())),
+            _ => None,
+        }
+    }
+
+    /// Builds the matcher for a class token. With `ascii` set, `.`, `\d`, `\w`
+    /// and `\s` only ever match ASCII characters; with `dot_all` set, `.` also
+    /// matches `\n` and `\r`.
+    pub fn create_complex_matcher(input: &str, ascii: bool, dot_all: bool) -> Matcher {
         match input.len() {
             1 => match input.chars().next().unwrap() {
-                '.' => Matcher::create_dot(),
-                'd' => Matcher::create_digit(),
-                'w' => Matcher::create_alphanumeric(),
-                _ => panic!("Unknown complex token: {}", input),
+                '.' => Matcher::create_dot(ascii, dot_all),
+                c => Matcher::create_class_escape(c, ascii)
+                    .unwrap_or_else(|| panic!("Unknown complex token: {}", input)),
             },
             2 => {
                 panic!("Complex tokens with length 2 are not supported: {}", input);
             }
             _ => {
                 // All regex of the form [..]
-                // Remove the first and last characters
+                let (ranges, negated) =
+                    parse_class(input, ascii, 0).expect("classes are checked when parsed");
+                Matcher::Range(ranges, negated)
+            }
+        }
+    }
+
+    /// The same matcher ignoring case: every char in a range also stands for
+    /// the chars case folding equates it with, for negated ranges too. With
+    /// `ascii`, only ASCII chars are equated.
+    pub fn case_folded(self, ascii: bool) -> Matcher {
+        match self {
+            Matcher::Range(ranges, negated) => {
+                let variants = ranges
+                    .iter()
+                    .flat_map(|&(start, end)| unicode::case_variants_between(start, end))
+                    .filter(|&&(c, variant)| !ascii || (c.is_ascii() && variant.is_ascii()))
+                    .map(|&(_, variant)| (variant, variant));
+                let folded = ranges.iter().copied().chain(variants).collect();
+                Matcher::Range(normalize(folded), negated)
+            }
+            other => other,
+        }
+    }
+
+    /// Whether some char is matched by both `self` and `other`. Only
+    /// meaningful for matchers consuming a single char, anything else is
+    /// taken to overlap.
+    pub fn overlaps(&self, other: &Matcher) -> bool {
+        match (self.char_ranges(), other.char_ranges()) {
+            (Some(a), Some(b)) => !intersect(&a, &b).is_empty(),
+            _ => true,
+        }
+    }
+
+    /// The chars a single-char matcher matches, as intervals.
+    fn char_ranges(&self) -> Option<Ranges> {
+        match self {
+            Matcher::Range(ranges, false) => Some(ranges.clone()),
+            Matcher::Range(ranges, true) => Some(complement(ranges)),
+            Matcher::Any {
+                except_newline: true,
+            } => Some(complement(&[('\n', '\n'), ('\r', '\r')])),
+            Matcher::Any {
+                except_newline: false,
+            } => Some(vec![('\u{0000}', char::MAX)]),
+            _ => None,
+        }
+    }
+
+    pub fn create_simple_matcher(input: &char) -> Matcher {
+        Matcher::append_literal(Matcher::create_blank(false), *input)
+    }
+}
+
+/// A set of chars: the ones in the ranges, or all but them when negated.
+type CharSet = (Ranges, bool);
+
+/// Checks that the bracket expression starting at position `at` of a pattern
+/// can be built, see `parse_class`.
+pub fn check_class(input: &str, ascii: bool, at: usize) -> Result<(), SyntaxError> {
+    parse_class(input, ascii, at).map(|_| ())
+}
+
+/// Parses a bracket expression like `[^a-z0-9]`. Besides ranges and single
+/// chars it supports the escapes `\d`, `\w` and `\s` and their negations,
+/// ASCII classes like `[:alpha:]`, nested classes, which are unioned with
+/// the rest (`[a[xyz]]`), intersection (`[a-z&&[^aeiou]]`) and subtraction
+/// (`[a-z--[aeiou]]`). The operators are applied left to right. Any other
+/// escaped char stands for itself. Errors report `at` as the position of the
+/// class.
+fn parse_class(input: &str, ascii: bool, at: usize) -> Result<CharSet, SyntaxError> {
+    // Remove the first and last characters
+    let inner = &input[1..input.len() - 1];
+    let negated = inner.starts_with('^');
+    let inner = if negated { &inner[1..] } else { inner };
+    if inner.is_empty() {
+        return Err(SyntaxError::EmptyClass(at));
+    }
 
-                let inner = &input[1..input.len() - 1];
-                if inner.is_empty() {
-                    panic!("Empty character class is not allowed");
+    let mut operands = split_operators(inner).into_iter();
+    let (_, first) = operands.next().unwrap();
+    let mut set = parse_items(first, ascii, at)?;
+    for (operator, operand) in operands {
+        let operand = parse_items(operand, ascii, at)?;
+        set = match operator {
+            "&&" => intersection(set, operand),
+            _ => intersection(set, (operand.0, !operand.1)),
+        };
+    }
+    let (ranges, set_negated) = set;
+    Ok((ranges, set_negated != negated))
+}
+
+/// Splits the body of a class at its top level `&&` and `--` operators, each
+/// operand paired with the operator preceding it (`""` for the first one).
+/// An operator missing an operand on either side is taken literally.
+fn split_operators(inner: &str) -> Vec<(&str, &str)> {
+    let mut operands = Vec::new();
+    let mut operator = "";
+    let mut start = 0;
+    let mut depth = 0;
+    let bytes = inner.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b'&' | b'-'
+                if depth == 0
+                    && bytes.get(i + 1) == Some(&bytes[i])
+                    && i > start
+                    && i + 2 < bytes.len() =>
+            {
+                operands.push((operator, &inner[start..i]));
+                operator = &inner[i..i + 2];
+                i += 2;
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    operands.push((operator, &inner[start..]));
+    operands
+}
+
+/// One item of a class: a char, or a set of them such as a nested class.
+enum Item {
+    Char(char),
+    Set(CharSet),
+}
+
+/// Parses a run of single chars, ranges, escapes and nested classes,
+/// unioning them.
+fn parse_items(items: &str, ascii: bool, at: usize) -> Result<CharSet, SyntaxError> {
+    let mut set: CharSet = (Vec::new(), false);
+    let mut plain = Vec::new();
+    let mut rest = items;
+
+    while let Some(item) = next_item(&mut rest, ascii, at)? {
+        let c = match item {
+            Item::Set(other) => {
+                set = union(set, other);
+                continue;
+            }
+            Item::Char(c) => c,
+        };
+        // A `-` between two chars makes a range, anywhere else it is a char
+        let mut lookahead = rest;
+        if let Some(after_dash) = lookahead.strip_prefix('-') {
+            lookahead = after_dash;
+            if let Some(Item::Char(end)) = next_item(&mut lookahead, ascii, at)? {
+                if c > end {
+                    return Err(SyntaxError::InvalidClassRange {
+                        range: format!("{}-{}", c, end),
+                        position: at,
+                    });
                 }
+                plain.push((c, end));
+                rest = lookahead;
+                continue;
+            }
+        }
+        plain.push((c, c));
+    }
 
-                let negated = inner.starts_with('^');
-                let inner = if negated { &inner[1..] } else { inner };
-                if inner.is_empty() {
-                    panic!("Empty character class is not allowed");
+    Ok(union(set, (plain, false)))
+}
+
+/// Takes the next item off the start of `rest`.
+fn next_item(rest: &mut &str, ascii: bool, at: usize) -> Result<Option<Item>, SyntaxError> {
+    let mut chars = rest.chars();
+    let Some(c) = chars.next() else {
+        return Ok(None);
+    };
+    let item = match c {
+        '[' => {
+            let posix = chars
+                .as_str()
+                .strip_prefix(':')
+                .and_then(|s| s.split_once(":]"));
+            if let Some((name, after)) = posix.filter(|(name, _)| is_class_name(name)) {
+                *rest = after;
+                return posix_class(name, at).map(|set| Some(Item::Set(set)));
+            }
+            // Find the matching bracket of the nested class
+            let mut depth = 0;
+            let mut end = rest.len();
+            let mut escaped = false;
+            for (i, c) in rest.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '[' => depth += 1,
+                    ']' => depth -= 1,
+                    _ => {}
                 }
-                let mut chars = Vec::new();
-
-                // Split the '-' into seperated ranges
-                let range_ends = inner.split('-').collect::<Vec<&str>>();
-                let mut prev_char = '\0';
-                for range_end in range_ends {
-                    if range_end.is_empty() {
-                        // the regex was of the form [^-]
-                        chars.push('-');
-                        prev_char = '-';
-                    } else {
-                        if prev_char != '\0' {
-                            // We have a range
-                            let start = prev_char;
-                            let end = range_end.chars().next().unwrap();
-                            if start > end {
-                                panic!("Invalid range in character class: {}-{}", start, end);
-                            }
-                            chars.extend(start..=end);
-                        }
-                        // Add the current characters
-                        for c in range_end.chars() {
-                            chars.push(c);
-                            prev_char = c;
-                        }
-                    }
+                if depth == 0 {
+                    end = i;
+                    break;
                 }
+            }
+            let class = &rest[..=end];
+            *rest = &rest[end + 1..];
+            return parse_class(class, ascii, at).map(|set| Some(Item::Set(set)));
+        }
+        '\\' => match chars.next() {
+            // The parser writes hex escapes as `\x{H...}`
+            Some('x') => {
+                let (hex, after) = chars.as_str()[1..].split_once('}').unwrap_or_default();
+                chars = after.chars();
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(Item::Char)
+                    .ok_or(SyntaxError::InvalidEscape(at))?
+            }
+            Some(letter) => match Matcher::create_class_escape(letter, ascii) {
+                Some(Matcher::Range(ranges, negated)) => Item::Set((ranges, negated)),
+                _ => Item::Char(letter),
+            },
+            None => Item::Char('\\'),
+        },
+        c => Item::Char(c),
+    };
+    *rest = chars.as_str();
+    Ok(Some(item))
+}
 
-                // Remove duplicates from chars
-                chars.sort();
-                chars.dedup();
+/// Whether `name` could be the name in `[:name:]` or `[:^name:]`, rather
+/// than chars between colons in a nested class.
+fn is_class_name(name: &str) -> bool {
+    let name = name.strip_prefix('^').unwrap_or(name);
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase())
+}
+
+/// The ASCII class called `name` in `[:name:]`, or its complement for
+/// `[:^name:]`.
+fn posix_class(name: &str, at: usize) -> Result<CharSet, SyntaxError> {
+    let (name, negated) = match name.strip_prefix('^') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let ranges: &[(char, char)] = match name {
+        "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "alpha" => &[('A', 'Z'), ('a', 'z')],
+        "ascii" => &[('\0', '\x7F')],
+        "blank" => &[('\t', '\t'), (' ', ' ')],
+        "cntrl" => &[('\0', '\x1F'), ('\x7F', '\x7F')],
+        "digit" => &[('0', '9')],
+        "graph" => &[('!', '~')],
+        "lower" => &[('a', 'z')],
+        "print" => &[(' ', '~')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "space" => &[('\t', '\r'), (' ', ' ')],
+        "upper" => &[('A', 'Z')],
+        "word" => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+        _ => {
+            return Err(SyntaxError::UnknownClassName {
+                name: name.into(),
+                position: at,
+            })
+        }
+    };
+    Ok((ranges.to_vec(), negated))
+}
+
+fn union(a: CharSet, b: CharSet) -> CharSet {
+    match (a, b) {
+        ((a, false), (b, false)) => (normalize([a, b].concat()), false),
+        ((a, false), (b, true)) | ((b, true), (a, false)) => (difference(&b, &a), true),
+        ((a, true), (b, true)) => (intersect(&a, &b), true),
+    }
+}
+
+fn intersection(a: CharSet, b: CharSet) -> CharSet {
+    match (a, b) {
+        ((a, false), (b, false)) => (intersect(&a, &b), false),
+        ((a, false), (b, true)) | ((b, true), (a, false)) => (difference(&a, &b), false),
+        ((a, true), (b, true)) => (normalize([a, b].concat()), true),
+    }
+}
+
+fn difference(a: &[(char, char)], b: &[(char, char)]) -> Ranges {
+    intersect(a, &complement(b))
+}
 
-                return Matcher::Range(chars, negated);
+fn intersect(a: &[(char, char)], b: &[(char, char)]) -> Ranges {
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start <= end {
+            result.push((start, end));
+        }
+        // Drop whichever interval ends first
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    result
+}
+
+fn complement(ranges: &[(char, char)]) -> Ranges {
+    let mut result = Vec::new();
+    let mut next = Some('\u{0000}');
+    for &(start, end) in ranges {
+        if let Some(gap_start) = next {
+            if let Some(gap_end) = char_before(start).filter(|&c| gap_start <= c) {
+                result.push((gap_start, gap_end));
             }
         }
+        next = char_after(end);
+    }
+    if let Some(gap_start) = next {
+        result.push((gap_start, char::MAX));
     }
+    result
+}
 
-    pub fn create_simple_matcher(input: &char) -> Matcher {
-        Matcher::append_literal(Matcher::create_blank(false), *input)
+/// Sorts the intervals and merges the ones overlapping or touching.
+fn normalize(mut ranges: Ranges) -> Ranges {
+    ranges.sort_unstable();
+    let mut merged: Ranges = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if char_after(*last_end).is_none_or(|c| start <= c) => {
+                *last_end = (*last_end).max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn ranges_of(chars: impl Iterator<Item = char>) -> Ranges {
+    normalize(chars.map(|c| (c, c)).collect())
+}
+
+fn contains(ranges: &[(char, char)], c: char) -> bool {
+    let after = ranges.partition_point(|&(start, _)| start <= c);
+    after > 0 && c <= ranges[after - 1].1
+}
+
+/// The next char, skipping the surrogate gap.
+fn char_after(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn char_before(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => (c as u32).checked_sub(1).and_then(char::from_u32),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
 
     #[test]
     fn test_epsilon() {
@@ -154,7 +551,7 @@ mod tests {
 
     #[test]
     fn test_alphanumeric() {
-        let matcher = Matcher::create_complex_matcher('w'.to_string().as_str());
+        let matcher = Matcher::create_complex_matcher('w'.to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(matcher.matches('a'));
         assert!(matcher.matches('Z'));
@@ -165,7 +562,7 @@ mod tests {
 
     #[test]
     fn test_digit() {
-        let matcher = Matcher::create_complex_matcher('d'.to_string().as_str());
+        let matcher = Matcher::create_complex_matcher('d'.to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(matcher.matches('0'));
         assert!(matcher.matches('9'));
@@ -175,7 +572,8 @@ mod tests {
 
     #[test]
     fn test_character_class() {
-        let matcher = Matcher::create_complex_matcher("[a-zA-Z0-9_]".to_string().as_str());
+        let matcher =
+            Matcher::create_complex_matcher("[a-zA-Z0-9_]".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(matcher.matches('a'));
         assert!(matcher.matches('Z'));
@@ -186,7 +584,8 @@ mod tests {
 
     #[test]
     fn test_negated_charclass() {
-        let matcher = Matcher::create_complex_matcher("[^a-zA-Z0-9_]".to_string().as_str());
+        let matcher =
+            Matcher::create_complex_matcher("[^a-zA-Z0-9_]".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(!matcher.matches('a'));
         assert!(!matcher.matches('Z'));
@@ -205,7 +604,7 @@ mod tests {
         assert!(!matcher.matches('a'));
         assert!(!matcher.matches(' '));
 
-        let matcher = Matcher::create_complex_matcher("[^_]".to_string().as_str());
+        let matcher = Matcher::create_complex_matcher("[^_]".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(!matcher.matches('_'));
         assert!(matcher.matches('a'));
@@ -214,7 +613,7 @@ mod tests {
 
     #[test]
     fn test_character_group() {
-        let matcher = Matcher::create_complex_matcher("[abz]".to_string().as_str());
+        let matcher = Matcher::create_complex_matcher("[abz]".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(matcher.matches('a'));
         assert!(matcher.matches('b'));
@@ -224,7 +623,7 @@ mod tests {
 
     #[test]
     fn test_negated_character_group() {
-        let matcher = Matcher::create_complex_matcher("[^abz]".to_string().as_str());
+        let matcher = Matcher::create_complex_matcher("[^abz]".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(!matcher.matches('a'));
         assert!(!matcher.matches('b'));
@@ -232,14 +631,245 @@ mod tests {
         assert!(matcher.matches('1'));
     }
 
+    #[test]
+    fn test_class_intersection() {
+        let consonants = Matcher::create_complex_matcher("[a-z&&[^aeiou]]", false, false);
+        assert!(consonants.matches('b'));
+        assert!(!consonants.matches('a'));
+        assert!(!consonants.matches('B'));
+
+        let negated = Matcher::create_complex_matcher("[^a-z&&[^aeiou]]", false, false);
+        assert!(negated.matches('a'));
+        assert!(!negated.matches('b'));
+
+        // Everything but (a or b, but not b or c)
+        let both = Matcher::create_complex_matcher("[^ab&&[^bc]]", false, false);
+        assert!(!both.matches('a'));
+        assert!(both.matches('b'));
+        assert!(both.matches('d'));
+    }
+
+    #[test]
+    fn test_class_subtraction() {
+        let matcher = Matcher::create_complex_matcher("[a-z--[aeiou]--xyz]", false, false);
+        assert!(matcher.matches('b'));
+        assert!(!matcher.matches('e'));
+        assert!(!matcher.matches('y'));
+
+        let matcher = Matcher::create_complex_matcher("[0-9--5]", false, false);
+        assert!(matcher.matches('4'));
+        assert!(!matcher.matches('5'));
+    }
+
+    #[test]
+    fn test_nested_class_union() {
+        let matcher = Matcher::create_complex_matcher("[a[x-z][^b-y]]", false, false);
+        assert!(matcher.matches('a'));
+        assert!(matcher.matches('y'));
+        assert!(matcher.matches('!'));
+        assert!(!matcher.matches('c'));
+    }
+
+    #[test]
+    fn test_literal_dashes_and_ampersands() {
+        let matcher = Matcher::create_complex_matcher("[a-]", false, false);
+        assert!(matcher.matches('-'));
+        let matcher = Matcher::create_complex_matcher("[&&]", false, false);
+        assert!(matcher.matches('&'));
+        assert!(!matcher.matches('a'));
+    }
+
+    #[test]
+    fn test_negated_class_escapes() {
+        let not_digit = Matcher::create_complex_matcher("D", false, false);
+        assert!(not_digit.matches('a'));
+        assert!(!not_digit.matches('٣'));
+        let not_word = Matcher::create_complex_matcher("W", true, false);
+        assert!(not_word.matches('-'));
+        assert!(not_word.matches('é'));
+        assert!(!not_word.matches('_'));
+        assert!(!Matcher::create_complex_matcher("S", false, false).matches(' '));
+    }
+
+    #[test]
+    fn test_escapes_in_classes() {
+        let matcher = Matcher::create_complex_matcher(r"[\d_]", false, false);
+        assert!(matcher.matches('7'));
+        assert!(matcher.matches('_'));
+        assert!(!matcher.matches('d'));
+
+        let matcher = Matcher::create_complex_matcher(r"[^\w]", false, false);
+        assert!(matcher.matches('-'));
+        assert!(!matcher.matches('a'));
+        // Only in ASCII mode is é not a word char
+        assert!(!matcher.matches('é'));
+        assert!(Matcher::create_complex_matcher(r"[^\w]", true, false).matches('é'));
+
+        let matcher = Matcher::create_complex_matcher(r"[\]\-\\]", false, false);
+        assert!(matcher.matches(']'));
+        assert!(matcher.matches('-'));
+        assert!(matcher.matches('\\'));
+        assert!(!matcher.matches('.'));
+        assert!(Matcher::create_complex_matcher(r"[\S&&\D]", false, false).matches('a'));
+    }
+
+    #[test]
+    fn test_posix_classes() {
+        let matcher = Matcher::create_complex_matcher("[[:alpha:]]", false, false);
+        assert!(matcher.matches('a'));
+        assert!(matcher.matches('Z'));
+        assert!(!matcher.matches('1'));
+        assert!(!matcher.matches('é'));
+
+        let matcher = Matcher::create_complex_matcher("[[:^digit:]x]", false, false);
+        assert!(matcher.matches('a'));
+        assert!(!matcher.matches('1'));
+
+        // Colons that don't name a class are just chars
+        let matcher = Matcher::create_complex_matcher("[[:1:]]", false, false);
+        assert!(matcher.matches(':'));
+        assert!(matcher.matches('1'));
+
+        assert_eq!(
+            check_class("[[:alfa:]]", false, 3),
+            Err(SyntaxError::UnknownClassName {
+                name: "alfa".to_string(),
+                position: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_case_folded() {
+        let matcher = Matcher::create_simple_matcher(&'k').case_folded(false);
+        assert!(matcher.matches('k'));
+        assert!(matcher.matches('K'));
+        assert!(matcher.matches('\u{212A}'));
+        assert!(!matcher.matches('x'));
+        let matcher = Matcher::create_simple_matcher(&'k').case_folded(true);
+        assert!(matcher.matches('K'));
+        assert!(!matcher.matches('\u{212A}'));
+
+        let matcher = Matcher::create_complex_matcher("[^a-cé]", false, false).case_folded(false);
+        assert!(!matcher.matches('B'));
+        assert!(!matcher.matches('É'));
+        assert!(matcher.matches('d'));
+
+        let matcher = Matcher::create_complex_matcher("[a-z]", false, false).case_folded(false);
+        assert!(matcher.matches('ſ'));
+        let matcher = Matcher::create_simple_matcher(&'σ').case_folded(false);
+        assert!(matcher.matches('ς'));
+        assert!(matcher.matches('Σ'));
+
+        // Multi-char case mappings are left out
+        let matcher = Matcher::create_simple_matcher(&'ß').case_folded(false);
+        assert_eq!(matcher, Matcher::Range(vec![('ß', 'ß'), ('ẞ', 'ẞ')], false));
+    }
+
+    #[test]
+    fn test_ranges_are_merged_intervals() {
+        let matcher = Matcher::create_complex_matcher("[x0-9a-zc-f]", false, false);
+        assert_eq!(matcher, Matcher::Range(vec![('0', '9'), ('a', 'z')], false));
+        let matcher = Matcher::create_complex_matcher("[a-z--[d-x]]", false, false);
+        assert_eq!(matcher, Matcher::Range(vec![('a', 'c'), ('y', 'z')], false));
+
+        let Matcher::Range(ranges, _) = Matcher::create_complex_matcher("w", false, false) else {
+            unreachable!();
+        };
+        assert!(ranges.len() < 1000);
+        assert!(ranges
+            .windows(2)
+            .all(|pair| char_after(pair[0].1).unwrap() < pair[1].0));
+    }
+
+    #[test]
+    fn test_complement() {
+        assert_eq!(
+            complement(&[('\u{0}', 'a'), ('c', '\u{D7FF}')]),
+            vec![('b', 'b'), ('\u{E000}', char::MAX)]
+        );
+        assert_eq!(complement(&[]), vec![('\u{0}', char::MAX)]);
+        assert_eq!(complement(&[('\u{0}', char::MAX)]), vec![]);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let digit = Matcher::create_complex_matcher("d", true, false);
+        let word = Matcher::create_complex_matcher("w", true, false);
+        let letter = Matcher::create_complex_matcher("[a-z]", true, false);
+        let not_letter = Matcher::create_complex_matcher("[^a-z]", true, false);
+        let dot = Matcher::create_complex_matcher(".", false, false);
+        assert!(digit.overlaps(&word));
+        assert!(!digit.overlaps(&letter));
+        assert!(!letter.overlaps(&not_letter));
+        assert!(dot.overlaps(&letter));
+        assert!(!dot.overlaps(&Matcher::create_simple_matcher(&'\n')));
+        assert!(Matcher::Backref(1).overlaps(&letter));
+    }
+
     #[test]
     fn test_dot_matcher() {
-        let matcher = Matcher::create_complex_matcher(".".to_string().as_str());
+        let matcher = Matcher::create_complex_matcher(".".to_string().as_str(), false, false);
         assert!(!matcher.is_epsilon());
         assert!(matcher.matches('a'));
         assert!(matcher.matches('1'));
         assert!(matcher.matches(' '));
         assert!(!matcher.matches('\n'));
         assert!(!matcher.matches('\r'));
+        assert_eq!(
+            matcher,
+            Matcher::Any {
+                except_newline: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_dot_all_matcher() {
+        let matcher = Matcher::create_complex_matcher(".", false, true);
+        assert!(matcher.matches('a'));
+        assert!(matcher.matches('\n'));
+        assert!(matcher.matches('\r'));
+
+        let matcher = Matcher::create_complex_matcher(".", true, true);
+        assert!(matcher.matches('\n'));
+        assert!(!matcher.matches('€'));
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let matcher = Matcher::create_complex_matcher("s", false, false);
+        assert!(matcher.matches(' '));
+        assert!(matcher.matches('\t'));
+        assert!(matcher.matches('\u{00A0}'));
+        assert!(!matcher.matches('a'));
+    }
+
+    #[test]
+    fn test_unicode_classes() {
+        assert!(Matcher::create_complex_matcher("w", false, false).matches('é'));
+        assert!(Matcher::create_complex_matcher("d", false, false).matches('٣'));
+        assert!(Matcher::create_complex_matcher(".", false, false).matches('€'));
+    }
+
+    #[test]
+    fn test_ascii_classes() {
+        let word = Matcher::create_complex_matcher("w", true, false);
+        assert!(word.matches('a'));
+        assert!(word.matches('_'));
+        assert!(!word.matches('é'));
+
+        let digit = Matcher::create_complex_matcher("d", true, false);
+        assert!(digit.matches('7'));
+        assert!(!digit.matches('٣'));
+
+        let space = Matcher::create_complex_matcher("s", true, false);
+        assert!(space.matches('\t'));
+        assert!(!space.matches('\u{00A0}'));
+
+        let dot = Matcher::create_complex_matcher(".", true, false);
+        assert!(dot.matches('a'));
+        assert!(!dot.matches('€'));
+        assert!(!dot.matches('\n'));
     }
 }
@@ -1,5 +1,6 @@
 mod matcher;
 mod state;
+mod unicode;
 
-pub use matcher::Matcher;
+pub use matcher::{check_class, Matcher};
 pub use state::State;
@@ -1,8 +1,14 @@
+use alloc::vec::Vec;
+
 use crate::regex::elements::Matcher;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct State {
     pub id: usize,
+    /// In priority order: when several of them lead to a match, every
+    /// engine reports the one through the earliest. That's what makes
+    /// quantifiers greedy or lazy and alternatives leftmost-first.
     pub transitions: Vec<(Matcher, usize)>,
 }
 
@@ -14,10 +20,18 @@ impl State {
         }
     }
 
+    /// Adds a transition with a lower priority than the existing ones.
     pub fn add_transition(&mut self, c: Matcher, next_state_id: usize) {
         self.transitions.push((c, next_state_id));
     }
 
+    /// Adds epsilon transitions to `preferred` and then to `other`, both with
+    /// a lower priority than the existing ones.
+    pub fn add_choice(&mut self, preferred: usize, other: usize) {
+        self.add_transition(Matcher::Epsilon, preferred);
+        self.add_transition(Matcher::Epsilon, other);
+    }
+
     pub fn shift_ids(&mut self, shift: usize) {
         self.id += shift;
         for (_, next_state_id) in &mut self.transitions {
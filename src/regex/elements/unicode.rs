@@ -0,0 +1,62 @@
+//! Unicode tables generated by `build.rs` from the standard library's
+//! Unicode data: the classes behind `\w`, `\d` and `\s`, and the chars
+//! case folding equates each char with.
+
+include!(concat!(env!("OUT_DIR"), "/unicode_tables.rs"));
+
+/// The chars case folding equates with those from `start` to `end`, each
+/// paired with the char it is equated with.
+pub fn case_variants_between(start: char, end: char) -> &'static [(char, char)] {
+    let first = CASE_VARIANTS.partition_point(|&(c, _)| c < start);
+    let after = CASE_VARIANTS.partition_point(|&(c, _)| c <= end);
+    &CASE_VARIANTS[first..after.max(first)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(table: &[(char, char)], c: char) -> bool {
+        table.iter().any(|&(start, end)| start <= c && c <= end)
+    }
+
+    #[test]
+    fn test_tables_agree_with_std() {
+        for c in [
+            'a', 'Z', '_', '7', 'é', 'ß', '٣', '漢', ' ', '\u{A0}', '\u{2028}', '-', '€',
+        ] {
+            assert_eq!(
+                contains(WORD, c),
+                c.is_alphanumeric() || c == '_',
+                "{:?}",
+                c
+            );
+            assert_eq!(contains(DIGIT, c), c.is_numeric(), "{:?}", c);
+            assert_eq!(contains(WHITESPACE, c), c.is_whitespace(), "{:?}", c);
+        }
+        assert!(WORD.windows(2).all(|pair| pair[0].1 < pair[1].0));
+    }
+
+    #[test]
+    fn test_case_variants_between() {
+        assert_eq!(case_variants_between('a', 'a'), [('a', 'A')]);
+        assert_eq!(case_variants_between('É', 'É'), [('É', 'é')]);
+        assert_eq!(case_variants_between('0', '9'), []);
+        assert_eq!(case_variants_between('y', 'b'), []);
+        // ß uppercases to "SS", which isn't a single char, but ẞ lowercases to ß
+        assert_eq!(case_variants_between('ß', 'ß'), [('ß', 'ẞ')]);
+        // Folding is transitive: the Kelvin sign and the long s join k and s
+        assert_eq!(
+            case_variants_between('k', 'k'),
+            [('k', 'K'), ('k', '\u{212A}')]
+        );
+        assert_eq!(
+            case_variants_between('\u{212A}', '\u{212A}'),
+            [('\u{212A}', 'K'), ('\u{212A}', 'k')]
+        );
+        assert_eq!(case_variants_between('ς', 'ς'), [('ς', 'Σ'), ('ς', 'σ')]);
+        assert_eq!(case_variants_between('ſ', 'ſ'), [('ſ', 'S'), ('ſ', 's')]);
+        assert_eq!(case_variants_between('ı', 'ı'), []);
+        assert_eq!(case_variants_between('A', 'z').len(), 56);
+    }
+}
@@ -1,10 +1,284 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use unicode_segmentation::UnicodeSegmentation;
+
 use crate::regex::elements::{Matcher, State};
+use crate::regex::error::MatchError;
+use crate::regex::nfa_regex::{is_line_end, is_line_start, trailing_terminator_len};
+use crate::regex::prefilter::Prefilter;
+use crate::regex::trace::{self, TraceEvent};
+
+/// Capture positions of a search path, see `Engine::compute`.
+pub type Slots = Vec<Option<usize>>;
+
+/// How deeply `(?R)` / `(?1)` calls may nest unless configured otherwise.
+pub const DEFAULT_RECURSION_LIMIT: usize = 100;
+
+/// How many bytes a compiled NFA may take unless configured otherwise.
+pub const DEFAULT_SIZE_LIMIT: usize = 10 * (1 << 20);
+
+/// Largest `Memo` a search uses, in bits. Searches that would need more
+/// go without one.
+const MAX_MEMO_BITS: usize = 64 * 1024 * 1024;
+
+/// Words in each page of a `Memo` over counts, which only has the pages of
+/// the triples a search reaches.
+const MEMO_PAGE_WORDS: usize = 1024;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Engine {
+    // Indexed by state id: the constructions number states densely, in order
     pub states: Vec<State>,
     pub start_state: usize,
     pub end_state: usize,
+    pub recursion_limit: usize,
+    // Steps a backtracking search may take, see `RegexOptions::step_limit`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub step_limit: Option<usize>,
+    // Whether `^` within the pattern also matches after every `\n`, see
+    // `RegexOptions::multi_line`. Saved with the rest of the options instead
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub multi_line: bool,
+}
+
+/// A pending subroutine call: where to continue once the called group's end
+/// state is reached, and the captures to restore at that point.
+#[derive(Debug)]
+struct Frame {
+    end: usize,
+    continuation: usize,
+    saved: Slots,
+}
+
+/// How a path got to the state it is queued at.
+#[derive(Debug)]
+enum Entry {
+    /// By consuming input, or by starting there.
+    Consume,
+    Epsilon,
+    /// Through a `GroupStart` or `GroupEnd` transition recording in `slot`.
+    Capture(usize),
+    /// Through a transition setting the repetition counter in `slot`.
+    Count {
+        slot: usize,
+        value: usize,
+    },
+    /// By calling a group ending at `end`, to continue at `continuation`.
+    Call {
+        end: usize,
+        continuation: usize,
+    },
+}
+
+/// An entry of the backtracking stack: a path still to follow, or a change
+/// the path being followed made, to undo once all its branches failed.
+#[derive(Debug)]
+enum Job {
+    /// Follows a path at `state` and offset `index`, which went through the
+    /// first `visited` entries of `SearchContext::visited`.
+    Explore {
+        state: usize,
+        index: usize,
+        visited: usize,
+        entry: Entry,
+    },
+    RestoreSlot {
+        slot: usize,
+        value: Option<usize>,
+    },
+    TruncateSlots(usize),
+    LeaveCall,
+    /// Undoes a return, with the slots the callee had.
+    ReenterCall(Frame, Slots),
+}
+
+/// Stands in `SearchContext::visited` where a repetition counter counted up:
+/// the states before it may be visited again at the same offset, as the
+/// count differs. The counter's bound still ends the loop.
+const COUNTED: usize = usize::MAX;
+
+/// Scratch space of the backtracking search. Instead of each path having its
+/// own copy of the captures, one path is followed at a time and the stack
+/// records how to undo what it changed, so once the buffers have grown to
+/// fit a pattern, searching with it allocates nothing but the slots of a
+/// match (and the captures saved by subroutine calls).
+#[derive(Debug, Default)]
+pub struct SearchContext {
+    stack: Vec<Job>,
+    // Capture slots of the path being followed
+    slots: Slots,
+    // (state, call depth, offset) of the states the path went through; a
+    // path reaching one again at the same offset is going round in a loop
+    visited: Vec<(usize, usize, usize)>,
+    calls: Vec<Frame>,
+    memo: Memo,
+    // Whether `memo` applies to the current search
+    memoize: bool,
+    // Paths followed by the current search, see `RegexOptions::step_limit`
+    steps: usize,
+}
+
+/// The (state, offset) pairs a search has already explored, one bit each.
+///
+/// Without backreferences, conditionals or calls, whether the rest of a path
+/// can match depends only on its state and offset, not on how it got there.
+/// The first path to reach a pair is also the one the search prefers, so any
+/// later path reaching it can be dropped: if the first one failed, so will
+/// it. That caps a search at states × offsets steps instead of exponentially
+/// many, e.g. for `(a|a)*b` on a run of `a`s.
+///
+/// Counted repetitions looping on a counter also depend on the counts, so
+/// with those the memo keeps the (state, offset, counts) triples explored
+/// instead, which still caps a search at states × offsets × the possible
+/// counts. As those can run into the hundreds of thousands, the bits then
+/// come in pages, allocated as the search reaches them.
+#[derive(Debug, Default)]
+struct Memo {
+    bits: Vec<u64>,
+    // First offset covered, and how many follow it
+    base: usize,
+    width: usize,
+    // The counter slots of the engine with the most they count to, and where
+    // in `bits` each page reached starts, if it has any, the last one apart
+    counters: Vec<(usize, usize)>,
+    pages: BTreeMap<usize, usize>,
+    last_page: Option<(usize, usize)>,
+}
+
+impl Memo {
+    /// Clears the memo for searching `input` from offset `from` on. Returns
+    /// whether to use it: not if the engine depends on more than the state,
+    /// offset and counts or the memo would be too large.
+    fn reset(&mut self, engine: &Engine, input: &str, from: usize) -> bool {
+        let Some(counters) = memo_counters(engine) else {
+            return false;
+        };
+        let width = input.len() - from + 1;
+        let Some(len) = engine.states.len().checked_mul(width) else {
+            return false;
+        };
+        self.base = from;
+        self.width = width;
+        self.counters = counters;
+        if !self.counters.is_empty() {
+            // Every triple needs a number of its own
+            let triples = self
+                .counters
+                .iter()
+                .try_fold(len, |len, (_, most)| len.checked_mul(most.checked_add(1)?));
+            self.bits.clear();
+            self.pages.clear();
+            self.last_page = None;
+            return triples.is_some();
+        }
+        if len > MAX_MEMO_BITS {
+            return false;
+        }
+        self.bits.clear();
+        self.bits.resize(len.div_ceil(64), 0);
+        true
+    }
+
+    /// Marks the pair, with the counts in `slots`, as explored, returning
+    /// whether it already was. Once the pages reached add up to the largest
+    /// memo, new ones count as never explored.
+    fn visit(&mut self, state: usize, index: usize, slots: &[Option<usize>]) -> bool {
+        let mut bit = state * self.width + index - self.base;
+        let mut word = bit / 64;
+        if !self.counters.is_empty() {
+            bit = self.counters.iter().fold(bit, |bit, &(slot, most)| {
+                bit * (most + 1) + count(slots, slot)
+            });
+            let page = bit / (64 * MEMO_PAGE_WORDS);
+            let start = match self.last_page {
+                Some((last, start)) if last == page => start,
+                _ => match self.pages.get(&page) {
+                    Some(&start) => start,
+                    None if self.bits.len() * 64 >= MAX_MEMO_BITS => return false,
+                    None => {
+                        let start = self.bits.len();
+                        self.bits.resize(start + MEMO_PAGE_WORDS, 0);
+                        self.pages.insert(page, start);
+                        start
+                    }
+                },
+            };
+            self.last_page = Some((page, start));
+            word = start + bit / 64 % MEMO_PAGE_WORDS;
+        }
+        let mask = 1 << (bit % 64);
+        let visited = self.bits[word] & mask != 0;
+        self.bits[word] |= mask;
+        visited
+    }
+}
+
+impl SearchContext {
+    /// Moves the path being followed on to `state`, entered at `index` the
+    /// way `entry` says.
+    fn enter(&mut self, state: usize, index: usize, visited: usize, entry: Entry) {
+        self.visited.truncate(visited);
+        match entry {
+            Entry::Consume => return,
+            Entry::Epsilon => {}
+            Entry::Capture(slot) => self.set_slot(slot, index),
+            Entry::Count { slot, value } => {
+                self.set_slot(slot, value);
+                if value > 0 {
+                    self.visited.push((COUNTED, 0, index));
+                }
+            }
+            Entry::Call { end, continuation } => {
+                self.calls.push(Frame {
+                    end,
+                    continuation,
+                    saved: self.slots.clone(),
+                });
+                self.stack.push(Job::LeaveCall);
+            }
+        }
+        self.visited.push((state, self.calls.len(), index));
+    }
+
+    /// Sets `slot` of the path being followed, growing the slots if needed.
+    fn set_slot(&mut self, slot: usize, value: usize) {
+        if self.slots.len() <= slot {
+            self.stack.push(Job::TruncateSlots(self.slots.len()));
+            self.slots.resize(slot + 2, None);
+        }
+        self.stack.push(Job::RestoreSlot {
+            slot,
+            value: self.slots[slot],
+        });
+        self.slots[slot] = Some(value);
+    }
+
+    /// Queues a path moving on to `state` without consuming input, unless
+    /// that would loop.
+    fn push_epsilon(&mut self, state: usize, index: usize, entry: Entry) {
+        let depth = self.calls.len() + usize::from(matches!(entry, Entry::Call { .. }));
+        // Going round again with a higher count is no loop
+        let counts_up = matches!(entry, Entry::Count { value, .. } if value > 0);
+        let looped = !counts_up
+            && self
+                .visited
+                .iter()
+                .rev()
+                .take_while(|visit| visit.2 == index && visit.0 != COUNTED)
+                .any(|visit| visit.0 == state && visit.1 == depth);
+        if !looped {
+            self.stack.push(Job::Explore {
+                state,
+                index,
+                visited: self.visited.len(),
+                entry,
+            });
+        }
+    }
 }
 
 impl Engine {
@@ -13,9 +287,17 @@ impl Engine {
             states: Vec::new(),
             start_state: 0,
             end_state: 0,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            step_limit: None,
+            multi_line: false,
         }
     }
 
+    /// Roughly the memory the NFA takes, in bytes.
+    pub fn approximate_size(&self) -> usize {
+        states_size(&self.states)
+    }
+
     pub fn add_states(&mut self, state: Vec<State>) {
         self.states.extend(state);
     }
@@ -29,73 +311,331 @@ impl Engine {
     }
 
     pub fn add_transition(&mut self, from_state: usize, matcher: Matcher, to_state: usize) {
-        if let Some(state) = self.states.iter_mut().find(|s| s.id == from_state) {
-            state.add_transition(matcher, to_state);
-        }
+        self.states[from_state].add_transition(matcher, to_state);
+    }
+
+    /// Whether a `Matcher::LineStart` matches at byte offset `index`: at the
+    /// start of `input`, or in multi-line mode also after a `\n`.
+    pub(super) fn at_line_start(&self, input: &str, index: usize) -> bool {
+        index == 0 || (self.multi_line && is_line_start(input, index))
     }
 
-    pub fn compute(&self, input: &str) -> i32 {
-        let mut stack: Vec<(usize, usize, Vec<usize>)> = vec![];
-        stack.push((self.start_state, 0, Vec::new()));
+    /// Whether a `Matcher::LineEnd` matches at byte offset `index`: at the
+    /// end of `input` or right before a final line terminator, or in
+    /// multi-line mode before any line terminator.
+    pub(super) fn at_line_end(&self, input: &str, index: usize) -> bool {
+        index + trailing_terminator_len(input) == input.len()
+            || index == input.len()
+            || (self.multi_line && is_line_end(input, index))
+    }
 
-        while stack.len() > 0 {
-            let (current_state_id, input_index, memory) = stack.pop().unwrap();
-            if current_state_id == self.end_state {
-                return input_index as i32;
-            }
+    /// Backtracking search from offset `start` of `input`, accepting only a
+    /// match whose end offset satisfies `accept`; paths reaching the end state
+    /// elsewhere are abandoned and the search backtracks into the remaining
+    /// alternatives. Returns the end offset of the first accepted match along
+    /// with the capture slots of the path that produced it: slot `2n` holds
+    /// the start and `2n + 1` the end of group n. `\G` only matches at offset
+    /// `search_start`, or nowhere if it is `None`. All offsets are bytes into
+    /// the whole input, which is decoded as the search walks it, one char per
+    /// step. The memo and step count in `ctx` are shared with other searches
+    /// of the same input, see `try_find`.
+    fn compute<F>(
+        &self,
+        ctx: &mut SearchContext,
+        input: &str,
+        start: usize,
+        search_start: Option<usize>,
+        accept: &F,
+    ) -> Result<Option<(usize, Slots)>, MatchError>
+    where
+        F: Fn(usize) -> bool,
+    {
+        ctx.stack.clear();
+        ctx.slots.clear();
+        ctx.visited.clear();
+        ctx.calls.clear();
+        ctx.stack.push(Job::Explore {
+            state: self.start_state,
+            index: start,
+            visited: 0,
+            entry: Entry::Consume,
+        });
 
-            // Make sure we only make epsilon transitions if we are out of bounds
-            if input_index >= input.chars().count() {
-                if let Some(state) = self.states.iter().find(|s| s.id == current_state_id) {
-                    for (_, next_state_id) in state
-                        .transitions
-                        .iter()
-                        .rev()
-                        .filter(|(m, _)| m.is_epsilon())
-                    {
-                        if memory.contains(&next_state_id) {
-                            continue; // Avoid cycles
-                        }
-                        let mut memory = memory.clone();
-                        memory.push(next_state_id.clone());
-                        stack.push((next_state_id.clone(), input_index, memory.clone()));
+        while let Some(job) = ctx.stack.pop() {
+            let (state_id, index) = match job {
+                Job::Explore {
+                    state,
+                    index,
+                    visited,
+                    entry,
+                } => {
+                    ctx.steps += 1;
+                    if let Some(limit) = self.step_limit.filter(|&limit| ctx.steps > limit) {
+                        return Err(MatchError::StepLimitExceeded { limit });
+                    }
+                    // Entered first, so that the memo sees the count it sets
+                    ctx.enter(state, index, visited, entry);
+                    if ctx.memoize && ctx.memo.visit(state, index, &ctx.slots) {
+                        continue;
                     }
+                    (state, index)
+                }
+                Job::RestoreSlot { slot, value } => {
+                    ctx.slots[slot] = value;
+                    continue;
+                }
+                Job::TruncateSlots(len) => {
+                    ctx.slots.truncate(len);
+                    continue;
+                }
+                Job::LeaveCall => {
+                    ctx.calls.pop();
+                    continue;
+                }
+                Job::ReenterCall(frame, callee) => {
+                    ctx.calls.push(frame);
+                    ctx.slots = callee;
+                    continue;
                 }
+            };
+
+            if let Some(frame) = ctx.calls.last() {
+                if state_id == frame.end {
+                    // Return from the call, dropping the captures it made
+                    let frame = ctx.calls.pop().unwrap();
+                    let callee = core::mem::replace(&mut ctx.slots, frame.saved.clone());
+                    let continuation = frame.continuation;
+                    ctx.stack.push(Job::ReenterCall(frame, callee));
+                    ctx.push_epsilon(continuation, index, Entry::Epsilon);
+                    continue;
+                }
+            } else if state_id == self.end_state {
+                if accept(index) {
+                    return Ok(Some((index, ctx.slots.clone())));
+                }
+                trace::emit(|| TraceEvent::Backtrack {
+                    state: state_id,
+                    offset: index,
+                });
                 continue;
             }
 
-            let input_char = input[input
-                .char_indices()
-                .nth(input_index)
-                .map(|(i, _)| i)
-                .unwrap()..]
-                .chars()
-                .next()
-                .unwrap();
-            if let Some(state) = self.states.iter().find(|s| s.id == current_state_id) {
-                for (matcher, next_state_id) in state
-                    .transitions
-                    .iter()
-                    .rev()
-                    .filter(|(m, _)| m.matches(input_char))
-                {
-                    if matcher.is_epsilon() {
-                        if memory.contains(&next_state_id) {
-                            continue; // Avoid cycles
+            let alternatives = ctx.stack.len();
+            let state = &self.states[state_id];
+            for (matcher, next_state_id) in &state.transitions {
+                let consumed = match matcher {
+                    _ if matcher.is_epsilon() => 0,
+                    Matcher::IfGroup(group, set) => {
+                        if group_participated(&ctx.slots, *group) == *set {
+                            0
+                        } else {
+                            continue;
                         }
-                        let mut memory = memory.clone();
-                        memory.push(next_state_id.clone());
-                        stack.push((next_state_id.clone(), input_index, memory.clone()));
-                    } else {
-                        if input_index + 1 <= input.chars().count() {
-                            stack.push((next_state_id.clone(), input_index + 1, Vec::new()));
+                    }
+                    Matcher::SearchStart => {
+                        if search_start == Some(index) {
+                            0
+                        } else {
+                            continue;
                         }
                     }
+                    Matcher::LineStart => {
+                        if self.at_line_start(input, index) {
+                            0
+                        } else {
+                            continue;
+                        }
+                    }
+                    Matcher::LineEnd => {
+                        if self.at_line_end(input, index) {
+                            0
+                        } else {
+                            continue;
+                        }
+                    }
+                    Matcher::WordStart { .. }
+                    | Matcher::WordEnd { .. }
+                    | Matcher::WordBoundary { .. } => {
+                        if at_word_edge(input, index, matcher) {
+                            0
+                        } else {
+                            continue;
+                        }
+                    }
+                    Matcher::CountReset(_) | Matcher::CountUp(_) => 0,
+                    Matcher::IfCount { slot, min, max } => {
+                        if (*min..=*max).contains(&count(&ctx.slots, *slot)) {
+                            0
+                        } else {
+                            continue;
+                        }
+                    }
+                    Matcher::Grapheme => match grapheme_len(input, index) {
+                        Some(len) => len,
+                        None => continue,
+                    },
+                    Matcher::Linebreak => match linebreak_len(input, index) {
+                        Some(len) => len,
+                        None => continue,
+                    },
+                    Matcher::Literal(literal) => match literal_len(input, index, literal) {
+                        Some(len) => len,
+                        None => continue,
+                    },
+                    Matcher::Backref(group) => {
+                        match captured_len(input, &ctx.slots, *group, index) {
+                            Some(len) => len,
+                            None => continue,
+                        }
+                    }
+                    Matcher::Call(group) => {
+                        if ctx.calls.len() < self.recursion_limit {
+                            if let Some((start, end)) = self.group_bounds(*group) {
+                                let continuation = *next_state_id;
+                                ctx.push_epsilon(start, index, Entry::Call { end, continuation });
+                            }
+                        }
+                        continue;
+                    }
+                    _ => match input[index..].chars().next() {
+                        Some(c) if matcher.matches(c) => c.len_utf8(),
+                        _ => continue,
+                    },
+                };
+
+                if consumed == 0 {
+                    let entry = match matcher {
+                        Matcher::CountReset(slot) => Entry::Count {
+                            slot: *slot,
+                            value: 0,
+                        },
+                        Matcher::CountUp(slot) => Entry::Count {
+                            slot: *slot,
+                            value: count(&ctx.slots, *slot) + 1,
+                        },
+                        _ => match capture_slot(matcher) {
+                            Some(slot) => Entry::Capture(slot),
+                            None => Entry::Epsilon,
+                        },
+                    };
+                    ctx.push_epsilon(*next_state_id, index, entry);
+                } else {
+                    ctx.stack.push(Job::Explore {
+                        state: *next_state_id,
+                        index: index + consumed,
+                        visited: ctx.visited.len(),
+                        entry: Entry::Consume,
+                    });
                 }
             }
+            // Pushed in priority order; reversed, the preferred one is
+            // followed first and the others are backtracked into in order
+            ctx.stack[alternatives..].reverse();
+            match ctx.stack[alternatives..].last() {
+                Some(&Job::Explore { state: to, .. }) => trace::emit(|| TraceEvent::Transition {
+                    from: state_id,
+                    to,
+                    offset: index,
+                }),
+                _ => trace::emit(|| TraceEvent::Backtrack {
+                    state: state_id,
+                    offset: index,
+                }),
+            }
         }
 
-        return -1;
+        Ok(None)
+    }
+
+    /// The leftmost match starting at offset `from` or later, as its start
+    /// and end offsets and capture slots, see `compute`. If `anchored`, only
+    /// a match starting right at `from` is tried, so a failed search costs a
+    /// single attempt. Otherwise the attempts move forward a char at a time,
+    /// or from one candidate of `prefilter` to the next. `\G` matches at
+    /// `from`. A search going over `step_limit` finds nothing.
+    pub fn find<F>(
+        &self,
+        input: &str,
+        from: usize,
+        anchored: bool,
+        prefilter: Option<&Prefilter>,
+        accept: F,
+    ) -> Option<(usize, usize, Slots)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut ctx = SearchContext::default();
+        self.try_find(&mut ctx, input, from, anchored, prefilter, accept)
+            .unwrap_or(None)
+    }
+
+    /// Like `find`, but a search going over `step_limit` is an error, and
+    /// the search runs in `ctx`, left over from earlier searches.
+    pub fn try_find<F>(
+        &self,
+        ctx: &mut SearchContext,
+        input: &str,
+        from: usize,
+        anchored: bool,
+        prefilter: Option<&Prefilter>,
+        accept: F,
+    ) -> Result<Option<(usize, usize, Slots)>, MatchError>
+    where
+        F: Fn(usize) -> bool,
+    {
+        // Shared by all attempts: a pair that failed once fails from any start
+        ctx.memoize = ctx.memo.reset(self, input, from);
+        ctx.steps = 0;
+        if anchored {
+            if prefilter.is_some_and(|prefilter| !prefilter.is_candidate(input, from)) {
+                return Ok(None);
+            }
+            let found = self.compute(ctx, input, from, Some(from), &accept)?;
+            return Ok(found.map(|(end, slots)| (from, end, slots)));
+        }
+
+        let mut start = from;
+        loop {
+            if let Some(prefilter) = prefilter {
+                match prefilter.find(input, start) {
+                    Some(candidate) => start = candidate,
+                    None => return Ok(None),
+                }
+            }
+            if let Some((end, slots)) = self.compute(ctx, input, start, Some(from), &accept)? {
+                return Ok(Some((start, end, slots)));
+            }
+            // The empty match at the very end was the last one to try
+            match input[start..].chars().next() {
+                Some(c) => start += c.len_utf8(),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The states just outside group `group`: the one that records its start
+    /// and the one reached after recording its end. Group 0 is the whole
+    /// pattern.
+    fn group_bounds(&self, group: usize) -> Option<(usize, usize)> {
+        if group == 0 {
+            return Some((self.start_state, self.end_state));
+        }
+        let mut start = None;
+        let mut end = None;
+        for state in &self.states {
+            for (matcher, next_state_id) in &state.transitions {
+                match matcher {
+                    Matcher::GroupStart(g) if *g == group && start.is_none() => {
+                        start = Some(state.id)
+                    }
+                    Matcher::GroupEnd(g) if *g == group && end.is_none() => {
+                        end = Some(*next_state_id)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some((start?, end?))
     }
 
     pub fn shift_ids(&mut self, shift: usize) {
@@ -104,12 +644,220 @@ impl Engine {
         }
         self.start_state += shift;
         self.end_state += shift;
+    }
 
-        #[cfg(debug_assertions)]
-        {
-            println!("Shifted start state to {}", self.start_state);
-            println!("Shifted end state to {}", self.end_state);
-            println!("Shifted states are {:?}", self.states);
+    /// The NFA with every literal split into a chain of single-char
+    /// transitions, for the engines stepping through the input a char at a
+    /// time. The new states come after the existing ones, which keep their
+    /// ids.
+    pub fn split_literals(&self) -> Cow<'_, Engine> {
+        let has_literals = self
+            .states
+            .iter()
+            .flat_map(|state| &state.transitions)
+            .any(|(matcher, _)| matches!(matcher, Matcher::Literal(_)));
+        if !has_literals {
+            return Cow::Borrowed(self);
         }
+        let mut engine = self.clone();
+        for state in &self.states {
+            for (transition, (matcher, next)) in state.transitions.iter().enumerate() {
+                let Matcher::Literal(literal) = matcher else {
+                    continue;
+                };
+                let chars: Vec<char> = literal.chars().collect();
+                // Built back to front, each new state leading to the last one
+                let mut target = *next;
+                for c in chars[1..].iter().rev() {
+                    let id = engine.states.len();
+                    engine.add_states(vec![State::new(id)]);
+                    engine.add_transition(id, Matcher::create_simple_matcher(c), target);
+                    target = id;
+                }
+                engine.states[state.id].transitions[transition] =
+                    (Matcher::create_simple_matcher(&chars[0]), target);
+            }
+        }
+        Cow::Owned(engine)
+    }
+}
+
+/// Roughly the memory `states` take, in bytes.
+pub(super) fn states_size(states: &[State]) -> usize {
+    let transitions = states.iter().flat_map(|state| &state.transitions);
+    let heap: usize = transitions
+        .clone()
+        .map(|(matcher, _)| match matcher {
+            Matcher::Range(ranges, _) => ranges.len() * size_of::<(char, char)>(),
+            Matcher::Literal(literal) => literal.len(),
+            _ => 0,
+        })
+        .sum();
+    size_of_val(states) + transitions.count() * size_of::<(Matcher, usize)>() + heap
+}
+
+/// The slot a `GroupStart` or `GroupEnd` transition records its offset in.
+fn capture_slot(matcher: &Matcher) -> Option<usize> {
+    match matcher {
+        Matcher::GroupStart(group) => Some(2 * group),
+        Matcher::GroupEnd(group) => Some(2 * group + 1),
+        _ => None,
+    }
+}
+
+pub(super) fn record_capture(slots: &mut Slots, matcher: &Matcher, index: usize) {
+    let Some(slot) = capture_slot(matcher) else {
+        return;
+    };
+    if slots.len() <= slot {
+        slots.resize(slot + 2, None);
+    }
+    slots[slot] = Some(index);
+}
+
+/// The counter slots of `engine` with the most each counts to, which the
+/// memo tells paths apart by, or `None` if paths depend on more than their
+/// state, offset and counts.
+fn memo_counters(engine: &Engine) -> Option<Vec<(usize, usize)>> {
+    let mut counters: Vec<(usize, usize)> = Vec::new();
+    for (matcher, _) in engine.states.iter().flat_map(|state| &state.transitions) {
+        match matcher {
+            Matcher::Backref(_) | Matcher::Call(_) | Matcher::IfGroup(..) => return None,
+            Matcher::IfCount { slot, max, .. } => {
+                match counters.iter_mut().find(|(counter, _)| counter == slot) {
+                    Some((_, most)) => *most = (*most).max(*max),
+                    None => counters.push((*slot, *max)),
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(counters)
+}
+
+/// The value of the repetition counter in `slot`.
+fn count(slots: &[Option<usize>], slot: usize) -> usize {
+    slots.get(slot).copied().flatten().unwrap_or(0)
+}
+
+fn group_participated(slots: &[Option<usize>], group: usize) -> bool {
+    matches!(
+        (slots.get(2 * group), slots.get(2 * group + 1)),
+        (Some(Some(_)), Some(Some(_)))
+    )
+}
+
+/// Length of the text captured by `group` if it also appears at `index`.
+/// A group that hasn't participated in the match never matches.
+fn captured_len(input: &str, slots: &[Option<usize>], group: usize, index: usize) -> Option<usize> {
+    let start = (*slots.get(2 * group)?)?;
+    let end = (*slots.get(2 * group + 1)?)?;
+    let captured = input.get(start..end)?;
+    input[index..]
+        .starts_with(captured)
+        .then_some(captured.len())
+}
+
+/// Length in bytes of the extended grapheme cluster starting at `index`.
+pub(super) fn grapheme_len(input: &str, index: usize) -> Option<usize> {
+    input[index..].graphemes(true).next().map(str::len)
+}
+
+/// Length in bytes of the line ending starting at `index`. `\r\n` is taken
+/// as a whole and never split into two line endings.
+pub(super) fn linebreak_len(input: &str, index: usize) -> Option<usize> {
+    let rest = &input[index..];
+    if rest.starts_with("\r\n") {
+        return Some(2);
+    }
+    match rest.chars().next()? {
+        c @ ('\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}') => {
+            Some(c.len_utf8())
+        }
+        _ => None,
+    }
+}
+
+/// Whether a `Matcher::WordStart`, `Matcher::WordEnd` or
+/// `Matcher::WordBoundary` matches at byte offset `index`: no word char
+/// comes right before it, none comes right after it, or there is a word char
+/// on exactly one side of it (on both or neither for `\B`).
+pub(super) fn at_word_edge(input: &str, index: usize, matcher: &Matcher) -> bool {
+    let is_word = |c: Option<char>, ascii| c.is_some_and(|c| Matcher::is_word_char(c, ascii));
+    let before = |ascii| is_word(input[..index].chars().next_back(), ascii);
+    let after = |ascii| is_word(input[index..].chars().next(), ascii);
+    match *matcher {
+        Matcher::WordStart { ascii } => !before(ascii),
+        Matcher::WordEnd { ascii } => !after(ascii),
+        Matcher::WordBoundary { ascii, negated } => (before(ascii) != after(ascii)) != negated,
+        _ => false,
+    }
+}
+
+/// Length in bytes of `literal` if `input` goes on with it at `index`.
+pub(super) fn literal_len(input: &str, index: usize, literal: &str) -> Option<usize> {
+    input[index..].starts_with(literal).then_some(literal.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_memo() {
+        let mut memo = Memo {
+            bits: vec![0; 2],
+            base: 10,
+            width: 40,
+            ..Memo::default()
+        };
+        assert!(!memo.visit(0, 10, &[]));
+        assert!(memo.visit(0, 10, &[]));
+        assert!(!memo.visit(2, 49, &[]));
+        assert!(!memo.visit(1, 10, &[]));
+        assert!(memo.visit(2, 49, &[]));
+
+        let mut memo = Memo {
+            base: 10,
+            width: 40,
+            counters: vec![(2, 5)],
+            ..Memo::default()
+        };
+        assert!(!memo.visit(0, 10, &[None, None, Some(1)]));
+        assert!(!memo.visit(0, 10, &[None, None, Some(2)]));
+        assert!(memo.visit(0, 10, &[Some(0), Some(3), Some(1)]));
+    }
+
+    #[test]
+    fn test_grapheme_len() {
+        let input = "e\u{301}x👨\u{200D}👩\u{200D}👧";
+        assert_eq!(grapheme_len(input, 0), Some(3));
+        assert_eq!(grapheme_len(input, 3), Some(1));
+        assert_eq!(grapheme_len(input, 4), Some(18));
+        assert_eq!(grapheme_len(input, 22), None);
+
+        let input = format!("a{}b", "\u{301}".repeat(40));
+        assert_eq!(grapheme_len(&input, 0), Some(81));
+    }
+
+    #[test]
+    fn test_linebreak_len() {
+        let input = "a\r\n\n\r\u{2028}";
+        assert_eq!(linebreak_len(input, 0), None);
+        assert_eq!(linebreak_len(input, 1), Some(2));
+        assert_eq!(linebreak_len(input, 3), Some(1));
+        assert_eq!(linebreak_len(input, 4), Some(1));
+        assert_eq!(linebreak_len(input, 5), Some(3));
+        assert_eq!(linebreak_len(input, 8), None);
+    }
+
+    #[test]
+    fn test_captured_len() {
+        let slots = vec![None, None, Some(0), Some(3)];
+        assert_eq!(captured_len("éa éa", &slots, 1, 4), Some(3));
+        assert_eq!(captured_len("éa éb", &slots, 1, 4), None);
+        assert_eq!(captured_len("éa é", &slots, 1, 4), None);
+        assert_eq!(captured_len("éa éa", &slots, 2, 4), None);
     }
 }
@@ -0,0 +1,89 @@
+use alloc::string::String;
+
+use thiserror::Error;
+
+/// A pattern that can't be parsed. Positions are char indices into it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SyntaxError {
+    #[error("nothing to repeat at position {0}")]
+    NothingToRepeat(usize),
+    #[error("unmatched ] at position {0}")]
+    UnmatchedBracket(usize),
+    #[error("unclosed character class at position {0}")]
+    UnclosedClass(usize),
+    /// `[]` or `[^]`, at any depth of a class.
+    #[error("empty character class at position {0}")]
+    EmptyClass(usize),
+    /// A range like `z-a`, in the class starting at `position`.
+    #[error("invalid range {range} in the character class at position {position}")]
+    InvalidClassRange { range: String, position: usize },
+    /// `\x` not followed by two hex digits or `{hex}` naming a char.
+    #[error("invalid escape at position {0}")]
+    InvalidEscape(usize),
+    /// `[:name:]` with a name that isn't one of the ASCII classes.
+    #[error("unknown class name {name:?} in the character class at position {position}")]
+    UnknownClassName { name: String, position: usize },
+    /// A backslash before a letter or digit that isn't one of the escapes,
+    /// like `\p{L}` or `\z`.
+    #[error("unsupported escape \\{escape} at position {position}")]
+    UnsupportedEscape { escape: char, position: usize },
+    #[error("trailing backslash at position {0}")]
+    TrailingBackslash(usize),
+    #[error("expected <name> after \\k at position {0}")]
+    ExpectedGroupName(usize),
+    #[error("invalid group name {name:?} at position {position}")]
+    InvalidGroupName { name: String, position: usize },
+    #[error("unknown group name {name:?} at position {position}")]
+    UnknownGroupName { name: String, position: usize },
+    /// `(?-n)` reaching back past the first group.
+    #[error("invalid relative group reference at position {0}")]
+    InvalidGroupReference(usize),
+    /// A backreference, subroutine call or condition on a group number the
+    /// pattern doesn't have, like `\2` or `(?2)` with only one group.
+    #[error("reference to nonexistent group {group} at position {position}")]
+    UnknownGroup { group: usize, position: usize },
+    #[error("unclosed group name at position {0}")]
+    UnclosedGroupName(usize),
+    #[error("unclosed condition at position {0}")]
+    UnclosedCondition(usize),
+    #[error("conditional with more than two branches at position {0}")]
+    TooManyBranches(usize),
+    /// `{n,m}` with `m` less than `n`.
+    #[error("invalid repetition {{{bounds}}} at position {position}")]
+    InvalidRepetition { bounds: String, position: usize },
+    #[error("unknown flag {flag:?} at position {position}")]
+    UnknownFlag { flag: char, position: usize },
+    #[error("unclosed flag group at position {0}")]
+    UnclosedFlags(usize),
+}
+
+/// A pattern that can't be turned into an NFA.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CompileError {
+    #[error(transparent)]
+    Syntax(#[from] SyntaxError),
+    /// Parsed, but not something an NFA can be built from, like the
+    /// unclosed group in `(a`, or not one the backend asked for can run.
+    #[error("unsupported pattern: {0}")]
+    Unsupported(String),
+    #[error("pattern too large: the NFA would exceed the size limit of {limit} bytes")]
+    TooLarge { limit: usize },
+}
+
+/// A search that was given up on.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MatchError {
+    #[error("search aborted: it took more than the limit of {limit} steps")]
+    StepLimitExceeded { limit: usize },
+}
+
+/// Bytes `Dfa::from_bytes` can't load.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DfaError {
+    #[error("not a serialized DFA")]
+    NotADfa,
+    #[error("unsupported DFA format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("corrupt DFA data")]
+    Corrupt,
+}
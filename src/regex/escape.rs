@@ -0,0 +1,56 @@
+//! `escape`, for putting literal text into a pattern.
+
+use alloc::string::String;
+
+/// `text` as a pattern matching exactly that text: every char with a meaning
+/// in patterns gets a backslash, and line breaks and tabs are written as
+/// `\n`, `\t` and the like. A user's search term can then be put into a
+/// larger pattern, as in `format!(r"^{}:\s*\d+$", escape(key))`.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_literal(&mut escaped, c);
+    }
+    escaped
+}
+
+/// Writes `c` so that it matches itself, escaping it if it has a meaning in
+/// patterns.
+pub(super) fn push_literal(out: &mut String, c: char) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\u{000C}' => out.push_str("\\f"),
+        '\u{000B}' => out.push_str("\\v"),
+        '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}' => {
+            out.push('\\');
+            out.push(c);
+        }
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use alloc::format;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("1.5*(x+y)"), r"1\.5\*\(x\+y\)");
+        assert_eq!(escape("a\tb\n"), r"a\tb\n");
+        assert_eq!(escape("plain text, é"), "plain text, é");
+    }
+
+    #[test]
+    fn test_escaped_text_matches_itself() {
+        let text = r"^[a-z]{2,}$ | (?i) \d \1 \k<n> ?*+ a\b.";
+        let regex = RegexNFA::new(escape(text));
+        let haystack = format!("before {} after", text);
+        let found = regex.find(&haystack).unwrap();
+        assert_eq!(found.as_str(), text);
+        assert!(!regex.matches("ab"));
+    }
+}
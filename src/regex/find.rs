@@ -0,0 +1,272 @@
+//! Matches as values: `RegexNFA::find`, `find_at` and the iterator over
+//! every match, plus `shortest_match` for when only where some match ends
+//! is needed.
+//!
+//! Unlike the span searches, which report char indices, a `Match` holds byte
+//! offsets into the haystack, so it can be sliced with directly and the text
+//! it covers is at hand without counting chars.
+
+use core::ops::Range;
+
+use crate::regex::engine::Slots;
+use crate::regex::nfa_regex::RegexNFA;
+
+/// Where a match is in the haystack it was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    pub(super) fn new(haystack: &'h str, start: usize, end: usize) -> Self {
+        Match {
+            haystack,
+            start,
+            end,
+        }
+    }
+
+    /// Byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The byte range of the match, to slice the haystack with.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The matched text.
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.range()]
+    }
+}
+
+/// The successive non-overlapping matches in a haystack, see
+/// `RegexNFA::find_iter`.
+pub struct Matches<'r, 'h> {
+    regex: &'r RegexNFA,
+    haystack: &'h str,
+    cursor: ByteCursor,
+}
+
+impl<'h> Iterator for Matches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let (start, end, _) = self.cursor.next(self.regex, self.haystack)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+}
+
+/// How far an iteration over successive matches got, like `Cursor` but in
+/// byte offsets.
+#[derive(Debug, Default)]
+pub(super) struct ByteCursor {
+    // Byte offset the next search starts at
+    at: usize,
+    // Byte offset where the previous match ended
+    last_end: Option<usize>,
+}
+
+impl ByteCursor {
+    /// The next match of `regex` in `haystack`, skipping an empty match
+    /// right where the previous one ended.
+    pub(super) fn next(
+        &mut self,
+        regex: &RegexNFA,
+        haystack: &str,
+    ) -> Option<(usize, usize, Slots)> {
+        loop {
+            let (start, end, slots) = regex.search_at(haystack, self.at)?;
+            if start == end && self.last_end == Some(end) {
+                let c = haystack[start..].chars().next()?;
+                self.at = start + c.len_utf8();
+                continue;
+            }
+            self.at = end;
+            self.last_end = Some(end);
+            return Some((start, end, slots));
+        }
+    }
+}
+
+impl RegexNFA {
+    /// The leftmost match in `haystack`. A search going over the step limit
+    /// finds nothing.
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        let (start, end, _) = self.search_at(haystack, 0)?;
+        Some(Match::new(haystack, start, end))
+    }
+
+    /// Like `find`, but only considers matches starting at byte offset
+    /// `start` or later, to resume a scan where it left off. The text before
+    /// `start` still counts as context: `^` doesn't match at `start` unless
+    /// it is 0 (or in multi-line mode follows a `\n`), and `\G` matches only
+    /// at `start`. Panics if `start` isn't a char boundary of `haystack`.
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        check_boundary(haystack, start);
+        let (start, end, _) = self.search_at(haystack, start)?;
+        Some(Match::new(haystack, start, end))
+    }
+
+    /// Byte offset where a match in `haystack` ends, found as cheaply as
+    /// possible: the search stops at the first end it comes across rather
+    /// than working out where the leftmost match ends, so `a+` gives 1 on
+    /// `aaa` and `b|abc` gives 2 on `abc`. The end of the leftmost match for
+    /// patterns that need backtracking. Use it where only whether there is a
+    /// match matters and a position is still handy.
+    pub fn shortest_match(&self, haystack: &str) -> Option<usize> {
+        self.shortest_match_at(haystack, 0)
+    }
+
+    /// Like `shortest_match`, for matches starting at byte offset `start`
+    /// or later as with `find_at`.
+    pub fn shortest_match_at(&self, haystack: &str, start: usize) -> Option<usize> {
+        check_boundary(haystack, start);
+        if !self.regular {
+            return self.search_at(haystack, start).map(|(_, end, _)| end);
+        }
+        self.with_cache(|cache| self.earliest_end_at(cache, haystack, start))
+    }
+
+    /// Every match in `haystack`, left to right. Like `next_match`, matches
+    /// never overlap and an empty match right where the previous one ended
+    /// is skipped: `a*` finds `""`, `"aaa"` in `baaa`.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches {
+            regex: self,
+            haystack,
+            cursor: ByteCursor::default(),
+        }
+    }
+
+    /// The start and end byte offsets and the capture slots of the leftmost
+    /// match starting at byte offset `from` or later.
+    pub(super) fn search_at(&self, haystack: &str, from: usize) -> Option<(usize, usize, Slots)> {
+        self.with_cache(|cache| self.try_find_at_with(cache, haystack, from))
+            .ok()?
+    }
+}
+
+/// Panics unless a search of `haystack` can start at byte offset `start`.
+fn check_boundary(haystack: &str, start: usize) {
+    assert!(
+        haystack.is_char_boundary(start),
+        "search start {} is not a char boundary of a haystack of {} bytes",
+        start,
+        haystack.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    fn all<'h>(pattern: &str, haystack: &'h str) -> Vec<&'h str> {
+        let regex = RegexNFA::new(pattern.to_string());
+        regex.find_iter(haystack).map(|m| m.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let regex = RegexNFA::new("\\d+".to_string());
+        let found = regex.find("año 2024, día 7").unwrap();
+        assert_eq!((found.start(), found.end()), (5, 9));
+        assert_eq!(found.as_str(), "2024");
+        assert_eq!(&"año 2024, día 7"[found.range()], "2024");
+        assert_eq!(regex.find("no digits"), None);
+    }
+
+    #[test]
+    fn test_find_iter() {
+        assert_eq!(all("\\d+", "1 22 333"), ["1", "22", "333"]);
+        assert_eq!(all("a|ab", "abab"), ["a", "a"]);
+        assert_eq!(all("x", "abc"), Vec::<&str>::new());
+        let regex = RegexNFA::new("é+".to_string());
+        let spans: Vec<_> = regex.find_iter("éé-é").map(|m| m.range()).collect();
+        assert_eq!(spans, [0..4, 5..7]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches() {
+        assert_eq!(all("a*", "baaa"), ["", "aaa"]);
+        assert_eq!(all("a*", "ab"), ["a", ""]);
+        let regex = RegexNFA::new("x*".to_string());
+        let starts: Vec<usize> = regex.find_iter("éa").map(|m| m.start()).collect();
+        assert_eq!(starts, [0, 2, 3]);
+    }
+
+    #[test]
+    fn test_find_at() {
+        let regex = RegexNFA::new("\\d+".to_string());
+        let haystack = "1 22 é 333";
+        let found = regex.find_at(haystack, 1).unwrap();
+        assert_eq!((found.start(), found.as_str()), (2, "22"));
+        assert_eq!(regex.find_at(haystack, 3).unwrap().as_str(), "2");
+        assert_eq!(regex.find_at(haystack, 8).unwrap().as_str(), "333");
+        assert_eq!(regex.find_at(haystack, haystack.len()), None);
+
+        // The text before the start is context, not cut off
+        assert_eq!(RegexNFA::new("^a".to_string()).find_at("aa", 1), None);
+        let found = RegexNFA::new("\\Ga".to_string()).find_at("aba", 2);
+        assert_eq!(found.map(|m| m.range()), Some(2..3));
+        assert_eq!(RegexNFA::new("\\Ga".to_string()).find_at("aba", 1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a char boundary")]
+    fn test_find_at_inside_char() {
+        RegexNFA::new("a".to_string()).find_at("éa", 1);
+    }
+
+    #[test]
+    fn test_shortest_match() {
+        let shortest = |pattern: &str, haystack: &str| {
+            RegexNFA::new(pattern.to_string()).shortest_match(haystack)
+        };
+        assert_eq!(shortest("a+", "aaa"), Some(1));
+        assert_eq!(shortest("b|abc", "abc"), Some(2));
+        assert_eq!(shortest("\\d{3}", "ab 1234"), Some(6));
+        assert_eq!(shortest("a$", "aa"), Some(2));
+        assert_eq!(shortest("x", "aaa"), None);
+        // Backreferences report the leftmost match
+        assert_eq!(shortest("(a)\\1+", "aaaa"), Some(4));
+        // Whichever engine runs, the end is the same
+        for pattern in ["[Hh]ello", "hel+o", "(?:he|h)llo", "h.*o"] {
+            assert_eq!(shortest(pattern, "say hello"), Some(9), "{}", pattern);
+        }
+
+        let regex = RegexNFA::new("^a".to_string());
+        assert_eq!(regex.shortest_match_at("aa", 1), None);
+        assert_eq!(regex.shortest_match_at("aa", 0), Some(1));
+    }
+
+    #[test]
+    fn test_find_iter_anchors() {
+        assert_eq!(all("^a", "aaa"), ["a"]);
+        assert_eq!(all("\\Ga", "aab"), ["a", "a"]);
+        assert_eq!(all("a$", "aa\n"), ["a"]);
+    }
+
+    #[test]
+    fn test_find_iter_is_linear() {
+        // Each search reads back from the end of its leftmost match, not
+        // from the end of the haystack. Reading back from the end would take
+        // some 10^10 steps here, which no test run sits through
+        let haystack = "x ".repeat(100_000);
+        for pattern in ["x", "x+", "\\w+|y", "abcd|x"] {
+            let regex = RegexNFA::new(pattern.to_string());
+            assert_eq!(regex.find_iter(&haystack).count(), 100_000, "{}", pattern);
+        }
+    }
+}
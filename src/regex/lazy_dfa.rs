@@ -0,0 +1,258 @@
+//! A DFA built lazily from the NFA while searching.
+//!
+//! Each DFA state stands for the set of NFA states the Thompson simulation
+//! would track at that point. States and their transitions are only computed
+//! when the input first needs them and are cached afterwards, so on repeated
+//! searches (every line of a file) most steps become a single table lookup.
+//! The cache is bounded: when it fills up it is thrown away and rebuilt, and a
+//! search that keeps thrashing it gives up so the caller can fall back to the
+//! NFA simulation.
+//!
+//! Only NFAs made of plain char transitions are supported; anything that
+//! asserts positions or consumes several chars at once (`\G`, `\X`, `\R`) or
+//! depends on the path taken stays with the NFA engines.
+
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::Engine;
+use crate::regex::Map;
+
+/// How many DFA states are cached unless configured otherwise.
+pub const DEFAULT_DFA_CACHE_LIMIT: usize = 4096;
+
+/// How often a single search may flush the cache before giving up.
+const MAX_CACHE_CLEARS: usize = 8;
+
+#[derive(Debug)]
+struct DfaState {
+    // Sorted NFA state ids
+    nfa_states: Vec<usize>,
+    is_match: bool,
+    transitions: Map<char, usize>,
+}
+
+#[derive(Debug)]
+pub struct LazyDfa {
+    anchored: bool,
+    end_state: usize,
+    cache_limit: usize,
+    states: Vec<DfaState>,
+    ids: Map<Vec<usize>, usize>,
+}
+
+impl LazyDfa {
+    /// Whether the NFA only uses transitions the DFA can follow.
+    pub fn supports(engine: &Engine) -> bool {
+        engine
+            .states
+            .iter()
+            .flat_map(|state| &state.transitions)
+            .all(|(matcher, _)| {
+                matcher.is_epsilon() || matches!(matcher, Matcher::Range(..) | Matcher::Any { .. })
+            })
+    }
+
+    /// A DFA for `engine`, caching at most `cache_limit` states. Unless
+    /// `anchored`, matches may start anywhere in the input.
+    pub fn new(engine: &Engine, anchored: bool, cache_limit: usize) -> Self {
+        LazyDfa {
+            anchored,
+            end_state: engine.end_state,
+            cache_limit: cache_limit.max(2),
+            states: Vec::new(),
+            ids: Map::new(),
+        }
+    }
+
+    /// Whether the NFA matches `input` ending at a byte offset accepted by
+    /// `accept`. `None` if the cache thrashed and the search was abandoned.
+    pub fn is_match<F>(&mut self, engine: &Engine, input: &str, accept: F) -> Option<bool>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut clears = 0;
+        let start = engine.dfa_closure(&[engine.start_state]);
+        let mut current = self.insert(start);
+
+        let mut index = 0;
+        for c in input.chars() {
+            let state = &self.states[current];
+            if state.is_match && accept(index) {
+                return Some(true);
+            }
+            if self.anchored && state.nfa_states.is_empty() {
+                return Some(false);
+            }
+            current = self.advance(engine, current, c, &mut clears)?;
+            index += c.len_utf8();
+        }
+
+        Some(self.states[current].is_match && accept(index))
+    }
+
+    /// Byte offset where the last match of `input` ends among those starting
+    /// no later than where the first match to end ends, or `Some(None)` if
+    /// there is no match. `None` if the cache thrashed. The leftmost match
+    /// is one of these, so a reverse search for where it starts can stop
+    /// reading there instead of at the end of the input.
+    pub fn match_extent(&mut self, engine: &Engine, input: &str) -> Option<Option<usize>> {
+        let mut clears = 0;
+        let start = engine.dfa_closure(&[engine.start_state]);
+        let mut current = self.insert(start);
+
+        let mut index = 0;
+        let mut chars = input.chars();
+        while !self.states[current].is_match {
+            let Some(c) = chars.next() else {
+                return Some(None);
+            };
+            current = self.advance(engine, current, c, &mut clears)?;
+            index += c.len_utf8();
+        }
+
+        // Only the attempts already started can go on; those states aren't
+        // cached, as the DFA's transitions start new attempts
+        let mut last = index;
+        let mut nfa_states = self.states[current].nfa_states.clone();
+        for c in chars {
+            nfa_states = self.step_states(engine, &nfa_states, c, false);
+            if nfa_states.is_empty() {
+                break;
+            }
+            index += c.len_utf8();
+            if nfa_states.binary_search(&self.end_state).is_ok() {
+                last = index;
+            }
+        }
+        Some(Some(last))
+    }
+
+    /// Reads `input` backwards from its end and returns the smallest byte
+    /// offset at which the NFA, meant to be a reversed one, is in its end
+    /// state. `Some(None)` if it never is, `None` if the cache thrashed.
+    pub fn rfind(&mut self, engine: &Engine, input: &str) -> Option<Option<usize>> {
+        let mut clears = 0;
+        let start = engine.dfa_closure(&[engine.start_state]);
+        let mut current = self.insert(start);
+
+        let mut index = input.len();
+        let mut found = None;
+        for c in input.chars().rev() {
+            let state = &self.states[current];
+            if state.is_match {
+                found = Some(index);
+            }
+            if self.anchored && state.nfa_states.is_empty() {
+                return Some(found);
+            }
+            current = self.advance(engine, current, c, &mut clears)?;
+            index -= c.len_utf8();
+        }
+
+        if self.states[current].is_match {
+            found = Some(index);
+        }
+        Some(found)
+    }
+
+    /// The DFA state searches begin in.
+    pub fn start(&mut self, engine: &Engine) -> usize {
+        let start = engine.dfa_closure(&[engine.start_state]);
+        self.insert(start)
+    }
+
+    /// The DFA state reached from `current` by consuming `c`. Unlike a
+    /// search, this never gives up on a thrashing cache.
+    pub fn next(&mut self, engine: &Engine, current: usize, c: char) -> usize {
+        self.advance(engine, current, c, &mut 0)
+            .expect("a single step clears the cache at most once")
+    }
+
+    /// Whether the NFA is in its end state in DFA state `id`.
+    pub fn is_match_state(&self, id: usize) -> bool {
+        self.states[id].is_match
+    }
+
+    /// Whether no NFA state is left in DFA state `id`, so an anchored search
+    /// can't match anymore.
+    pub fn is_dead_state(&self, id: usize) -> bool {
+        self.states[id].nfa_states.is_empty()
+    }
+
+    #[cfg(test)]
+    pub fn cached_states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The DFA state reached from `current` by consuming `c`, from the cache
+    /// if it's there. `None` once the cache was cleared too often.
+    fn advance(
+        &mut self,
+        engine: &Engine,
+        current: usize,
+        c: char,
+        clears: &mut usize,
+    ) -> Option<usize> {
+        if let Some(&next) = self.states[current].transitions.get(&c) {
+            return Some(next);
+        }
+        let next_states = self.step(engine, current, c);
+        if self.states.len() >= self.cache_limit {
+            *clears += 1;
+            if *clears > MAX_CACHE_CLEARS {
+                return None;
+            }
+            self.states.clear();
+            self.ids.clear();
+            Some(self.insert(next_states))
+        } else {
+            let next = self.insert(next_states);
+            self.states[current].transitions.insert(c, next);
+            Some(next)
+        }
+    }
+
+    /// The NFA states reached from DFA state `from` by consuming `c`.
+    fn step(&self, engine: &Engine, from: usize, c: char) -> Vec<usize> {
+        // A new match attempt may start at every position
+        self.step_states(engine, &self.states[from].nfa_states, c, !self.anchored)
+    }
+
+    /// The NFA states reached from `nfa_states` by consuming `c`, with the
+    /// start of a new attempt if `start_again`.
+    fn step_states(
+        &self,
+        engine: &Engine,
+        nfa_states: &[usize],
+        c: char,
+        start_again: bool,
+    ) -> Vec<usize> {
+        let mut targets = Vec::new();
+        for &id in nfa_states {
+            for (matcher, next) in &engine.states[id].transitions {
+                if !matcher.is_epsilon() && matcher.matches(c) {
+                    targets.push(*next);
+                }
+            }
+        }
+        if start_again {
+            targets.push(engine.start_state);
+        }
+        engine.dfa_closure(&targets)
+    }
+
+    fn insert(&mut self, nfa_states: Vec<usize>) -> usize {
+        if let Some(&id) = self.ids.get(&nfa_states) {
+            return id;
+        }
+        let id = self.states.len();
+        self.ids.insert(nfa_states.clone(), id);
+        self.states.push(DfaState {
+            is_match: nfa_states.binary_search(&self.end_state).is_ok(),
+            nfa_states,
+            transitions: Map::new(),
+        });
+        id
+    }
+}
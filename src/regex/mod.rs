@@ -1,6 +1,63 @@
+mod ast;
+mod byte_class;
+mod bytes;
+mod cache;
+mod captures;
+mod dfa;
+mod dot;
 mod elements;
 mod engine;
+mod error;
+mod escape;
+mod find;
+mod lazy_dfa;
+mod multi;
 mod nfa_regex;
+mod one_pass;
+mod options;
 mod parser;
+#[cfg(feature = "std")]
+mod pattern_cache;
+mod pike;
+mod prefilter;
+mod replace;
+mod reverse;
+mod set;
+mod shift_or;
+mod split;
+mod stream;
+mod thompson;
+mod trace;
 
+/// What the DFAs look their states up in. There is no `HashMap` without
+/// std, so `no_std` builds make do with a `BTreeMap`.
+#[cfg(feature = "std")]
+type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+pub use ast::{parse, Ast};
+pub use bytes::{BytesMatch, BytesMatches};
+pub use cache::Cache;
+pub use captures::{CaptureMatches, Captures};
+pub use dfa::Dfa;
+pub use error::{CompileError, DfaError, MatchError, SyntaxError};
+pub use escape::escape;
+pub use find::{Match, Matches};
+pub use multi::{MultiPattern, MultiPatternBuilder, PatternMatch};
+pub use nfa_regex::Cursor;
 pub use nfa_regex::RegexNFA;
+pub use options::{Backend, MatchKind, RegexOptions};
+pub use parser::Flags;
+#[cfg(feature = "std")]
+pub use pattern_cache::{
+    clear_pattern_cache, pattern_cache_stats, set_pattern_cache_capacity, PatternCacheStats,
+    DEFAULT_PATTERN_CACHE_CAPACITY,
+};
+pub use replace::{NoExpand, Replacer};
+pub use set::RegexSet;
+pub use split::{Split, SplitN};
+pub use stream::{Status, Stream};
+pub use trace::TraceEvent;
+#[cfg(feature = "std")]
+pub use trace::{capture, set_tracer};
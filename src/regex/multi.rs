@@ -0,0 +1,318 @@
+//! Several patterns searched as one matcher: `MultiPatternBuilder` and
+//! `MultiPattern`.
+//!
+//! Unlike a `RegexSet`, which only says which patterns match, a
+//! `MultiPattern` finds matches like a single regex and tells which pattern
+//! each one came from. Every pattern is compiled with options of its own, so
+//! one can ignore case while the next doesn't. The patterns behave like the
+//! alternatives of one pattern: the leftmost match wins and, of matches
+//! starting at the same place, the one of the pattern added first. Each
+//! keeps its own groups, so a backreference refers to its own pattern.
+//!
+//! Whether anything matches, and what, is answered by a `RegexSet` of the
+//! patterns in a single pass over the haystack; the patterns are only run
+//! one by one to find where they match, and then only those the set says
+//! match somewhere.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::matcher::Matcher;
+use crate::regex::bytes::Decoded;
+use crate::regex::elements::Matcher as Step;
+use crate::regex::error::CompileError;
+use crate::regex::nfa_regex::RegexNFA;
+use crate::regex::options::RegexOptions;
+use crate::regex::set::RegexSet;
+
+/// Collects patterns, each with its options, to compile into a
+/// `MultiPattern`. A pattern's ID is its index in the order it was added.
+#[derive(Debug, Clone, Default)]
+pub struct MultiPatternBuilder {
+    patterns: Vec<(String, RegexOptions)>,
+    // What `add` compiles a pattern with
+    options: RegexOptions,
+}
+
+impl MultiPatternBuilder {
+    pub fn new() -> Self {
+        MultiPatternBuilder::default()
+    }
+
+    /// A builder whose `add` compiles patterns with `options`.
+    pub fn with_options(options: RegexOptions) -> Self {
+        MultiPatternBuilder {
+            patterns: Vec::new(),
+            options,
+        }
+    }
+
+    /// Adds `pattern` with the builder's options, returning its ID.
+    pub fn add<S: Into<String>>(&mut self, pattern: S) -> usize {
+        let options = self.options.clone();
+        self.add_with_options(pattern, options)
+    }
+
+    /// Adds `pattern` with options of its own, returning its ID.
+    pub fn add_with_options<S: Into<String>>(
+        &mut self,
+        pattern: S,
+        options: RegexOptions,
+    ) -> usize {
+        self.patterns.push((pattern.into(), options));
+        self.patterns.len() - 1
+    }
+
+    /// Number of patterns added so far.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Compiles every pattern. Fails on the first invalid one.
+    pub fn build(&self) -> Result<MultiPattern, CompileError> {
+        let regexes: Vec<RegexNFA> = self
+            .patterns
+            .iter()
+            .map(|(pattern, options)| RegexNFA::try_with_options(pattern.clone(), options.clone()))
+            .collect::<Result<_, _>>()?;
+        let (set, unsimulated) = RegexSet::simulating(&regexes);
+        // `\G` matches where a search starts, which the set takes to be the
+        // start of the haystack
+        let always_run = (0..regexes.len())
+            .filter(|&pattern| {
+                unsimulated.contains(&pattern) || has_search_start(&regexes[pattern])
+            })
+            .collect();
+        Ok(MultiPattern {
+            regexes,
+            set,
+            unsimulated,
+            always_run,
+        })
+    }
+}
+
+/// A match of a `MultiPattern`: which pattern matched, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMatch {
+    pattern: usize,
+    start: usize,
+    end: usize,
+}
+
+impl PatternMatch {
+    /// The ID of the pattern that matched.
+    pub fn pattern(&self) -> usize {
+        self.pattern
+    }
+
+    /// Byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset right after the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The byte range of the match, to slice the haystack with.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// Patterns compiled by a `MultiPatternBuilder`, searched together. As a
+/// `Matcher` it reports the matches of whichever pattern matches leftmost,
+/// and the groups of that pattern.
+pub struct MultiPattern {
+    regexes: Vec<RegexNFA>,
+    // Every pattern but those in `unsimulated`, which it can't run
+    set: RegexSet,
+    unsimulated: Vec<usize>,
+    // Patterns searched for matches even if the set doesn't match them
+    always_run: Vec<usize>,
+}
+
+impl MultiPattern {
+    /// Number of patterns.
+    pub fn len(&self) -> usize {
+        self.regexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// The compiled pattern with ID `pattern`. Panics if there is none.
+    pub fn regex(&self, pattern: usize) -> &RegexNFA {
+        &self.regexes[pattern]
+    }
+
+    /// The leftmost match in `haystack` and the pattern it is of.
+    pub fn find_pattern(&self, haystack: &[u8]) -> Option<PatternMatch> {
+        self.find_pattern_at(haystack, 0)
+    }
+
+    /// Like `find_pattern`, for matches starting at byte offset `at` or
+    /// later as with `Matcher::find_at`.
+    pub fn find_pattern_at(&self, haystack: &[u8], at: usize) -> Option<PatternMatch> {
+        let mut candidates = self.set.matches(&Decoded::new(haystack).text);
+        candidates.extend(&self.always_run);
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut leftmost: Option<PatternMatch> = None;
+        for pattern in candidates {
+            let Some(found) = Matcher::find_at(&self.regexes[pattern], haystack, at) else {
+                continue;
+            };
+            if leftmost
+                .as_ref()
+                .is_none_or(|best| found.start < best.start)
+            {
+                leftmost = Some(PatternMatch {
+                    pattern,
+                    start: found.start,
+                    end: found.end,
+                });
+                if found.start == at {
+                    // Nothing can start further left, nor win a tie
+                    break;
+                }
+            }
+        }
+        leftmost
+    }
+
+    /// IDs of the patterns matching `haystack` anywhere, in increasing order.
+    pub fn matching_patterns(&self, haystack: &[u8]) -> Vec<usize> {
+        let decoded = Decoded::new(haystack);
+        let mut matching = self.set.matches(&decoded.text);
+        matching.extend(
+            self.unsimulated
+                .iter()
+                .filter(|&&pattern| self.regexes[pattern].matches(&decoded.text)),
+        );
+        matching.sort_unstable();
+        matching
+    }
+}
+
+/// Whether `regex` has a `\G`.
+fn has_search_start(regex: &RegexNFA) -> bool {
+    regex
+        .engine
+        .states
+        .iter()
+        .flat_map(|state| &state.transitions)
+        .any(|(step, _)| *step == Step::SearchStart)
+}
+
+impl Matcher for MultiPattern {
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        self.find_pattern_at(haystack, at)
+            .map(|found| found.range())
+    }
+
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        let decoded = Decoded::new(haystack);
+        self.set.is_match(&decoded.text)
+            || self
+                .unsimulated
+                .iter()
+                .any(|&pattern| self.regexes[pattern].matches(&decoded.text))
+    }
+
+    fn captures(&self, haystack: &[u8]) -> Option<Vec<Option<Range<usize>>>> {
+        let found = self.find_pattern(haystack)?;
+        Matcher::captures(&self.regexes[found.pattern], haystack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::FindIter;
+    use alloc::vec;
+
+    fn build(patterns: &[(&str, bool)]) -> MultiPattern {
+        let mut builder = MultiPatternBuilder::new();
+        for &(pattern, case_insensitive) in patterns {
+            let options = RegexOptions::new().case_insensitive(case_insensitive);
+            builder.add_with_options(pattern, options);
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_reports_the_pattern() {
+        let mut builder = MultiPatternBuilder::new();
+        assert_eq!(builder.add("ERROR"), 0);
+        assert_eq!(builder.add(r"\d+"), 1);
+        let multi = builder.build().unwrap();
+        let found = multi.find_pattern(b"code 42: ERROR").unwrap();
+        assert_eq!((found.pattern(), found.range()), (1, 5..7));
+        let found = multi.find_pattern_at(b"code 42: ERROR", 7).unwrap();
+        assert_eq!((found.pattern(), found.range()), (0, 9..14));
+        assert_eq!(multi.find_pattern(b"none"), None);
+    }
+
+    #[test]
+    fn test_per_pattern_options() {
+        let multi = build(&[("error", true), ("Warn", false)]);
+        assert!(multi.is_match(b"ERROR"));
+        assert!(!multi.is_match(b"WARN"));
+        assert_eq!(multi.matching_patterns(b"Error, Warn"), [0, 1]);
+        assert_eq!(multi.matching_patterns(b"warn"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_leftmost_then_first_added() {
+        let multi = build(&[("a", false), ("ab", false), ("b+", false)]);
+        let all: Vec<_> = FindIter::new(&multi, b"ab bb").collect();
+        assert_eq!(all, [0..1, 1..2, 3..5]);
+        let multi = build(&[("ab", false), ("a", false)]);
+        assert_eq!(multi.find_pattern(b"ab").unwrap().pattern(), 0);
+    }
+
+    #[test]
+    fn test_groups_of_each_pattern() {
+        let multi = build(&[(r"(\d)\1", false), (r"(\w)(\w)\2", false)]);
+        assert_eq!(
+            multi.captures(b"xyy 11"),
+            Some(vec![Some(0..3), Some(0..1), Some(1..2)])
+        );
+        assert_eq!(
+            multi.captures(b"11 xyy"),
+            Some(vec![Some(0..2), Some(0..1)])
+        );
+    }
+
+    #[test]
+    fn test_patterns_the_set_cannot_run() {
+        // Backreferences need backtracking, `\G` matches where a search starts
+        let multi = build(&[(r"(\w)\1", false), (r"\Gb", false), ("c", false)]);
+        assert_eq!(multi.unsimulated, [0]);
+        assert_eq!(multi.matching_patterns(b"xaab"), [0]);
+        assert!(multi.is_match(b"zz"));
+        let found = multi.find_pattern_at(b"aab", 2).unwrap();
+        assert_eq!((found.pattern(), found.range()), (1, 2..3));
+        let all: Vec<_> = FindIter::new(&multi, b"bbc").collect();
+        assert_eq!(all, [0..2, 2..3]);
+    }
+
+    #[test]
+    fn test_first_invalid_pattern_fails() {
+        let mut builder = MultiPatternBuilder::new();
+        builder.add("ok");
+        builder.add("a|*");
+        assert!(builder.build().is_err());
+        assert!(MultiPatternBuilder::new().build().unwrap().is_empty());
+    }
+}
@@ -1,15 +1,79 @@
-use std::vec;
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
 
+use crate::regex::cache::{Cache, Pool};
+use crate::regex::dfa::Dfa;
 use crate::regex::elements::{Matcher, State};
-use crate::regex::engine::Engine;
-use crate::regex::parser::Token;
+use crate::regex::engine::{states_size, Engine, SearchContext, Slots};
+use crate::regex::error::{CompileError, MatchError};
+use crate::regex::lazy_dfa::LazyDfa;
+use crate::regex::one_pass::OnePass;
+use crate::regex::options::{Backend, MatchKind, RegexOptions};
+use crate::regex::parser::{Flags, GroupName, Token};
+use crate::regex::prefilter::Prefilter;
+use crate::regex::reverse::ReverseDfa;
+use crate::regex::shift_or::ShiftOr;
+use crate::regex::trace::{self, TraceEvent};
+
+/// The span of a match followed by those of its groups, see
+/// `RegexNFA::match_spans`.
+type Spans = Vec<Option<(usize, usize)>>;
+
+/// How far an iteration over successive matches got, see
+/// `RegexNFA::next_match`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor {
+    /// Char index the next search starts at.
+    pub at: usize,
+    /// Char index where the previous match ended, if there was one.
+    last_end: Option<usize>,
+}
+
+impl Cursor {
+    /// A cursor starting the iteration at char index `at`.
+    pub fn new(at: usize) -> Self {
+        Cursor { at, last_end: None }
+    }
+}
 
 #[allow(dead_code)]
 pub struct RegexNFA {
     pub engine: Engine,
-    pattern: String, 
-    starts_with: bool,
-    ends_with: bool,
+    pattern: String,
+    pub(super) starts_with: bool,
+    pub(super) ends_with: bool,
+    pub(super) groups: usize,
+    // Name and index of every named group
+    pub(super) group_names: Arc<[GroupName]>,
+    // Whether `matches` can use the linear time simulation
+    pub(super) regular: bool,
+    // Whether `match_spans` can use the Pike VM
+    pike: bool,
+    // Used by `match_spans` instead when the pattern is anchored and one-pass
+    one_pass: Option<OnePass>,
+    // Used by `matches` for short fixed-length patterns
+    shift_or: Option<ShiftOr>,
+    // Whether a lazy DFA is tried before the simulation, see `Cache`
+    lazy_dfa: bool,
+    // The NFA with its literals split into chars, for the lazy DFA, if it
+    // has any
+    pub(super) char_engine: Option<Engine>,
+    // Finds where the leftmost match starts for `match_spans`
+    pub(super) reverse: Option<ReverseDfa>,
+    // Finds where matches may start, before running any engine
+    prefilter: Option<Prefilter>,
+    match_kind: MatchKind,
+    pub(super) dfa_cache_limit: usize,
+    // `^` and `$` match at every line boundary
+    pub(super) multi_line: bool,
+    backend: Backend,
+    // Lent to searches not given a cache of their own
+    caches: Pool,
 }
 
 enum Quantifier {
@@ -20,299 +84,1124 @@ enum Quantifier {
 
 impl RegexNFA {
     pub fn new(pattern: String) -> Self {
-        let tokens = crate::regex::parser::postfix_generator(&pattern);
-        let engine = create_engine(&tokens);
-        let starts_with = matches!(tokens.first(), Some(Token::StartRef));
-        let ends_with = matches!(tokens.last(), Some(Token::EndRef));
-        RegexNFA {
+        RegexNFA::with_options(pattern, RegexOptions::default())
+    }
+
+    /// Panics if the pattern is invalid, see `try_with_options`.
+    pub fn with_options(pattern: String, options: RegexOptions) -> Self {
+        RegexNFA::try_with_options(pattern, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(pattern: String) -> Result<Self, CompileError> {
+        RegexNFA::try_with_options(pattern, RegexOptions::default())
+    }
+
+    pub fn try_with_options(pattern: String, options: RegexOptions) -> Result<Self, CompileError> {
+        let mut flags = Flags {
+            ascii: options.ascii,
+            dot_all: options.dot_matches_new_line,
+            case_insensitive: options.case_insensitive,
+        };
+        let (mut tokens, group_names) = crate::regex::parser::postfix_with_names(&pattern, flags)?;
+        if options.smart_case && !flags.case_insensitive && !has_uppercase(&tokens) {
+            flags.case_insensitive = true;
+            tokens = crate::regex::parser::postfix_generator(&pattern, flags)?;
+        }
+        let mut engine = create_engine(&tokens, &options)?;
+        engine.recursion_limit = options.recursion_limit;
+        engine.step_limit = options.step_limit;
+        let groups = group_count(&tokens);
+        RegexNFA::from_engine(
+            engine,
+            pattern,
+            // A whole line is as if the pattern were between `^` and `$`
+            options.whole_line || matches!(tokens.first(), Some(Token::StartRef)),
+            options.whole_line || matches!(tokens.last(), Some(Token::EndRef)),
+            groups,
+            group_names,
+            &options,
+        )
+    }
+
+    /// Sets up everything besides the NFA itself, which only depends on the
+    /// NFA and the options. Fails if the NFA can't run on the backend the
+    /// options ask for.
+    fn from_engine(
+        mut engine: Engine,
+        pattern: String,
+        starts_with: bool,
+        ends_with: bool,
+        groups: usize,
+        group_names: Vec<GroupName>,
+        options: &RegexOptions,
+    ) -> Result<Self, CompileError> {
+        engine.multi_line = options.multi_line;
+        let backend = options.backend;
+        let regular = engine.is_regular() && backend != Backend::Backtrack;
+        let shift_or = engine.shift_or().filter(|_| backend == Backend::Auto);
+        // The lazy DFA steps through the input a char at a time
+        let chars = engine.split_literals();
+        let lazy_dfa = options.dfa_cache_limit > 0
+            && LazyDfa::supports(&chars)
+            && matches!(backend, Backend::Auto | Backend::LazyDfa);
+        if backend == Backend::LazyDfa && !lazy_dfa {
+            return Err(CompileError::Unsupported(
+                "the lazy DFA can't run this pattern".to_string(),
+            ));
+        }
+        if backend == Backend::PikeVm && !engine.supports_pike() {
+            return Err(CompileError::Unsupported(
+                "the Pike VM can't run backreferences, calls or conditionals".to_string(),
+            ));
+        }
+        let char_engine = match chars {
+            Cow::Owned(chars) if lazy_dfa => Some(chars),
+            _ => None,
+        };
+        // With `$` matching before every line break, matches could end
+        // anywhere the reverse DFA would have to start from
+        let reverse = (options.dfa_cache_limit > 0
+            && !starts_with
+            && !(ends_with && options.multi_line)
+            && matches!(backend, Backend::Auto | Backend::LazyDfa))
+        .then(|| ReverseDfa::new(&engine, ends_with, options.dfa_cache_limit))
+        .flatten();
+        let prefilter = Prefilter::new(&engine);
+        let pike = engine.supports_pike() && backend != Backend::Backtrack;
+        // Only anchored searches can use it
+        let one_pass = if starts_with && backend == Backend::Auto {
+            engine.one_pass()
+        } else {
+            None
+        };
+        Ok(RegexNFA {
             engine,
             pattern,
             starts_with,
             ends_with,
+            groups,
+            group_names: group_names.into(),
+            regular,
+            pike,
+            one_pass,
+            shift_or,
+            lazy_dfa,
+            char_engine,
+            reverse,
+            prefilter,
+            match_kind: options.match_kind,
+            dfa_cache_limit: options.dfa_cache_limit,
+            multi_line: options.multi_line,
+            backend,
+            caches: Pool::default(),
+        })
+    }
+
+    /// Empty search state for one thread to pass to the `_with` searches,
+    /// see `Cache`. The other searches don't need one.
+    pub fn create_cache(&self) -> Cache {
+        Cache {
+            dfa: self
+                .lazy_dfa
+                .then(|| LazyDfa::new(self.dfa_engine(), self.starts_with, self.dfa_cache_limit)),
+            reverse: self.reverse.as_ref().map(ReverseDfa::create_cache),
+            backtrack: SearchContext::default(),
         }
     }
 
+    /// The NFA the lazy DFAs run: `engine` without literals.
+    fn dfa_engine(&self) -> &Engine {
+        self.char_engine.as_ref().unwrap_or(&self.engine)
+    }
+
+    /// Whether some match could contain a line break, e.g. for `a\nb`, `\R`,
+    /// `\s` or `(?s).`. Only such patterns need a haystack spanning several
+    /// lines; all others can be run one line at a time.
+    pub fn can_match_newline(&self) -> bool {
+        self.engine
+            .states
+            .iter()
+            .flat_map(|state| &state.transitions)
+            .any(|(matcher, _)| match matcher {
+                Matcher::Range(..) | Matcher::Any { .. } => matcher.matches('\n'),
+                Matcher::Literal(literal) => literal.contains('\n'),
+                Matcher::Linebreak | Matcher::Grapheme => true,
+                // A backreference or call only repeats what other transitions match
+                _ => false,
+            })
+    }
+
+    /// Whether `input` contains a match. Runs in linear time unless the
+    /// pattern needs backtracking (backreferences, calls, conditionals).
     pub fn matches(&self, input: &str) -> bool {
-        if input.is_empty() {
-            return self.engine.compute(input) != -1;
+        self.with_cache(|cache| self.matches_with(cache, input))
+    }
+
+    /// Like `matches`, with the search state in `cache`.
+    pub fn matches_with(&self, cache: &mut Cache, input: &str) -> bool {
+        self.try_matches_with(cache, input).unwrap_or(false)
+    }
+
+    /// Like `matches`, but a search going over the step limit is an error
+    /// rather than no match.
+    pub fn try_matches(&self, input: &str) -> Result<bool, MatchError> {
+        self.with_cache(|cache| self.try_matches_with(cache, input))
+    }
+
+    /// Like `try_matches`, with the search state in `cache`.
+    pub fn try_matches_with(&self, cache: &mut Cache, input: &str) -> Result<bool, MatchError> {
+        if !self.regular {
+            return Ok(self.try_match_spans_at_with(cache, input, 0)?.is_some());
         }
+        Ok(self.is_match_at(cache, input, 0))
+    }
 
-        if self.starts_with {
-            let index = self.engine.compute(input);
-            if index >= 0 {
-                if self.ends_with {
-                    if index == input.len() as i32 {
-                        return true; // Matches the entire input
-                    }
-                    return false;
-                }
-                return true; // Matches from the start
+    /// Runs `search` with a cache from the pool.
+    pub(super) fn with_cache<T>(&self, search: impl FnOnce(&mut Cache) -> T) -> T {
+        self.caches.with(|| self.create_cache(), search)
+    }
+
+    /// Whether a match starts at byte offset `from` or later. Requires
+    /// `regular`.
+    fn is_match_at(&self, cache: &mut Cache, input: &str, from: usize) -> bool {
+        self.earliest_end_at(cache, input, from).is_some()
+    }
+
+    /// Byte offset where the first match starting at byte offset `from` or
+    /// later to end ends, which needn't be where the leftmost match ends.
+    /// Requires `regular`.
+    pub(super) fn earliest_end_at(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        from: usize,
+    ) -> Option<usize> {
+        if self.starts_with && self.multi_line {
+            // `^` matches at the start of every line
+            return line_starts(input, from)
+                .find_map(|start| self.earliest_end_from(cache, input, start));
+        }
+        if self.starts_with && from > 0 {
+            return None;
+        }
+        self.earliest_end_from(cache, input, from)
+    }
+
+    /// Whether a match starts at byte offset `from` (or later unless
+    /// anchored), using Shift-Or, the DFA or the linear simulation.
+    fn is_match_from(&self, cache: &mut Cache, input: &str, from: usize) -> bool {
+        self.earliest_end_from(cache, input, from).is_some()
+    }
+
+    /// Like `is_match_from`, returning where the first match to end ends.
+    /// Every engine here stops at the first end it accepts.
+    fn earliest_end_from(&self, cache: &mut Cache, input: &str, from: usize) -> Option<usize> {
+        let from = self.first_candidate(input, from)?;
+        let end = Cell::new(None);
+        let accept = |index: usize| {
+            let allowed = self.end_allowed(input, index);
+            if allowed {
+                end.set(Some(index));
+            }
+            allowed
+        };
+
+        if let Some(shift_or) = &self.shift_or {
+            return shift_or
+                .is_match(input, from, self.starts_with, accept)
+                .then(|| end.get())
+                .flatten();
+        }
+        if let Some(dfa) = &mut cache.dfa {
+            let found = dfa.is_match(self.dfa_engine(), &input[from..], |index| {
+                accept(from + index)
+            });
+            if let Some(found) = found {
+                return found.then(|| end.get()).flatten();
             }
-            return false;
         }
+        self.engine
+            .is_match_linear(input, from, self.starts_with, accept)
+            .then(|| end.get())
+            .flatten()
+    }
 
-        // Slice input and keep checking until found
-        for i in 0..input.len() {
-            let slice = input.chars().skip(i).take(input.len() - i).collect::<String>();
-            let index = self.engine.compute(&slice);
-            if index >= 0 {
-                if self.ends_with {
-                    if index as usize + i == input.len() {
-                        return true; // Matches the entire input
-                    }
-                    return false;
+    /// Whether `$` allows a match to end at byte offset `index`: at the end
+    /// of `input` or right before a final line terminator, or before any
+    /// line terminator in multi-line mode.
+    fn end_allowed(&self, input: &str, index: usize) -> bool {
+        if !self.ends_with {
+            return true;
+        }
+        if self.multi_line {
+            return is_line_end(input, index);
+        }
+        index == input.len() || index + trailing_terminator_len(input) == input.len()
+    }
+
+    /// Byte offset of the first place at or after `from` where a match could
+    /// start, according to the prefilter. `None` if there is none.
+    fn first_candidate(&self, input: &str, from: usize) -> Option<usize> {
+        match &self.prefilter {
+            None => Some(from),
+            Some(prefilter) if self.starts_with => {
+                prefilter.is_candidate(input, from).then_some(from)
+            }
+            Some(prefilter) => prefilter.find(input, from),
+        }
+    }
+
+    /// The pattern as a minimized DFA, which can be saved with
+    /// `Dfa::to_bytes` and used instead of compiling the pattern again. Only
+    /// for patterns without backreferences, calls, conditionals or `\G`,
+    /// `\X` and `\R`, and the DFA can't report group spans.
+    pub fn determinize(&self) -> Option<Dfa> {
+        self.engine
+            .split_literals()
+            .determinize(self.starts_with)
+            .map(|dfa| dfa.anchored_end(self.ends_with))
+    }
+
+    /// Finds the leftmost match and returns its span followed by the span of
+    /// every capture group, as char index ranges into `input`. Groups that
+    /// didn't take part in the match are `None`.
+    pub fn match_spans(&self, input: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        self.match_spans_at(input, 0)
+    }
+
+    /// The next of the successive matches in `input`, as spans like
+    /// `match_spans`, moving `cursor` past it. Matches never overlap: each
+    /// search starts where the previous match ended, and an empty match right
+    /// there is skipped, the search moving on a char. So `a*` finds `(0, 0)`
+    /// and `(1, 4)` in `baaa` but not `(4, 4)`, and every call either ends
+    /// further into the input or returns `None`.
+    pub fn next_match(
+        &self,
+        input: &str,
+        cursor: &mut Cursor,
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        loop {
+            let spans = self.match_spans_at(input, cursor.at)?;
+            let (start, end) = spans[0].expect("the whole match always has a span");
+            if start == end && cursor.last_end == Some(end) {
+                // Past the end of the input `match_spans_at` finds nothing
+                cursor.at = start + 1;
+                continue;
+            }
+            cursor.at = end;
+            cursor.last_end = Some(end);
+            return Some(spans);
+        }
+    }
+
+    /// Like `match_spans`, but only considers matches starting at char index
+    /// `from` or later. `\G` matches at `from`, so passing the end of the
+    /// previous match forces the next one to follow it directly. A search
+    /// going over the step limit finds nothing.
+    pub fn match_spans_at(&self, input: &str, from: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        self.with_cache(|cache| self.match_spans_at_with(cache, input, from))
+    }
+
+    /// Like `match_spans_at`, with the search state in `cache`.
+    pub fn match_spans_at_with(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        from: usize,
+    ) -> Option<Spans> {
+        self.try_match_spans_at_with(cache, input, from)
+            .unwrap_or(None)
+    }
+
+    /// Like `match_spans_at`, but a search going over the step limit is an
+    /// error rather than no match.
+    pub fn try_match_spans_at(
+        &self,
+        input: &str,
+        from: usize,
+    ) -> Result<Option<Spans>, MatchError> {
+        self.with_cache(|cache| self.try_match_spans_at_with(cache, input, from))
+    }
+
+    /// Like `try_match_spans_at`, with the search state in `cache`.
+    pub fn try_match_spans_at_with(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        from: usize,
+    ) -> Result<Option<Spans>, MatchError> {
+        let offset = match input.char_indices().nth(from) {
+            Some((offset, _)) => offset,
+            None if from == input.chars().count() => input.len(),
+            None => return Ok(None),
+        };
+        let Some((start, end, slots)) = self.try_find_at_with(cache, input, offset)? else {
+            return Ok(None);
+        };
+
+        // The engine works in byte offsets
+        let char_index = |byte: usize| from + input[offset..byte].chars().count();
+        let mut spans = vec![Some((char_index(start), char_index(end)))];
+        for group in 1..=self.groups {
+            let span = match (slots.get(2 * group), slots.get(2 * group + 1)) {
+                (Some(Some(s)), Some(Some(e))) => Some((char_index(*s), char_index(*e))),
+                _ => None,
+            };
+            spans.push(span);
+        }
+        Ok(Some(spans))
+    }
+
+    /// The leftmost match starting at byte offset `from` or later, as the
+    /// byte offsets it starts and ends at and the capture slots.
+    pub(super) fn try_find_at_with(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        from: usize,
+    ) -> Result<Option<(usize, usize, Slots)>, MatchError> {
+        // `^` only matches at the very start, not where the search starts
+        if self.starts_with && from > 0 && !self.multi_line {
+            return Ok(None);
+        }
+        if self.starts_with && self.multi_line {
+            // `^` matches at the start of every line
+            for start in line_starts(input, from) {
+                if let Some(found) = self.find_from(cache, input, start)? {
+                    return Ok(Some(found));
                 }
-                return true; // Found a match
             }
+            return Ok(None);
+        }
+        self.find_from(cache, input, from)
+    }
+
+    /// The leftmost match starting at byte offset `at` or later.
+    fn find_from(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        at: usize,
+    ) -> Result<Option<(usize, usize, Slots)>, MatchError> {
+        // Rule out lines without a match before backtracking from every
+        // position
+        if self.regular && !self.is_match_from(cache, input, at) {
+            return Ok(None);
+        }
+
+        let terminator = trailing_terminator_len(input);
+        let accept = |index: usize| self.end_allowed(input, index);
+        let leftmost = self.leftmost_start(cache, input, at, terminator);
+        let found = if let Some(one_pass) = &self.one_pass {
+            one_pass.find(&self.engine, input, at, accept)
+        } else if let Some(leftmost) = leftmost {
+            let Some(start) = leftmost else {
+                return Ok(None);
+            };
+            if self.pike {
+                self.engine.pike_find(input, start, true, accept)
+            } else {
+                let ctx = &mut cache.backtrack;
+                self.engine
+                    .try_find(ctx, input, start, true, None, accept)?
+            }
+        } else if self.pike {
+            self.engine.pike_find(input, at, self.starts_with, accept)
+        } else {
+            let prefilter = self.prefilter.as_ref();
+            let ctx = &mut cache.backtrack;
+            self.engine
+                .try_find(ctx, input, at, self.starts_with, prefilter, accept)?
+        };
+        let Some((start, end, slots)) = found else {
+            return Ok(None);
+        };
+
+        if self.match_kind == MatchKind::LeftmostLongest {
+            return self.longest_at(cache, input, start, accept);
+        }
+        Ok(Some((start, end, slots)))
+    }
+
+    /// Byte offset where the leftmost match at or after `from` starts,
+    /// according to the reverse DFA: `Some(None)` if there is no match.
+    /// `None` if there is no reverse DFA or its cache thrashed.
+    fn leftmost_start(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        from: usize,
+        terminator: usize,
+    ) -> Option<Option<usize>> {
+        let reverse = self.reverse.as_ref()?;
+        if !self.ends_with {
+            // Reading back from the end of the input every time would make
+            // `find_iter` quadratic
+            let forward = cache.dfa.as_mut();
+            let end = match forward.map(|dfa| dfa.match_extent(self.dfa_engine(), &input[from..])) {
+                Some(Some(None)) => return Some(None),
+                Some(Some(Some(end))) => from + end,
+                _ => input.len(),
+            };
+            return reverse.leftmost_start(cache.reverse.as_mut()?, input, from, end);
+        }
+        let dfa = cache.reverse.as_mut()?;
+        // `$` matches at the very end or before a final line terminator
+        let mut start = reverse.leftmost_start(dfa, input, from, input.len())?;
+        if terminator > 0 && from + terminator <= input.len() {
+            let before = reverse.leftmost_start(dfa, input, from, input.len() - terminator)?;
+            start = match (start, before) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        Some(start)
+    }
+
+    /// The longest match starting at byte offset `start`, with the groups
+    /// the pattern prefers among the ways of matching that much.
+    fn longest_at<F>(
+        &self,
+        cache: &mut Cache,
+        input: &str,
+        start: usize,
+        accept: F,
+    ) -> Result<Option<(usize, usize, Slots)>, MatchError>
+    where
+        F: Fn(usize) -> bool,
+    {
+        // Turning every end down makes the engines go through all of them
+        let longest = Cell::new(None);
+        let record = |index: usize| {
+            if accept(index) && longest.get().is_none_or(|end| end < index) {
+                longest.set(Some(index));
+            }
+            false
+        };
+        if self.regular {
+            self.engine.is_match_linear(input, start, true, record);
+        } else {
+            let ctx = &mut cache.backtrack;
+            self.engine
+                .try_find(ctx, input, start, true, None, record)?;
+        }
+        let Some(longest) = longest.get() else {
+            return Ok(None);
+        };
+        let at_longest = |index: usize| index == longest;
+        if self.pike {
+            Ok(self.engine.pike_find(input, start, true, at_longest))
+        } else {
+            let ctx = &mut cache.backtrack;
+            self.engine
+                .try_find(ctx, input, start, true, None, at_longest)
         }
+    }
+}
+
+/// What `RegexNFA` is saved as: the compiled NFA and what the searches need
+/// besides it. The accelerators (DFAs, prefilter, ...) are rebuilt on load,
+/// which is cheap next to parsing and compiling a large pattern.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Compiled<E, S, N> {
+    engine: E,
+    pattern: S,
+    starts_with: bool,
+    ends_with: bool,
+    groups: usize,
+    #[serde(default)]
+    group_names: N,
+    match_kind: MatchKind,
+    dfa_cache_limit: usize,
+    #[serde(default)]
+    multi_line: bool,
+    #[serde(default)]
+    backend: Backend,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for RegexNFA {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Compiled {
+            engine: &self.engine,
+            pattern: self.pattern.as_str(),
+            starts_with: self.starts_with,
+            ends_with: self.ends_with,
+            groups: self.groups,
+            group_names: &*self.group_names,
+            match_kind: self.match_kind,
+            dfa_cache_limit: self.dfa_cache_limit,
+            multi_line: self.multi_line,
+            backend: self.backend,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RegexNFA {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let compiled = Compiled::<Engine, String, Vec<GroupName>>::deserialize(deserializer)?;
+        let engine = &compiled.engine;
+        // States are looked up by id everywhere
+        let valid = engine.states.iter().enumerate().all(|(index, state)| {
+            state.id == index
+                && state
+                    .transitions
+                    .iter()
+                    .all(|(_, next)| *next < engine.states.len())
+        }) && engine.start_state < engine.states.len()
+            && engine.end_state < engine.states.len();
+        if !valid {
+            return Err(serde::de::Error::custom(
+                "invalid NFA: state ids out of place",
+            ));
+        }
+        let options = RegexOptions::new()
+            .match_kind(compiled.match_kind)
+            .dfa_cache_limit(compiled.dfa_cache_limit)
+            .multi_line(compiled.multi_line)
+            .backend(compiled.backend);
+        RegexNFA::from_engine(
+            compiled.engine,
+            compiled.pattern,
+            compiled.starts_with,
+            compiled.ends_with,
+            compiled.groups,
+            compiled.group_names,
+            &options,
+        )
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Whether the pattern spells out an uppercase letter, in a literal or a
+/// bracket expression. Escapes like `\G` or group names don't count.
+fn has_uppercase(tokens: &[Token]) -> bool {
+    tokens.iter().any(|token| match token {
+        Token::Literal(c, _) => c.is_uppercase(),
+        Token::ComplexLiteral(class, _) => {
+            class.starts_with('[') && class.chars().any(char::is_uppercase)
+        }
+        _ => false,
+    })
+}
 
-        return false;
+/// Number of chars taken up by a line terminator (`\n` or `\r\n`) at the very
+/// end of `input`. `$` is allowed to match just before it, so whole buffers and
+/// NUL-separated records ending in a newline behave like the stripped line would.
+pub(super) fn trailing_terminator_len(input: &str) -> usize {
+    if input.ends_with("\r\n") {
+        2
+    } else if input.ends_with('\n') {
+        1
+    } else {
+        0
     }
 }
 
-fn create_engine(tokens: &Vec<Token>) -> Engine {
+/// Whether a line of `input` starts at byte offset `index`.
+pub(super) fn is_line_start(input: &str, index: usize) -> bool {
+    index == 0 || input.as_bytes()[index - 1] == b'\n'
+}
+
+/// Whether a line of `input` ends at byte offset `index`, before a `\n` or
+/// `\r\n` or at the very end.
+pub(super) fn is_line_end(input: &str, index: usize) -> bool {
+    let rest = &input.as_bytes()[index..];
+    rest.is_empty() || rest.starts_with(b"\n") || rest.starts_with(b"\r\n")
+}
+
+/// Byte offsets at or after `from` where a line starts.
+fn line_starts(input: &str, from: usize) -> impl Iterator<Item = usize> + '_ {
+    let first = is_line_start(input, from).then_some(from);
+    let after_newlines =
+        memchr::memchr_iter(b'\n', &input.as_bytes()[from..]).map(move |found| from + found + 1);
+    first.into_iter().chain(after_newlines)
+}
+
+/// The number of capture groups in the pattern `tokens` were parsed from.
+fn group_count(tokens: &[Token]) -> usize {
+    tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Group(index) => Some(*index),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// How many states a counted repetition may expand to. Larger ones loop over
+/// a single copy, counting the repetitions in a slot after the groups' ones.
+const MAX_REPEAT_STATES: usize = 1000;
 
-    let mut engine_stack: Vec<Engine> = vec![];
+fn create_engine(tokens: &[Token], options: &RegexOptions) -> Result<Engine, CompileError> {
+    let size_limit = options.size_limit;
+    let too_large = CompileError::TooLarge { limit: size_limit };
+    let mut builder = Builder {
+        next_counter: 2 * (group_count(tokens) + 1),
+        ..Builder::default()
+    };
+    let mut fragment_stack: Vec<Fragment> = vec![];
 
     let mut iter = tokens.iter().peekable();
     while let Some(token) = iter.next() {
         match token {
-            Token::Literal(c) => {
-                let nfa = literal_nfa(c.clone());
-                engine_stack.push(nfa);
-            }
-            Token::ComplexLiteral(s) => {
-                let nfa = comple_nfa(&s);
-                engine_stack.push(nfa);
-            }
-            Token::Star => {
-                if let Some(next_token) = iter.peek() {
-                    if next_token == &&Token::Question {
-                        iter.next();
-                        let engine = engine_stack.pop().expect("Expected engine for star");
-                        let nfa = special_nfa_quantifier(engine, true, Quantifier::Star);
-                        engine_stack.push(nfa);
-                        continue;
-                    }
-                }
-
-                let engine = engine_stack.pop().expect("Expected engine for star");
-                let nfa = special_nfa_quantifier(engine, false, Quantifier::Star);
-                engine_stack.push(nfa);
-            }
-            Token::Question => {
-                if let Some(next_token) = iter.peek() {
-                    if next_token == &&Token::Question {
-                        iter.next();
-                        let engine = engine_stack.pop().expect("Expected engine for question");
-                        let nfa = special_nfa_quantifier(engine, true, Quantifier::Plus);
-                        engine_stack.push(nfa);
-                        continue;
-                    }
+            Token::Literal(c, flags) => {
+                let nfa = builder.literal(*c, *flags);
+                fragment_stack.push(nfa);
+            }
+            Token::ComplexLiteral(s, flags) => {
+                let nfa = builder.complex(s, *flags);
+                fragment_stack.push(nfa);
+            }
+            Token::Group(index) => {
+                let fragment = operand(&mut fragment_stack, "empty group")?;
+                let nfa = builder.group(fragment, *index);
+                fragment_stack.push(nfa);
+            }
+            Token::Conditional(group) => {
+                let otherwise = operand(&mut fragment_stack, "conditional without a branch")?;
+                let then = operand(&mut fragment_stack, "conditional without a branch")?;
+                let nfa = builder.conditional(*group, then, otherwise);
+                fragment_stack.push(nfa);
+            }
+            Token::Empty => {
+                let nfa = builder.one_step(Matcher::Epsilon);
+                fragment_stack.push(nfa);
+            }
+            Token::Backref(index) => {
+                let nfa = builder.one_step(Matcher::Backref(*index));
+                fragment_stack.push(nfa);
+            }
+            Token::Call(group) => {
+                let nfa = builder.one_step(Matcher::Call(*group));
+                fragment_stack.push(nfa);
+            }
+            Token::SearchStart => {
+                let nfa = builder.one_step(Matcher::SearchStart);
+                fragment_stack.push(nfa);
+            }
+            Token::LineStart => {
+                let nfa = builder.one_step(Matcher::LineStart);
+                fragment_stack.push(nfa);
+            }
+            Token::LineEnd => {
+                let nfa = builder.one_step(Matcher::LineEnd);
+                fragment_stack.push(nfa);
+            }
+            Token::WordBoundary(negated, flags) => {
+                let nfa = builder.one_step(Matcher::WordBoundary {
+                    ascii: flags.ascii,
+                    negated: *negated,
+                });
+                fragment_stack.push(nfa);
+            }
+            Token::Grapheme => {
+                let nfa = builder.one_step(Matcher::Grapheme);
+                fragment_stack.push(nfa);
+            }
+            Token::Linebreak => {
+                let nfa = builder.one_step(Matcher::Linebreak);
+                fragment_stack.push(nfa);
+            }
+            Token::Star | Token::Question | Token::Plus => {
+                let quantifier = match token {
+                    Token::Star => Quantifier::Star,
+                    Token::Question => Quantifier::Question,
+                    _ => Quantifier::Plus,
+                };
+                let lazy = iter.next_if_eq(&&Token::Lazy).is_some();
+                let fragment = operand(&mut fragment_stack, "quantifier with nothing to repeat")?;
+                let nfa = builder.quantifier(fragment, lazy, quantifier);
+                fragment_stack.push(nfa);
+            }
+            Token::Repeat(min, max) => {
+                let lazy = iter.next_if_eq(&&Token::Lazy).is_some();
+                let fragment = operand(&mut fragment_stack, "repetition with nothing to repeat")?;
+                let copies = max.unwrap_or(*min + 1);
+                if fragment.len.saturating_mul(copies) > MAX_REPEAT_STATES {
+                    let nfa = builder.counted(fragment, *min, *max, lazy);
+                    fragment_stack.push(nfa);
+                    continue;
                 }
-
-                let engine = engine_stack.pop().expect("Expected engine for question");
-                let nfa = special_nfa_quantifier(engine, false, Quantifier::Question);
-                engine_stack.push(nfa);
-            }
-            Token::Plus => {
-                if let Some(next_token) = iter.peek() {
-                    if next_token == &&Token::Question {
-                        iter.next();
-                        let engine = engine_stack.pop().expect("Expected engine for plus");
-                        let nfa = special_nfa_quantifier(engine, true, Quantifier::Plus);
-                        engine_stack.push(nfa);
-                        continue;
-                    }
+                // Check before making all the copies
+                if builder.size(fragment).saturating_mul(copies) > size_limit {
+                    return Err(too_large);
                 }
-
-                let engine = engine_stack.pop().expect("Expected engine for plus");
-                let nfa = special_nfa_quantifier(engine, false, Quantifier::Plus);
-                engine_stack.push(nfa);
+                let nfa = builder.repeat(fragment, *min, *max, lazy);
+                fragment_stack.push(nfa);
             }
             Token::Or => {
-                let right = engine_stack.pop().expect("Expected right engine for union");
-                let left = engine_stack.pop().expect("Expected left engine for union");
-                let nfa = union_nfa(left, right);
-                engine_stack.push(nfa);
+                let right = operand(&mut fragment_stack, "empty alternative")?;
+                let left = operand(&mut fragment_stack, "empty alternative")?;
+                let nfa = builder.union(left, right);
+                fragment_stack.push(nfa);
             }
             Token::Concat => {
-                let right = engine_stack
-                    .pop()
-                    .expect("Expected right engine for concat");
-                let left = engine_stack.pop().expect("Expected left engine for concat");
-                let nfa = concat_nfa(left, right);
-                engine_stack.push(nfa);
+                let right = operand(&mut fragment_stack, "concatenation missing a part")?;
+                let left = operand(&mut fragment_stack, "concatenation missing a part")?;
+                let nfa = builder.concat(left, right);
+                fragment_stack.push(nfa);
             }
             Token::StartRef | Token::EndRef => {}
+            Token::LBracket | Token::RBracket => {
+                return Err(CompileError::Unsupported(
+                    "unbalanced parentheses".to_string(),
+                ));
+            }
             _ => {
-                panic!("Unexpected token: {:?}", token);
+                return Err(CompileError::Unsupported(format!("unexpected {:?}", token)));
             }
         }
     }
 
-    assert_eq!(
-        engine_stack.len(),
-        1,
-        "Expected exactly one engine in stack after processing tokens"
-    );
-    engine_stack.pop().expect("Expected final engine")
+    let mut fragment = operand(&mut fragment_stack, "nothing to match")?;
+    if !fragment_stack.is_empty() {
+        return Err(CompileError::Unsupported(
+            "parts of the pattern left unconnected".to_string(),
+        ));
+    }
+    if options.word {
+        let ascii = options.ascii;
+        let before = builder.one_step(Matcher::WordStart { ascii });
+        fragment = builder.concat(before, fragment);
+        let after = builder.one_step(Matcher::WordEnd { ascii });
+        fragment = builder.concat(fragment, after);
+    }
+    let mut engine = builder.engine;
+    engine.set_start_state(fragment.start);
+    engine.set_end_state(fragment.end);
+    if engine.approximate_size() > size_limit {
+        return Err(too_large);
+    }
+    Ok(engine)
+}
+
+/// Pops the operand of an operator off `stack`, failing with `missing` if
+/// the parser left none, as for `()` or `a|`.
+fn operand(stack: &mut Vec<Fragment>, missing: &str) -> Result<Fragment, CompileError> {
+    stack
+        .pop()
+        .ok_or_else(|| CompileError::Unsupported(missing.to_string()))
 }
 
-fn one_step_nfa(matcher: Matcher) -> Engine {
-    let mut engine = Engine::new();
-    let mut start_state = State::new(0);
-    let end_state = State::new(1);
-    start_state.add_transition(matcher, 1);
-    engine.add_states(vec![start_state, end_state]);
-    engine.set_start_state(0);
-    engine.set_end_state(1);
-    engine
+/// The NFA under construction. States are only ever appended, so they keep
+/// their ids and combining fragments never copies or renumbers them.
+struct Builder {
+    engine: Engine,
+    // Slot for the counter of the next counted repetition
+    next_counter: usize,
 }
 
-fn literal_nfa(c: char) -> Engine {
-    one_step_nfa(Matcher::create_simple_matcher(&c))
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            engine: Engine::new(),
+            next_counter: 0,
+        }
+    }
 }
 
-fn comple_nfa(input: &str) -> Engine {
-    one_step_nfa(Matcher::create_complex_matcher(input))
+/// Part of the NFA in a `Builder`, for part of the pattern. Its states are
+/// `len` consecutive ones from `first` on: the fragments of the operands
+/// an operator combines are always the latest ones built.
+#[derive(Debug, Clone, Copy)]
+struct Fragment {
+    first: usize,
+    len: usize,
+    start: usize,
+    end: usize,
 }
 
-fn union_nfa(left: Engine, mut right: Engine) -> Engine {
-    let mut engine = Engine::new();
-    let start_state_id = left.states.len() + right.states.len();
-    let end_state_id = start_state_id + 1;
+impl Builder {
+    fn add_state(&mut self) -> usize {
+        let id = self.engine.states.len();
+        self.engine.add_states(vec![State::new(id)]);
+        id
+    }
 
-    engine.add_states(left.states.clone());
-    right.shift_ids(left.states.len());
-    engine.add_states(right.states);
+    fn add_transition(&mut self, from: usize, matcher: Matcher, to: usize) {
+        self.engine.add_transition(from, matcher, to);
+    }
 
-    engine.set_start_state(start_state_id);
-    engine.set_end_state(end_state_id);
+    /// The fragment of the states from `first` on, traced as `construct`.
+    fn finish(&self, construct: &'static str, first: usize, start: usize, end: usize) -> Fragment {
+        let fragment = Fragment {
+            first,
+            len: self.engine.states.len() - first,
+            start,
+            end,
+        };
+        trace::emit(|| TraceEvent::Built {
+            construct,
+            start,
+            end,
+            states: fragment.len,
+        });
+        fragment
+    }
 
-    engine.add_states(vec![State::new(start_state_id), State::new(end_state_id)]);
+    /// Roughly the memory the fragment takes, in bytes.
+    fn size(&self, fragment: Fragment) -> usize {
+        states_size(&self.engine.states[fragment.first..fragment.first + fragment.len])
+    }
 
-    // Add epsilon transitions from the start state to both left and right engines
-    engine.add_transition(start_state_id, Matcher::Epsilon, left.start_state);
-    engine.add_transition(start_state_id, Matcher::Epsilon, right.start_state);
+    /// A copy of `fragment` with states of its own.
+    fn copy(&mut self, fragment: Fragment) -> Fragment {
+        let shift = self.engine.states.len() - fragment.first;
+        let mut states = self.engine.states[fragment.first..fragment.first + fragment.len].to_vec();
+        for state in &mut states {
+            state.shift_ids(shift);
+        }
+        self.engine.add_states(states);
+        Fragment {
+            first: fragment.first + shift,
+            len: fragment.len,
+            start: fragment.start + shift,
+            end: fragment.end + shift,
+        }
+    }
 
-    // Add epsilon transitions from both left and right engines to the end state
-    engine.add_transition(left.end_state, Matcher::Epsilon, end_state_id);
-    engine.add_transition(right.end_state, Matcher::Epsilon, end_state_id);
+    fn one_step(&mut self, matcher: Matcher) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
+        self.add_transition(start, matcher, end);
+        self.finish("single step", start, start, end)
+    }
 
-    #[cfg(debug_assertions)]
-    {
-        println!(
-            "Created concat NFA with start state {} and end state {}",
-            start_state_id, end_state_id
-        );
+    fn literal(&mut self, c: char, flags: Flags) -> Fragment {
+        let matcher = Matcher::create_simple_matcher(&c);
+        if flags.case_insensitive {
+            return self.one_step(matcher.case_folded(flags.ascii));
+        }
+        self.one_step(matcher)
+    }
 
-        println!("Final states: {:?}", engine.states);
+    fn complex(&mut self, input: &str, flags: Flags) -> Fragment {
+        let matcher = Matcher::create_complex_matcher(input, flags.ascii, flags.dot_all);
+        if flags.case_insensitive {
+            return self.one_step(matcher.case_folded(flags.ascii));
+        }
+        self.one_step(matcher)
     }
 
-    engine
-}
+    /// What `fragment` matches if it is a single transition matching exactly
+    /// one char or a literal.
+    fn literal_text(&self, fragment: Fragment) -> Option<String> {
+        if fragment.len != 2 {
+            return None;
+        }
+        let [(matcher, next)] = &self.engine.states[fragment.start].transitions[..] else {
+            return None;
+        };
+        if *next != fragment.end {
+            return None;
+        }
+        match matcher {
+            Matcher::Range(ranges, false) => match ranges[..] {
+                [(c, end)] if c == end => Some(c.to_string()),
+                _ => None,
+            },
+            Matcher::Literal(literal) => Some(literal.to_string()),
+            _ => None,
+        }
+    }
 
-fn concat_nfa(left: Engine, mut right: Engine) -> Engine {
-    let mut engine = Engine::new();
-    let start_state_id = left.states.len() + right.states.len();
-    let end_state_id = start_state_id + 1;
+    fn union(&mut self, left: Fragment, right: Fragment) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
 
-    engine.add_states(left.states.clone());
-    right.shift_ids(left.states.len());
-    engine.add_states(right.states);
+        // Add epsilon transitions from the start state to both left and right
+        // engines, preferring left
+        self.engine.states[start].add_choice(left.start, right.start);
 
-    engine.set_start_state(start_state_id);
-    engine.set_end_state(end_state_id);
+        // Add epsilon transitions from both left and right engines to the end state
+        self.add_transition(left.end, Matcher::Epsilon, end);
+        self.add_transition(right.end, Matcher::Epsilon, end);
 
-    engine.add_states(vec![State::new(start_state_id), State::new(end_state_id)]);
+        self.finish("union", left.first, start, end)
+    }
 
-    // Add epsilon transition from the end of left to the start of right
-    engine.add_transition(left.end_state, Matcher::Epsilon, right.start_state);
+    fn concat(&mut self, left: Fragment, right: Fragment) -> Fragment {
+        // Unless they are the latest states, like the copies of a repetition
+        let latest = right.first + right.len == self.engine.states.len();
+        let texts = (self.literal_text(left), self.literal_text(right));
+        if let (true, Some(mut text), Some(rest)) = (latest, texts.0, texts.1) {
+            // One transition comparing the whole run instead of a chain of
+            // single chars glued together by epsilons
+            text.push_str(&rest);
+            self.engine.states.truncate(right.first);
+            self.engine.states[left.start].transitions[0].0 = Matcher::Literal(text.into());
+            return self.finish("literal", left.first, left.start, left.end);
+        }
 
-    // Add transitions from the start state to the left engine
-    engine.add_transition(start_state_id, Matcher::Epsilon, left.start_state);
+        let start = self.add_state();
+        let end = self.add_state();
 
-    // Add transitions from the end of right to the end state
-    engine.add_transition(right.end_state, Matcher::Epsilon, end_state_id);
+        // Add epsilon transition from the end of left to the start of right
+        self.add_transition(left.end, Matcher::Epsilon, right.start);
 
-    #[cfg(debug_assertions)]
-    {
-        println!(
-            "Created concat NFA with start state {} and end state {}",
-            start_state_id, end_state_id
-        );
+        // Add transitions from the start state to the left engine
+        self.add_transition(start, Matcher::Epsilon, left.start);
 
-        println!("Final states: {:?}", engine.states);
+        // Add transitions from the end of right to the end state
+        self.add_transition(right.end, Matcher::Epsilon, end);
+
+        self.finish("concat", left.first, start, end)
     }
 
-    engine
-}
+    fn group(&mut self, inner: Fragment, index: usize) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
 
-fn special_nfa_quantifier(engine: Engine, lazy: bool, quantifier: Quantifier) -> Engine {
-    let mut new_engine = Engine::new();
-    let start_state_id = engine.states.len();
-    let end_state_id = start_state_id + 1;
-
-    new_engine.add_states(engine.states.clone());
-    new_engine.set_start_state(start_state_id);
-    new_engine.set_end_state(end_state_id);
-
-    new_engine.add_states(vec![State::new(start_state_id), State::new(end_state_id)]);
-
-    // Order of epsilon transitions depends on wether the quantifier is lazy or not
-    match quantifier {
-        Quantifier::Star => {
-            if lazy {
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, engine.start_state);
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, end_state_id);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, start_state_id);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, end_state_id);
-            } else {
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, end_state_id);
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, engine.start_state);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, end_state_id);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, start_state_id);
-            }
+        // Record the group boundaries on the way in and out of the inner engine
+        self.add_transition(start, Matcher::GroupStart(index), inner.start);
+        self.add_transition(inner.end, Matcher::GroupEnd(index), end);
+
+        self.finish("group", inner.first, start, end)
+    }
+
+    fn conditional(&mut self, group: usize, then: Fragment, otherwise: Fragment) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
+
+        // Only one branch is ever viable, depending on whether the group captured
+        self.add_transition(start, Matcher::IfGroup(group, true), then.start);
+        self.add_transition(start, Matcher::IfGroup(group, false), otherwise.start);
+
+        self.add_transition(then.end, Matcher::Epsilon, end);
+        self.add_transition(otherwise.end, Matcher::Epsilon, end);
+
+        self.finish("conditional", then.first, start, end)
+    }
+
+    /// Matches `inner` between `min` and `max` times (no upper bound if
+    /// `None`) by chaining copies of it: the required ones first, then either
+    /// a star or nested optional copies `(x(x)?)?` for the rest.
+    fn repeat(&mut self, inner: Fragment, min: usize, max: Option<usize>, lazy: bool) -> Fragment {
+        let optional = max.map_or(1, |max| max - min);
+        if min + optional == 0 {
+            // `x{0}`: the states of `x` stay behind, unreachable
+            let empty = self.one_step(Matcher::Epsilon);
+            return Fragment {
+                first: inner.first,
+                len: self.engine.states.len() - inner.first,
+                ..empty
+            };
         }
-        Quantifier::Question => {
-            if lazy {
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, end_state_id);
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, engine.start_state);
-            } else {
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, engine.start_state);
-                new_engine.add_transition(start_state_id, Matcher::Epsilon, end_state_id);
-            }
-            new_engine.add_transition(engine.end_state, Matcher::Epsilon, end_state_id);
+        // All copies first, while `inner` is still untouched
+        let mut copies = vec![inner];
+        for _ in 1..min + optional {
+            copies.push(self.copy(inner));
         }
-        Quantifier::Plus => {
-            if lazy {
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, end_state_id);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, start_state_id);
-            } else {
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, start_state_id);
-                new_engine.add_transition(engine.end_state, Matcher::Epsilon, end_state_id);
+
+        let mut rest = None;
+        if max.is_none() {
+            rest = Some(self.quantifier(copies[min], lazy, Quantifier::Star));
+        } else {
+            for &copy in copies[min..].iter().rev() {
+                let nested = match rest {
+                    Some(rest) => self.concat(copy, rest),
+                    None => copy,
+                };
+                rest = Some(self.quantifier(nested, lazy, Quantifier::Question));
             }
-            new_engine.add_transition(start_state_id, Matcher::Epsilon, engine.start_state);
         }
+
+        let mut chain = copies[..min].iter().copied().chain(rest);
+        let mut fragment = chain.next().expect("Expected a copy to repeat");
+        for next in chain {
+            fragment = self.concat(fragment, next);
+        }
+        fragment
     }
 
-    #[cfg(debug_assertions)]
-    {
-        println!(
-            "Created special NFA with start state {} and end state {}",
-            start_state_id, end_state_id
-        );
+    /// Matches `inner` between `min` and `max` times like `repeat`, but with
+    /// a single copy of it looping back while a counter says it may: states
+    /// stay constant in the bounds, at the cost of the pattern needing the
+    /// backtracking search.
+    fn counted(&mut self, inner: Fragment, min: usize, max: Option<usize>, lazy: bool) -> Fragment {
+        // `x{n,}` is `x{n}x*`, the star going over a copy of its own
+        let rest = max.is_none().then(|| self.copy(inner));
+        let max = max.unwrap_or(min);
+        let slot = self.next_counter;
+        self.next_counter += 1;
 
-        println!("Final states: {:?}", new_engine.states);
+        let start = self.add_state();
+        let head = self.add_state();
+        let end = self.add_state();
+        self.add_transition(start, Matcher::CountReset(slot), head);
+        let more = Matcher::IfCount {
+            slot,
+            min: 0,
+            max: max - 1,
+        };
+        let done = Matcher::IfCount { slot, min, max };
+        // Greedy repetitions prefer going round again, lazy ones leaving
+        if lazy {
+            self.add_transition(head, done, end);
+            self.add_transition(head, more, inner.start);
+        } else {
+            self.add_transition(head, more, inner.start);
+            self.add_transition(head, done, end);
+        }
+        self.add_transition(inner.end, Matcher::CountUp(slot), head);
+        let counted = self.finish("counted", inner.first, start, end);
+
+        match rest {
+            Some(rest) => {
+                let star = self.quantifier(rest, lazy, Quantifier::Star);
+                self.concat(counted, star)
+            }
+            None => counted,
+        }
     }
 
-    new_engine
+    /// Epsilon transitions from `from` to `more`, matching the quantified
+    /// part (again), and to `done`, leaving it. `more` is preferred unless
+    /// `lazy`.
+    fn add_quantifier_choice(&mut self, from: usize, more: usize, done: usize, lazy: bool) {
+        let (preferred, other) = if lazy { (done, more) } else { (more, done) };
+        self.engine.states[from].add_choice(preferred, other);
+    }
+
+    fn quantifier(&mut self, inner: Fragment, lazy: bool, quantifier: Quantifier) -> Fragment {
+        let start = self.add_state();
+        let end = self.add_state();
+
+        // Greedy quantifiers prefer going on with `inner`, lazy ones leaving it
+        match quantifier {
+            Quantifier::Star => {
+                self.add_quantifier_choice(start, inner.start, end, lazy);
+                self.add_quantifier_choice(inner.end, start, end, lazy);
+            }
+            Quantifier::Question => {
+                self.add_quantifier_choice(start, inner.start, end, lazy);
+                self.add_transition(inner.end, Matcher::Epsilon, end);
+            }
+            Quantifier::Plus => {
+                self.add_transition(start, Matcher::Epsilon, inner.start);
+                self.add_quantifier_choice(inner.end, start, end, lazy);
+            }
+        }
+
+        let construct = match quantifier {
+            Quantifier::Star => "star",
+            Quantifier::Question => "question",
+            Quantifier::Plus => "plus",
+        };
+        self.finish(construct, inner.first, start, end)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::regex::engine::DEFAULT_SIZE_LIMIT;
+    use crate::regex::error::SyntaxError;
 
     #[test]
     fn test_single_character_match() {
@@ -399,7 +1288,1367 @@ mod tests {
         assert!(regex_nfa.matches("ba"));
         assert!(!regex_nfa.matches("ab"));
         assert!(regex_nfa.matches("cba"));
+        assert!(regex_nfa.matches("aba"));
+    }
+
+    #[test]
+    fn test_end_ref_before_trailing_newline() {
+        let regex_nfa = RegexNFA::new("foo$".to_string());
+        assert!(regex_nfa.matches("foo\n"));
+        assert!(regex_nfa.matches("foo\r\n"));
+        assert!(regex_nfa.matches("a foo\n"));
+        assert!(!regex_nfa.matches("foo\n\n"));
+        assert!(!regex_nfa.matches("foo\nbar"));
+
+        let regex_nfa = RegexNFA::new("^foo$".to_string());
+        assert!(regex_nfa.matches("foo\n"));
+        assert!(!regex_nfa.matches("foox\n"));
+    }
+
+    #[test]
+    fn test_end_ref_with_multibyte_input() {
+        let regex_nfa = RegexNFA::new("b$".to_string());
+        assert!(regex_nfa.matches("éb"));
+        assert!(!regex_nfa.matches("bé"));
+    }
+
+    #[test]
+    fn test_ascii_option() {
+        let unicode = RegexNFA::new("^\\w+$".to_string());
+        assert!(unicode.matches("café"));
+
+        let ascii = RegexNFA::with_options("^\\w+$".to_string(), RegexOptions::new().ascii(true));
+        assert!(ascii.matches("cafe"));
+        assert!(!ascii.matches("café"));
+    }
+
+    #[test]
+    fn test_inline_ascii_flag() {
+        let regex_nfa = RegexNFA::new("^(?-u)\\d$".to_string());
+        assert!(regex_nfa.matches("7"));
+        assert!(!regex_nfa.matches("٣"));
+
+        let regex_nfa = RegexNFA::new("^(?-u:\\d)\\d$".to_string());
+        assert!(regex_nfa.matches("7٣"));
+        assert!(!regex_nfa.matches("٣7"));
+    }
+
+    #[test]
+    fn test_dot_matches_new_line_option() {
+        let regex_nfa = RegexNFA::new("a.b".to_string());
+        assert!(regex_nfa.matches("a-b"));
+        assert!(!regex_nfa.matches("a\nb"));
+
+        let options = RegexOptions::new().dot_matches_new_line(true);
+        let regex_nfa = RegexNFA::with_options("a.b".to_string(), options);
+        assert!(regex_nfa.matches("a\nb"));
+
+        let regex_nfa = RegexNFA::new("a(?s:.)b".to_string());
+        assert!(regex_nfa.matches("a\nb"));
+    }
+
+    #[test]
+    fn test_backref_match() {
+        let regex_nfa = RegexNFA::new("(a|b)\\1".to_string());
+        assert!(regex_nfa.matches("aa"));
+        assert!(regex_nfa.matches("xbb"));
+        assert!(!regex_nfa.matches("ab"));
+
+        let regex_nfa = RegexNFA::new("^(\\w+) \\1$".to_string());
+        assert!(regex_nfa.matches("hello hello"));
+        assert!(!regex_nfa.matches("hello world"));
+    }
+
+    #[test]
+    fn test_named_backref_match() {
+        let regex_nfa = RegexNFA::new("(?P<q>[\"']).*\\k<q>".to_string());
+        assert!(regex_nfa.matches("say \"hi\" now"));
+        assert!(regex_nfa.matches("say 'hi' now"));
+        assert!(!regex_nfa.matches("say \"hi' now"));
+
+        let regex_nfa = RegexNFA::new("^(?<word>\\w+)-\\k<word>$".to_string());
+        assert!(regex_nfa.matches("abc-abc"));
+        assert!(!regex_nfa.matches("abc-abd"));
+    }
+
+    #[test]
+    fn test_backref_to_unset_group_fails() {
+        let regex_nfa = RegexNFA::new("(a)?b\\1".to_string());
+        assert!(regex_nfa.matches("aba"));
+        assert!(!regex_nfa.matches("b"));
+    }
+
+    #[test]
+    fn test_conditional_match() {
+        // Optional opening bracket requires the closing one
+        let regex_nfa = RegexNFA::new("^(<)?\\w+(?(1)>)$".to_string());
+        assert!(regex_nfa.matches("<tag>"));
+        assert!(regex_nfa.matches("tag"));
+        assert!(!regex_nfa.matches("<tag"));
+        assert!(!regex_nfa.matches("tag>"));
+    }
+
+    #[test]
+    fn test_conditional_else_match() {
+        let regex_nfa = RegexNFA::new("^(?P<q>\")?(?(q)[a-z ]+\"|[a-z]+)$".to_string());
+        assert!(regex_nfa.matches("\"two words\""));
+        assert!(regex_nfa.matches("word"));
+        assert!(!regex_nfa.matches("two words"));
+        assert!(!regex_nfa.matches("\"open"));
+    }
+
+    #[test]
+    fn test_match_spans() {
+        let regex_nfa = RegexNFA::new("(\\w+)=(\\d+)?x".to_string());
+        assert_eq!(
+            regex_nfa.match_spans("set ab=12x"),
+            Some(vec![Some((4, 10)), Some((4, 6)), Some((7, 9))])
+        );
+        assert_eq!(
+            regex_nfa.match_spans("é a=x"),
+            Some(vec![Some((2, 5)), Some((2, 3)), None])
+        );
+        // Char indices, although the engine steps through bytes
+        assert_eq!(
+            regex_nfa.match_spans("ñé=12x"),
+            Some(vec![Some((0, 6)), Some((0, 2)), Some((3, 5))])
+        );
+        assert_eq!(regex_nfa.match_spans("nothing"), None);
+    }
+
+    #[test]
+    fn test_branch_reset_match() {
+        // The date field is group 1 whichever format matched
+        let regex_nfa = RegexNFA::new("(?|(\\d\\d)/\\d\\d|\\d\\d-(\\d\\d))!\\1".to_string());
+        assert!(regex_nfa.matches("12/34!12"));
+        assert!(regex_nfa.matches("12-34!34"));
+        assert!(!regex_nfa.matches("12-34!12"));
+
+        let regex_nfa = RegexNFA::new("(?|(a)|(b))(c)".to_string());
+        assert_eq!(
+            regex_nfa.match_spans("bc"),
+            Some(vec![Some((0, 2)), Some((0, 1)), Some((1, 2))])
+        );
     }
 
-    // TODO: Test lazy quantifiers
+    #[test]
+    fn test_recursion_match() {
+        let regex_nfa = RegexNFA::new("^\\((?:[^()]|(?R))*\\)$".to_string());
+        assert!(regex_nfa.matches("()"));
+        assert!(regex_nfa.matches("(a(b)(c(d))e)"));
+        assert!(!regex_nfa.matches("(a(b)"));
+        assert!(!regex_nfa.matches("(a))"));
+    }
+
+    #[test]
+    fn test_subroutine_call_match() {
+        let regex_nfa = RegexNFA::new("^key=(\\((?:[^()]|(?1))*\\))$".to_string());
+        assert!(regex_nfa.matches("key=(x(y)z)"));
+        assert!(!regex_nfa.matches("key=(x(y z)"));
+
+        // Captures made inside the call are dropped when it returns
+        let regex_nfa = RegexNFA::new("(?<d>\\d)-(?&d)".to_string());
+        assert_eq!(
+            regex_nfa.match_spans("1-2"),
+            Some(vec![Some((0, 3)), Some((0, 1))])
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let pattern = "^(\\((?1)?\\))$".to_string();
+        let options = RegexOptions::new().recursion_limit(2);
+        let regex_nfa = RegexNFA::with_options(pattern.clone(), options);
+        assert!(regex_nfa.matches("((()))"));
+        assert!(!regex_nfa.matches("(((())))"));
+        assert!(RegexNFA::new(pattern).matches("(((())))"));
+
+        // Left recursion never consumes anything and is cut off by the limit
+        let regex_nfa = RegexNFA::new("(?R)?a".to_string());
+        assert!(regex_nfa.matches("a"));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // Every way of splitting the a's between the groups fails on the `c`
+        let pattern = "(a|aa)*\\1c".to_string();
+        let input = "a".repeat(30);
+        let options = RegexOptions::new().step_limit(Some(1000));
+        let regex_nfa = RegexNFA::with_options(pattern.clone(), options);
+        assert_eq!(
+            regex_nfa.try_matches(&input),
+            Err(MatchError::StepLimitExceeded { limit: 1000 })
+        );
+        assert!(!regex_nfa.matches(&input));
+        assert_eq!(regex_nfa.match_spans(&input), None);
+
+        // A match found within the limit is reported as usual
+        assert_eq!(regex_nfa.try_matches("aac"), Ok(true));
+        assert_eq!(
+            regex_nfa.try_match_spans_at("xaac", 1),
+            Ok(Some(vec![Some((1, 4)), Some((1, 2))]))
+        );
+
+        // The steps of all attempts count towards the limit
+        let options = RegexOptions::new().step_limit(Some(50));
+        let regex_nfa = RegexNFA::with_options("(a)\\1".to_string(), options);
+        assert!(regex_nfa.try_matches(&"ab".repeat(100)).is_err());
+        assert_eq!(regex_nfa.try_matches("aa"), Ok(true));
+    }
+
+    #[test]
+    fn test_search_context_reuse() {
+        // One context for every search, left over mid-search by the first
+        let mut ctx = SearchContext::default();
+        let limited = RegexNFA::with_options(
+            "(a|aa)*\\1c".to_string(),
+            RegexOptions::new().step_limit(Some(1000)),
+        );
+        let input = "a".repeat(30);
+        assert!(limited
+            .engine
+            .try_find(&mut ctx, &input, 0, false, None, |_| true)
+            .is_err());
+
+        let cases = [
+            ("(a)(b)?(c)", "xacb"),
+            ("(\\w)\\1", "abccd"),
+            ("\\((?:[^()]|(?R))*\\)", "f((a)(b))"),
+            ("(?<n>[ab])(?(<n>)c|d)", "bc"),
+            ("(a*)*b", "aaab"),
+            ("(x)|(a)", "ab"),
+        ];
+        for (pattern, input) in cases {
+            let engine = RegexNFA::new(pattern.to_string()).engine;
+            assert_eq!(
+                engine.try_find(&mut ctx, input, 0, false, None, |_| true),
+                Ok(engine.find(input, 0, false, None, |_| true)),
+                "{} on {}",
+                pattern,
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_start_anchor() {
+        let regex_nfa = RegexNFA::new("\\G\\d".to_string());
+        assert!(regex_nfa.matches("1a"));
+        assert!(!regex_nfa.matches("a1"));
+
+        // Scanning token by token stops at the first gap
+        let mut tokens = Vec::new();
+        let mut from = 0;
+        while let Some(spans) = regex_nfa.match_spans_at("12a3", from) {
+            let (start, end) = spans[0].unwrap();
+            tokens.push(start);
+            from = end;
+        }
+        assert_eq!(tokens, vec![0, 1]);
+
+        // Without \G the search is free to skip ahead
+        let regex_nfa = RegexNFA::new("\\d".to_string());
+        assert_eq!(
+            regex_nfa.match_spans_at("12a3", 2).unwrap()[0],
+            Some((3, 4))
+        );
+    }
+
+    #[test]
+    fn test_quantifier_priorities() {
+        let cases = [
+            ("(a*)(a*)", vec![Some((0, 3)), Some((0, 3)), Some((3, 3))]),
+            ("(a*?)(a*)", vec![Some((0, 3)), Some((0, 0)), Some((0, 3))]),
+            ("(a+?)(a*)", vec![Some((0, 3)), Some((0, 1)), Some((1, 3))]),
+            ("(a??)(a*)", vec![Some((0, 3)), Some((0, 0)), Some((0, 3))]),
+            ("(a?)(a*)", vec![Some((0, 3)), Some((0, 1)), Some((1, 3))]),
+            (
+                "(a{1,2}?)(a*)",
+                vec![Some((0, 3)), Some((0, 1)), Some((1, 3))],
+            ),
+            (
+                "(a{1,2})(a*)",
+                vec![Some((0, 3)), Some((0, 2)), Some((2, 3))],
+            ),
+            ("(a|aa)(a*)", vec![Some((0, 3)), Some((0, 1)), Some((1, 3))]),
+            ("(a*?)$", vec![Some((0, 3)), Some((0, 3))]),
+            (
+                "(a??)(a)\\2",
+                vec![Some((0, 2)), Some((0, 0)), Some((0, 1))],
+            ),
+        ];
+        for (pattern, expected) in cases {
+            // The backtracker, the Pike VM and one-pass all follow the same
+            // priorities
+            for options in [RegexOptions::new(), RegexOptions::new().dfa_cache_limit(0)] {
+                let regex_nfa = RegexNFA::with_options(pattern.to_string(), options.clone());
+                let anchored = RegexNFA::with_options(format!("^{}", pattern), options);
+                assert_eq!(
+                    regex_nfa.match_spans("aaa"),
+                    Some(expected.clone()),
+                    "{}",
+                    pattern
+                );
+                assert_eq!(
+                    anchored.match_spans("aaa"),
+                    Some(expected.clone()),
+                    "^{}",
+                    pattern
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_match_progress() {
+        let all = |pattern: &str, input: &str| {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let mut cursor = Cursor::new(0);
+            let mut found = Vec::new();
+            while let Some(spans) = regex_nfa.next_match(input, &mut cursor) {
+                found.push(spans[0].unwrap());
+            }
+            found
+        };
+        assert_eq!(all("a*", "baaa"), [(0, 0), (1, 4)]);
+        assert_eq!(all("a*", ""), [(0, 0)]);
+        assert_eq!(all("x*", "aé"), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(all("a?", "aba"), [(0, 1), (2, 3)]);
+        assert_eq!(all("a*?", "aa"), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(all("\\d+", "1 23"), [(0, 1), (2, 4)]);
+        assert_eq!(all("^a*", "aab"), [(0, 2)]);
+
+        // Starting further in
+        let regex_nfa = RegexNFA::new("b*".to_string());
+        let mut cursor = Cursor::new(1);
+        assert_eq!(
+            regex_nfa.next_match("abba", &mut cursor).unwrap()[0],
+            Some((1, 3))
+        );
+        assert_eq!(cursor.at, 3);
+        assert_eq!(
+            regex_nfa.next_match("abba", &mut cursor).unwrap()[0],
+            Some((4, 4))
+        );
+        assert_eq!(regex_nfa.next_match("abba", &mut cursor), None);
+    }
+
+    #[test]
+    fn test_search_start_in_alternation() {
+        let regex_nfa = RegexNFA::new("\\Ga|b".to_string());
+        assert_eq!(regex_nfa.match_spans_at("xab", 1).unwrap()[0], Some((1, 2)));
+        assert_eq!(regex_nfa.match_spans_at("xab", 0).unwrap()[0], Some((2, 3)));
+        assert_eq!(regex_nfa.match_spans_at("ab", 2), None);
+    }
+
+    #[test]
+    fn test_class_set_operations() {
+        let regex_nfa = RegexNFA::new("^[a-z&&[^aeiou]]+$".to_string());
+        assert!(regex_nfa.matches("rhythm"));
+        assert!(!regex_nfa.matches("rhyme"));
+
+        let regex_nfa = RegexNFA::new("[0-9--[05]]".to_string());
+        assert!(regex_nfa.matches("x7"));
+        assert!(!regex_nfa.matches("x50"));
+    }
+
+    #[test]
+    fn test_grapheme_cluster() {
+        let regex_nfa = RegexNFA::new("^\\X$".to_string());
+        assert!(regex_nfa.matches("e\u{301}"));
+        assert!(regex_nfa.matches("🇫🇷"));
+        assert!(regex_nfa.matches("\r\n"));
+        assert!(!regex_nfa.matches("ab"));
+
+        // A cluster is never split, so `.` can't pick up its tail
+        let regex_nfa = RegexNFA::new("^\\X.$".to_string());
+        assert!(!regex_nfa.matches("e\u{301}"));
+        assert_eq!(
+            RegexNFA::new("\\X{2}".to_string()).match_spans("👍🏽!x"),
+            Some(vec![Some((0, 3))])
+        );
+    }
+
+    #[test]
+    fn test_any_linebreak() {
+        let regex_nfa = RegexNFA::new("^a\\Rb$".to_string());
+        assert!(regex_nfa.matches("a\nb"));
+        assert!(regex_nfa.matches("a\r\nb"));
+        assert!(regex_nfa.matches("a\rb"));
+        assert!(regex_nfa.matches("a\u{2028}b"));
+        assert!(!regex_nfa.matches("a\n\nb"));
+
+        // \r\n is one line ending, not \r followed by another one
+        assert!(!RegexNFA::new("^\\R\\R$".to_string()).matches("\r\n"));
+        assert!(RegexNFA::new("^\\R{2}$".to_string()).matches("\r\n\n"));
+    }
+
+    #[test]
+    fn test_dangling_quantifier() {
+        assert!(matches!(
+            RegexNFA::try_new("*abc".to_string()),
+            Err(CompileError::Syntax(SyntaxError::NothingToRepeat(0)))
+        ));
+        assert!(RegexNFA::try_new("a*bc".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let error = CompileError::TooLarge {
+            limit: DEFAULT_SIZE_LIMIT,
+        };
+        // Thousands of copies of a class with hundreds of ranges
+        assert!(matches!(RegexNFA::try_new("\\w".repeat(2000)), Err(e) if e == error));
+        assert!(RegexNFA::try_new("a{1,100}".to_string()).is_ok());
+
+        let options = RegexOptions::new().size_limit(1000);
+        assert!(matches!(
+            RegexNFA::try_with_options("a{1,100}".to_string(), options.clone()),
+            Err(CompileError::TooLarge { limit: 1000 })
+        ));
+        assert!(RegexNFA::try_with_options("abc".to_string(), options).is_ok());
+    }
+
+    #[test]
+    fn test_malformed_patterns_are_errors() {
+        let unsupported = |pattern: &str| match RegexNFA::try_new(pattern.to_string()) {
+            Err(CompileError::Unsupported(reason)) => reason,
+            other => panic!("{:?} compiled to {:?}", pattern, other.map(|_| ())),
+        };
+        assert_eq!(unsupported("(a"), "unbalanced parentheses");
+    }
+
+    #[test]
+    fn test_empty_patterns_and_alternatives() {
+        // Each matches the empty string wherever nothing else matches
+        for (pattern, haystack, expected) in [
+            ("", "abc", Some((0, 0))),
+            ("", "", Some((0, 0))),
+            ("a|", "ba", Some((0, 0))),
+            ("a|", "ab", Some((0, 1))),
+            ("|a", "ab", Some((0, 0))),
+            ("x(|a)b", "xab", Some((0, 3))),
+            ("x(|a)b", "xb", Some((0, 2))),
+            ("x()y", "xy", Some((0, 2))),
+            ("x(?:a||b)y", "xy", Some((0, 2))),
+            ("^$", "", Some((0, 0))),
+            ("^(|a)$", "b", None),
+        ] {
+            let regex = RegexNFA::new(pattern.to_string());
+            let found = regex.find(haystack).map(|m| (m.start(), m.end()));
+            assert_eq!(found, expected, "{:?} in {:?}", pattern, haystack);
+        }
+        let caps = RegexNFA::new("x(|a)b".to_string()).captures("xb").unwrap();
+        assert_eq!(caps.get(1).map(|m| m.as_str()), Some(""));
+    }
+
+    #[test]
+    fn test_long_patterns_build_linear_nfas() {
+        // Every atom and every concat adds two states, nothing is duplicated
+        let pattern = "a.".repeat(5000);
+        let regex_nfa = RegexNFA::new(pattern.clone());
+        assert_eq!(regex_nfa.engine.states.len(), 4 * pattern.len() - 2);
+
+        let words: Vec<String> = (0..2000).map(|i| format!("w{}", i)).collect();
+        let regex_nfa = RegexNFA::new(words.join("|"));
+        assert!(regex_nfa.matches("a w1999 b"));
+        assert!(!regex_nfa.matches("w"));
+    }
+
+    #[test]
+    fn test_literal_runs() {
+        // A single transition for the whole run
+        let regex_nfa = RegexNFA::new("error code".to_string());
+        assert_eq!(regex_nfa.engine.states.len(), 2);
+        assert!(regex_nfa.matches("an error code!"));
+        assert!(!regex_nfa.matches("an error cod"));
+
+        // Every engine finds what it finds with a char at a time
+        let patterns = [
+            "(ab|abc)cd",
+            "x(yz)*y",
+            "ab+ab",
+            "(héllo)+ wörld",
+            "(foo|foobar)\\1",
+        ];
+        let inputs = [
+            "abcd",
+            "abccd",
+            "xyzyzy",
+            "abbbab",
+            "héllohéllo wörld",
+            "foobarfoobar",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let engine = &regex_nfa.engine;
+            let chars = engine.split_literals();
+            assert!(engine.states.len() < chars.states.len(), "{}", pattern);
+            for input in inputs {
+                assert_eq!(
+                    engine.find(input, 0, false, None, |_| true),
+                    chars.find(input, 0, false, None, |_| true),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+                if engine.supports_pike() {
+                    assert_eq!(
+                        engine.pike_find(input, 0, false, |_| true),
+                        chars.pike_find(input, 0, false, |_| true),
+                        "{} on {:?}",
+                        pattern,
+                        input
+                    );
+                }
+                if engine.is_regular() {
+                    assert_eq!(
+                        engine.is_match_linear(input, 0, false, |_| true),
+                        chars.is_match_linear(input, 0, false, |_| true),
+                        "{} on {:?}",
+                        pattern,
+                        input
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_repeat_copies_are_independent() {
+        let regex_nfa = RegexNFA::new("(a|b){2,3}c".to_string());
+        assert!(regex_nfa.matches("abc"));
+        assert!(regex_nfa.matches("babc"));
+        assert!(!regex_nfa.matches("ac"));
+        assert_eq!(
+            regex_nfa.match_spans("xbabc"),
+            Some(vec![Some((1, 5)), Some((3, 4))])
+        );
+        let regex_nfa = RegexNFA::new("x{0}y".to_string());
+        assert!(regex_nfa.matches("y"));
+        assert!(!regex_nfa.matches("x"));
+    }
+
+    #[test]
+    #[should_panic(expected = "nothing to repeat at position 2")]
+    fn test_dangling_quantifier_panics_in_new() {
+        RegexNFA::new("a|*".to_string());
+    }
+
+    #[test]
+    fn test_multi_line_haystack() {
+        let haystack = "first line\nkey:\n  value\r\nlast";
+
+        let regex_nfa = RegexNFA::new("key:\\n +(\\w+)".to_string());
+        assert_eq!(
+            regex_nfa.match_spans(haystack),
+            Some(vec![Some((11, 23)), Some((18, 23))])
+        );
+
+        let regex_nfa = RegexNFA::new("value\\Rlast$".to_string());
+        assert!(regex_nfa.matches(haystack));
+
+        // `.` stops at line breaks unless (?s) is set
+        assert!(!RegexNFA::new("line.key".to_string()).matches(haystack));
+        assert!(RegexNFA::new("(?s)line.key".to_string()).matches(haystack));
+        assert!(RegexNFA::new("^first.*$".to_string()).matches("first\n"));
+    }
+
+    #[test]
+    fn test_multi_line() {
+        let haystack = "first line\nkey: a\nvalue b\r\nlast";
+        let multi_line = RegexOptions::new().multi_line(true);
+        for options in [multi_line.clone(), multi_line.clone().dfa_cache_limit(0)] {
+            let spans = |pattern: &str, from: usize| {
+                RegexNFA::with_options(pattern.to_string(), options.clone())
+                    .match_spans_at(haystack, from)
+                    .map(|spans| spans[0])
+            };
+            assert_eq!(spans("^v\\w+", 0), Some(Some((18, 23))));
+            assert_eq!(spans("^\\w+", 12), Some(Some((18, 23))));
+            assert_eq!(spans("^\\w+", 11), Some(Some((11, 14))));
+            assert_eq!(spans("e$", 0), Some(Some((9, 10))));
+            assert_eq!(spans("a$", 0), Some(Some((16, 17))));
+            assert_eq!(spans("b$", 0), Some(Some((24, 25))));
+            // Matches may span lines
+            assert_eq!(spans("^key: a\\nvalue", 0), Some(Some((11, 23))));
+            assert_eq!(spans("^(\\w)\\1$", 0), None);
+            assert!(
+                RegexNFA::with_options("^last$".to_string(), options.clone()).matches(haystack)
+            );
+            assert!(RegexNFA::with_options("^(l)a".to_string(), options).matches(haystack));
+        }
+
+        // Otherwise only the whole input counts
+        assert!(!RegexNFA::new("^last$".to_string()).matches(haystack));
+        assert!(!RegexNFA::new("a$".to_string()).matches(haystack));
+        let regex_nfa = RegexNFA::with_options("^(a)\\1$".to_string(), multi_line);
+        assert_eq!(
+            regex_nfa.match_spans("x\naa\ny"),
+            Some(vec![Some((2, 4)), Some((2, 3))])
+        );
+    }
+
+    #[test]
+    fn test_line_start_within_pattern() {
+        let spans = |pattern: &str, options: RegexOptions, input: &str| {
+            RegexNFA::with_options(pattern.to_string(), options)
+                .match_spans(input)
+                .map(|spans| spans[0])
+        };
+        let single = RegexOptions::new();
+        let multi_line = RegexOptions::new().multi_line(true);
+        for options in [single.clone(), single.clone().dfa_cache_limit(0)] {
+            assert_eq!(
+                spans("(?:^|,)b", options.clone(), "b,b"),
+                Some(Some((0, 1)))
+            );
+            assert_eq!(
+                spans("(?:^|,)b", options.clone(), "ab,b"),
+                Some(Some((2, 4)))
+            );
+            // Only one alternative is anchored
+            assert_eq!(spans("^a|b", options.clone(), "xb"), Some(Some((1, 2))));
+            assert_eq!(spans("b|^a", options.clone(), "xa"), None);
+            assert_eq!(spans("a^b", options.clone(), "ab"), None);
+            assert_eq!(spans("^$", options.clone(), ""), Some(Some((0, 0))));
+            assert_eq!(spans("^", options.clone(), "ab"), Some(Some((0, 0))));
+        }
+
+        // After a `\n` only in multi-line mode, keyed on the byte before
+        assert_eq!(spans("a\n^b", single, "a\nb"), None);
+        assert_eq!(
+            spans("a\n^b", multi_line.clone(), "a\nb"),
+            Some(Some((0, 3)))
+        );
+        assert_eq!(
+            spans("x|^b", multi_line.clone(), "a\nb"),
+            Some(Some((2, 3)))
+        );
+        assert_eq!(spans("(^b)\\1", multi_line, "ab\nbb"), Some(Some((3, 5))));
+    }
+
+    #[test]
+    fn test_line_end_within_pattern() {
+        let multi_line = RegexOptions::new().multi_line(true);
+        for backend in [Backend::Auto, Backend::Backtrack, Backend::PikeVm] {
+            let spans = |pattern: &str, input: &str| {
+                RegexNFA::with_options(pattern.to_string(), RegexOptions::new().backend(backend))
+                    .match_spans(input)
+                    .map(|spans| spans[0])
+            };
+            // Only one alternative is anchored
+            assert_eq!(spans("a|b$", "bx a"), Some(Some((3, 4))));
+            assert_eq!(spans("a$|b", "ab"), Some(Some((1, 2))));
+            assert_eq!(spans("(?:,|$)x", "x"), None);
+            assert_eq!(spans("b(?:,|$)", "ab"), Some(Some((1, 2))));
+            assert_eq!(spans("b(?:,|$)", "ab\n"), Some(Some((1, 2))));
+            assert_eq!(spans("a$b", "ab"), None);
+            assert_eq!(spans("$", "ab"), Some(Some((2, 2))));
+        }
+        let regex_nfa = RegexNFA::with_options("a(?:$|,)".to_string(), multi_line);
+        assert_eq!(
+            regex_nfa.match_spans("ba\nb").map(|spans| spans[0]),
+            Some(Some((1, 2)))
+        );
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        for backend in [Backend::Auto, Backend::Backtrack, Backend::PikeVm] {
+            let regex = |pattern: &str| {
+                RegexNFA::with_options(pattern.to_string(), RegexOptions::new().backend(backend))
+            };
+            assert_eq!(
+                regex("\\bcat\\b").match_spans("bobcat cat"),
+                Some(vec![Some((7, 10))])
+            );
+            assert_eq!(
+                regex("\\Bcat").match_spans("cat bobcat"),
+                Some(vec![Some((7, 10))])
+            );
+            assert!(!regex("a\\bb").matches("ab"));
+            assert!(regex("\\b").matches("a"));
+            assert!(!regex("\\b").matches(" - "));
+        }
+        assert!(!RegexNFA::new("\\bx".to_string()).matches("üx"));
+        assert!(RegexNFA::new("(?-u)\\bx".to_string()).matches("üx"));
+    }
+
+    #[test]
+    fn test_can_match_newline() {
+        let can_match = |pattern: &str| RegexNFA::new(pattern.to_string()).can_match_newline();
+        assert!(can_match("a\\nb"));
+        assert!(can_match("a\\Rb"));
+        assert!(can_match("\\s+"));
+        assert!(can_match("(?s)a.b"));
+        assert!(can_match("[^x]"));
+        assert!(!can_match("a.b"));
+        assert!(!can_match("\\w+\\d"));
+        assert!(!can_match("[^\\nx]"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let options = RegexOptions::new().case_insensitive(true);
+        let regex_nfa = RegexNFA::with_options("hello [a-c]+\\w".to_string(), options);
+        assert!(regex_nfa.matches("HeLLo AbCx"));
+        assert!(!regex_nfa.matches("HeLLo d"));
+
+        let regex_nfa = RegexNFA::new("(?i)straße".to_string());
+        assert!(regex_nfa.matches("STRAßE"));
+        assert!(!RegexNFA::new("straße".to_string()).matches("STRAßE"));
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let smart = |pattern: &str| {
+            RegexNFA::with_options(pattern.to_string(), RegexOptions::new().smart_case(true))
+        };
+        assert!(smart("error").matches("ERROR: disk full"));
+        assert!(smart("[e]rror").matches("ERROR"));
+        assert!(!smart("Error").matches("ERROR"));
+        assert!(!smart("[E]rror").matches("ERROR"));
+        // Escapes and group names aren't letters of the pattern
+        assert!(smart("\\Gerror\\R").matches("ERROR\n"));
+        assert!(smart("(?<Word>x)y").matches("XY"));
+        // An explicit flag still wins
+        assert!(!smart("(?-i)error").matches("ERROR"));
+    }
+
+    #[test]
+    fn test_word() {
+        let word = |pattern: &str, options: RegexOptions| {
+            RegexNFA::with_options(pattern.to_string(), options.word(true))
+        };
+        for backend in [Backend::Auto, Backend::Backtrack, Backend::PikeVm] {
+            let regex_nfa = word("cat", RegexOptions::new().backend(backend));
+            assert!(regex_nfa.matches("a cat."));
+            assert!(regex_nfa.matches("cat"));
+            assert!(!regex_nfa.matches("cats"));
+            assert!(!regex_nfa.matches("bobcat"));
+            // Past a match that isn't a word to one that is
+            assert_eq!(regex_nfa.match_spans("cats cat"), Some(vec![Some((5, 8))]));
+            let regex_nfa = word("a+", RegexOptions::new().backend(backend));
+            assert_eq!(regex_nfa.match_spans("baa aa"), Some(vec![Some((4, 6))]));
+        }
+        // Only the chars around the match count, not what it starts or ends with
+        assert!(word("-x", RegexOptions::new()).matches("a -x b"));
+        assert!(!word("-x", RegexOptions::new()).matches("a-x b"));
+        assert!(!word("x", RegexOptions::new()).matches("üx"));
+        assert!(word("x", RegexOptions::new().ascii(true)).matches("üx"));
+        // Groups keep their numbers
+        let regex_nfa = word("(a)(b)?", RegexOptions::new());
+        assert_eq!(
+            regex_nfa.match_spans("ab a"),
+            Some(vec![Some((0, 2)), Some((0, 1)), Some((1, 2))])
+        );
+        let lazy_dfa = RegexOptions::new().backend(Backend::LazyDfa);
+        assert!(RegexNFA::try_with_options("cat".to_string(), lazy_dfa.word(true)).is_err());
+    }
+
+    #[test]
+    fn test_whole_line() {
+        let whole_line = |pattern: &str, options: RegexOptions| {
+            RegexNFA::with_options(pattern.to_string(), options.whole_line(true))
+        };
+        for backend in [
+            Backend::Auto,
+            Backend::Backtrack,
+            Backend::PikeVm,
+            Backend::LazyDfa,
+        ] {
+            let regex_nfa = whole_line("a|ab", RegexOptions::new().backend(backend));
+            assert_eq!(regex_nfa.match_spans("ab"), Some(vec![Some((0, 2))]));
+            assert!(regex_nfa.matches("a\n"));
+            assert!(!regex_nfa.matches("abc"));
+            assert!(!regex_nfa.matches("cab"));
+        }
+        // Anchors already in the pattern change nothing
+        assert!(whole_line("^a+$", RegexOptions::new()).matches("aaa"));
+        let regex_nfa = whole_line("b+", RegexOptions::new().multi_line(true));
+        assert_eq!(regex_nfa.match_spans("bc\nbb\n"), Some(vec![Some((3, 5))]));
+    }
+
+    #[test]
+    fn test_linear_time_on_ambiguous_patterns() {
+        // Exponential for a backtracking search
+        let input = "a".repeat(40);
+        assert!(!RegexNFA::new("(a|a)*b".to_string()).matches(&input));
+        assert!(!RegexNFA::new("^(a*)*b".to_string()).matches(&input));
+        assert!(RegexNFA::new("(a|a)*$".to_string()).matches(&input));
+    }
+
+    #[test]
+    fn test_linear_and_backtracking_agree() {
+        let patterns = [
+            "a+b",
+            "^ab*$",
+            "c?d$",
+            "(x|yz)+w",
+            "\\d{2,3}",
+            "^\\Gab",
+            "a\\Rb",
+            "\\X\\X$",
+            "[a-c&&[^b]]+",
+            "(?i)hello",
+            "q*",
+        ];
+        let inputs = [
+            "",
+            "ab",
+            "aab",
+            "abbb",
+            "cd\n",
+            "xyzw",
+            "12",
+            "a\r\nb",
+            "e\u{301}x",
+            "acac",
+            "HeLLo",
+            "zzz",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert!(regex_nfa.regular, "{}", pattern);
+            for input in inputs {
+                assert_eq!(
+                    regex_nfa.matches(input),
+                    regex_nfa.match_spans(input).is_some(),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+        assert!(!RegexNFA::new("(a)\\1".to_string()).regular);
+    }
+
+    #[test]
+    fn test_lazy_dfa_agrees_with_simulation() {
+        let patterns = [
+            "a+b",
+            "^ab*$",
+            "c?d$",
+            "(x|yz)+w",
+            "\\d{2,3}",
+            "(?i)hello",
+            "[^a]b",
+            "q*",
+        ];
+        let inputs = [
+            "", "ab", "aab", "abbb", "cd\n", "xyzw", "12", "HeLLo", "bb", "zzz",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert!(regex_nfa.lazy_dfa, "{}", pattern);
+            for input in inputs {
+                // Twice, the second time from the cache
+                for _ in 0..2 {
+                    assert_eq!(
+                        regex_nfa.matches(input),
+                        regex_nfa.match_spans(input).is_some(),
+                        "{} on {:?}",
+                        pattern,
+                        input
+                    );
+                }
+            }
+        }
+        assert!(!RegexNFA::new("a\\Rb".to_string()).lazy_dfa);
+    }
+
+    #[test]
+    fn test_lazy_dfa_cache_limit() {
+        // (a|b)*a(a|b){8} needs hundreds of DFA states
+        let pattern = "(a|b)*a(a|b){8}$".to_string();
+        let input = "abbabaabbbababbaabab".repeat(4);
+        let expected =
+            RegexNFA::with_options(pattern.clone(), RegexOptions::new().dfa_cache_limit(0))
+                .matches(&input);
+        for limit in [2, 16, 4096] {
+            let options = RegexOptions::new().dfa_cache_limit(limit);
+            let regex_nfa = RegexNFA::with_options(pattern.clone(), options);
+            let mut cache = regex_nfa.create_cache();
+            assert_eq!(
+                regex_nfa.matches_with(&mut cache, &input),
+                expected,
+                "limit {}",
+                limit
+            );
+            assert!(cache.dfa.unwrap().cached_states() <= limit);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<RegexNFA>();
+
+        let regex_nfa = RegexNFA::new("(\\w+)@(\\w+)\\.com".to_string());
+        let lines: Vec<String> = (0..200)
+            .map(|i| format!("user{}@host{}.com", i, i % 7))
+            .collect();
+        std::thread::scope(|scope| {
+            for chunk in lines.chunks(50) {
+                let regex_nfa = &regex_nfa;
+                scope.spawn(move || {
+                    // Half with a cache of their own, half from the pool
+                    let mut cache = regex_nfa.create_cache();
+                    for (i, line) in chunk.iter().enumerate() {
+                        let spans = if i % 2 == 0 {
+                            regex_nfa.match_spans_at_with(&mut cache, line, 0)
+                        } else {
+                            regex_nfa.match_spans(line)
+                        };
+                        let at = line.find('@').unwrap();
+                        assert_eq!(spans.unwrap()[1], Some((0, at)), "{}", line);
+                        assert!(!regex_nfa.matches_with(&mut cache, &line[at..]));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_memoized_backtracking() {
+        // Exponential without the memo: every a can be either branch
+        let regex_nfa = RegexNFA::new("(a|a)*b".to_string());
+        let input = "a".repeat(64);
+        assert_eq!(
+            regex_nfa
+                .engine
+                .find(&input, 0, false, None, |_| true)
+                .map(|(s, e, _)| (s, e)),
+            None
+        );
+        let input = format!("{}b", input);
+        assert_eq!(
+            regex_nfa.match_spans(&input),
+            Some(vec![Some((0, 65)), Some((63, 64))])
+        );
+    }
+
+    #[test]
+    fn test_pike_vm_agrees_with_backtracking() {
+        let patterns = [
+            "(a+)(b*)",
+            "(a|ab)(c|bcd)(d*)",
+            "(a*?)(a*)",
+            "(a??)(a+)",
+            "((a)|b)+",
+            "(x(y)?)+?z",
+            "(?:(a)|(b))*c",
+            "^(\\w+)\\s(\\w+)$",
+            "(é+)(.)",
+            "\\G(ab)",
+            "(a{2,3})(a*)",
+            "(a*)*b",
+        ];
+        let inputs = [
+            "",
+            "aab",
+            "abcd",
+            "aaaa",
+            "abab",
+            "xyxz",
+            "xyxyz",
+            "abac",
+            "hi there\n",
+            "ééx",
+            "abab",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let engine = &regex_nfa.engine;
+            assert!(engine.supports_pike(), "{}", pattern);
+            for input in inputs {
+                for anchored in [false, true] {
+                    assert_eq!(
+                        engine.pike_find(input, 0, anchored, |_| true),
+                        engine.find(input, 0, anchored, None, |_| true),
+                        "{} on {:?}",
+                        pattern,
+                        input
+                    );
+                }
+                let at_end = |index: usize| index == input.len();
+                let second = input.char_indices().nth(1).map_or(input.len(), |(o, _)| o);
+                assert_eq!(
+                    engine.pike_find(input, second, false, at_end),
+                    engine.find(input, second, false, None, at_end),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+        assert!(!RegexNFA::new("(a)\\1".to_string()).engine.supports_pike());
+        assert!(!RegexNFA::new("a\\R".to_string()).engine.supports_pike());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let options = RegexOptions::new().match_kind(MatchKind::LeftmostLongest);
+        for pattern in ["^(\\w+)=(\\d+)$", "(a|ab)(c|bcd)", "(\\w)\\1", "a{2,4}"] {
+            let regex_nfa = RegexNFA::with_options(pattern.to_string(), options.clone());
+            let json = serde_json::to_string(&regex_nfa).unwrap();
+            let loaded: RegexNFA = serde_json::from_str(&json).unwrap();
+            for input in ["key=42", "abcd", "xaay", "aaaaa", ""] {
+                assert_eq!(loaded.matches(input), regex_nfa.matches(input));
+                assert_eq!(loaded.match_spans(input), regex_nfa.match_spans(input));
+            }
+        }
+
+        let named = RegexNFA::new("(?<key>\\w+)=".to_string());
+        let loaded: RegexNFA =
+            serde_json::from_str(&serde_json::to_string(&named).unwrap()).unwrap();
+        assert_eq!(&loaded.captures("k=v").unwrap()["key"], "k");
+
+        let mut value: serde_json::Value =
+            serde_json::to_value(RegexNFA::new("ab".to_string())).unwrap();
+        value["engine"]["start_state"] = 1000.into();
+        assert!(serde_json::from_value::<RegexNFA>(value).is_err());
+    }
+
+    #[test]
+    fn test_leftmost_longest() {
+        let longest = RegexOptions::new().match_kind(MatchKind::LeftmostLongest);
+        let cases = [
+            ("a|ab", "xab", vec![Some((1, 2))], vec![Some((1, 3))]),
+            ("a*?", "aaa", vec![Some((0, 0))], vec![Some((0, 3))]),
+            (
+                "(a|ab)(c|bcd)?",
+                "abcd",
+                vec![Some((0, 4)), Some((0, 1)), Some((1, 4))],
+                vec![Some((0, 4)), Some((0, 1)), Some((1, 4))],
+            ),
+            (
+                "(a|aa)\\1?",
+                "aaaa",
+                vec![Some((0, 2)), Some((0, 1))],
+                vec![Some((0, 4)), Some((0, 2))],
+            ),
+            ("(?:a|ab)$", "ab\n", vec![Some((0, 2))], vec![Some((0, 2))]),
+        ];
+        for (pattern, input, first, longest_spans) in cases {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert_eq!(regex_nfa.match_spans(input), Some(first), "{}", pattern);
+            let regex_nfa = RegexNFA::with_options(pattern.to_string(), longest.clone());
+            assert_eq!(
+                regex_nfa.match_spans(input),
+                Some(longest_spans),
+                "{}",
+                pattern
+            );
+        }
+        let regex_nfa = RegexNFA::with_options("b|bc".to_string(), longest);
+        assert_eq!(regex_nfa.match_spans("abc"), Some(vec![Some((1, 3))]));
+        assert_eq!(regex_nfa.match_spans("xyz"), None);
+    }
+
+    #[test]
+    fn test_one_pass_agrees_with_backtracking() {
+        let patterns = [
+            "(\\w+)=(\\d+)",
+            "([a-z]*):\\s(.*)",
+            "(a+)(b*)",
+            "(a*?)b",
+            "(x(y)?)+z",
+            "(é+)(x?)",
+            "(a|b)c|d",
+            "a*",
+            "(a)?b",
+        ];
+        let inputs = [
+            "", "key=42", "key=", "ab: c", "aab", "abb", "xyxz", "ééx", "ac", "d",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let engine = &regex_nfa.engine;
+            let Some(one_pass) = engine.one_pass() else {
+                panic!("{} should be one-pass", pattern);
+            };
+            for input in inputs {
+                let at_end = |index: usize| index == input.len();
+                assert_eq!(
+                    one_pass.find(engine, input, 0, |_| true),
+                    engine.find(input, 0, true, None, |_| true),
+                    "{} on {:?}",
+                    pattern,
+                    input
+                );
+                assert_eq!(
+                    one_pass.find(engine, input, 0, at_end),
+                    engine.find(input, 0, true, None, at_end),
+                    "{} on {:?} up to the end",
+                    pattern,
+                    input
+                );
+            }
+        }
+        for pattern in ["(a|ab)", "\\w+\\d", "a*a", "(a)\\1", "\\Ga"] {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert!(regex_nfa.engine.one_pass().is_none(), "{}", pattern);
+        }
+
+        let regex_nfa = RegexNFA::new("^(\\w+)=(\\d+)$".to_string());
+        assert!(regex_nfa.one_pass.is_some());
+        assert_eq!(
+            regex_nfa.match_spans("key=42\n"),
+            Some(vec![Some((0, 6)), Some((0, 3)), Some((4, 6))])
+        );
+        assert_eq!(regex_nfa.match_spans("key=4x"), None);
+    }
+
+    #[test]
+    fn test_anchored_search() {
+        let regex_nfa = RegexNFA::new("a+b".to_string());
+        let engine = &regex_nfa.engine;
+        assert_eq!(engine.find("xaab", 0, true, None, |_| true), None);
+        assert_eq!(
+            engine
+                .find("xaab", 1, true, None, |_| true)
+                .map(|(s, e, _)| (s, e)),
+            Some((1, 4))
+        );
+        assert_eq!(
+            engine
+                .find("xaab", 0, false, None, |_| true)
+                .map(|(s, e, _)| (s, e)),
+            Some((1, 4))
+        );
+
+        let regex_nfa = RegexNFA::new("^(a)b".to_string());
+        assert_eq!(
+            regex_nfa.match_spans("abab"),
+            Some(vec![Some((0, 2)), Some((0, 1))])
+        );
+        assert_eq!(regex_nfa.match_spans_at("abab", 2), None);
+        assert_eq!(regex_nfa.match_spans("xab"), None);
+        assert_eq!(regex_nfa.match_spans_at("ab", 5), None);
+    }
+
+    #[test]
+    fn test_prefilter() {
+        let regex_nfa = RegexNFA::new("ab+c".to_string());
+        assert!(regex_nfa.prefilter.is_some());
+        assert!(regex_nfa.matches("xxabxabbc"));
+        assert!(!regex_nfa.matches("xxabxabb"));
+        assert_eq!(regex_nfa.match_spans("xxabxabbc"), Some(vec![Some((5, 9))]));
+        assert_eq!(
+            regex_nfa.match_spans_at("ñabcabc", 2),
+            Some(vec![Some((4, 7))])
+        );
+
+        let regex_nfa = RegexNFA::new("^ab".to_string());
+        assert!(regex_nfa.matches("abc"));
+        assert!(!regex_nfa.matches("xab"));
+
+        let regex_nfa = RegexNFA::new("(ERROR|WARN) \\d+".to_string());
+        assert!(matches!(
+            regex_nfa.prefilter,
+            Some(Prefilter::Alternatives(_))
+        ));
+        assert!(regex_nfa.matches("12:00 WARN 3"));
+        assert!(!regex_nfa.matches("12:00 WARN x, ERROR y"));
+        assert_eq!(
+            regex_nfa.match_spans("ERROR x WARN 42"),
+            Some(vec![Some((8, 15)), Some((8, 12))])
+        );
+
+        let regex_nfa = RegexNFA::new("([0-9]+):\\1".to_string());
+        assert!(matches!(regex_nfa.prefilter, Some(Prefilter::FirstByte(_))));
+        assert_eq!(
+            regex_nfa.match_spans("at 12:3 and 12:12"),
+            Some(vec![Some((12, 17)), Some((12, 14))])
+        );
+
+        // Overlapping occurrences are all tried
+        let regex_nfa = RegexNFA::new("(aa)\\1b".to_string());
+        assert_eq!(
+            regex_nfa.match_spans("aaaaab"),
+            Some(vec![Some((1, 6)), Some((1, 3))])
+        );
+    }
+
+    #[test]
+    fn test_states_indexed_by_id() {
+        for pattern in [
+            "a",
+            "(a|bc)*d",
+            "(?<x>a{2,4})?\\k<x>",
+            "(a)?(?(1)a|b)+?",
+            "((a)(?1))",
+        ] {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let states = &regex_nfa.engine.states;
+            assert!(states.iter().enumerate().all(|(i, state)| state.id == i));
+        }
+    }
+
+    #[test]
+    fn test_greedy_and_lazy_quantifiers() {
+        let spans = |pattern: &str, input: &str| {
+            RegexNFA::new(pattern.to_string())
+                .match_spans(input)
+                .unwrap()[0]
+        };
+        assert_eq!(spans("a*", "aaa"), Some((0, 3)));
+        assert_eq!(spans("a*?", "aaa"), Some((0, 0)));
+        assert_eq!(spans("a+?", "aaa"), Some((0, 1)));
+        assert_eq!(spans("a??b", "ab"), Some((0, 2)));
+        assert_eq!(spans("<.+?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(spans("<.+>", "<a><b>"), Some((0, 6)));
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let regex_nfa = RegexNFA::new("^\\d{3}-\\d{2,4}$".to_string());
+        assert!(regex_nfa.matches("123-45"));
+        assert!(regex_nfa.matches("123-4567"));
+        assert!(!regex_nfa.matches("12-45"));
+        assert!(!regex_nfa.matches("123-45678"));
+
+        let regex_nfa = RegexNFA::new("^(ab){2,}$".to_string());
+        assert!(!regex_nfa.matches("ab"));
+        assert!(regex_nfa.matches("ababab"));
+
+        let regex_nfa = RegexNFA::new("^a{0}b$".to_string());
+        assert!(regex_nfa.matches("b"));
+        assert!(!regex_nfa.matches("ab"));
+
+        // Not a repetition, the braces are literal
+        assert!(RegexNFA::new("a{,2}".to_string()).matches("a{,2}"));
+    }
+
+    #[test]
+    fn test_large_counted_repetition() {
+        let spans = |pattern: &str, input: &str| {
+            RegexNFA::new(pattern.to_string())
+                .match_spans(input)
+                .map(|spans| spans[0])
+        };
+        // A counter instead of a copy per repetition
+        let regex_nfa = RegexNFA::new("a{1,100000}".to_string());
+        assert!(regex_nfa.engine.states.len() < 10);
+        assert!(!regex_nfa.regular);
+
+        let a = |n: usize| "a".repeat(n);
+        let exact = RegexNFA::new("^a{1000,2000}$".to_string());
+        assert!(exact.matches(&a(1000)));
+        assert!(exact.matches(&a(2000)));
+        assert!(!exact.matches(&a(999)));
+        assert!(!exact.matches(&a(2001)));
+        assert_eq!(spans("a{1,2000}", &a(3000)), Some(Some((0, 2000))));
+        assert_eq!(spans("a{1,2000}?", &a(3000)), Some(Some((0, 1))));
+        assert_eq!(spans("a{1500,}", &a(1600)), Some(Some((0, 1600))));
+        assert_eq!(spans("a{1500,}?", &a(1600)), Some(Some((0, 1500))));
+        assert_eq!(spans("a{1500,}", &a(1499)), None);
+
+        // Groups report their last repetition, copies and nesting count apart
+        let regex_nfa = RegexNFA::new("(ab){1000}c".to_string());
+        let input = format!("{}c", "ab".repeat(1000));
+        assert_eq!(
+            regex_nfa.match_spans(&input),
+            Some(vec![Some((0, 2001)), Some((1998, 2000))])
+        );
+        assert_eq!(
+            spans("(?:a{600}b){2}", &format!("{0}b{0}b", a(600))),
+            Some(Some((0, 1202)))
+        );
+        assert_eq!(spans("(?:(?:a{600}){2}b){600}", "ab"), None);
+        // Empty repetitions count too
+        assert_eq!(spans("^(?:a?){2000}$", ""), Some(Some((0, 0))));
+        assert_eq!(spans("^(?:a?){2000}$", &a(10)), Some(Some((0, 10))));
+
+        // Failing paths are memoized with their counts, not retried
+        let options = RegexOptions::new().step_limit(Some(1_000_000));
+        let regex_nfa = RegexNFA::with_options("(?:(?:\\w*\\w*)*c){200}".to_string(), options);
+        assert_eq!(regex_nfa.try_matches(&"ab".repeat(8)), Ok(false));
+    }
+
+    #[test]
+    fn test_lazy_counted_repetition() {
+        let spans = |pattern: &str, input: &str| {
+            RegexNFA::new(pattern.to_string())
+                .match_spans(input)
+                .unwrap()[0]
+        };
+        assert_eq!(spans("a{2,4}", "aaaaa"), Some((0, 4)));
+        assert_eq!(spans("a{2,4}?", "aaaaa"), Some((0, 2)));
+        assert_eq!(spans("a{2,}", "aaaaa"), Some((0, 5)));
+        assert_eq!(spans("a{2,}?", "aaaaa"), Some((0, 2)));
+        assert_eq!(spans("a{2,4}?b", "aaab"), Some((0, 4)));
+    }
+
+    #[test]
+    fn test_quantified_group_ending_in_quantifier() {
+        let spans = |pattern: &str, input: &str| {
+            RegexNFA::new(pattern.to_string())
+                .match_spans(input)
+                .unwrap()[0]
+        };
+        // The `?` applies to the group, not as a lazy marker to the `*`
+        assert_eq!(spans("(?:a*)?", "aa"), Some((0, 2)));
+        assert_eq!(spans("(?:a{1,2})?", "aa"), Some((0, 2)));
+        assert_eq!(spans("(?:a*?)?", "aa"), Some((0, 0)));
+        assert_eq!(spans("(?:a*)??", "aa"), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let patterns = [
+            "a+b",
+            "^ab*$",
+            "(x|yz)+w",
+            "\\d{2,3}",
+            "(?i)hello",
+            "(a)(b)?",
+            "q*",
+        ];
+        let inputs = ["", "ab", "aab", "cd\n", "xyzw", "x12", "HeLLo", "abb"];
+        let backends = [Backend::Backtrack, Backend::PikeVm, Backend::LazyDfa];
+        for pattern in patterns {
+            let auto = RegexNFA::new(pattern.to_string());
+            for backend in backends {
+                let options = RegexOptions::new().backend(backend);
+                let regex_nfa = RegexNFA::try_with_options(pattern.to_string(), options).unwrap();
+                for input in inputs {
+                    assert_eq!(
+                        regex_nfa.match_spans(input),
+                        auto.match_spans(input),
+                        "{} with {:?} on {:?}",
+                        pattern,
+                        backend,
+                        input
+                    );
+                    assert_eq!(regex_nfa.matches(input), auto.matches(input));
+                }
+            }
+        }
+        let backtrack = RegexOptions::new().backend(Backend::Backtrack);
+        let regex_nfa = RegexNFA::with_options("a+b".to_string(), backtrack);
+        assert!(!regex_nfa.regular && !regex_nfa.pike && !regex_nfa.lazy_dfa);
+    }
+
+    #[test]
+    fn test_backend_that_cannot_run_the_pattern() {
+        let compile = |pattern: &str, options: RegexOptions| {
+            RegexNFA::try_with_options(pattern.to_string(), options)
+        };
+        let pike_vm = RegexOptions::new().backend(Backend::PikeVm);
+        let lazy_dfa = RegexOptions::new().backend(Backend::LazyDfa);
+        assert!(matches!(
+            compile("(a)\\1", pike_vm.clone()),
+            Err(CompileError::Unsupported(_))
+        ));
+        assert!(compile("(a)\\1", lazy_dfa.clone()).is_err());
+        assert!(compile("a\\Rb", lazy_dfa.clone()).is_err());
+        assert!(compile("ab", lazy_dfa.dfa_cache_limit(0)).is_err());
+        let backtrack = RegexOptions::new().backend(Backend::Backtrack);
+        assert!(compile("(a)\\1", backtrack).unwrap().matches("aa"));
+    }
 }
@@ -0,0 +1,179 @@
+//! Matcher for one-pass NFAs, filling in captures without backtracking.
+//!
+//! An NFA is one-pass when, from any state the search can be in, the next
+//! char decides which consuming transition to follow: the transitions
+//! reachable through epsilons never share a char. Then an anchored search
+//! only ever has one live thread, plus at most one match found earlier to
+//! fall back on, and the capture positions are those of that single path.
+//! Many practical patterns qualify, e.g. `^(\w+)=(\d+)$` or `^[a-z]*:\s`;
+//! `^(a|ab)` and `^\w+\d` don't.
+//!
+//! Like the lazy DFA, only NFAs made of plain char transitions are
+//! supported. The epsilon closure of every state a search can stop at is
+//! computed up front, in the order the backtracking engine would follow it,
+//! so the match and groups reported are the ones `Engine::find` reports.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::{record_capture, Engine, Slots};
+use crate::regex::lazy_dfa::LazyDfa;
+
+/// Building the closures gives up past this many transitions in total.
+const MAX_ONE_PASS_TRANSITIONS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub struct OnePass {
+    // Indexed by state id; empty for states no consuming transition leads to
+    closures: Vec<Closure>,
+    start_state: usize,
+}
+
+/// What can follow a state without consuming input.
+#[derive(Debug, Clone, Default)]
+struct Closure {
+    // Consuming transitions as (state, transition index), most preferred
+    // first, with the group markers passed on the way to them
+    transitions: Vec<(usize, usize, Vec<Matcher>)>,
+    // Reaching the end state: how many transitions are preferred over it, and
+    // the group markers passed on the way
+    end: Option<(usize, Vec<Matcher>)>,
+}
+
+impl Engine {
+    /// The one-pass matcher for this NFA, or `None` if it isn't one-pass or
+    /// uses anything besides plain char transitions.
+    pub fn one_pass(&self) -> Option<OnePass> {
+        if !LazyDfa::supports(self) {
+            return None;
+        }
+        let mut closures = vec![Closure::default(); self.states.len()];
+        let mut total = 0;
+        let entries = self.states.iter().flat_map(|state| {
+            state
+                .transitions
+                .iter()
+                .filter(|(matcher, _)| !matcher.is_epsilon())
+                .map(|(_, next)| *next)
+        });
+        let mut done = vec![false; self.states.len()];
+        for id in core::iter::once(self.start_state).chain(entries) {
+            if done[id] {
+                continue;
+            }
+            done[id] = true;
+            let closure = self.one_pass_closure(id)?;
+            total += closure.transitions.len();
+            if total > MAX_ONE_PASS_TRANSITIONS {
+                return None;
+            }
+            closures[id] = closure;
+        }
+        Some(OnePass {
+            closures,
+            start_state: self.start_state,
+        })
+    }
+
+    /// The closure of `state`, or `None` if two of its consuming transitions
+    /// match the same char.
+    fn one_pass_closure(&self, state: usize) -> Option<Closure> {
+        let mut closure = Closure::default();
+        let mut seen = vec![false; self.states.len()];
+        // Depth first, through the transitions of each state in order, like
+        // the backtracking engine; a state reached again is only reached by
+        // a less preferred path
+        let mut stack: Vec<(usize, usize, Vec<Matcher>)> = vec![(state, 0, Vec::new())];
+        seen[state] = true;
+        while let Some(top) = stack.last_mut() {
+            let (id, transition) = (top.0, top.1);
+            if id == self.end_state {
+                let (_, _, markers) = stack.pop().expect("Stack is not empty");
+                closure.end = Some((closure.transitions.len(), markers));
+                continue;
+            }
+            let Some((matcher, next)) = self.states[id].transitions.get(transition) else {
+                stack.pop();
+                continue;
+            };
+            top.1 += 1;
+            let mut markers = top.2.clone();
+            if !matcher.is_epsilon() {
+                let ambiguous = closure.transitions.iter().any(|(s, t, _)| {
+                    let (other, _) = &self.states[*s].transitions[*t];
+                    matcher.overlaps(other)
+                });
+                if ambiguous {
+                    return None;
+                }
+                closure.transitions.push((id, transition, markers));
+                continue;
+            }
+            if seen[*next] {
+                continue;
+            }
+            seen[*next] = true;
+            if matches!(matcher, Matcher::GroupStart(_) | Matcher::GroupEnd(_)) {
+                markers.push(matcher.clone());
+            }
+            stack.push((*next, 0, markers));
+        }
+        Some(closure)
+    }
+}
+
+impl OnePass {
+    /// The same as `Engine::find` for an anchored search: the match starting
+    /// at byte offset `from`, if any.
+    pub fn find<F>(
+        &self,
+        engine: &Engine,
+        input: &str,
+        from: usize,
+        accept: F,
+    ) -> Option<(usize, usize, Slots)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut slots = Slots::new();
+        let mut state = self.start_state;
+        let mut index = from;
+        // A match found earlier, for when the path taken from there fails
+        let mut fallback = None;
+        loop {
+            let closure = &self.closures[state];
+            let c = input[index..].chars().next();
+            let taken = c.and_then(|c| {
+                closure.transitions.iter().position(|(s, t, _)| {
+                    let (matcher, _) = &engine.states[*s].transitions[*t];
+                    matcher.matches(c)
+                })
+            });
+
+            if let Some((preferred_over, markers)) = &closure.end {
+                if accept(index) {
+                    let mut matched = slots.clone();
+                    for marker in markers {
+                        record_capture(&mut matched, marker, index);
+                    }
+                    // Paths listed after the end state lose to this match
+                    if taken.is_none_or(|position| *preferred_over <= position) {
+                        return Some((from, index, matched));
+                    }
+                    fallback = Some((from, index, matched));
+                }
+            }
+
+            let (Some(c), Some(position)) = (c, taken) else {
+                return fallback;
+            };
+            let (s, t, markers) = &closure.transitions[position];
+            for marker in markers {
+                record_capture(&mut slots, marker, index);
+            }
+            index += c.len_utf8();
+            state = engine.states[*s].transitions[*t].1;
+        }
+    }
+}
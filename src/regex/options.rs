@@ -0,0 +1,182 @@
+use crate::regex::engine::{DEFAULT_RECURSION_LIMIT, DEFAULT_SIZE_LIMIT};
+use crate::regex::lazy_dfa::DEFAULT_DFA_CACHE_LIMIT;
+
+/// Which match is reported when several start at the same place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchKind {
+    /// The one the pattern prefers, as in Perl: alternatives are tried left
+    /// to right and quantifiers take as much as they can (or as little, when
+    /// lazy). `a|ab` matches `a` in `ab`.
+    #[default]
+    LeftmostFirst,
+    /// The longest one, as in POSIX grep. `a|ab` matches `ab` in `ab`; among
+    /// the ways of matching that much, groups are reported for the one the
+    /// pattern prefers.
+    LeftmostLongest,
+}
+
+/// Which engine runs the searches of a `RegexNFA`. Forcing one is for
+/// debugging a mismatch or comparing engines; `Auto` is never slower. The
+/// literal prefilter skipping to where matches can start still runs with
+/// every engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+    /// The fastest engine that can run the pattern, picked for each search:
+    /// Shift-Or, the lazy DFAs, the one-pass matcher, the Pike VM or
+    /// backtracking.
+    #[default]
+    Auto,
+    /// Backtracking for everything. Runs any pattern, in exponential time
+    /// for some.
+    Backtrack,
+    /// The linear-time simulations: Thompson's NFA simulation for whether
+    /// there is a match, the Pike VM for its span and groups. Patterns with
+    /// backreferences, calls or conditionals fail to compile.
+    PikeVm,
+    /// The lazy DFAs for whether there is a match and where it starts, the
+    /// Pike VM or backtracking only for its end and groups. A DFA whose
+    /// cache keeps filling up still gives way to the NFA simulation.
+    /// Patterns the DFA can't run, such as those with `\G`, `\X` or
+    /// backreferences, fail to compile, and so does any with
+    /// `dfa_cache_limit` 0.
+    LazyDfa,
+}
+
+/// Compile-time options for a `RegexNFA`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RegexOptions {
+    /// Restrict `.`, `\d`, `\w` and `\s` to ASCII. The same as starting the
+    /// pattern with `(?-u)`.
+    pub ascii: bool,
+    /// Let `.` match `\n` and `\r` too. The same as starting the pattern
+    /// with `(?s)`.
+    pub dot_matches_new_line: bool,
+    /// Ignore case. The same as starting the pattern with `(?i)`.
+    pub case_insensitive: bool,
+    /// Ignore case unless the pattern contains an uppercase letter.
+    pub smart_case: bool,
+    /// How deeply `(?R)` / `(?1)` calls may nest. Deeper paths simply fail to
+    /// match, which also stops left-recursive patterns from running away.
+    pub recursion_limit: usize,
+    /// How many states the lazy DFA may cache before starting over. Bounds
+    /// its memory use (the reverse DFA finding match starts has a cache of
+    /// its own); 0 disables both.
+    pub dfa_cache_limit: usize,
+    /// How many steps a backtracking search may take before it is given up
+    /// on, so patterns from untrusted sources can't tie up the CPU; `None`
+    /// for no limit. The `try_` searches report it as an error, the others
+    /// as no match. The linear-time engines don't count steps.
+    pub step_limit: Option<usize>,
+    /// Roughly how many bytes the compiled NFA may take. Larger patterns,
+    /// like `a{1,100000}`, fail to compile instead of eating up memory.
+    pub size_limit: usize,
+    /// Which of the matches starting leftmost `match_spans` reports.
+    pub match_kind: MatchKind,
+    /// Let `^` and `$` match at the start and end of every line rather than
+    /// of the whole input, for searching buffers of several lines at once.
+    pub multi_line: bool,
+    /// Which engine runs the searches.
+    pub backend: Backend,
+    /// Only match where no word char comes right before or right after the
+    /// match, as `grep -w` does: `cat` then matches in `a cat.` but not in
+    /// `cats`. The pattern is compiled between two word-boundary assertions,
+    /// so a search goes on to later matches past one that isn't a word.
+    pub word: bool,
+    /// Only match the whole input, or the whole line in multi-line mode, as
+    /// `grep -x` does: the pattern is anchored as if it were between `^`
+    /// and `$`, so `a|ab` matches `ab` but not `abc`.
+    pub whole_line: bool,
+}
+
+impl Default for RegexOptions {
+    fn default() -> Self {
+        RegexOptions {
+            ascii: false,
+            dot_matches_new_line: false,
+            case_insensitive: false,
+            smart_case: false,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            dfa_cache_limit: DEFAULT_DFA_CACHE_LIMIT,
+            step_limit: None,
+            size_limit: DEFAULT_SIZE_LIMIT,
+            match_kind: MatchKind::LeftmostFirst,
+            multi_line: false,
+            backend: Backend::Auto,
+            word: false,
+            whole_line: false,
+        }
+    }
+}
+
+impl RegexOptions {
+    pub fn new() -> Self {
+        RegexOptions::default()
+    }
+
+    pub fn ascii(mut self, yes: bool) -> Self {
+        self.ascii = yes;
+        self
+    }
+
+    pub fn dot_matches_new_line(mut self, yes: bool) -> Self {
+        self.dot_matches_new_line = yes;
+        self
+    }
+
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    pub fn smart_case(mut self, yes: bool) -> Self {
+        self.smart_case = yes;
+        self
+    }
+
+    pub fn recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit;
+        self
+    }
+
+    pub fn dfa_cache_limit(mut self, limit: usize) -> Self {
+        self.dfa_cache_limit = limit;
+        self
+    }
+
+    pub fn step_limit(mut self, limit: Option<usize>) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    pub fn size_limit(mut self, bytes: usize) -> Self {
+        self.size_limit = bytes;
+        self
+    }
+
+    pub fn match_kind(mut self, kind: MatchKind) -> Self {
+        self.match_kind = kind;
+        self
+    }
+
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.multi_line = yes;
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn word(mut self, yes: bool) -> Self {
+        self.word = yes;
+        self
+    }
+
+    pub fn whole_line(mut self, yes: bool) -> Self {
+        self.whole_line = yes;
+        self
+    }
+}
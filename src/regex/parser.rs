@@ -1,129 +1,397 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::str::Chars;
+
+use crate::regex::elements::check_class;
+use crate::regex::error::SyntaxError;
+
+/// Inline flags in effect at a given point of the pattern. They start out from
+/// the compile options and can be changed by `(?flags)` / `(?flags:...)` groups.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Flags {
+    pub ascii: bool,
+    pub dot_all: bool,
+    pub case_insensitive: bool,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Plus,
     Star,
     Question,
-    Literal(char),
+    Lazy, // A `?` right after a quantifier, making it lazy
+    Literal(char, Flags),
     EndRef,
     StartRef,
-    ComplexLiteral(String),
+    LineStart, // A `^` not anchoring the whole pattern, matched where it stands
+    LineEnd,   // A `$` not anchoring the whole pattern, matched where it stands
+    WordBoundary(bool, Flags), // \b, or \B when negated
+    ComplexLiteral(String, Flags),
     LBracket,
     RBracket,
+    Group(usize),                 // Closes capture group n, right after its RBracket
+    Backref(usize),               // \1 or \k<name>
+    Call(usize),                  // (?R) for n = 0, (?n) or (?&name) otherwise
+    SearchStart,                  // \G, where the search started
+    Grapheme,                     // \X, one extended grapheme cluster
+    Linebreak,                    // \R, any line ending
+    Repeat(usize, Option<usize>), // {n}, {n,} or {n,m}, None meaning unbounded
+    CondElse,                     // Separates the branches of a conditional
+    Conditional(usize),           // Closes a conditional on group n, right after its RBracket
+    Empty,                        // An empty pattern, alternative, group or conditional branch
     Concat,
     Or,
     None,
 }
 
-fn parse(input: &str) -> Vec<Token> {
+/// The kind of a group that has been opened but not closed yet.
+enum OpenGroup {
+    NonCapturing,
+    Capture(usize),
+    Conditional { group: usize, has_else: bool },
+    // (?|...): every alternative numbers its groups from `base` on
+    BranchReset { base: usize, max: usize },
+}
+
+/// The name and index of a named group.
+pub type GroupName = (String, usize);
+
+fn parse(input: &str, mut flags: Flags) -> Result<(Vec<Token>, Vec<GroupName>), SyntaxError> {
     let mut tokens = Vec::new();
     let mut chars = input.chars().peekable();
+    let char_count = input.chars().count();
+    // Position of the char just taken from `chars`
+    let position = |chars: &Peekable<Chars>| char_count - chars.clone().count() - 1;
     let mut current_token = Token::None;
+    // Flags to restore when each open group is closed, and what it is
+    let mut open_groups: Vec<(Flags, OpenGroup)> = Vec::new();
+    let mut group_count = 0;
+    let mut group_names: Vec<(String, usize)> = Vec::new();
+    // The group numbers referred to and where, checked once every group
+    // is known since a call may come before the group it calls
+    let mut references: Vec<(usize, usize)> = Vec::new();
+    // How many `[` of the current character class are still open, and
+    // where it started
+    let mut class_depth = 0;
+    let mut class_start = 0;
 
     while let Some(c) = chars.next() {
         match c {
             '+' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('+');
                 } else {
+                    check_repeatable(&tokens, false, position(&chars))?;
                     tokens.push(Token::Plus);
                 }
             }
             '*' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('*');
                 } else {
+                    check_repeatable(&tokens, false, position(&chars))?;
                     tokens.push(Token::Star);
                 }
             }
             '?' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('?');
                 } else {
-                    tokens.push(Token::Question);
+                    check_repeatable(&tokens, true, position(&chars))?;
+                    if tokens.last().is_some_and(is_quantifier) {
+                        tokens.push(Token::Lazy);
+                    } else {
+                        tokens.push(Token::Question);
+                    }
                 }
             }
             '$' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('$');
                 } else {
                     tokens.push(Token::EndRef);
                 }
             }
             '^' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('^');
-                } else {
+                } else if tokens.is_empty() {
                     tokens.push(Token::StartRef);
+                } else {
+                    tokens.push(Token::LineStart);
                 }
             }
             '|' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('|');
+                } else if let Some((_, OpenGroup::BranchReset { base, max })) =
+                    open_groups.last_mut()
+                {
+                    *max = (*max).max(group_count);
+                    group_count = *base;
+                    close_operand(&mut tokens);
+                    tokens.push(Token::Or);
+                } else if let Some((_, OpenGroup::Conditional { has_else, .. })) =
+                    open_groups.last_mut()
+                {
+                    if *has_else {
+                        return Err(SyntaxError::TooManyBranches(position(&chars)));
+                    }
+                    *has_else = true;
+                    if tokens.last() == Some(&Token::LBracket) {
+                        tokens.push(Token::Empty);
+                    }
+                    tokens.push(Token::CondElse);
                 } else {
+                    close_operand(&mut tokens);
                     tokens.push(Token::Or);
                 }
             }
+            '{' => {
+                let at = position(&chars);
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
+                    s.push('{');
+                } else if let Some((min, max)) = parse_repetition(&mut chars, at)? {
+                    check_repeatable(&tokens, false, position(&chars))?;
+                    tokens.push(Token::Repeat(min, max));
+                } else {
+                    // Not a valid repetition, so it's just a brace
+                    tokens.push(Token::Literal('{', flags));
+                }
+            }
             '[' => {
-                current_token = Token::ComplexLiteral(String::from('['));
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
+                    // A nested class, e.g. the operand of `[a-z&&[^aeiou]]`
+                    s.push('[');
+                } else {
+                    current_token = Token::ComplexLiteral(String::from('['), flags);
+                    class_start = position(&chars);
+                }
+                class_depth += 1;
             }
             ']' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push(']');
-                    tokens.push(current_token);
-                    current_token = Token::None;
+                    class_depth -= 1;
+                    if class_depth == 0 {
+                        check_class(s, flags.ascii, class_start)?;
+                        tokens.push(current_token);
+                        current_token = Token::None;
+                    }
                 } else {
-                    panic!("Unmatched closing bracket in regex");
+                    return Err(SyntaxError::UnmatchedBracket(position(&chars)));
                 }
             }
             '(' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('(');
+                } else if chars.peek() == Some(&'?') {
+                    let at = position(&chars);
+                    chars.next();
+                    if let Some(name) = parse_group_name(&mut chars, at)? {
+                        group_count += 1;
+                        group_names.push((name, group_count));
+                        open_groups.push((flags, OpenGroup::Capture(group_count)));
+                        tokens.push(Token::LBracket);
+                        continue;
+                    }
+                    if let Some(group) = parse_call(&mut chars, group_count, &group_names, at)? {
+                        references.push((group, at));
+                        tokens.push(Token::Call(group));
+                        continue;
+                    }
+                    if chars.peek() == Some(&'|') {
+                        chars.next();
+                        open_groups.push((
+                            flags,
+                            OpenGroup::BranchReset {
+                                base: group_count,
+                                max: group_count,
+                            },
+                        ));
+                        tokens.push(Token::LBracket);
+                        continue;
+                    }
+                    if chars.peek() == Some(&'(') {
+                        chars.next();
+                        let group = parse_condition(&mut chars, &group_names, at)?;
+                        references.push((group, at));
+                        open_groups.push((
+                            flags,
+                            OpenGroup::Conditional {
+                                group,
+                                has_else: false,
+                            },
+                        ));
+                        tokens.push(Token::LBracket);
+                        continue;
+                    }
+                    let (new_flags, scoped) = parse_flags(&mut chars, flags, at)?;
+                    if scoped {
+                        // (?flags:...) only applies until the group closes
+                        open_groups.push((flags, OpenGroup::NonCapturing));
+                        tokens.push(Token::LBracket);
+                    }
+                    // (?flags) applies until the enclosing group closes
+                    flags = new_flags;
                 } else {
+                    group_count += 1;
+                    open_groups.push((flags, OpenGroup::Capture(group_count)));
                     tokens.push(Token::LBracket);
                 }
             }
             ')' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push(')');
                 } else {
-                    tokens.push(Token::RBracket);
+                    let Some((outer, group)) = open_groups.pop() else {
+                        tokens.push(Token::RBracket);
+                        continue;
+                    };
+                    flags = outer;
+                    match group {
+                        OpenGroup::NonCapturing => {
+                            close_operand(&mut tokens);
+                            tokens.push(Token::RBracket);
+                        }
+                        OpenGroup::BranchReset { max, .. } => {
+                            // Later groups are numbered after the widest alternative
+                            group_count = group_count.max(max);
+                            close_operand(&mut tokens);
+                            tokens.push(Token::RBracket);
+                        }
+                        OpenGroup::Capture(index) => {
+                            close_operand(&mut tokens);
+                            tokens.push(Token::RBracket);
+                            tokens.push(Token::Group(index));
+                        }
+                        OpenGroup::Conditional { group, has_else } => {
+                            if tokens.last() == Some(&Token::LBracket) {
+                                tokens.push(Token::Empty);
+                            }
+                            // Both branches are always present, the else may be empty
+                            if !has_else {
+                                tokens.push(Token::CondElse);
+                            }
+                            if tokens.last() == Some(&Token::CondElse) {
+                                tokens.push(Token::Empty);
+                            }
+                            tokens.push(Token::RBracket);
+                            tokens.push(Token::Conditional(group));
+                        }
+                    }
                 }
             }
             '\\' => {
-                if let Some(next_char) = chars.next() {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
+                    // Control and hex escapes are resolved here; the rest are
+                    // kept for the class, escaped brackets not counting
+                    let at = position(&chars);
+                    match chars.next() {
+                        Some(next_char) => match control_escape(next_char) {
+                            Some(control) => s.push(control),
+                            None if next_char == 'x' => {
+                                let c = parse_hex_escape(&mut chars, at)?;
+                                s.push_str(&format!("\\x{{{:X}}}", c as u32));
+                            }
+                            None if next_char.is_alphanumeric()
+                                && !matches!(next_char, 'd' | 'w' | 's' | 'D' | 'W' | 'S') =>
+                            {
+                                return Err(SyntaxError::UnsupportedEscape {
+                                    escape: next_char,
+                                    position: at,
+                                });
+                            }
+                            None => {
+                                s.push('\\');
+                                s.push(next_char);
+                            }
+                        },
+                        None => return Err(SyntaxError::TrailingBackslash(at)),
+                    }
+                } else if let Some(next_char) = chars.next() {
+                    let at = position(&chars) - 1;
+                    if let Some(control) = control_escape(next_char) {
+                        tokens.push(Token::Literal(control, flags));
+                        continue;
+                    }
                     match next_char {
-                        'd' => tokens.push(Token::ComplexLiteral("d".to_string())), // Placeholder for digit
-                        'w' => tokens.push(Token::ComplexLiteral("w".to_string())), // Placeholder for word character
-                        's' => tokens.push(Token::ComplexLiteral("s".to_string())), // Placeholder for whitespace
-                        _ => tokens.push(Token::Literal(next_char)),
-                        // TODO: Handle back references and other escape sequences
+                        'd' | 'w' | 's' | 'D' | 'W' | 'S' => {
+                            // Placeholders for the classes, see `Matcher::create_complex_matcher`
+                            tokens.push(Token::ComplexLiteral(next_char.to_string(), flags))
+                        }
+                        'b' => tokens.push(Token::WordBoundary(false, flags)),
+                        'B' => tokens.push(Token::WordBoundary(true, flags)),
+                        'x' => {
+                            tokens.push(Token::Literal(parse_hex_escape(&mut chars, at)?, flags))
+                        }
+                        'G' => tokens.push(Token::SearchStart),
+                        'X' => tokens.push(Token::Grapheme),
+                        'R' => tokens.push(Token::Linebreak),
+                        '1'..='9' => {
+                            let group = next_char.to_digit(10).unwrap() as usize;
+                            references.push((group, at));
+                            tokens.push(Token::Backref(group))
+                        }
+                        'k' => {
+                            let name = parse_backref_name(&mut chars, at)?;
+                            tokens.push(Token::Backref(group_index(&group_names, name, at)?));
+                        }
+                        // Escaped punctuation stands for itself, letters and
+                        // digits are kept for escapes to come
+                        _ if next_char.is_alphanumeric() => {
+                            return Err(SyntaxError::UnsupportedEscape {
+                                escape: next_char,
+                                position: at,
+                            })
+                        }
+                        _ => tokens.push(Token::Literal(next_char, flags)),
                     }
                 } else {
-                    panic!("Invalid escape sequence in regex");
+                    return Err(SyntaxError::TrailingBackslash(position(&chars)));
                 }
             }
             '.' => {
-                if let Token::ComplexLiteral(ref mut s) = current_token {
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
                     s.push('.');
                 } else {
-                    tokens.push(Token::ComplexLiteral(".".to_string())); // Placeholder for dot
+                    tokens.push(Token::ComplexLiteral(".".to_string(), flags)); // Placeholder for dot
                 }
             }
             _ => {
-                if current_token == Token::None {
-                    tokens.push(Token::Literal(c));
+                if let Token::ComplexLiteral(ref mut s, _) = current_token {
+                    s.push(c);
                 } else {
-                    if let Token::ComplexLiteral(ref mut s) = current_token {
-                        s.push(c);
-                    } else {
-                        panic!("Unexpected character after complex literal start");
-                    }
+                    tokens.push(Token::Literal(c, flags));
                 }
             }
         }
+    }
+    if current_token != Token::None {
+        return Err(SyntaxError::UnclosedClass(class_start));
+    }
+    close_operand(&mut tokens);
+    if let Some(&(group, position)) = references.iter().find(|(group, _)| *group > group_count) {
+        return Err(SyntaxError::UnknownGroup { group, position });
+    }
 
-        if current_token != Token::None && chars.peek().is_none() {
-            panic!("Invalid regex");
+    // A leading `^` only anchors the whole pattern if it applies to every
+    // alternative of it. With nothing else to match, as in `^` or `^$`, it
+    // is all there is to the pattern
+    let anchors = tokens.iter().skip(1).any(|token| *token != Token::EndRef);
+    if tokens.first() == Some(&Token::StartRef) && (has_top_level_or(&tokens) || !anchors) {
+        tokens[0] = Token::LineStart;
+    }
+
+    // Likewise for a trailing `$`, and one anywhere else is matched where it
+    // stands
+    let top_level_or = has_top_level_or(&tokens);
+    let last = tokens.len().saturating_sub(1);
+    for (i, token) in tokens.iter_mut().enumerate() {
+        if *token == Token::EndRef && (i != last || i == 0 || top_level_or) {
+            *token = Token::LineEnd;
         }
     }
 
@@ -140,26 +408,342 @@ fn parse(input: &str) -> Vec<Token> {
         }
     }
 
-    final_tokens
+    Ok((final_tokens, group_names))
+}
+
+/// The char written as `\xHH` or `\x{H...}`, the `\x` at position `at`
+/// already taken from `chars`.
+fn parse_hex_escape(chars: &mut Peekable<Chars>, at: usize) -> Result<char, SyntaxError> {
+    let braced = chars.next_if_eq(&'{').is_some();
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_hexdigit()) {
+        digits.push(c);
+        if !braced && digits.len() == 2 {
+            break;
+        }
+    }
+    let closed = !braced || chars.next_if_eq(&'}').is_some();
+    let valid_length = if braced {
+        (1..=8).contains(&digits.len())
+    } else {
+        digits.len() == 2
+    };
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .filter(|_| closed && valid_length)
+        .and_then(char::from_u32)
+        .ok_or(SyntaxError::InvalidEscape(at))
+}
+
+/// The char written as `\n`, `\r`, `\t`, `\f` or `\v`.
+fn control_escape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'f' => Some('\u{000C}'),
+        'v' => Some('\u{000B}'),
+        _ => None,
+    }
+}
+
+fn is_quantifier(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Plus | Token::Star | Token::Question | Token::Repeat(..)
+    )
+}
+
+/// Checks that a quantifier found at `position` follows something it can
+/// repeat. A `?` right after another quantifier makes that one lazy instead.
+fn check_repeatable(
+    tokens: &[Token],
+    lazy_marker: bool,
+    position: usize,
+) -> Result<(), SyntaxError> {
+    let repeatable = match tokens {
+        [.., prev, last] if is_quantifier(last) => lazy_marker && !is_quantifier(prev),
+        [.., last] => !matches!(
+            last,
+            Token::Or
+                | Token::LBracket
+                | Token::CondElse
+                | Token::StartRef
+                | Token::EndRef
+                | Token::LineStart
+                | Token::WordBoundary(..)
+                | Token::Lazy
+        ),
+        [] => false,
+    };
+    if repeatable {
+        Ok(())
+    } else {
+        Err(SyntaxError::NothingToRepeat(position))
+    }
+}
+
+/// Whether `tokens` has a `|` outside of any group.
+fn has_top_level_or(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    for token in tokens {
+        match token {
+            Token::LBracket => depth += 1,
+            Token::RBracket => depth -= 1,
+            Token::Or if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The index of the group called `name`, referred to at position `at`.
+fn group_index(group_names: &[GroupName], name: String, at: usize) -> Result<usize, SyntaxError> {
+    group_names
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, i)| *i)
+        .ok_or(SyntaxError::UnknownGroupName { name, position: at })
+}
+
+/// Parses the name of a `(?P<name>...)` or `(?<name>...)` group, positioned
+/// right after the `?` of the group at `at`. Leaves `chars` untouched for
+/// any other kind of group.
+fn parse_group_name(chars: &mut Peekable<Chars>, at: usize) -> Result<Option<String>, SyntaxError> {
+    let mut lookahead = chars.clone();
+    if lookahead.peek() == Some(&'P') {
+        lookahead.next();
+    }
+    if lookahead.next() != Some('<') {
+        return Ok(None);
+    }
+    let name: String = lookahead.by_ref().take_while(|&c| c != '>').collect();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(SyntaxError::InvalidGroupName { name, position: at });
+    }
+    *chars = lookahead;
+    Ok(Some(name))
+}
+
+/// Parses a recursion or subroutine call, positioned right after the `(?`:
+/// `(?R)`, `(?0)`, `(?n)`, relative `(?-n)` / `(?+n)`, `(?&name)` and
+/// `(?P>name)`. Leaves `chars` untouched for any other kind of group.
+fn parse_call(
+    chars: &mut Peekable<Chars>,
+    group_count: usize,
+    group_names: &[GroupName],
+    at: usize,
+) -> Result<Option<usize>, SyntaxError> {
+    let mut lookahead = chars.clone();
+    let Some(first) = lookahead.next() else {
+        return Ok(None);
+    };
+    let group = match first {
+        'R' => {
+            if lookahead.next() != Some(')') {
+                return Ok(None);
+            }
+            0
+        }
+        '&' => call_target(&mut lookahead, group_names, at)?,
+        'P' => {
+            if lookahead.next() != Some('>') {
+                return Ok(None);
+            }
+            call_target(&mut lookahead, group_names, at)?
+        }
+        c @ ('0'..='9' | '-' | '+') => {
+            let mut number = String::new();
+            if c.is_ascii_digit() {
+                number.push(c);
+            }
+            while let Some(d) = lookahead.next_if(char::is_ascii_digit) {
+                number.push(d);
+            }
+            if number.is_empty() || lookahead.next() != Some(')') {
+                return Ok(None);
+            }
+            let n: usize = number
+                .parse()
+                .map_err(|_| SyntaxError::InvalidGroupReference(at))?;
+            match c {
+                // (?-1) is the most recently opened group, (?+1) the next one
+                '-' => (group_count + 1)
+                    .checked_sub(n)
+                    .filter(|g| *g > 0)
+                    .ok_or(SyntaxError::InvalidGroupReference(at))?,
+                '+' => group_count + n,
+                _ => n,
+            }
+        }
+        _ => return Ok(None),
+    };
+    *chars = lookahead;
+    Ok(Some(group))
+}
+
+/// Resolves the `name)` part of a named subroutine call to its group.
+fn call_target(
+    chars: &mut Peekable<Chars>,
+    group_names: &[GroupName],
+    at: usize,
+) -> Result<usize, SyntaxError> {
+    let name: String = chars.by_ref().take_while(|&c| c != ')').collect();
+    group_index(group_names, name, at)
+}
+
+/// Parses the condition of a `(?(cond)then|else)` group, positioned right after
+/// the inner `(`: a group number, `<name>`, `'name'` or a bare name.
+fn parse_condition(
+    chars: &mut Peekable<Chars>,
+    group_names: &[GroupName],
+    at: usize,
+) -> Result<usize, SyntaxError> {
+    let mut condition = String::new();
+    for c in chars.by_ref() {
+        if c == ')' {
+            let name = condition.trim_matches(|c| c == '<' || c == '>' || c == '\'');
+            if let Ok(group) = name.parse::<usize>() {
+                return Ok(group);
+            }
+            return group_index(group_names, name.to_string(), at);
+        }
+        condition.push(c);
+    }
+    Err(SyntaxError::UnclosedCondition(at))
+}
+
+/// Parses the `<name>` part of the `\k<name>` backreference at `at`.
+fn parse_backref_name(chars: &mut Peekable<Chars>, at: usize) -> Result<String, SyntaxError> {
+    if chars.next() != Some('<') {
+        return Err(SyntaxError::ExpectedGroupName(at));
+    }
+    let mut name = String::new();
+    for c in chars.by_ref() {
+        if c == '>' {
+            return Ok(name);
+        }
+        name.push(c);
+    }
+    Err(SyntaxError::UnclosedGroupName(at))
+}
+
+/// Parses the bounds of a `{n}`, `{n,}` or `{n,m}` repetition, positioned
+/// right after the `{` at `at`. Leaves `chars` untouched if it isn't one.
+fn parse_repetition(
+    chars: &mut Peekable<Chars>,
+    at: usize,
+) -> Result<Option<(usize, Option<usize>)>, SyntaxError> {
+    let mut lookahead = chars.clone();
+    let mut bounds = String::new();
+    for c in lookahead.by_ref() {
+        if c == '}' {
+            break;
+        }
+        if !c.is_ascii_digit() && c != ',' {
+            return Ok(None);
+        }
+        bounds.push(c);
+    }
+    let parsed = match bounds.split_once(',') {
+        Some((min, "")) => min.parse().ok().map(|min| (min, None)),
+        Some((min, max)) => min.parse().ok().zip(max.parse().ok().map(Some)),
+        None => bounds.parse().ok().map(|n| (n, Some(n))),
+    };
+    let Some((min, max)) = parsed else {
+        return Ok(None);
+    };
+    if max.is_some_and(|max| max < min) {
+        return Err(SyntaxError::InvalidRepetition {
+            bounds,
+            position: at,
+        });
+    }
+    *chars = lookahead;
+    Ok(Some((min, max)))
+}
+
+/// Parses the flag letters of the `(?...)` group at `at`, positioned right
+/// after the `?`. Returns the resulting flags and whether the group is
+/// scoped (`(?flags:...)`) rather than applying to the rest of the enclosing
+/// group (`(?flags)`).
+fn parse_flags(
+    chars: &mut Peekable<Chars>,
+    mut flags: Flags,
+    at: usize,
+) -> Result<(Flags, bool), SyntaxError> {
+    let mut negated = false;
+    for c in chars.by_ref() {
+        match c {
+            '-' => negated = true,
+            'u' => flags.ascii = negated,
+            's' => flags.dot_all = !negated,
+            'i' => flags.case_insensitive = !negated,
+            ':' => return Ok((flags, true)),
+            ')' => return Ok((flags, false)),
+            flag => return Err(SyntaxError::UnknownFlag { flag, position: at }),
+        }
+    }
+    Err(SyntaxError::UnclosedFlags(at))
+}
+
+/// Adds an `Empty` operand where the pattern, an alternative or a group
+/// ends before anything was matched, as in `a|`, `(|a)` or `()`.
+fn close_operand(tokens: &mut Vec<Token>) {
+    if matches!(tokens.last(), None | Some(Token::LBracket | Token::Or)) {
+        tokens.push(Token::Empty);
+    }
 }
 
 fn needs_concat(prev: &Token, next: &Token) -> bool {
     matches!(
         prev,
-        Token::Literal(_)
-            | Token::ComplexLiteral(_)
+        Token::Literal(..)
+            | Token::ComplexLiteral(..)
             | Token::RBracket
+            | Token::Group(_)
+            | Token::Conditional(_)
+            | Token::Backref(_)
+            | Token::Call(_)
+            | Token::SearchStart
+            | Token::LineStart
+            | Token::LineEnd
+            | Token::WordBoundary(..)
+            | Token::Grapheme
+            | Token::Linebreak
             | Token::Star
             | Token::Plus
             | Token::Question
+            | Token::Lazy
+            | Token::Repeat(..)
     ) && matches!(
         next,
-        Token::Literal(_) | Token::ComplexLiteral(_) | Token::LBracket
+        Token::Literal(..)
+            | Token::ComplexLiteral(..)
+            | Token::Backref(_)
+            | Token::Call(_)
+            | Token::SearchStart
+            | Token::LineStart
+            | Token::LineEnd
+            | Token::WordBoundary(..)
+            | Token::Grapheme
+            | Token::Linebreak
+            | Token::LBracket
     )
 }
 
-pub fn postfix_generator(input: &str) -> Vec<Token> {
-    let tokens = parse(input);
+pub fn postfix_generator(input: &str, flags: Flags) -> Result<Vec<Token>, SyntaxError> {
+    postfix_with_names(input, flags).map(|(tokens, _)| tokens)
+}
+
+/// Like `postfix_generator`, also returning the pattern's named groups.
+pub fn postfix_with_names(
+    input: &str,
+    flags: Flags,
+) -> Result<(Vec<Token>, Vec<GroupName>), SyntaxError> {
+    let (tokens, group_names) = parse(input, flags)?;
+    let starts_with = tokens.contains(&Token::StartRef);
+    let ends_with = tokens.contains(&Token::EndRef);
 
     // Remove startPrefix and endPrefix tokens
     let parsed_tokens: Vec<Token> = tokens
@@ -172,22 +756,40 @@ pub fn postfix_generator(input: &str) -> Vec<Token> {
 
     for token in parsed_tokens {
         match token {
-            Token::Literal(_) | Token::ComplexLiteral(_) => {
+            Token::Literal(..)
+            | Token::ComplexLiteral(..)
+            | Token::Backref(_)
+            | Token::Call(_)
+            | Token::SearchStart
+            | Token::LineStart
+            | Token::LineEnd
+            | Token::WordBoundary(..)
+            | Token::Grapheme
+            | Token::Linebreak
+            | Token::Empty => {
                 output.push(token);
             }
-            Token::Plus | Token::Star | Token::Question => {
-                stack.push(token);
+            Token::Group(_) | Token::Conditional(_) => {
+                // The group's contents were just completed by its RBracket
+                output.push(token);
             }
-            Token::Concat => {
+            Token::CondElse => {
+                // Completes the then-branch; the else-branch follows it in the
+                // output and the Conditional token takes both
                 while let Some(top) = stack.last() {
-                    if matches!(top, Token::Plus | Token::Star | Token::Question) {
+                    if *top != Token::LBracket {
                         output.push(stack.pop().unwrap());
                     } else {
                         break;
                     }
                 }
-                stack.push(token);
             }
+            Token::Plus | Token::Star | Token::Question | Token::Repeat(..) | Token::Lazy => {
+                // Quantifiers bind tightest and apply to the operand just
+                // completed; a trailing `?` marking them lazy stays after them
+                output.push(token);
+            }
+            Token::Concat => stack.push(token),
             Token::Or => {
                 while let Some(top) = stack.last() {
                     if *top != Token::LBracket && *top != Token::RBracket {
@@ -218,20 +820,24 @@ pub fn postfix_generator(input: &str) -> Vec<Token> {
     }
 
     // Add back the start and end references if they were present
-    let tokens = parse(input);
-    if tokens.contains(&Token::StartRef) {
+    if starts_with {
         output.insert(0, Token::StartRef);
     }
-    if tokens.contains(&Token::EndRef) {
+    if ends_with {
         output.push(Token::EndRef);
     }
 
-    return output;
+    Ok((output, group_names))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::regex::parser::{postfix_generator, Token};
+    use crate::regex::error::SyntaxError;
+    use crate::regex::parser::{postfix_generator, Flags, Token};
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
 
     fn to_string(tokens: Vec<Token>) -> String {
         tokens
@@ -239,22 +845,32 @@ mod tests {
             .map(|token| match token {
                 Token::Plus => "+".to_string(),
                 Token::Star => "*".to_string(),
-                Token::Question => "?".to_string(),
-                Token::Literal(c) => c.to_string(),
-                Token::EndRef => "$".to_string(),
-                Token::StartRef => "^".to_string(),
-                Token::ComplexLiteral(s) => s,
+                Token::Question | Token::Lazy => "?".to_string(),
+                Token::Literal(c, _) => c.to_string(),
+                Token::EndRef | Token::LineEnd => "$".to_string(),
+                Token::StartRef | Token::LineStart => "^".to_string(),
+                Token::ComplexLiteral(s, _) => s,
                 Token::LBracket => "(".to_string(),
                 Token::RBracket => ")".to_string(),
                 Token::Concat => ".".to_string(), // Concat is implicit
                 Token::Or => "|".to_string(),
+                Token::Backref(n) => format!("\\{}", n),
+                Token::Conditional(n) => format!("?({})", n),
+                Token::Call(n) => format!("(?{})", n),
+                Token::SearchStart => "\\G".to_string(),
+                Token::Grapheme => "\\X".to_string(),
+                Token::Linebreak => "\\R".to_string(),
+                Token::WordBoundary(false, _) => "\\b".to_string(),
+                Token::WordBoundary(true, _) => "\\B".to_string(),
+                Token::Repeat(min, Some(max)) => format!("{{{},{}}}", min, max),
+                Token::Repeat(min, None) => format!("{{{},}}", min),
                 _ => "".to_string(), // Handle other tokens if needed
             })
             .collect()
     }
 
     fn to_postfix(input: &str) -> String {
-        let tokens = postfix_generator(input);
+        let tokens = postfix_generator(input, Flags::default()).unwrap();
         to_string(tokens)
     }
 
@@ -335,7 +951,61 @@ mod tests {
 
     #[test]
     fn test_plus_and_question() {
-        assert_eq!(to_postfix("a+?"), "a?+");
+        assert_eq!(to_postfix("a+?"), "a+?");
+    }
+
+    #[test]
+    fn test_repetition() {
+        assert_eq!(to_postfix("a{2}b"), "a{2,2}b.");
+        assert_eq!(to_postfix("(ab){1,}"), "ab.{1,}");
+        assert_eq!(to_postfix("a{0,3}?|b"), "a{0,3}?b|");
+        assert_eq!(to_postfix("a{x}"), "a{x}...");
+        assert_eq!(to_postfix("[{]{2,}"), "[{]{2,}");
+    }
+
+    #[test]
+    fn test_repetition_out_of_order() {
+        assert_eq!(
+            postfix_generator("a{3,1}", Flags::default()),
+            Err(SyntaxError::InvalidRepetition {
+                bounds: "3,1".to_string(),
+                position: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_nested_charclass() {
+        assert_eq!(to_postfix("[a-z&&[^aeiou]]x"), "[a-z&&[^aeiou]]x.");
+        assert_eq!(to_postfix("[a-z--[aeiou]]+"), "[a-z--[aeiou]]+");
+    }
+
+    #[test]
+    fn test_nothing_to_repeat() {
+        let error = |input: &str| postfix_generator(input, Flags::default()).unwrap_err();
+        assert_eq!(error("*abc"), SyntaxError::NothingToRepeat(0));
+        assert_eq!(error("a|*"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("(+a)"), SyntaxError::NothingToRepeat(1));
+        assert_eq!(error("^?"), SyntaxError::NothingToRepeat(1));
+        assert_eq!(error("{2}"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("a**"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("a+??"), SyntaxError::NothingToRepeat(3));
+        assert_eq!(error("é|+"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("(a)(?(1)|*)"), SyntaxError::NothingToRepeat(9));
+    }
+
+    #[test]
+    fn test_quantifiers_that_repeat() {
+        assert_eq!(to_postfix("a??"), "a??");
+        assert_eq!(to_postfix("(a)+?"), "a+?");
+        assert_eq!(to_postfix("[*]*"), "[*]*");
+    }
+
+    #[test]
+    fn test_control_escapes() {
+        assert_eq!(to_postfix("a\\nb"), "a\nb..");
+        assert_eq!(to_postfix("\\r\\t"), "\r\t.");
+        assert_eq!(to_postfix("[\\n\\t]"), "[\n\t]");
     }
 
     #[test]
@@ -347,4 +1017,237 @@ mod tests {
     fn test_negated_charclass() {
         assert_eq!(to_postfix("[^abc]x"), "[^abc]x.");
     }
+
+    fn groups(input: &str) -> Vec<usize> {
+        postfix_generator(input, Flags::default())
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Group(n) => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        assert_eq!(groups("(a)b"), vec![1]);
+        assert_eq!(groups("(a(b))*"), vec![2, 1]);
+        assert_eq!(groups("(?:a)(b)"), vec![1]);
+        assert_eq!(groups("(?s:a)"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_named_groups_and_backrefs() {
+        assert_eq!(to_postfix("(a)\\1"), "a\\1.");
+        assert_eq!(to_postfix("(?P<x>a)\\k<x>"), "a\\1.");
+        assert_eq!(to_postfix("(b)(?<y>a)\\k<y>"), "ba\\2..");
+        assert_eq!(groups("(b)(?<y>a)\\k<y>"), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_recursion_and_calls() {
+        assert_eq!(to_postfix("a(?R)?b"), "a(?0)?b..");
+        assert_eq!(to_postfix("(a)(?1)"), "a(?1).");
+        assert_eq!(to_postfix("(a)(b(?-1))"), "ab(?2)..");
+        assert_eq!(to_postfix("(?+1)(a)"), "(?1)a.");
+        assert_eq!(to_postfix("(?<x>a)(?&x)(?P>x)"), "a(?1)(?1)..");
+        assert_eq!(to_postfix("(?-u)a"), "a");
+    }
+
+    #[test]
+    fn test_branch_reset_groups() {
+        assert_eq!(groups("(?|(a)|(b))"), vec![1, 1]);
+        assert_eq!(groups("(?|(a)(b)|(c))(d)"), vec![1, 2, 1, 3]);
+        assert_eq!(groups("(x)(?|(a)|(b)(c))(d)"), vec![1, 2, 2, 3, 4]);
+        assert_eq!(to_postfix("(?|a|b)c"), "ab|c.");
+    }
+
+    #[test]
+    fn test_search_start() {
+        assert_eq!(to_postfix("\\Gab"), "\\Gab..");
+        assert_eq!(to_postfix("a|\\Gb"), "a\\Gb.|");
+    }
+
+    #[test]
+    fn test_grapheme_and_linebreak() {
+        assert_eq!(to_postfix("\\X+a"), "\\X+a.");
+        assert_eq!(to_postfix("a\\Rb"), "a\\Rb..");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(to_postfix("\\bab\\B"), "\\bab\\B...");
+        assert_eq!(to_postfix("\\Da\\W"), "DaW..");
+        assert_eq!(to_postfix("\\x41\\x{e9}"), "Aé.");
+        assert_eq!(to_postfix("[\\x41-\\x5a\\]]"), "[\\x{41}-\\x{5A}\\]]");
+    }
+
+    #[test]
+    fn test_line_end_within_pattern() {
+        // Anchoring the whole pattern, the `$` is moved to the end
+        assert_eq!(to_postfix("ab$"), "ab.$");
+        assert_eq!(to_postfix("(a|b)$"), "ab|$");
+        // Otherwise it stays where it is
+        assert_eq!(to_postfix("a|b$"), "ab$.|");
+        assert_eq!(to_postfix("a$b"), "a$b..");
+        assert_eq!(to_postfix("(a$)"), "a$.");
+        let tokens = postfix_generator("$", Flags::default()).unwrap();
+        assert_eq!(tokens, vec![Token::LineEnd]);
+    }
+
+    #[test]
+    fn test_conditional() {
+        assert_eq!(to_postfix("(a)?(?(1)b|c)"), "a?bc?(1).");
+        assert_eq!(to_postfix("(a)?(?(1)bd|c)e"), "a?bd.c?(1)e..");
+        assert_eq!(to_postfix("(?<x>a)(?(<x>)b)"), "ab?(1).");
+        assert_eq!(to_postfix("(a)(?(1)|c)"), "ac?(1).");
+        assert_eq!(to_postfix("(a)(?(1)(b|c)|d)"), "abc|d?(1).");
+    }
+
+    #[test]
+    fn test_conditional_with_three_branches() {
+        assert_eq!(
+            postfix_generator("(a)(?(1)b|c|d)", Flags::default()),
+            Err(SyntaxError::TooManyBranches(11))
+        );
+    }
+
+    #[test]
+    fn test_unknown_backref_name() {
+        assert_eq!(
+            postfix_generator("(?P<x>a)\\k<y>", Flags::default()),
+            Err(SyntaxError::UnknownGroupName {
+                name: "y".to_string(),
+                position: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_malformed_syntax() {
+        let error = |input: &str| postfix_generator(input, Flags::default()).unwrap_err();
+        assert_eq!(error("a]"), SyntaxError::UnmatchedBracket(1));
+        assert_eq!(error("x[a-z"), SyntaxError::UnclosedClass(1));
+        assert_eq!(error("[a[b]"), SyntaxError::UnclosedClass(0));
+        assert_eq!(error("a[]"), SyntaxError::EmptyClass(1));
+        assert_eq!(error("[a[^]]"), SyntaxError::EmptyClass(0));
+        assert_eq!(
+            error("[z-a]"),
+            SyntaxError::InvalidClassRange {
+                range: "z-a".to_string(),
+                position: 0
+            }
+        );
+        assert_eq!(error("ab\\"), SyntaxError::TrailingBackslash(2));
+        assert_eq!(error("[a\\"), SyntaxError::TrailingBackslash(2));
+        assert_eq!(error("a\\x4"), SyntaxError::InvalidEscape(1));
+        assert_eq!(error("\\x{110000}"), SyntaxError::InvalidEscape(0));
+        assert_eq!(error("[\\x{41]"), SyntaxError::InvalidEscape(1));
+        let unsupported = |escape, position| SyntaxError::UnsupportedEscape { escape, position };
+        assert_eq!(error("\\p{L}"), unsupported('p', 0));
+        assert_eq!(error("ab\\z"), unsupported('z', 2));
+        assert_eq!(error("\\Aa"), unsupported('A', 0));
+        assert_eq!(error("x\\u{41}"), unsupported('u', 1));
+        assert_eq!(error("\\0"), unsupported('0', 0));
+        assert_eq!(error("[a\\p]"), unsupported('p', 2));
+        assert_eq!(
+            error("[[:alfa:]]"),
+            SyntaxError::UnknownClassName {
+                name: "alfa".to_string(),
+                position: 0
+            }
+        );
+        assert_eq!(error("a$*"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("\\b+"), SyntaxError::NothingToRepeat(2));
+        assert_eq!(error("\\kx"), SyntaxError::ExpectedGroupName(0));
+        assert_eq!(error("(a)\\k<a"), SyntaxError::UnclosedGroupName(3));
+        assert_eq!(
+            error("(?<a-b>x)"),
+            SyntaxError::InvalidGroupName {
+                name: "a-b".to_string(),
+                position: 0
+            }
+        );
+        assert_eq!(error("(a)(?-2)"), SyntaxError::InvalidGroupReference(3));
+        assert_eq!(
+            error("(?&nope)"),
+            SyntaxError::UnknownGroupName {
+                name: "nope".to_string(),
+                position: 0
+            }
+        );
+        assert_eq!(error("(?(1"), SyntaxError::UnclosedCondition(0));
+        let unknown = |group, position| SyntaxError::UnknownGroup { group, position };
+        assert_eq!(error("(?1)"), unknown(1, 0));
+        assert_eq!(error("(a)(?2)"), unknown(2, 3));
+        assert_eq!(error("(a)(?+1)"), unknown(2, 3));
+        assert_eq!(error("(?(2)a|b)"), unknown(2, 0));
+        assert_eq!(error("(a)\\2"), unknown(2, 3));
+        assert_eq!(error("(?|(a)|(b))\\2"), unknown(2, 11));
+        assert_eq!(
+            error("a(?x)"),
+            SyntaxError::UnknownFlag {
+                flag: 'x',
+                position: 1
+            }
+        );
+        assert_eq!(error("(?i"), SyntaxError::UnclosedFlags(0));
+    }
+
+    #[test]
+    fn test_flag_group_is_dropped() {
+        assert_eq!(to_postfix("a(?-u)b"), "ab.");
+        assert_eq!(to_postfix("(?-u:a)b"), "ab.");
+    }
+
+    #[test]
+    fn test_ascii_flag_scoping() {
+        let ascii = Flags {
+            ascii: true,
+            ..Flags::default()
+        };
+        let unicode = Flags::default();
+        let classes = |input: &str, flags: Flags| -> Vec<Flags> {
+            postfix_generator(input, flags)
+                .unwrap()
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::ComplexLiteral(_, flags) => Some(flags),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(classes("\\w(?-u)\\w", unicode), vec![unicode, ascii]);
+        assert_eq!(classes("(?-u:\\w)\\w", unicode), vec![ascii, unicode]);
+        assert_eq!(classes("(a(?-u)\\w)\\w", unicode), vec![ascii, unicode]);
+        assert_eq!(classes("\\d(?u).", ascii), vec![ascii, unicode]);
+    }
+
+    #[test]
+    fn test_case_insensitive_flag() {
+        let literals = |input: &str| -> Vec<bool> {
+            postfix_generator(input, Flags::default())
+                .unwrap()
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Literal(_, flags) => Some(flags.case_insensitive),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(literals("a(?i)b"), vec![false, true]);
+        assert_eq!(literals("(?i:a)b"), vec![true, false]);
+        assert_eq!(literals("(?i)a(?-i)b"), vec![true, false]);
+    }
+
+    #[test]
+    fn test_dot_all_flag() {
+        let tokens = postfix_generator("(?s).", Flags::default()).unwrap();
+        assert!(matches!(
+            tokens.as_slice(),
+            [Token::ComplexLiteral(_, Flags { dot_all: true, .. })]
+        ));
+    }
 }
@@ -0,0 +1,241 @@
+//! A process-wide cache of compiled patterns, for programs compiling the
+//! same patterns over and over, such as a server evaluating user rules on
+//! every request.
+//!
+//! Nothing goes through it unless asked to: `RegexNFA::new` always
+//! compiles, while `RegexNFA::cached` looks the pattern and its options up
+//! first and shares the regex compiled the last time. The cache holds a
+//! bounded number of regexes, dropping the least recently used one to make
+//! room for a new one. Compiling happens outside the lock, so a slow pattern
+//! never holds up threads looking up others; two threads missing on the
+//! same pattern at once both compile it.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::regex::error::CompileError;
+use crate::regex::nfa_regex::RegexNFA;
+use crate::regex::options::RegexOptions;
+
+/// How many regexes the cache holds until `set_pattern_cache_capacity`
+/// says otherwise.
+pub const DEFAULT_PATTERN_CACHE_CAPACITY: usize = 256;
+
+/// How the pattern cache has done since the process started or since
+/// `clear_pattern_cache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatternCacheStats {
+    /// Lookups that found the regex already compiled.
+    pub hits: u64,
+    /// Lookups that had to compile it, whether or not it compiled.
+    pub misses: u64,
+    /// Regexes dropped to make room for others, or for a smaller capacity.
+    pub evictions: u64,
+    /// Regexes in the cache now.
+    pub len: usize,
+    /// Regexes the cache may hold.
+    pub capacity: usize,
+}
+
+type Key = (String, RegexOptions);
+
+/// The regexes, least recently used first in `order`.
+struct PatternCache {
+    regexes: HashMap<Key, (Arc<RegexNFA>, u64)>,
+    // The key of every regex by when it was last used
+    order: BTreeMap<u64, Key>,
+    // Ticks on every lookup, to order the uses
+    clock: u64,
+    stats: PatternCacheStats,
+}
+
+impl PatternCache {
+    fn new(capacity: usize) -> Self {
+        PatternCache {
+            regexes: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            stats: PatternCacheStats {
+                capacity,
+                ..PatternCacheStats::default()
+            },
+        }
+    }
+
+    /// The regex for `key`, now the most recently used, if it is cached.
+    fn get(&mut self, key: &Key) -> Option<Arc<RegexNFA>> {
+        self.clock += 1;
+        let Some((regex, used)) = self.regexes.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        let key = self.order.remove(used).expect("cached regexes are ordered");
+        *used = self.clock;
+        self.order.insert(self.clock, key);
+        Some(regex.clone())
+    }
+
+    fn insert(&mut self, key: Key, regex: Arc<RegexNFA>) {
+        if self.stats.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((_, used)) = self.regexes.insert(key.clone(), (regex, self.clock)) {
+            // Compiled by another thread meanwhile
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, key);
+        self.evict();
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.stats.capacity = capacity;
+        self.evict();
+    }
+
+    /// Drops the least recently used regexes until they fit the capacity.
+    fn evict(&mut self) {
+        while self.regexes.len() > self.stats.capacity {
+            let (_, key) = self.order.pop_first().expect("cached regexes are ordered");
+            self.regexes.remove(&key);
+            self.stats.evictions += 1;
+        }
+    }
+
+    fn stats(&self) -> PatternCacheStats {
+        PatternCacheStats {
+            len: self.regexes.len(),
+            ..self.stats
+        }
+    }
+}
+
+fn cache() -> MutexGuard<'static, PatternCache> {
+    static CACHE: OnceLock<Mutex<PatternCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(PatternCache::new(DEFAULT_PATTERN_CACHE_CAPACITY)));
+    // Nothing that can panic runs with the lock held
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl RegexNFA {
+    /// The regex for `pattern` from the process-wide pattern cache, compiled
+    /// and added to it if it isn't there yet, see `pattern_cache`. Patterns
+    /// that fail to compile aren't cached.
+    pub fn cached(pattern: &str) -> Result<Arc<RegexNFA>, CompileError> {
+        RegexNFA::cached_with_options(pattern, &RegexOptions::default())
+    }
+
+    /// Like `cached`, for `pattern` compiled with `options`. The same
+    /// pattern with other options is another entry.
+    pub fn cached_with_options(
+        pattern: &str,
+        options: &RegexOptions,
+    ) -> Result<Arc<RegexNFA>, CompileError> {
+        let key = (pattern.to_string(), options.clone());
+        if let Some(regex) = cache().get(&key) {
+            return Ok(regex);
+        }
+        let regex = Arc::new(RegexNFA::try_with_options(key.0.clone(), key.1.clone())?);
+        cache().insert(key, regex.clone());
+        Ok(regex)
+    }
+}
+
+/// Makes the pattern cache hold at most `capacity` regexes, dropping the
+/// least recently used ones if it holds more. With 0, `RegexNFA::cached`
+/// compiles every time.
+pub fn set_pattern_cache_capacity(capacity: usize) {
+    cache().set_capacity(capacity);
+}
+
+/// How the pattern cache has done so far.
+pub fn pattern_cache_stats() -> PatternCacheStats {
+    cache().stats()
+}
+
+/// Empties the pattern cache and starts its stats over, keeping its
+/// capacity. Regexes handed out before stay usable.
+pub fn clear_pattern_cache() {
+    let mut cache = cache();
+    let capacity = cache.stats.capacity;
+    *cache = PatternCache::new(capacity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(pattern: &str) -> Key {
+        (pattern.to_string(), RegexOptions::default())
+    }
+
+    fn regex(pattern: &str) -> Arc<RegexNFA> {
+        Arc::new(RegexNFA::new(pattern.to_string()))
+    }
+
+    #[test]
+    fn test_least_recently_used_goes() {
+        let mut cache = PatternCache::new(2);
+        cache.insert(key("a"), regex("a"));
+        cache.insert(key("b"), regex("b"));
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), regex("c"));
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+        assert_eq!(
+            cache.stats(),
+            PatternCacheStats {
+                hits: 3,
+                misses: 1,
+                evictions: 1,
+                len: 2,
+                capacity: 2,
+            }
+        );
+        cache.set_capacity(1);
+        // `a` was used before `c`
+        assert!(cache.get(&key("a")).is_none());
+        assert_eq!((cache.stats().len, cache.stats().evictions), (1, 2));
+        cache.set_capacity(0);
+        cache.insert(key("a"), regex("a"));
+        assert_eq!(cache.stats().len, 0);
+    }
+
+    #[test]
+    fn test_options_are_part_of_the_key() {
+        let mut cache = PatternCache::new(4);
+        cache.insert(key("a"), regex("a"));
+        let ignoring_case = ("a".to_string(), RegexOptions::new().case_insensitive(true));
+        assert!(cache.get(&ignoring_case).is_none());
+    }
+
+    #[test]
+    fn test_cached() {
+        // The only test using the process-wide cache, which the others
+        // running at the same time would disturb
+        let first = RegexNFA::cached(r"\d+ cached").unwrap();
+        let again = RegexNFA::cached(r"\d+ cached").unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert!(again.matches("42 cached"));
+        let options = RegexOptions::new().case_insensitive(true);
+        let other = RegexNFA::cached_with_options(r"\d+ cached", &options).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert!(RegexNFA::cached("a|*").is_err());
+        let stats = pattern_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (1, 3, 2));
+
+        clear_pattern_cache();
+        assert_eq!(pattern_cache_stats().len, 0);
+        assert!(first.matches("7 cached"));
+        set_pattern_cache_capacity(0);
+        let uncached = RegexNFA::cached(r"\d+ cached").unwrap();
+        assert!(!Arc::ptr_eq(&first, &uncached));
+        assert_eq!(pattern_cache_stats().len, 0);
+        set_pattern_cache_capacity(DEFAULT_PATTERN_CACHE_CAPACITY);
+    }
+}
@@ -0,0 +1,244 @@
+//! Pike VM: the breadth-first simulation, with capture slots.
+//!
+//! Like `is_match_linear` this advances every live thread one char at a
+//! time, but each thread also carries the capture slots of the path it
+//! stands for. Threads are kept in the order the backtracking engine would
+//! try their paths in, and when two paths reach the same state at the same
+//! offset only the preferred one survives, so the match and groups reported
+//! are exactly the ones `Engine::find` reports, in O(states × input) time.
+//!
+//! Only NFAs whose transitions consume a single char or a literal are
+//! supported. A thread partway through a literal keeps its place in the list
+//! and compares one more char of it per step, so it is preferred exactly as
+//! a chain of single-char transitions would be. `\X` and `\R`, whose length
+//! depends on the input, and the non-regular constructs stay with the
+//! backtracking engine.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::{at_word_edge, record_capture, Engine, Slots};
+
+/// Where a thread stands, in the order the threads are preferred in.
+enum Step {
+    /// About to try transition `transition` of state `state`, which
+    /// consumes a char, or the next char of a literal after the first
+    /// `progress` bytes.
+    Consume {
+        state: usize,
+        transition: usize,
+        progress: usize,
+        start: usize,
+        slots: Slots,
+    },
+    /// Reached the end state.
+    Match { start: usize, slots: Slots },
+}
+
+impl Engine {
+    /// Whether `pike_find` can run this NFA.
+    pub fn supports_pike(&self) -> bool {
+        self.states
+            .iter()
+            .flat_map(|state| &state.transitions)
+            .all(|(matcher, _)| {
+                matcher.is_epsilon()
+                    || matches!(
+                        matcher,
+                        Matcher::Range(..)
+                            | Matcher::Any { .. }
+                            | Matcher::Literal(_)
+                            | Matcher::SearchStart
+                            | Matcher::LineStart
+                            | Matcher::LineEnd
+                            | Matcher::WordStart { .. }
+                            | Matcher::WordEnd { .. }
+                            | Matcher::WordBoundary { .. }
+                    )
+            })
+    }
+
+    /// The same as `find`, without backtracking. Requires `supports_pike`.
+    pub fn pike_find<F>(
+        &self,
+        input: &str,
+        from: usize,
+        anchored: bool,
+        accept: F,
+    ) -> Option<(usize, usize, Slots)>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut vm = Vm {
+            engine: self,
+            input,
+            seen: vec![usize::MAX; self.states.len()],
+            search_start: from,
+        };
+        let mut current: Vec<Step> = Vec::new();
+        let mut matched = None;
+
+        let offsets = input[from..]
+            .char_indices()
+            .map(|(offset, _)| from + offset)
+            .chain(core::iter::once(input.len()));
+        for index in offsets {
+            // A new attempt is the least preferred: every earlier start wins
+            if matched.is_none() && (index == from || !anchored) {
+                vm.add_thread(&mut current, self.start_state, index, index, Vec::new());
+            }
+            // An assertion failing where an attempt starts leaves it no
+            // thread, but a later attempt may still match
+            if current.is_empty() && (anchored || matched.is_some()) {
+                break;
+            }
+
+            let c = input[index..].chars().next();
+            let next_index = index + c.map_or(0, char::len_utf8);
+            let mut next = Vec::new();
+            for step in current.drain(..) {
+                match step {
+                    Step::Match { start, slots } => {
+                        if accept(index) {
+                            // Less preferred threads can't win anymore
+                            matched = Some((start, index, slots));
+                            break;
+                        }
+                    }
+                    Step::Consume {
+                        state,
+                        transition,
+                        progress,
+                        start,
+                        slots,
+                    } => {
+                        let (matcher, target) = &self.states[state].transitions[transition];
+                        let Some(c) = c else {
+                            continue;
+                        };
+                        match matcher {
+                            Matcher::Literal(literal) => {
+                                if !literal[progress..].starts_with(c) {
+                                    continue;
+                                }
+                                let progress = progress + c.len_utf8();
+                                if progress < literal.len() {
+                                    // On to the next char of the literal
+                                    next.push(Step::Consume {
+                                        state,
+                                        transition,
+                                        progress,
+                                        start,
+                                        slots,
+                                    });
+                                    continue;
+                                }
+                            }
+                            _ if !matcher.matches(c) => continue,
+                            _ => {}
+                        }
+                        vm.add_thread(&mut next, *target, next_index, start, slots);
+                    }
+                }
+            }
+            current = next;
+        }
+
+        matched
+    }
+}
+
+struct Vm<'a> {
+    engine: &'a Engine,
+    input: &'a str,
+    // The offset each state was last reached at, to keep only the first
+    // thread reaching it
+    seen: Vec<usize>,
+    search_start: usize,
+}
+
+impl Vm<'_> {
+    /// Adds the threads following `state` at offset `index` without
+    /// consuming input to `list`, most preferred first.
+    fn add_thread(
+        &mut self,
+        list: &mut Vec<Step>,
+        state: usize,
+        index: usize,
+        start: usize,
+        slots: Slots,
+    ) {
+        let engine = self.engine;
+        // Depth first, through the transitions of each state in order: the
+        // state, the next transition to look at and the slots so far
+        let mut stack: Vec<(usize, usize, Slots)> = Vec::new();
+        self.enter(list, &mut stack, state, index, start, slots);
+
+        while let Some(top) = stack.last_mut() {
+            let (state, transition) = (top.0, top.1);
+            let Some((matcher, target)) = engine.states[state].transitions.get(transition) else {
+                stack.pop();
+                continue;
+            };
+            top.1 += 1;
+            let mut slots = top.2.clone();
+            match matcher {
+                _ if matcher.is_epsilon() => {
+                    record_capture(&mut slots, matcher, index);
+                    self.enter(list, &mut stack, *target, index, start, slots);
+                }
+                Matcher::SearchStart => {
+                    if index == self.search_start {
+                        self.enter(list, &mut stack, *target, index, start, slots);
+                    }
+                }
+                Matcher::LineStart => {
+                    if engine.at_line_start(self.input, index) {
+                        self.enter(list, &mut stack, *target, index, start, slots);
+                    }
+                }
+                Matcher::LineEnd => {
+                    if engine.at_line_end(self.input, index) {
+                        self.enter(list, &mut stack, *target, index, start, slots);
+                    }
+                }
+                Matcher::WordStart { .. }
+                | Matcher::WordEnd { .. }
+                | Matcher::WordBoundary { .. } => {
+                    if at_word_edge(self.input, index, matcher) {
+                        self.enter(list, &mut stack, *target, index, start, slots);
+                    }
+                }
+                _ => list.push(Step::Consume {
+                    state,
+                    transition,
+                    progress: 0,
+                    start,
+                    slots,
+                }),
+            }
+        }
+    }
+
+    /// Visits `state`, unless an earlier thread already did at this offset.
+    fn enter(
+        &mut self,
+        list: &mut Vec<Step>,
+        stack: &mut Vec<(usize, usize, Slots)>,
+        state: usize,
+        index: usize,
+        start: usize,
+        slots: Slots,
+    ) {
+        if self.seen[state] == index {
+            return;
+        }
+        self.seen[state] = index;
+        if state == self.engine.end_state {
+            list.push(Step::Match { start, slots });
+        } else {
+            stack.push((state, 0, slots));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Literal prefilters run before the engines.
+//!
+//! Most grep patterns start with plain text, like `error` in `error: \d+`.
+//! No match can start anywhere but at an occurrence of that text, and
+//! finding occurrences with a substring search (`memchr::memmem`) is far
+//! faster than stepping any of the engines through the input. Lines without
+//! one are skipped outright; on the others the engines start at the
+//! occurrences only. Alternations of literals, like `ERROR|WARN|FATAL`, get
+//! the same treatment with an Aho-Corasick automaton looking for all of the
+//! alternatives at once. Failing that, a pattern whose matches all start with
+//! a char of some class, like `[0-9]+:`, skips to the next byte that can
+//! start such a char.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use aho_corasick::{AhoCorasick, Anchored, Input, MatchKind, StartKind};
+use memchr::memmem::Finder;
+
+use crate::regex::byte_class::ByteClass;
+use crate::regex::elements::Matcher;
+use crate::regex::engine::Engine;
+
+#[derive(Debug)]
+pub enum Prefilter {
+    /// Every match starts with this literal.
+    Prefix(Box<Finder<'static>>),
+    /// Every match starts with one of these literals.
+    Alternatives(AhoCorasick),
+    /// Every match starts with a char beginning with one of these bytes.
+    FirstByte(Box<ByteClass>),
+}
+
+/// Classes with more bytes than this would hardly skip anything.
+const MAX_FIRST_BYTES: usize = 128;
+
+impl Prefilter {
+    /// A prefilter for the NFA, if its matches have something in common to
+    /// look for.
+    pub fn new(engine: &Engine) -> Option<Prefilter> {
+        let engine = &engine.split_literals();
+        match &required_literals(engine)[..] {
+            [] => first_bytes(engine).map(|class| Prefilter::FirstByte(Box::new(class))),
+            [prefix] => Some(Prefilter::Prefix(Box::new(
+                Finder::new(prefix.as_bytes()).into_owned(),
+            ))),
+            literals => AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .start_kind(StartKind::Both)
+                .build(literals)
+                .ok()
+                .map(Prefilter::Alternatives),
+        }
+    }
+
+    /// Byte offset of the first place at or after `from` where a match could
+    /// start.
+    pub fn find(&self, haystack: &str, from: usize) -> Option<usize> {
+        match self {
+            Prefilter::Prefix(finder) => finder
+                .find(&haystack.as_bytes()[from..])
+                .map(|hit| from + hit),
+            Prefilter::Alternatives(automaton) => automaton
+                .find(Input::new(haystack).range(from..))
+                .map(|hit| hit.start()),
+            Prefilter::FirstByte(class) => class.find(haystack.as_bytes(), from),
+        }
+    }
+
+    /// Whether a match could start at byte offset `at`.
+    pub fn is_candidate(&self, haystack: &str, at: usize) -> bool {
+        match self {
+            Prefilter::Prefix(finder) => haystack.as_bytes()[at..].starts_with(finder.needle()),
+            Prefilter::Alternatives(automaton) => automaton
+                .find(Input::new(haystack).range(at..).anchored(Anchored::Yes))
+                .is_some(),
+            Prefilter::FirstByte(class) => haystack
+                .as_bytes()
+                .get(at)
+                .is_some_and(|&byte| class.contains(byte)),
+        }
+    }
+}
+
+/// Literals every match of the NFA starts with one of, as `Prefilter::new`
+/// looks for them. Empty if there are none.
+pub(super) fn start_literals(engine: &Engine) -> Vec<String> {
+    required_literals(&engine.split_literals())
+}
+
+/// Literals every match of the NFA starts with one of, each spelled out as
+/// far as the NFA does so a char at a time. Empty when some match could
+/// start with a char that isn't fixed.
+fn required_literals(engine: &Engine) -> Vec<String> {
+    let start = engine.dfa_closure(&[engine.start_state]);
+    let Some(first) = literal_steps(engine, &start) else {
+        return Vec::new();
+    };
+    let mut literals: Vec<String> = Vec::new();
+    for (c, next) in first {
+        let mut literal = c.to_string();
+        let mut current = engine.dfa_closure(&[next]);
+        // Extend the literal while every way forward takes the same char
+        while let Some(steps) = literal_steps(engine, &current) {
+            let Some(&(c, _)) = steps.first() else {
+                break;
+            };
+            if steps.iter().any(|&(other, _)| other != c) {
+                break;
+            }
+            literal.push(c);
+            let targets: Vec<usize> = steps.iter().map(|&(_, next)| next).collect();
+            current = engine.dfa_closure(&targets);
+        }
+        if !literals.contains(&literal) {
+            literals.push(literal);
+        }
+    }
+    literals
+}
+
+/// The bytes every match of the NFA starts with one of. `None` if a match
+/// could be empty, start with something else than a char of a class, or
+/// start with too many different bytes.
+fn first_bytes(engine: &Engine) -> Option<ByteClass> {
+    let start = engine.dfa_closure(&[engine.start_state]);
+    if start.binary_search(&engine.end_state).is_ok() {
+        return None;
+    }
+    let mut chars = Vec::new();
+    for &id in &start {
+        for (matcher, _) in &engine.states[id].transitions {
+            if matcher.is_epsilon() {
+                continue;
+            }
+            let Matcher::Range(ranges, false) = matcher else {
+                return None;
+            };
+            chars.extend_from_slice(ranges);
+        }
+    }
+    let class = ByteClass::from_char_ranges(&chars);
+    (class.len() <= MAX_FIRST_BYTES).then_some(class)
+}
+
+/// The single chars the NFA states `ids` go on with, and where to. `None`
+/// if a match could end there, or go on with anything but a single char.
+fn literal_steps(engine: &Engine, ids: &[usize]) -> Option<Vec<(char, usize)>> {
+    if ids.binary_search(&engine.end_state).is_ok() {
+        return None;
+    }
+    let mut steps = Vec::new();
+    for &id in ids {
+        for (matcher, next) in &engine.states[id].transitions {
+            if matcher.is_epsilon() {
+                continue;
+            }
+            let Matcher::Range(ranges, false) = matcher else {
+                return None;
+            };
+            match ranges[..] {
+                [(c, end)] if c == end => steps.push((c, *next)),
+                _ => return None,
+            }
+        }
+    }
+    Some(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+
+    fn literals(pattern: &str) -> Vec<String> {
+        required_literals(&RegexNFA::new(pattern.to_string()).engine.split_literals())
+    }
+
+    #[test]
+    fn test_required_prefix() {
+        assert_eq!(literals("error: \\d+"), ["error: "]);
+        assert_eq!(literals("^abc$"), ["abc"]);
+        assert_eq!(literals("(ab)(c|d)"), ["ab"]);
+        assert_eq!(literals("a+b"), ["a"]);
+        assert_eq!(literals("(?:ab){2}x"), ["ababx"]);
+        assert!(literals("a*").is_empty());
+        assert!(literals("\\d+").is_empty());
+        assert!(literals("(?i)abc").is_empty());
+        assert!(literals("\\Gab").is_empty());
+        assert!(literals("(x)?(?(1)a|a)").is_empty());
+    }
+
+    #[test]
+    fn test_required_alternatives() {
+        assert_eq!(literals("ERROR|WARN|FATAL"), ["ERROR", "WARN", "FATAL"]);
+        assert_eq!(literals("(ERROR|WARN): \\d+"), ["ERROR: ", "WARN: "]);
+        assert_eq!(literals("ab|ac|ab"), ["ab", "ac"]);
+        assert_eq!(literals("x(a|b)|yz"), ["x", "yz"]);
+        assert_eq!(literals("a*b"), ["a", "b"]);
+        assert!(literals("ERROR|\\d").is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let prefilter = Prefilter::new(&RegexNFA::new("né+".to_string()).engine).unwrap();
+        assert_eq!(prefilter.find("a né né", 0), Some(2));
+        assert_eq!(prefilter.find("a né né", 3), Some(6));
+        assert_eq!(prefilter.find("a né né", 7), None);
+        assert!(prefilter.is_candidate("a né", 2));
+        assert!(!prefilter.is_candidate("a né", 0));
+        assert!(Prefilter::new(&RegexNFA::new("x?".to_string()).engine).is_none());
+
+        let prefilter = Prefilter::new(&RegexNFA::new("wé|ab".to_string()).engine).unwrap();
+        assert!(matches!(prefilter, Prefilter::Alternatives(_)));
+        assert_eq!(prefilter.find("xx wé ab", 0), Some(3));
+        assert_eq!(prefilter.find("xx wé ab", 4), Some(7));
+        assert_eq!(prefilter.find("xx wé ab", 8), None);
+        assert!(prefilter.is_candidate("xx wé ab", 7));
+        assert!(!prefilter.is_candidate("xx wé ab", 6));
+    }
+
+    #[test]
+    fn test_first_bytes() {
+        let prefilter = Prefilter::new(&RegexNFA::new("[0-9]+:".to_string()).engine).unwrap();
+        assert!(matches!(prefilter, Prefilter::FirstByte(_)));
+        assert_eq!(prefilter.find("at 12:30", 0), Some(3));
+        assert_eq!(prefilter.find("at 12:30", 5), Some(6));
+        assert_eq!(prefilter.find("at 12:30", 8), None);
+        assert!(prefilter.is_candidate("at 12:30", 4));
+        assert!(!prefilter.is_candidate("at 12:30", 5));
+        assert!(!prefilter.is_candidate("at 12:30", 8));
+
+        let prefilter = Prefilter::new(&RegexNFA::new("(?i)é".to_string()).engine).unwrap();
+        assert_eq!(prefilter.find("xÉ", 0), Some(1));
+
+        // Optional first atom, negated class, anything and too broad a class
+        for pattern in ["[0-9]*", "[^:]+:", ".x", "[\u{1}-\u{10FFFF}]"] {
+            assert!(
+                Prefilter::new(&RegexNFA::new(pattern.to_string()).engine).is_none(),
+                "{}",
+                pattern
+            );
+        }
+    }
+}
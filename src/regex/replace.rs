@@ -0,0 +1,197 @@
+//! Replacing matches: `RegexNFA::replace`, `replace_all` and `replacen`.
+//!
+//! What a match is replaced with comes from a `Replacer`. A string is a
+//! template: `$1` or `${1}` stand for a group by index, `$name` or `${name}`
+//! for a named one, and `$$` for a plain `$`. A group that doesn't exist or
+//! didn't take part stands for nothing. A `$name` takes the longest run of
+//! letters, digits and `_` after the `$`, so `$1a` is the group named `1a`;
+//! write `${1}a` to follow group 1 with an `a`. A closure taking the
+//! `Captures` computes the replacement instead.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+
+use crate::regex::captures::Captures;
+use crate::regex::nfa_regex::RegexNFA;
+
+/// What the matches of a replacement are replaced with.
+pub trait Replacer {
+    /// Appends the replacement for the match `caps` to `dst`.
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+/// Replaces matches with a string as it is, `$` and all.
+#[derive(Debug, Clone, Copy)]
+pub struct NoExpand<'s>(pub &'s str);
+
+impl Replacer for NoExpand<'_> {
+    fn replace_append(&mut self, _: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self.0);
+    }
+}
+
+impl Captures<'_> {
+    /// Appends `template` to `dst` with the `$` references in it replaced by
+    /// the groups they name, see the module documentation.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            dst.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+            let Some((reference, after)) = group_reference(rest) else {
+                dst.push('$');
+                continue;
+            };
+            let group = match reference.parse::<usize>() {
+                Ok(index) => self.get(index),
+                Err(_) => self.name(reference),
+            };
+            if let Some(group) = group {
+                dst.push_str(group.as_str());
+            }
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+}
+
+/// The group reference at the start of `text`, which follows a `$`, and
+/// the text after it. `None` if there is none, the `$` then being literal.
+fn group_reference(text: &str) -> Option<(&str, &str)> {
+    if let Some(braced) = text.strip_prefix('{') {
+        let close = braced.find('}')?;
+        let name = &braced[..close];
+        return (!name.is_empty()).then(|| (name, &braced[close + 1..]));
+    }
+    let len = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    (len > 0).then(|| text.split_at(len))
+}
+
+impl RegexNFA {
+    /// `haystack` with its leftmost match replaced by `replacer`. Borrows
+    /// `haystack` when there is no match.
+    pub fn replace<'h, R: Replacer>(&self, haystack: &'h str, replacer: R) -> Cow<'h, str> {
+        self.replacen(haystack, 1, replacer)
+    }
+
+    /// `haystack` with every match `find_iter` finds replaced by `replacer`.
+    pub fn replace_all<'h, R: Replacer>(&self, haystack: &'h str, replacer: R) -> Cow<'h, str> {
+        self.replacen(haystack, 0, replacer)
+    }
+
+    /// `haystack` with its first `limit` matches replaced by `replacer`, or
+    /// all of them if `limit` is 0.
+    pub fn replacen<'h, R: Replacer>(
+        &self,
+        haystack: &'h str,
+        limit: usize,
+        mut replacer: R,
+    ) -> Cow<'h, str> {
+        let mut matches = self.captures_iter(haystack).peekable();
+        if matches.peek().is_none() {
+            return Cow::Borrowed(haystack);
+        }
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut replaced = String::with_capacity(haystack.len());
+        // Byte offset up to which `haystack` is in `replaced`
+        let mut copied = 0;
+        for caps in matches.take(limit) {
+            let found = caps.get_match();
+            replaced.push_str(&haystack[copied..found.start()]);
+            replacer.replace_append(&caps, &mut replaced);
+            copied = found.end();
+        }
+        replaced.push_str(&haystack[copied..]);
+        Cow::Owned(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn regex(pattern: &str) -> RegexNFA {
+        RegexNFA::new(pattern.to_string())
+    }
+
+    #[test]
+    fn test_replace() {
+        let re = regex("\\d+");
+        assert_eq!(re.replace("a1b22c", "#"), "a#b22c");
+        assert_eq!(re.replace_all("a1b22c", "#"), "a#b#c");
+        assert_eq!(re.replacen("1 2 3 4", 2, "n"), "n n 3 4");
+        assert!(matches!(re.replace_all("abc", "#"), Cow::Borrowed("abc")));
+        assert_eq!(regex("x*").replace_all("aé", "-"), "-a-é-");
+    }
+
+    #[test]
+    fn test_templates() {
+        let re = regex("(?<key>\\w+)=(\\w+)");
+        assert_eq!(re.replace_all("a=1 b=2", "$2=$1"), "1=a 2=b");
+        assert_eq!(re.replace_all("a=1", "${key}:${2}"), "a:1");
+        assert_eq!(re.replace_all("a=1", "$key$$"), "a$");
+        // `$2x` names a group that doesn't exist
+        assert_eq!(re.replace_all("a=1", "[$2x]"), "[]");
+        assert_eq!(re.replace_all("a=1", "[${2}x]"), "[1x]");
+        assert_eq!(re.replace_all("a=1", "$ ${} ${2"), "$ ${} ${2");
+        assert_eq!(re.replace_all("a=1", NoExpand("$1")), "$1");
+    }
+
+    #[test]
+    fn test_missing_group_is_empty() {
+        let re = regex("(a)|(b)");
+        assert_eq!(re.replace_all("ab", "[$1|$2]"), "[a|][|b]");
+    }
+
+    #[test]
+    fn test_closure_replacer() {
+        let re = regex("\\d+");
+        let doubled = re.replace_all("3 apples, 10 pears", |caps: &Captures| {
+            (caps[0].parse::<u32>().unwrap() * 2).to_string()
+        });
+        assert_eq!(doubled, "6 apples, 20 pears");
+
+        let mut seen = 0;
+        let numbered = regex("x").replace_all("xyx", |_: &Captures| {
+            seen += 1;
+            seen.to_string()
+        });
+        assert_eq!(numbered, "1y2");
+    }
+}
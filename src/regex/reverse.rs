@@ -0,0 +1,154 @@
+//! Finding where the leftmost match starts by running the NFA backwards.
+//!
+//! An unanchored search otherwise has to try every offset in turn until one
+//! leads to a match. Reversing every transition of the NFA gives an automaton
+//! matching the reversed strings; run as a lazy DFA over the input from its
+//! end, it reaches its end state exactly at the offsets where some match
+//! starts. The smallest such offset is where the leftmost match starts, so
+//! one linear backwards pass leaves a single anchored search to find the
+//! match itself and its groups.
+//!
+//! Like the lazy DFA, only NFAs made of plain char transitions can be
+//! reversed.
+
+use alloc::vec::Vec;
+
+use crate::regex::elements::{Matcher, State};
+use crate::regex::engine::Engine;
+use crate::regex::lazy_dfa::LazyDfa;
+
+impl Engine {
+    /// The NFA with every transition pointing the other way, and start and
+    /// end swapped. Group markers become plain epsilons, and literals are
+    /// spelled backwards.
+    pub fn reversed(&self) -> Engine {
+        let mut states: Vec<State> = (0..self.states.len()).map(State::new).collect();
+        for state in &self.states {
+            for (matcher, next) in &state.transitions {
+                let matcher = match matcher {
+                    _ if matcher.is_epsilon() => Matcher::Epsilon,
+                    Matcher::Literal(literal) => Matcher::Literal(literal.chars().rev().collect()),
+                    _ => matcher.clone(),
+                };
+                states[*next].add_transition(matcher, state.id);
+            }
+        }
+        let mut engine = Engine::new();
+        engine.add_states(states);
+        engine.set_start_state(self.end_state);
+        engine.set_end_state(self.start_state);
+        engine.recursion_limit = self.recursion_limit;
+        engine
+    }
+}
+
+#[derive(Debug)]
+pub struct ReverseDfa {
+    engine: Engine,
+    anchored_end: bool,
+    cache_limit: usize,
+}
+
+impl ReverseDfa {
+    /// Unless `anchored_end`, matches may end anywhere in the input. `None`
+    /// if the NFA can't be run as a lazy DFA.
+    pub fn new(engine: &Engine, anchored_end: bool, cache_limit: usize) -> Option<Self> {
+        let engine = engine.split_literals();
+        if !LazyDfa::supports(&engine) {
+            return None;
+        }
+        Some(ReverseDfa {
+            engine: engine.reversed(),
+            anchored_end,
+            cache_limit,
+        })
+    }
+
+    /// An empty DFA to run the reversed NFA with, see `Cache`.
+    pub fn create_cache(&self) -> LazyDfa {
+        LazyDfa::new(&self.engine, self.anchored_end, self.cache_limit)
+    }
+
+    /// The smallest byte offset at or after `from` where a match ending at
+    /// byte offset `end` starts (ending anywhere up to `end` unless
+    /// anchored). `Some(None)` if there is none and `None` if the DFA's
+    /// cache thrashed. `dfa` is from `create_cache`.
+    pub fn leftmost_start(
+        &self,
+        dfa: &mut LazyDfa,
+        input: &str,
+        from: usize,
+        end: usize,
+    ) -> Option<Option<usize>> {
+        let found = dfa.rfind(&self.engine, &input[from..end])?;
+        Some(found.map(|start| from + start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::{RegexNFA, RegexOptions};
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_reversed_matches_reversed_input() {
+        let regex_nfa = RegexNFA::new("ab+c".to_string());
+        let reversed = regex_nfa.engine.reversed();
+        assert!(reversed.is_match_linear("xcbbay", 0, false, |_| true));
+        assert!(!reversed.is_match_linear("abbc", 0, false, |_| true));
+    }
+
+    #[test]
+    fn test_leftmost_start() {
+        let regex_nfa = RegexNFA::new("a+b|c".to_string());
+        let reverse = ReverseDfa::new(&regex_nfa.engine, false, 16).unwrap();
+        let dfa = &mut reverse.create_cache();
+        assert_eq!(reverse.leftmost_start(dfa, "xxaab c", 0, 7), Some(Some(2)));
+        assert_eq!(reverse.leftmost_start(dfa, "xxaab c", 3, 7), Some(Some(3)));
+        assert_eq!(reverse.leftmost_start(dfa, "xxaab c", 5, 7), Some(Some(6)));
+        assert_eq!(reverse.leftmost_start(dfa, "xxaa", 0, 4), Some(None));
+        assert!(ReverseDfa::new(&RegexNFA::new("\\Ga".to_string()).engine, false, 16).is_none());
+    }
+
+    #[test]
+    fn test_spans_agree_without_reverse_dfa() {
+        let patterns = [
+            "a+b",
+            "(a|ab)(c|bcd)",
+            "abcd|c",
+            "x*",
+            "(\\w+)@(\\w+)$",
+            "é(.)",
+            "b$",
+        ];
+        let inputs = [
+            "",
+            "aab",
+            "xaabcd",
+            "abcd",
+            "me@host\n",
+            "me@host x",
+            "aé!",
+            "ab\r\n",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert!(regex_nfa.reverse.is_some(), "{}", pattern);
+            let options = RegexOptions::new().dfa_cache_limit(0);
+            let without = RegexNFA::with_options(pattern.to_string(), options);
+            for input in inputs {
+                for from in 0..=input.chars().count() {
+                    assert_eq!(
+                        regex_nfa.match_spans_at(input, from),
+                        without.match_spans_at(input, from),
+                        "{} on {:?} from {}",
+                        pattern,
+                        input,
+                        from
+                    );
+                }
+            }
+        }
+    }
+}
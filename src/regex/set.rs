@@ -0,0 +1,349 @@
+//! Several patterns compiled into one NFA and searched together.
+//!
+//! The NFAs of the patterns are laid side by side in one engine, each keeping
+//! its own start and end state, and the breadth-first simulation runs them
+//! all in a single pass over the input. Reaching a pattern's end state marks
+//! that pattern as matched, and the search goes on until every pattern has
+//! matched or the input is exhausted. Patterns that need backtracking
+//! (backreferences, calls, conditionals) can't be part of the simulation and
+//! are run on their own.
+//!
+//! A pattern whose matches all start with one of a few literals, as most
+//! grep patterns do, only starts where those occur: one Aho-Corasick pass
+//! finds the occurrences for every such pattern, so a line mentioning none
+//! of them costs that pass and no simulation at all.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use aho_corasick::AhoCorasick;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::{at_word_edge, grapheme_len, linebreak_len, literal_len, Engine};
+use crate::regex::error::CompileError;
+use crate::regex::nfa_regex::{is_line_end, is_line_start, trailing_terminator_len};
+use crate::regex::options::RegexOptions;
+use crate::regex::prefilter::{start_literals, Prefilter};
+use crate::regex::RegexNFA;
+
+pub struct RegexSet {
+    // The regular patterns side by side; its own start and end are unused
+    engine: Engine,
+    // Start state of every regular pattern without start literals, whether
+    // it is anchored by `^`, and what its matches start with if known
+    starts: Vec<(usize, bool, Option<Prefilter>)>,
+    // The start literals of the others, and the starts of the patterns each
+    // literal begins, indexed like the literals
+    literals: Vec<String>,
+    literal_starts: Vec<Vec<(usize, bool)>>,
+    prefixes: Option<AhoCorasick>,
+    // How many patterns the simulation runs
+    simulated: usize,
+    // Indexed by state id: the pattern whose end state it is, and whether
+    // that pattern is anchored by `$`
+    ends: Vec<Option<(usize, bool)>>,
+    // Patterns run on their own, with their index
+    backtracking: Vec<(usize, RegexNFA)>,
+    len: usize,
+    // `^` and `$` match at every line boundary
+    multi_line: bool,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<Self, CompileError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        RegexSet::with_options(patterns, RegexOptions::default())
+    }
+
+    /// Compiles every pattern with the same options. Fails on the first
+    /// invalid pattern.
+    pub fn with_options<I, S>(patterns: I, options: RegexOptions) -> Result<Self, CompileError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut set = RegexSet::empty(options.multi_line);
+        for (index, pattern) in patterns.into_iter().enumerate() {
+            set.len += 1;
+            let regex = RegexNFA::try_with_options(pattern.into(), options.clone())?;
+            if !set.simulate_too(index, &regex) {
+                set.backtracking.push((index, regex));
+            }
+        }
+        set.finish();
+        Ok(set)
+    }
+
+    /// A set of the regexes the simulation can run, numbered by their index
+    /// in `regexes`, and the indices of the others, which it never reports
+    /// as matching: those needing backtracking, and those whose `^` and `$`
+    /// don't match at line boundaries like the first regex's do. For callers
+    /// that keep the regexes and run the others themselves.
+    pub(crate) fn simulating(regexes: &[RegexNFA]) -> (RegexSet, Vec<usize>) {
+        let multi_line = regexes.first().is_some_and(|regex| regex.multi_line);
+        let mut set = RegexSet::empty(multi_line);
+        set.len = regexes.len();
+        let others = (0..regexes.len())
+            .filter(|&index| !set.simulate_too(index, &regexes[index]))
+            .collect();
+        set.finish();
+        (set, others)
+    }
+
+    fn empty(multi_line: bool) -> Self {
+        let mut engine = Engine::new();
+        engine.multi_line = multi_line;
+        RegexSet {
+            engine,
+            starts: Vec::new(),
+            literals: Vec::new(),
+            literal_starts: Vec::new(),
+            prefixes: None,
+            simulated: 0,
+            ends: Vec::new(),
+            backtracking: Vec::new(),
+            len: 0,
+            multi_line,
+        }
+    }
+
+    /// Adds `regex` to the simulation as pattern `index`, unless the
+    /// simulation can't run it. Returns whether it was added.
+    fn simulate_too(&mut self, index: usize, regex: &RegexNFA) -> bool {
+        if !regex.engine.is_regular() || regex.multi_line != self.multi_line {
+            return false;
+        }
+        let literals = start_literals(&regex.engine);
+        let mut engine = regex.engine.clone();
+        engine.shift_ids(self.engine.states.len());
+        let start = (engine.start_state, regex.starts_with);
+        if literals.is_empty() {
+            self.starts
+                .push((start.0, start.1, Prefilter::new(&regex.engine)));
+        }
+        for literal in literals {
+            match self.literals.iter().position(|other| *other == literal) {
+                Some(known) => self.literal_starts[known].push(start),
+                None => {
+                    self.literals.push(literal);
+                    self.literal_starts.push(vec![start]);
+                }
+            }
+        }
+        self.simulated += 1;
+        self.ends.resize(engine.end_state + 1, None);
+        self.ends[engine.end_state] = Some((index, regex.ends_with));
+        self.engine.add_states(engine.states);
+        true
+    }
+
+    /// Readies the set for searching once every pattern is in.
+    fn finish(&mut self) {
+        self.ends.resize(self.engine.states.len(), None);
+        if !self.literals.is_empty() {
+            let prefixes = AhoCorasick::new(&self.literals).expect("literals are short enough");
+            self.prefixes = Some(prefixes);
+        }
+    }
+
+    /// Number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether any of the patterns matches `input`.
+    pub fn is_match(&self, input: &str) -> bool {
+        !self.matches_until(input, true).is_empty()
+    }
+
+    /// Indices of the patterns matching `input`, in increasing order.
+    pub fn matches(&self, input: &str) -> Vec<usize> {
+        self.matches_until(input, false)
+    }
+
+    /// The matching patterns; with `first`, stops after finding one.
+    fn matches_until(&self, input: &str, first: bool) -> Vec<usize> {
+        let mut matched = vec![false; self.len];
+        let mut remaining = self.simulated;
+        if remaining > 0 {
+            self.simulate(input, &mut matched, &mut remaining, first);
+        }
+        for (index, regex) in &self.backtracking {
+            if first && matched.contains(&true) {
+                break;
+            }
+            matched[*index] = regex.matches(input);
+        }
+        (0..self.len).filter(|&index| matched[index]).collect()
+    }
+
+    /// The breadth-first simulation of `Engine::is_match_linear`, over every
+    /// regular pattern at once.
+    fn simulate(&self, input: &str, matched: &mut [bool], remaining: &mut usize, first: bool) {
+        let terminator = trailing_terminator_len(input);
+        let can_start = |index: usize, anchored: bool| {
+            !anchored || index == 0 || (self.multi_line && is_line_start(input, index))
+        };
+        let mut pending: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        // Patterns with start literals start where one occurs
+        if let Some(prefixes) = &self.prefixes {
+            for hit in prefixes.find_overlapping_iter(input) {
+                let starts = &self.literal_starts[hit.pattern().as_usize()];
+                for &(start, anchored) in starts {
+                    if can_start(hit.start(), anchored) {
+                        pending.entry(hit.start()).or_default().push(start);
+                    }
+                }
+            }
+        }
+        if pending.is_empty() && self.starts.is_empty() {
+            return;
+        }
+        let mut seen = vec![usize::MAX; self.engine.states.len()];
+
+        let mut offsets = input
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(core::iter::once(input.len()));
+        loop {
+            // Without patterns starting everywhere, only the offsets some
+            // path gets to need a look
+            let next = if self.starts.is_empty() {
+                pending.keys().next().copied()
+            } else {
+                offsets.next()
+            };
+            let Some(index) = next else {
+                break;
+            };
+            let mut stack = pending.remove(&index).unwrap_or_default();
+            stack.extend(
+                self.starts
+                    .iter()
+                    .filter(|(_, anchored, first)| {
+                        can_start(index, *anchored)
+                            && first
+                                .as_ref()
+                                .is_none_or(|first| first.is_candidate(input, index))
+                    })
+                    .map(|(start, ..)| *start),
+            );
+
+            while let Some(id) = stack.pop() {
+                if seen[id] == index {
+                    continue;
+                }
+                seen[id] = index;
+
+                if let Some((pattern, anchored_end)) = self.ends[id] {
+                    let accept = !anchored_end
+                        || index == input.len()
+                        || index + terminator == input.len()
+                        || (self.multi_line && is_line_end(input, index));
+                    if accept && !matched[pattern] {
+                        matched[pattern] = true;
+                        *remaining -= 1;
+                        if first || *remaining == 0 {
+                            return;
+                        }
+                    }
+                }
+
+                for (matcher, next) in &self.engine.states[id].transitions {
+                    let consumed = match matcher {
+                        _ if matcher.is_epsilon() => Some(0),
+                        Matcher::SearchStart => (index == 0).then_some(0),
+                        Matcher::LineStart => self.engine.at_line_start(input, index).then_some(0),
+                        Matcher::LineEnd => self.engine.at_line_end(input, index).then_some(0),
+                        Matcher::WordStart { .. }
+                        | Matcher::WordEnd { .. }
+                        | Matcher::WordBoundary { .. } => {
+                            at_word_edge(input, index, matcher).then_some(0)
+                        }
+                        Matcher::Grapheme => grapheme_len(input, index),
+                        Matcher::Linebreak => linebreak_len(input, index),
+                        Matcher::Literal(literal) => literal_len(input, index, literal),
+                        _ => input[index..]
+                            .chars()
+                            .next()
+                            .filter(|&c| matcher.matches(c))
+                            .map(char::len_utf8),
+                    };
+                    match consumed {
+                        Some(0) => stack.push(*next),
+                        Some(len) => pending.entry(index + len).or_default().push(*next),
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_reports_every_matching_pattern() {
+        let patterns = ["ERROR", "WARN", "^\\d+", "(\\w)\\1", "end$", "\\Gstart"];
+        let set = RegexSet::new(patterns).unwrap();
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.backtracking.len(), 1);
+
+        let inputs = [
+            "",
+            "ERROR: disk full",
+            "12 WARN ERROR",
+            "x12 WARN",
+            "see you at the end\n",
+            "end is near",
+            "start of a llama",
+            "restart",
+        ];
+        for input in inputs {
+            let expected: Vec<usize> = patterns
+                .iter()
+                .enumerate()
+                .filter(|(_, pattern)| RegexNFA::new(pattern.to_string()).matches(input))
+                .map(|(index, _)| index)
+                .collect();
+            assert_eq!(set.matches(input), expected, "{:?}", input);
+            assert_eq!(set.is_match(input), !expected.is_empty(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_multi_line() {
+        let options = RegexOptions::new().multi_line(true);
+        let set = RegexSet::with_options(["^b", "a$", "^c", "(x)\\1$"], options).unwrap();
+        assert_eq!(set.matches("a\r\nb\nxx\n"), [0, 1, 3]);
+        let set = RegexSet::new(["^b", "a$"]).unwrap();
+        assert!(!set.is_match("a\r\nb"));
+
+        // `^` within a pattern too
+        let options = RegexOptions::new().multi_line(true);
+        let set = RegexSet::with_options(["x|^b", "a|^x"], options).unwrap();
+        assert_eq!(set.matches("a\nb"), [0, 1]);
+        let set = RegexSet::new(["x|^b", "a|^x"]).unwrap();
+        assert_eq!(set.matches("a\nb"), [1]);
+    }
+
+    #[test]
+    fn test_invalid_and_empty() {
+        assert!(RegexSet::new(["a", "*b"]).is_err());
+        let set = RegexSet::new(Vec::<String>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("anything"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Bit-parallel (Shift-Or) matcher for short fixed-length patterns.
+//!
+//! When the NFA is a straight chain of single-char steps, like `error\d{3}`
+//! or `[Hh]ello`, each step is a "position" and the set of positions that
+//! could be reached so far fits in the bits of a `u64`. Every char of the
+//! input then costs a shift, an OR with that char's mask and a test, instead
+//! of a walk over NFA states. Patterns with alternation or repetition other
+//! than a fixed count aren't chains and keep using the other engines.
+//!
+//! Bits are inverted, as usual for Shift-Or: a 0 marks a live position.
+
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::Engine;
+
+/// Positions that fit in the state word.
+const MAX_POSITIONS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct ShiftOr {
+    // Masks of ASCII chars, precomputed; others are computed from `positions`
+    ascii: [u64; 128],
+    positions: Vec<Matcher>,
+    // The bit of the last position
+    last: u64,
+}
+
+impl Engine {
+    /// The Shift-Or matcher for this NFA, or `None` unless it is a chain of
+    /// plain char transitions and literals, 1 to 64 chars in all.
+    pub fn shift_or(&self) -> Option<ShiftOr> {
+        let mut positions = Vec::new();
+        let mut state = self.start_state;
+        let mut steps = 0;
+        while state != self.end_state {
+            let [(matcher, next)] = self.states[state].transitions.as_slice() else {
+                return None;
+            };
+            match matcher {
+                Matcher::Range(..) | Matcher::Any { .. } => positions.push(matcher.clone()),
+                Matcher::Literal(literal) => {
+                    positions.extend(literal.chars().map(|c| Matcher::create_simple_matcher(&c)))
+                }
+                _ if matcher.is_epsilon() => {}
+                _ => return None,
+            }
+            // A chain visits every state at most once
+            steps += 1;
+            if positions.len() > MAX_POSITIONS || steps > self.states.len() {
+                return None;
+            }
+            state = *next;
+        }
+        if positions.is_empty() {
+            return None;
+        }
+
+        let mut shift_or = ShiftOr {
+            ascii: [0; 128],
+            last: 1 << (positions.len() - 1),
+            positions,
+        };
+        for byte in 0..128u8 {
+            shift_or.ascii[byte as usize] = shift_or.compute_mask(byte as char);
+        }
+        Some(shift_or)
+    }
+}
+
+impl ShiftOr {
+    /// Whether a match starts at byte offset `from` or later (only at `from`
+    /// if `anchored`), ending at an offset accepted by `accept`.
+    pub fn is_match<F>(&self, input: &str, from: usize, anchored: bool, accept: F) -> bool
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut live = u64::MAX;
+        for (offset, c) in input[from..].char_indices() {
+            // Shifting in a 0 starts a new match at this char
+            let no_start = u64::from(anchored && offset > 0);
+            live = (live << 1) | no_start | self.mask(c);
+            if live & self.last == 0 && accept(from + offset + c.len_utf8()) {
+                return true;
+            }
+            if anchored && live == u64::MAX {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn mask(&self, c: char) -> u64 {
+        match self.ascii.get(c as usize) {
+            Some(mask) => *mask,
+            None => self.compute_mask(c),
+        }
+    }
+
+    /// A 0 bit for every position matching `c`, 1s elsewhere, including the
+    /// bits past the last position.
+    fn compute_mask(&self, c: char) -> u64 {
+        self.positions
+            .iter()
+            .enumerate()
+            .filter(|(_, matcher)| matcher.matches(c))
+            .fold(u64::MAX, |mask, (i, _)| mask & !(1 << i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::regex::RegexNFA;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_agrees_with_nfa() {
+        let patterns = [
+            "abc",
+            "a.c",
+            "[Hh]ello",
+            "\\d{3}-\\d{4}",
+            "(ab)c",
+            "(?i)straße",
+            "é.",
+            "x",
+        ];
+        let inputs = [
+            "", "abc", "xabcx", "ab", "aXc", "hello", "Hello!", "555-1234", "55-1234", "STRASSE",
+            "Straße", "éé", "xé", "a\nc",
+        ];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            let engine = &regex_nfa.engine;
+            let shift_or = engine.shift_or().unwrap();
+            for input in inputs {
+                for anchored in [false, true] {
+                    let at_end = |index: usize| index == input.len();
+                    assert_eq!(
+                        shift_or.is_match(input, 0, anchored, |_| true),
+                        engine.is_match_linear(input, 0, anchored, |_| true),
+                        "{} on {:?}",
+                        pattern,
+                        input
+                    );
+                    assert_eq!(
+                        shift_or.is_match(input, 0, anchored, at_end),
+                        engine.is_match_linear(input, 0, anchored, at_end),
+                        "{} on {:?} up to the end",
+                        pattern,
+                        input
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        for pattern in ["a|b", "ab*", "a?", "a{2,3}", "(a)\\1", "\\Ga", "a\\R"] {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            assert!(regex_nfa.engine.shift_or().is_none(), "{}", pattern);
+        }
+        let long = "a".repeat(65);
+        assert!(RegexNFA::new(long).engine.shift_or().is_none());
+        assert!(RegexNFA::new("a".repeat(64)).engine.shift_or().is_some());
+    }
+}
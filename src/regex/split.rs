@@ -0,0 +1,128 @@
+//! Splitting a haystack by a pattern: `RegexNFA::split` and `splitn`.
+//!
+//! The pieces are the text between the matches `find_iter` finds, so a
+//! match at the very start or end gives an empty first or last piece, and
+//! two matches in a row an empty one between them: `,` splits `a,,b,` into
+//! `a`, ``, `b` and ``.
+
+use crate::regex::find::Matches;
+use crate::regex::nfa_regex::RegexNFA;
+
+/// The pieces of a haystack between the matches of a pattern, see
+/// `RegexNFA::split`.
+pub struct Split<'r, 'h> {
+    matches: Matches<'r, 'h>,
+    haystack: &'h str,
+    // Byte offset the next piece starts at, `None` once the last one is out
+    start: Option<usize>,
+}
+
+impl<'h> Iterator for Split<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        let start = self.start?;
+        match self.matches.next() {
+            Some(found) => {
+                self.start = Some(found.end());
+                Some(&self.haystack[start..found.start()])
+            }
+            None => {
+                self.start = None;
+                Some(&self.haystack[start..])
+            }
+        }
+    }
+}
+
+/// At most a given number of pieces of a haystack, see `RegexNFA::splitn`.
+pub struct SplitN<'r, 'h> {
+    split: Split<'r, 'h>,
+    // Pieces still to give out
+    remaining: usize,
+}
+
+impl<'h> Iterator for SplitN<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.remaining {
+            0 => None,
+            1 => {
+                // The last piece takes the rest, matches and all
+                self.remaining = 0;
+                let start = self.split.start.take()?;
+                Some(&self.split.haystack[start..])
+            }
+            _ => {
+                self.remaining -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+impl RegexNFA {
+    /// The pieces of `haystack` between the matches of the pattern. There is
+    /// always one more piece than there are matches, so a haystack without
+    /// any gives itself, and an empty one a single empty piece.
+    pub fn split<'r, 'h>(&'r self, haystack: &'h str) -> Split<'r, 'h> {
+        Split {
+            matches: self.find_iter(haystack),
+            haystack,
+            start: Some(0),
+        }
+    }
+
+    /// Like `split`, stopping after `limit` pieces: the last one is the rest
+    /// of `haystack` after the first `limit - 1` matches.
+    pub fn splitn<'r, 'h>(&'r self, haystack: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            split: self.split(haystack),
+            remaining: limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    fn split<'h>(pattern: &str, haystack: &'h str) -> Vec<&'h str> {
+        RegexNFA::new(pattern.to_string()).split(haystack).collect()
+    }
+
+    fn splitn<'h>(pattern: &str, haystack: &'h str, limit: usize) -> Vec<&'h str> {
+        RegexNFA::new(pattern.to_string())
+            .splitn(haystack, limit)
+            .collect()
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("\\s*,\\s*", "a , b,c"), ["a", "b", "c"]);
+        assert_eq!(split(",", "a,,b,"), ["a", "", "b", ""]);
+        assert_eq!(split(",", ",a"), ["", "a"]);
+        assert_eq!(split(",", "abc"), ["abc"]);
+        assert_eq!(split(",", ""), [""]);
+        assert_eq!(split("é", "aébéc"), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_split_empty_matches() {
+        assert_eq!(split("x*", "abc"), ["", "a", "b", "c", ""]);
+        assert_eq!(split("x*", ""), ["", ""]);
+        assert_eq!(split("b*", "abba"), ["", "a", "a", ""]);
+    }
+
+    #[test]
+    fn test_splitn() {
+        assert_eq!(splitn(",", "a,b,c,d", 2), ["a", "b,c,d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 1), ["a,b,c,d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 0), Vec::<&str>::new());
+        assert_eq!(splitn(",", "a,b", 5), ["a", "b"]);
+        assert_eq!(splitn(",", "a,", 2), ["a", ""]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Searching input that arrives in chunks.
+//!
+//! The other searches need the whole haystack as one `&str`. A `Stream`
+//! instead takes the input a chunk of bytes at a time, e.g. as it is read
+//! from a socket, and keeps the lazy DFA state between chunks, so a huge
+//! record never has to be held in memory. A char split between two chunks
+//! is put back together, and invalid UTF-8 is read as U+FFFD like the CLI
+//! does. Only whether and where a match ends is reported; spans and groups
+//! need the input at hand.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use crate::regex::engine::Engine;
+use crate::regex::lazy_dfa::LazyDfa;
+use crate::regex::nfa_regex::RegexNFA;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// A match ends at this byte offset into the input fed so far.
+    Match(usize),
+    /// No match so far, but the rest of the input could still make one.
+    NeedMore,
+    /// No match, however the input goes on.
+    NoMatch,
+}
+
+pub struct Stream<'r> {
+    regex: &'r RegexNFA,
+    /// The NFA of `regex` with its literals split into chars.
+    engine: Cow<'r, Engine>,
+    dfa: LazyDfa,
+    current: usize,
+    /// Bytes consumed so far, not counting `partial`.
+    offset: usize,
+    /// The first bytes of a char the last chunk ended in the middle of.
+    partial: Vec<u8>,
+    /// The last two chars consumed, each with whether the DFA was in a match
+    /// state right before it, for `$` before a final line terminator.
+    tail: [Option<(char, bool)>; 2],
+    status: Status,
+}
+
+impl RegexNFA {
+    /// A search over input fed in chunks, see `Stream`. `None` for patterns
+    /// the lazy DFA can't run (backreferences, calls, conditionals, `\G`,
+    /// `\X`, `\R` and repetitions counted by a counter), and for `^` and `$`
+    /// in multi-line mode.
+    pub fn stream(&self) -> Option<Stream<'_>> {
+        let anchored = self.starts_with || self.ends_with;
+        let engine = match &self.char_engine {
+            Some(engine) => Cow::Borrowed(engine),
+            None => self.engine.split_literals(),
+        };
+        if !LazyDfa::supports(&engine) || (anchored && self.multi_line) {
+            return None;
+        }
+        let mut dfa = LazyDfa::new(&engine, self.starts_with, self.dfa_cache_limit);
+        let current = dfa.start(&engine);
+        Some(Stream {
+            regex: self,
+            engine,
+            dfa,
+            current,
+            offset: 0,
+            partial: Vec::new(),
+            tail: [None; 2],
+            status: Status::NeedMore,
+        })
+    }
+}
+
+impl Stream<'_> {
+    /// Searches `chunk`, the input following the chunks fed before. Once a
+    /// match is found or ruled out, the status no longer changes and further
+    /// input is ignored. A pattern ending in `$` only matches at the end of
+    /// the input, so until `finish` it never reports a match.
+    pub fn feed(&mut self, chunk: &[u8]) -> Status {
+        let mut chunk = chunk;
+        if !self.partial.is_empty() {
+            // Put the split char back together, decoding on from there
+            let taken = chunk.len().min(4 - self.partial.len());
+            let mut head = core::mem::take(&mut self.partial);
+            head.extend_from_slice(&chunk[..taken]);
+            let left = self.consume(&head);
+            if left >= taken {
+                // Still not a whole char
+                self.partial = head[head.len() - left..].to_vec();
+                return self.status;
+            }
+            chunk = &chunk[taken - left..];
+        }
+        let left = self.consume(chunk);
+        if self.status == Status::NeedMore {
+            self.partial = chunk[chunk.len() - left..].to_vec();
+        }
+        self.status
+    }
+
+    /// Ends the input, deciding whether it matched. An incomplete char at
+    /// the end counts as invalid.
+    pub fn finish(mut self) -> Status {
+        if !self.partial.is_empty() {
+            let len = self.partial.len();
+            self.step(char::REPLACEMENT_CHARACTER, len);
+        }
+        if self.status != Status::NeedMore {
+            return self.status;
+        }
+        let at_end = self.dfa.is_match_state(self.current);
+        if at_end {
+            return Status::Match(self.offset);
+        }
+        // `$` also matches before a final line terminator
+        let before_terminator = match self.tail {
+            [Some(('\r', matched)), Some(('\n', _))] if matched => Some(2),
+            [_, Some(('\n', matched))] if matched => Some(1),
+            _ => None,
+        };
+        match before_terminator {
+            Some(len) if self.regex.ends_with => Status::Match(self.offset - len),
+            _ => Status::NoMatch,
+        }
+    }
+
+    /// Steps through the chars of `bytes`, stopping early once the status is
+    /// settled. Returns how many bytes at the end are the beginning of a char
+    /// and were left alone.
+    fn consume(&mut self, bytes: &[u8]) -> usize {
+        let mut bytes = bytes;
+        while self.status == Status::NeedMore {
+            let (valid, invalid) = match core::str::from_utf8(bytes) {
+                Ok(text) => (text, None),
+                Err(error) => {
+                    let valid = core::str::from_utf8(&bytes[..error.valid_up_to()])
+                        .expect("valid up to there");
+                    (valid, Some(error.error_len()))
+                }
+            };
+            for c in valid.chars() {
+                self.step(c, c.len_utf8());
+                if self.status != Status::NeedMore {
+                    return 0;
+                }
+            }
+            bytes = &bytes[valid.len()..];
+            match invalid {
+                None => return 0,
+                // The input ends in the middle of a char
+                Some(None) => return bytes.len(),
+                Some(Some(len)) => {
+                    self.step(char::REPLACEMENT_CHARACTER, len);
+                    bytes = &bytes[len..];
+                }
+            }
+        }
+        0
+    }
+
+    /// Consumes `c`, `len` bytes of input, unless the status is settled
+    /// before it.
+    fn step(&mut self, c: char, len: usize) {
+        let matched = self.dfa.is_match_state(self.current);
+        if matched && !self.regex.ends_with {
+            self.status = Status::Match(self.offset);
+            return;
+        }
+        if self.regex.starts_with && self.dfa.is_dead_state(self.current) {
+            self.status = Status::NoMatch;
+            return;
+        }
+        self.tail = [self.tail[1], Some((c, matched))];
+        self.current = self.dfa.next(&self.engine, self.current, c);
+        self.offset += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn search_in_chunks(pattern: &str, input: &[u8], size: usize) -> Status {
+        let regex_nfa = RegexNFA::new(pattern.to_string());
+        let mut stream = regex_nfa.stream().unwrap();
+        for chunk in input.chunks(size) {
+            stream.feed(chunk);
+        }
+        stream.finish()
+    }
+
+    #[test]
+    fn test_agrees_with_matches() {
+        let patterns = ["wörld", "^héllo", "o+ w", "d$", "x*", "[^a-z]{3}", "é\\n$"];
+        let inputs = ["héllo wörld", "héllo wörld\n", "héllo wörld\r\n", "", "é\n"];
+        for pattern in patterns {
+            let regex_nfa = RegexNFA::new(pattern.to_string());
+            for input in inputs {
+                for size in 1..=input.len().max(1) {
+                    let status = search_in_chunks(pattern, input.as_bytes(), size);
+                    assert_eq!(
+                        matches!(status, Status::Match(_)),
+                        regex_nfa.matches(input),
+                        "{} on {:?} in chunks of {}",
+                        pattern,
+                        input,
+                        size
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_feed() {
+        let regex_nfa = RegexNFA::new("wörld".to_string());
+        let mut stream = regex_nfa.stream().unwrap();
+        let input = "héllo wörld!".as_bytes();
+        // Split in the middle of the ö
+        assert_eq!(stream.feed(&input[..9]), Status::NeedMore);
+        assert_eq!(stream.feed(&input[9..]), Status::Match(13));
+        assert_eq!(stream.feed(b"more"), Status::Match(13));
+        assert_eq!(stream.finish(), Status::Match(13));
+
+        // Anchored searches give up early
+        let regex_nfa = RegexNFA::new("^ab".to_string());
+        let mut stream = regex_nfa.stream().unwrap();
+        assert_eq!(stream.feed(b"a"), Status::NeedMore);
+        assert_eq!(stream.feed(b"cab"), Status::NoMatch);
+
+        // `$` only matches once the input is over
+        let regex_nfa = RegexNFA::new("b$".to_string());
+        let mut stream = regex_nfa.stream().unwrap();
+        assert_eq!(stream.feed(b"ab\r"), Status::NeedMore);
+        assert_eq!(stream.feed(b"\n"), Status::NeedMore);
+        assert_eq!(stream.finish(), Status::Match(2));
+
+        assert!(RegexNFA::new("(a)\\1".to_string()).stream().is_none());
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        assert_eq!(
+            search_in_chunks("a\u{FFFD}b", b"xa\xffb", 1),
+            Status::Match(4)
+        );
+        // A truncated char at the very end
+        assert_eq!(
+            search_in_chunks("a\u{FFFD}$", b"a\xc3", 1),
+            Status::Match(2)
+        );
+        assert_eq!(search_in_chunks("é", b"\xc3\xc3\xa9", 1), Status::Match(3));
+    }
+}
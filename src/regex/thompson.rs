@@ -0,0 +1,140 @@
+//! Breadth-first (Thompson) simulation of the NFA.
+//!
+//! Instead of trying one path at a time like `Engine::compute`, every state
+//! reachable after the first `i` chars is tracked at once, so each char of the
+//! input is looked at once per NFA state. That bounds a search to
+//! O(states × input) no matter how ambiguous the pattern is, e.g. `(a|a)*b`
+//! on a long run of `a`s. The price is that only regular constructs are
+//! supported: backreferences, subroutine calls, conditionals and counted
+//! repetitions looping on a counter depend on the path taken and need the
+//! backtracking engine.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::regex::elements::Matcher;
+use crate::regex::engine::{at_word_edge, grapheme_len, linebreak_len, literal_len, Engine};
+
+impl Engine {
+    /// Whether `is_match_linear` can run this NFA.
+    pub fn is_regular(&self) -> bool {
+        !self
+            .states
+            .iter()
+            .flat_map(|state| &state.transitions)
+            .any(|(matcher, _)| {
+                matches!(
+                    matcher,
+                    Matcher::Backref(_)
+                        | Matcher::Call(_)
+                        | Matcher::IfGroup(..)
+                        | Matcher::CountReset(_)
+                        | Matcher::CountUp(_)
+                        | Matcher::IfCount { .. }
+                )
+            })
+    }
+
+    /// Whether the NFA matches `input` starting at byte offset `from` or later
+    /// (only at `from` if `anchored`), ending at an offset accepted by
+    /// `accept`. `\G` matches at `from`. Requires `is_regular`.
+    pub fn is_match_linear<F>(&self, input: &str, from: usize, anchored: bool, accept: F) -> bool
+    where
+        F: Fn(usize) -> bool,
+    {
+        // When each state was last added to a set, to add it only once per offset
+        let mut seen = vec![usize::MAX; self.states.len()];
+        // States to continue from at later offsets; most transitions consume
+        // one char, literals, \X and \R may consume more
+        let mut pending: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+        let offsets = input[from..]
+            .char_indices()
+            .map(|(offset, _)| from + offset)
+            .chain(core::iter::once(input.len()));
+        for index in offsets {
+            let mut stack = pending.remove(&index).unwrap_or_default();
+            if index == from || !anchored {
+                stack.push(self.start_state);
+            }
+            if stack.is_empty() {
+                if pending.is_empty() {
+                    return false;
+                }
+                continue;
+            }
+
+            while let Some(id) = stack.pop() {
+                if seen[id] == index {
+                    continue;
+                }
+                seen[id] = index;
+
+                if id == self.end_state && accept(index) {
+                    return true;
+                }
+
+                for (matcher, next) in &self.states[id].transitions {
+                    let consumed = match matcher {
+                        _ if matcher.is_epsilon() => Some(0),
+                        Matcher::SearchStart => (index == from).then_some(0),
+                        Matcher::LineStart => self.at_line_start(input, index).then_some(0),
+                        Matcher::LineEnd => self.at_line_end(input, index).then_some(0),
+                        Matcher::WordStart { .. }
+                        | Matcher::WordEnd { .. }
+                        | Matcher::WordBoundary { .. } => {
+                            at_word_edge(input, index, matcher).then_some(0)
+                        }
+                        Matcher::Grapheme => grapheme_len(input, index),
+                        Matcher::Linebreak => linebreak_len(input, index),
+                        Matcher::Literal(literal) => literal_len(input, index, literal),
+                        _ => input[index..]
+                            .chars()
+                            .next()
+                            .filter(|&c| matcher.matches(c))
+                            .map(char::len_utf8),
+                    };
+                    match consumed {
+                        Some(0) => stack.push(*next),
+                        Some(len) => pending.entry(index + len).or_default().push(*next),
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// All NFA states reachable from `ids` without consuming input, sorted
+    /// and without those the DFAs don't need to tell apart.
+    pub(super) fn dfa_closure(&self, ids: &[usize]) -> Vec<usize> {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack = ids.to_vec();
+        let mut closure = Vec::new();
+        while let Some(id) = stack.pop() {
+            if seen[id] {
+                continue;
+            }
+            seen[id] = true;
+            closure.push(id);
+            for (matcher, next) in &self.states[id].transitions {
+                if matcher.is_epsilon() {
+                    stack.push(*next);
+                }
+            }
+        }
+        closure.sort_unstable();
+        closure.retain(|&id| {
+            // States only leading elsewhere through epsilons don't matter once
+            // the closure is known, except the end state
+            id == self.end_state
+                || self.states[id]
+                    .transitions
+                    .iter()
+                    .any(|(matcher, _)| !matcher.is_epsilon())
+        });
+        closure
+    }
+}
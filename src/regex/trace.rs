@@ -0,0 +1,153 @@
+//! Opt-in tracing of how patterns are compiled and searched.
+//!
+//! The NFA builders and the backtracking engine report what they do as
+//! `TraceEvent`s to a tracer installed for the current thread. Without one
+//! (the default) nothing is built or printed; the check costs a thread-local
+//! flag read. `--trace` prints the events to stderr, and tests collect them
+//! with `capture`. Tracers are kept per thread, so `no_std` builds have
+//! none and trace nothing.
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// An NFA fragment was built for part of the pattern.
+    Built {
+        construct: &'static str,
+        start: usize,
+        end: usize,
+        states: usize,
+    },
+    /// The backtracking search followed a transition at byte offset `offset`.
+    Transition {
+        from: usize,
+        to: usize,
+        offset: usize,
+    },
+    /// A path of the backtracking search went nowhere at `state` and
+    /// `offset`, and the search went back to the latest alternative.
+    Backtrack { state: usize, offset: usize },
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Built {
+                construct,
+                start,
+                end,
+                states,
+            } => write!(
+                f,
+                "built {} NFA: start {}, end {}, {} states",
+                construct, start, end, states
+            ),
+            TraceEvent::Transition { from, to, offset } => {
+                write!(f, "at {}: {} -> {}", offset, from, to)
+            }
+            TraceEvent::Backtrack { state, offset } => {
+                write!(f, "at {}: backtrack from {}", offset, state)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+type Tracer = Box<dyn FnMut(&TraceEvent)>;
+
+#[cfg(feature = "std")]
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static TRACER: RefCell<Option<Tracer>> = const { RefCell::new(None) };
+}
+
+/// Installs `tracer` for the current thread, replacing any other, or removes
+/// it with `None`.
+#[cfg(feature = "std")]
+pub fn set_tracer(tracer: Option<Tracer>) {
+    ENABLED.with(|enabled| enabled.set(tracer.is_some()));
+    TRACER.with(|current| *current.borrow_mut() = tracer);
+}
+
+/// Runs `f` and returns what it returned along with every event it traced.
+/// Any tracer installed before is put back afterwards.
+#[cfg(feature = "std")]
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<TraceEvent>) {
+    let events = alloc::rc::Rc::new(RefCell::new(Vec::new()));
+    let sink = events.clone();
+    let previous = TRACER.with(|current| current.borrow_mut().take());
+    set_tracer(Some(Box::new(move |event| {
+        sink.borrow_mut().push(event.clone())
+    })));
+    let result = f();
+    set_tracer(previous);
+    let events = events.take();
+    (result, events)
+}
+
+/// Hands the event made by `event` to the tracer, if there is one.
+#[cfg(feature = "std")]
+pub(super) fn emit(event: impl FnOnce() -> TraceEvent) {
+    if !ENABLED.with(Cell::get) {
+        return;
+    }
+    let event = event();
+    TRACER.with(|current| {
+        // A tracer compiling patterns itself doesn't see those events
+        if let Ok(mut current) = current.try_borrow_mut() {
+            if let Some(tracer) = current.as_mut() {
+                tracer(&event);
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "std"))]
+pub(super) fn emit(_event: impl FnOnce() -> TraceEvent) {}
+
+// Tracers need std, to be kept per thread
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+
+    #[test]
+    fn test_capture() {
+        let (regex_nfa, events) = capture(|| RegexNFA::new("ab|c".to_string()));
+        let engine = &regex_nfa.engine;
+        assert_eq!(
+            events.last(),
+            Some(&TraceEvent::Built {
+                construct: "union",
+                start: engine.start_state,
+                end: engine.end_state,
+                states: engine.states.len(),
+            })
+        );
+        assert!(events
+            .iter()
+            .all(|event| matches!(event, TraceEvent::Built { .. })));
+
+        let (found, events) = capture(|| engine.find("x", 0, true, None, |_| true));
+        assert_eq!(found, None);
+        assert!(events.contains(&TraceEvent::Transition {
+            from: engine.start_state,
+            to: engine.states[engine.start_state].transitions[0].1,
+            offset: 0,
+        }));
+        assert!(matches!(
+            events.last(),
+            Some(TraceEvent::Backtrack { offset: 0, .. })
+        ));
+
+        // Nothing is traced outside `capture`
+        let (_, events) = capture(|| ());
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! `Searcher::search_async`: the same search over tokio's `AsyncBufRead`, for
+//! services grepping streams, such as container logs or sockets, without
+//! tying up a thread while they wait for data.
+//!
+//! Only reading is asynchronous. Each line is matched and handed to the sink
+//! as soon as it is read, so a sink doing slow work blocks the task it runs
+//! on; one that needs to do IO of its own is better off queueing the lines.
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::error::Result;
+use crate::matcher::Matcher;
+use crate::searcher::binary::is_binary;
+use crate::searcher::search::{line_bytes, LineSearch};
+use crate::searcher::{Searcher, Sink};
+
+impl Searcher {
+    /// Searches the lines of `reader` as `search_reader` does, waiting for
+    /// each line instead of blocking on it.
+    pub async fn search_async<M, R, S>(
+        &self,
+        matcher: &M,
+        mut reader: R,
+        sink: &mut S,
+    ) -> Result<()>
+    where
+        M: Matcher + ?Sized,
+        R: AsyncBufRead + Unpin,
+        S: Sink + ?Sized,
+    {
+        let binary = self.detects_binary() && is_binary(reader.fill_buf().await?);
+        if binary && !self.search_binary(sink)? {
+            return Ok(());
+        }
+        let mut lines = reader.split(self.line_terminator);
+        let mut search = LineSearch::new(self, matcher, binary);
+        while let Some(line) = lines.next_segment().await? {
+            if !search.line(line_bytes(line, self.line_terminator), sink)? {
+                break;
+            }
+        }
+        Ok(search.finish(sink)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::searcher::{BinaryDetection, SinkMatch};
+    use std::io;
+
+    fn lines(searcher: &Searcher, pattern: &str, input: &'static [u8]) -> Vec<(u64, String)> {
+        let regex = RegexNFA::new(pattern.to_string());
+        let mut found = Vec::new();
+        let mut sink = |line: &SinkMatch<'_>| {
+            found.push((line.line_number(), line.line().to_string()));
+            Ok(true)
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(searcher.search_async(&regex, input, &mut sink))
+            .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_search_async() {
+        let searcher = Searcher::new();
+        assert_eq!(
+            lines(&searcher, r"\d", b"a1\r\nb\nc2"),
+            [(1, "a1".to_string()), (3, "c2".to_string())]
+        );
+        let searcher = searcher.line_terminator(0);
+        assert_eq!(lines(&searcher, "^b", b"a\0b\0"), [(2, "b".to_string())]);
+    }
+
+    #[test]
+    fn test_binary_quits() {
+        let searcher = Searcher::new().binary_detection(BinaryDetection::Quit);
+        assert_eq!(lines(&searcher, "a", b"a\0\na\n"), []);
+    }
+
+    #[tokio::test]
+    async fn test_stopping_early() {
+        let regex = RegexNFA::new("a".to_string());
+        let (client, server) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            let mut client = client;
+            client.write_all(b"a1\na2\n").await.unwrap();
+            // The search has what it needs and must not wait for more
+            std::future::pending::<()>().await;
+        });
+        let mut seen = 0;
+        let mut sink = |_: &SinkMatch<'_>| -> io::Result<bool> {
+            seen += 1;
+            Ok(false)
+        };
+        Searcher::new()
+            .search_async(&regex, tokio::io::BufReader::new(server), &mut sink)
+            .await
+            .unwrap();
+        assert_eq!(seen, 1);
+        writer.abort();
+    }
+}
@@ -0,0 +1,49 @@
+//! Binary input detection.
+//!
+//! Detection only looks at the first block of buffered input, so it works on
+//! streams such as stdin just as well as on files: nothing is consumed, and
+//! the caller keeps reading from the same reader afterwards.
+
+use std::io::{self, BufRead};
+
+/// Whether the data waiting in `reader`'s buffer (filling it if empty) looks
+/// binary, i.e. contains a NUL byte. Leaves the reader's position untouched.
+pub fn starts_binary<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    let block = reader.fill_buf()?;
+    Ok(is_binary(block))
+}
+
+pub fn is_binary(block: &[u8]) -> bool {
+    block.contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor, Read};
+
+    #[test]
+    fn test_is_binary() {
+        assert!(is_binary(b"ELF\x00\x01"));
+        assert!(!is_binary(b"plain text\n"));
+        assert!(!is_binary(b""));
+    }
+
+    #[test]
+    fn test_starts_binary_does_not_consume() {
+        let mut reader = BufReader::new(Cursor::new(b"ab\x00cd".to_vec()));
+        assert!(starts_binary(&mut reader).unwrap());
+
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"ab\x00cd");
+    }
+
+    #[test]
+    fn test_only_first_block_is_inspected() {
+        let mut data = vec![b'a'; 16];
+        data.push(0);
+        let mut reader = BufReader::with_capacity(8, Cursor::new(data));
+        assert!(!starts_binary(&mut reader).unwrap());
+    }
+}
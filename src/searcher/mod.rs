@@ -0,0 +1,36 @@
+//! Searching inputs line by line with a compiled pattern.
+//!
+//! The matcher, a regex or any other `Matcher`, only ever sees one line at a
+//! time; this module is what gets the lines to it. A `Searcher` reads a
+//! reader, a file or decoded records, runs the matcher over each line and
+//! reports what it finds to a `Sink`: the matching lines, the context around
+//! them and whether the input looked binary. What happens to them, printing, counting or
+//! collecting, is up to the sink. `walk_directory` does the same for whole
+//! directory trees, a directory at a time, and `walk_files` lists the files
+//! it would search. `search_path` hands out the matches under a path one by
+//! one as an iterator. `grep_reader` does the same with the matching lines
+//! of a reader. With the `tokio` feature, `Searcher::search_async` reads
+//! from an `AsyncBufRead` instead. A `SearchSummary` totals what searches
+//! went through and found.
+
+#[cfg(feature = "tokio")]
+mod async_search;
+mod binary;
+mod path;
+mod reader;
+mod search;
+mod sink;
+mod stats;
+mod walk;
+
+pub use binary::{is_binary, starts_binary};
+pub use path::{search_path, FileMatch, SearchPath};
+pub use reader::{grep_reader, GrepReader, MatchedLine};
+pub(crate) use search::line_text;
+pub use search::{BinaryDetection, Searcher};
+pub use sink::{ContextKind, Sink, SinkContext, SinkFinish, SinkMatch};
+pub use stats::{SearchSummary, StatsSink};
+pub use walk::{
+    walk_directory, walk_directory_with_summary, walk_files, DirectoryResults, FileResults,
+    WalkFiles,
+};
@@ -0,0 +1,181 @@
+//! `search_path`: every match under a path, as an iterator, for programs
+//! that want the matches rather than printed lines.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::decoder::{DecoderRegistry, Records};
+use crate::error::{Error, Result};
+use crate::matcher::{FindIter, Matcher};
+use crate::searcher::walk::entries;
+
+/// A match in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatch {
+    pub path: PathBuf,
+    /// The line the match is on, counting from 1.
+    pub line_number: u64,
+    /// The byte range of the match in `line`.
+    pub span: Range<usize>,
+    /// The whole line, without its terminator.
+    pub line: String,
+}
+
+/// The matches under a path, see `search_path`.
+pub struct SearchPath<'m> {
+    matcher: &'m dyn Matcher,
+    decoders: DecoderRegistry,
+    // Paths still to search, the next one last
+    pending: Vec<PathBuf>,
+    // The file being searched and how many of its lines were read
+    current: Option<(PathBuf, Records<'static>, u64)>,
+    // Matches of the last line read not handed out yet
+    found: VecDeque<FileMatch>,
+}
+
+/// Searches the file at `path` or, for a directory, every file under it,
+/// yielding each match in turn. Files are read as they are reached, with the
+/// default decoders. Directories are searched like `walk_directory` does:
+/// the files directly in one before its subdirectories, skipping hidden
+/// ones. An error reading a file or directory is yielded in place of its
+/// matches and the search goes on with the next one.
+pub fn search_path<'m>(path: &Path, matcher: &'m dyn Matcher) -> SearchPath<'m> {
+    SearchPath {
+        matcher,
+        decoders: DecoderRegistry::new(),
+        pending: vec![path.to_path_buf()],
+        current: None,
+        found: VecDeque::new(),
+    }
+}
+
+impl SearchPath<'_> {
+    /// Reads files through `decoders` instead of the default ones.
+    pub fn decoders(mut self, decoders: DecoderRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Queues the entries of the directory `dir`.
+    fn enter(&mut self, dir: &Path) -> Result<()> {
+        let (files, subdirectories) = entries(dir)?;
+        self.pending.extend(subdirectories.into_iter().rev());
+        self.pending.extend(files.into_iter().rev());
+        Ok(())
+    }
+}
+
+impl Iterator for SearchPath<'_> {
+    type Item = Result<FileMatch>;
+
+    fn next(&mut self) -> Option<Result<FileMatch>> {
+        loop {
+            if let Some(found) = self.found.pop_front() {
+                return Some(Ok(found));
+            }
+
+            if let Some((path, records, line_number)) = &mut self.current {
+                match records.next() {
+                    Some(Ok(line)) => {
+                        *line_number += 1;
+                        for span in FindIter::new(self.matcher, line.as_bytes()) {
+                            self.found.push_back(FileMatch {
+                                path: path.clone(),
+                                line_number: *line_number,
+                                span,
+                                line: line.clone(),
+                            });
+                        }
+                    }
+                    Some(Err(error)) => {
+                        let path = self.current.take().unwrap().0;
+                        return Some(Err(Error::io(path, error)));
+                    }
+                    None => self.current = None,
+                }
+                continue;
+            }
+
+            let path = self.pending.pop()?;
+            if path.is_dir() {
+                if let Err(error) = self.enter(&path) {
+                    return Some(Err(error));
+                }
+            } else {
+                match self.decoders.open(&path) {
+                    Ok(records) => self.current = Some((path, records, 0)),
+                    Err(error) => return Some(Err(Error::io(path, error))),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::test_util::TempDir;
+
+    fn fixture(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("path-{}", name));
+        dir.file("a.txt", "one apple\nno\napple and apricot\n");
+        dir.file("sub/b.txt", "avocado\n");
+        dir.file(".hidden/c.txt", "apple\n");
+        dir
+    }
+
+    fn summary(matches: SearchPath<'_>) -> Vec<(PathBuf, u64, Range<usize>)> {
+        matches
+            .map(|found| {
+                let found = found.unwrap();
+                (found.path, found.line_number, found.span)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = fixture("directory");
+        let root = dir.path();
+        let regex = RegexNFA::new("a[a-z]+".to_string());
+        assert_eq!(
+            summary(search_path(root, &regex)),
+            [
+                (root.join("a.txt"), 1, 4..9),
+                (root.join("a.txt"), 3, 0..5),
+                (root.join("a.txt"), 3, 6..9),
+                (root.join("a.txt"), 3, 10..17),
+                (root.join("sub/b.txt"), 1, 0..7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_file() {
+        let dir = fixture("file");
+        let root = dir.path();
+        let regex = RegexNFA::new("apple".to_string());
+        let matches: Vec<FileMatch> = search_path(&root.join("a.txt"), &regex)
+            .map(|found| found.unwrap())
+            .collect();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].line, "apple and apricot");
+        assert_eq!(&matches[1].line[matches[1].span.clone()], "apple");
+    }
+
+    #[test]
+    fn test_errors_name_the_path() {
+        let dir = fixture("missing");
+        let root = dir.path();
+        let regex = RegexNFA::new("a".to_string());
+        let missing = root.join("missing.txt");
+        let results: Vec<Result<FileMatch>> = search_path(&missing, &regex).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].as_ref().unwrap_err().path(),
+            Some(missing.as_path())
+        );
+    }
+}
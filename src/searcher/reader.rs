@@ -0,0 +1,109 @@
+//! `grep_reader`: the matching lines of a reader as an iterator, for programs
+//! that want them without writing a `Sink`.
+
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::matcher::{FindIter, Matcher};
+use crate::searcher::search::line_text;
+
+/// A line with at least one match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedLine {
+    /// The line's number, counting from 1.
+    pub line_number: u64,
+    /// The whole line, without its terminator.
+    pub line: String,
+    /// The byte range of every match in `line`, in order.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// The matching lines of a reader, see `grep_reader`.
+pub struct GrepReader<'m, R> {
+    matcher: &'m dyn Matcher,
+    lines: io::Split<R>,
+    line_number: u64,
+    // Set after an error, which ends the iteration
+    failed: bool,
+}
+
+/// Reads `reader` line by line, yielding every line `matcher` matches with
+/// the spans of its matches. Lines are read as `Searcher::search_reader`
+/// reads them, ending at `\n` or `\r\n`, with invalid UTF-8 replaced. An
+/// error reading is yielded once and ends the iteration.
+pub fn grep_reader<'m, R: BufRead>(reader: R, matcher: &'m dyn Matcher) -> GrepReader<'m, R> {
+    GrepReader {
+        matcher,
+        lines: reader.split(b'\n'),
+        line_number: 0,
+        failed: false,
+    }
+}
+
+impl<R: BufRead> Iterator for GrepReader<'_, R> {
+    type Item = Result<MatchedLine>;
+
+    fn next(&mut self) -> Option<Result<MatchedLine>> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line_text(line, b'\n'),
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(error.into()));
+                }
+            };
+            self.line_number += 1;
+            let spans: Vec<_> = FindIter::new(self.matcher, line.as_bytes()).collect();
+            if !spans.is_empty() {
+                return Some(Ok(MatchedLine {
+                    line_number: self.line_number,
+                    line,
+                    spans,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use std::io::{BufReader, Cursor, Read};
+
+    #[test]
+    fn test_matched_lines() {
+        let regex = RegexNFA::new(r"\d+".to_string());
+        let input = Cursor::new(b"a 1 b 22\r\nnone\n\xff 3");
+        let lines: Vec<MatchedLine> = grep_reader(input, &regex)
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line_number, 1);
+        assert_eq!(lines[0].spans, [2..3, 6..8]);
+        assert_eq!(lines[1].line, "\u{fffd} 3");
+        assert_eq!(lines[1].line_number, 3);
+        assert_eq!(&lines[1].line[lines[1].spans[0].clone()], "3");
+    }
+
+    /// Fails on the first read.
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("gone"))
+        }
+    }
+
+    #[test]
+    fn test_error_ends_the_lines() {
+        let regex = RegexNFA::new("a".to_string());
+        let mut lines = grep_reader(BufReader::new(Broken), &regex);
+        assert_eq!(lines.next().unwrap().unwrap_err().to_string(), "gone");
+        assert!(lines.next().is_none());
+    }
+}
@@ -0,0 +1,644 @@
+//! `Searcher`: the loop running a matcher over every line of an input.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use crate::decoder::{DecoderRegistry, Records};
+use crate::error::{Error, Result};
+use crate::matcher::Matcher;
+use crate::searcher::binary::starts_binary;
+use crate::searcher::sink::{ContextKind, Sink, SinkContext, SinkFinish, SinkMatch};
+
+/// What a `Searcher` does about a reader that looks binary, with a NUL byte
+/// in its first block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryDetection {
+    /// Don't look: search it like text.
+    #[default]
+    None,
+    /// Tell the sink with `Sink::binary_data`, which decides whether the
+    /// search goes on.
+    Report,
+    /// Stop without searching it. The sink only hears of it in
+    /// `SinkFinish::binary`.
+    Quit,
+}
+
+/// Searches inputs line by line and reports the matching lines, and any
+/// context asked for, to a `Sink`.
+#[derive(Debug, Clone)]
+pub struct Searcher {
+    /// Lines to report before each match.
+    pub before_context: usize,
+    /// Lines to report after each match.
+    pub after_context: usize,
+    /// Whether to check the first block of a reader or file for NUL bytes,
+    /// and what to do when there are some. Files are checked once decoded.
+    /// Records from `search_records` are never checked, and neither are
+    /// inputs whose lines end at NUL.
+    pub binary_detection: BinaryDetection,
+    /// The byte lines of a reader or decoded file end at. With `\n`, the
+    /// default, a `\r` before it is dropped too; `0` reads NUL-separated
+    /// records such as the output of `find -print0`. Records from
+    /// `search_records` come split already.
+    pub line_terminator: u8,
+    /// Lines longer than this many bytes are skipped: never matched nor
+    /// reported as context, though they still count toward line numbers.
+    /// `None` for no limit.
+    pub max_line_length: Option<usize>,
+    /// Report the lines that don't match as the matching ones, and the
+    /// lines that do as context, as `grep -v` does.
+    pub invert_match: bool,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Searcher {
+            before_context: 0,
+            after_context: 0,
+            binary_detection: BinaryDetection::None,
+            line_terminator: b'\n',
+            max_line_length: None,
+            invert_match: false,
+        }
+    }
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Searcher::default()
+    }
+
+    pub fn before_context(mut self, lines: usize) -> Self {
+        self.before_context = lines;
+        self
+    }
+
+    pub fn after_context(mut self, lines: usize) -> Self {
+        self.after_context = lines;
+        self
+    }
+
+    pub fn binary_detection(mut self, detection: BinaryDetection) -> Self {
+        self.binary_detection = detection;
+        self
+    }
+
+    pub fn line_terminator(mut self, terminator: u8) -> Self {
+        self.line_terminator = terminator;
+        self
+    }
+
+    pub fn max_line_length(mut self, bytes: Option<usize>) -> Self {
+        self.max_line_length = bytes;
+        self
+    }
+
+    pub fn invert_match(mut self, yes: bool) -> Self {
+        self.invert_match = yes;
+        self
+    }
+
+    /// Searches the lines of `reader`, which end at the line terminator.
+    /// Bytes that aren't valid UTF-8 are matched as U+FFFD, but reported as
+    /// they are by `SinkMatch::bytes`.
+    pub fn search_reader<M, R, S>(&self, matcher: &M, reader: R, sink: &mut S) -> Result<()>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink + ?Sized,
+    {
+        Ok(self.search_bytes(matcher, reader, sink)?)
+    }
+
+    /// Searches `records`, as decoded by a `Decoder`.
+    pub fn search_records<M, S>(
+        &self,
+        matcher: &M,
+        records: Records<'_>,
+        sink: &mut S,
+    ) -> Result<()>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let lines = records.map(|record| record.map(String::into_bytes));
+        Ok(self.search_lines(matcher, lines, false, sink)?)
+    }
+
+    /// Searches the file at `path`, read through `decoders`. Once decoded it
+    /// is searched like `search_reader` searches a reader: split at the line
+    /// terminator and checked for binary data. Errors reading it carry its
+    /// path.
+    pub fn search_file<M, S>(
+        &self,
+        matcher: &M,
+        path: &Path,
+        decoders: &DecoderRegistry,
+        sink: &mut S,
+    ) -> Result<()>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        decoders
+            .open_reader(path)
+            .and_then(|reader| self.search_bytes(matcher, reader, sink))
+            .map_err(|error| Error::io(path, error))
+    }
+
+    /// Searches the lines of `reader` like `search_reader`, handing each
+    /// matching line to `on_match` until it breaks. Nothing past the line it
+    /// breaks on is read, so `|_| ControlFlow::Break(())` answers whether
+    /// there is a match at all as soon as one comes up. Returns the break,
+    /// if there was one.
+    pub fn search_with<M, R, B, F>(
+        &self,
+        matcher: &M,
+        reader: R,
+        on_match: F,
+    ) -> Result<ControlFlow<B>>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        F: FnMut(&SinkMatch<'_>) -> ControlFlow<B>,
+    {
+        let mut sink = ControlFlowSink::new(on_match);
+        self.search_reader(matcher, reader, &mut sink)?;
+        Ok(sink.flow)
+    }
+
+    /// Like `search_with`, for the file at `path` as `search_file` reads it.
+    pub fn search_file_with<M, B, F>(
+        &self,
+        matcher: &M,
+        path: &Path,
+        decoders: &DecoderRegistry,
+        on_match: F,
+    ) -> Result<ControlFlow<B>>
+    where
+        M: Matcher + ?Sized,
+        F: FnMut(&SinkMatch<'_>) -> ControlFlow<B>,
+    {
+        let mut sink = ControlFlowSink::new(on_match);
+        self.search_file(matcher, path, decoders, &mut sink)?;
+        Ok(sink.flow)
+    }
+
+    fn search_bytes<M, R, S>(&self, matcher: &M, mut reader: R, sink: &mut S) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink + ?Sized,
+    {
+        let binary = self.detects_binary() && starts_binary(&mut reader)?;
+        if binary && !self.search_binary(sink)? {
+            return Ok(());
+        }
+        let terminator = self.line_terminator;
+        let lines = reader
+            .split(terminator)
+            .map(|line| Ok(line_bytes(line?, terminator)));
+        self.search_lines(matcher, lines, binary, sink)
+    }
+
+    fn search_lines<M, I, S>(
+        &self,
+        matcher: &M,
+        lines: I,
+        binary: bool,
+        sink: &mut S,
+    ) -> io::Result<()>
+    where
+        M: Matcher + ?Sized,
+        I: Iterator<Item = io::Result<Vec<u8>>>,
+        S: Sink + ?Sized,
+    {
+        let mut search = LineSearch::new(self, matcher, binary);
+        for line in lines {
+            if !search.line(line?, sink)? {
+                break;
+            }
+        }
+        search.finish(sink)
+    }
+
+    /// Whether the first block of a reader is checked for binary data.
+    pub(super) fn detects_binary(&self) -> bool {
+        self.binary_detection != BinaryDetection::None && self.line_terminator != 0
+    }
+
+    /// Deals with a reader found to be binary, returning whether to search
+    /// it anyway. If not, the search is over and `sink` has been told so.
+    pub(super) fn search_binary<S>(&self, sink: &mut S) -> io::Result<bool>
+    where
+        S: Sink + ?Sized,
+    {
+        if self.binary_detection == BinaryDetection::Report && sink.binary_data()? {
+            return Ok(true);
+        }
+        sink.finish(&SinkFinish {
+            binary: true,
+            ..SinkFinish::default()
+        })?;
+        Ok(false)
+    }
+}
+
+/// A search going through the lines of an input one at a time, with what it
+/// keeps from one line to the next.
+pub(super) struct LineSearch<'s, M: ?Sized> {
+    searcher: &'s Searcher,
+    matcher: &'s M,
+    finish: SinkFinish,
+    // The last lines that weren't reported, for context before a match
+    before: VecDeque<(u64, Vec<u8>)>,
+    // Lines still to report after the last match
+    after: usize,
+    // The last line reported, to tell the sink about gaps
+    last_reported: Option<u64>,
+}
+
+impl<'s, M: Matcher + ?Sized> LineSearch<'s, M> {
+    pub(super) fn new(searcher: &'s Searcher, matcher: &'s M, binary: bool) -> Self {
+        LineSearch {
+            searcher,
+            matcher,
+            finish: SinkFinish {
+                binary,
+                ..SinkFinish::default()
+            },
+            before: VecDeque::with_capacity(searcher.before_context),
+            after: 0,
+            last_reported: None,
+        }
+    }
+
+    /// Searches the next line, returning whether the search goes on.
+    pub(super) fn line<S>(&mut self, line: Vec<u8>, sink: &mut S) -> io::Result<bool>
+    where
+        S: Sink + ?Sized,
+    {
+        self.finish.lines_searched += 1;
+        self.finish.bytes_searched += line.len() as u64;
+        let line_number = self.finish.lines_searched;
+        if self
+            .searcher
+            .max_line_length
+            .is_some_and(|max| line.len() > max)
+        {
+            // Context before a match doesn't reach back over it
+            self.before.clear();
+            return Ok(true);
+        }
+
+        if self.matcher.is_match(&line) != self.searcher.invert_match {
+            self.finish.lines_matched += 1;
+            self.after = self.searcher.after_context;
+            while let Some((line_number, line)) = self.before.pop_front() {
+                let context = SinkContext {
+                    line_number,
+                    line: &String::from_utf8_lossy(&line),
+                    bytes: &line,
+                    kind: ContextKind::Before,
+                };
+                if !self.report_break(line_number, sink)? || !sink.context(&context)? {
+                    return Ok(false);
+                }
+            }
+            Ok(self.report_break(line_number, sink)?
+                && sink.matched(&SinkMatch {
+                    line_number,
+                    line: &String::from_utf8_lossy(&line),
+                    bytes: &line,
+                })?)
+        } else if self.after > 0 {
+            self.after -= 1;
+            self.last_reported = Some(line_number);
+            sink.context(&SinkContext {
+                line_number,
+                line: &String::from_utf8_lossy(&line),
+                bytes: &line,
+                kind: ContextKind::After,
+            })
+        } else {
+            let context = self.searcher.before_context;
+            if context > 0 {
+                if self.before.len() == context {
+                    self.before.pop_front();
+                }
+                self.before.push_back((line_number, line));
+            }
+            Ok(true)
+        }
+    }
+
+    /// Ends the search, after the last line or a line that stopped it.
+    pub(super) fn finish<S>(self, sink: &mut S) -> io::Result<()>
+    where
+        S: Sink + ?Sized,
+    {
+        sink.finish(&self.finish)
+    }
+
+    /// Tells `sink` about a gap before `line_number`, the next line to be
+    /// reported, if there is one and context is on.
+    fn report_break<S>(&mut self, line_number: u64, sink: &mut S) -> io::Result<bool>
+    where
+        S: Sink + ?Sized,
+    {
+        let previous = self.last_reported.replace(line_number);
+        let searcher = self.searcher;
+        let context = searcher.before_context > 0 || searcher.after_context > 0;
+        match previous {
+            Some(previous) if context && line_number > previous + 1 => sink.context_break(),
+            _ => Ok(true),
+        }
+    }
+}
+
+/// The sink of `search_with`, keeping the break that stopped the search.
+struct ControlFlowSink<F, B> {
+    on_match: F,
+    flow: ControlFlow<B>,
+}
+
+impl<F, B> ControlFlowSink<F, B> {
+    fn new(on_match: F) -> Self {
+        ControlFlowSink {
+            on_match,
+            flow: ControlFlow::Continue(()),
+        }
+    }
+}
+
+impl<F, B> Sink for ControlFlowSink<F, B>
+where
+    F: FnMut(&SinkMatch<'_>) -> ControlFlow<B>,
+{
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+        self.flow = (self.on_match)(found);
+        Ok(self.flow.is_continue())
+    }
+}
+
+/// A line split off at `terminator`, without a `\r` ending it when that is
+/// `\n`.
+pub(super) fn line_bytes(mut line: Vec<u8>, terminator: u8) -> Vec<u8> {
+    if terminator == b'\n' && line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    line
+}
+
+/// Like `line_bytes`, with bytes that aren't valid UTF-8 replaced with
+/// U+FFFD.
+pub(crate) fn line_text(line: Vec<u8>, terminator: u8) -> String {
+    String::from_utf8(line_bytes(line, terminator))
+        .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::test_util::TempDir;
+    use std::io::Cursor;
+
+    /// Every event, as `m` (match), `b`/`a` (context), `binary` and `end`
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Sink for Events {
+        fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+            self.0
+                .push(format!("m{}:{}", found.line_number(), found.line()));
+            Ok(true)
+        }
+
+        fn context(&mut self, context: &SinkContext<'_>) -> io::Result<bool> {
+            let kind = match context.kind() {
+                ContextKind::Before => 'b',
+                ContextKind::After => 'a',
+            };
+            self.0.push(format!(
+                "{}{}:{}",
+                kind,
+                context.line_number(),
+                context.line()
+            ));
+            Ok(true)
+        }
+
+        fn context_break(&mut self) -> io::Result<bool> {
+            self.0.push("--".to_string());
+            Ok(true)
+        }
+
+        fn binary_data(&mut self) -> io::Result<bool> {
+            self.0.push("binary".to_string());
+            Ok(true)
+        }
+
+        fn finish(&mut self, finish: &SinkFinish) -> io::Result<()> {
+            self.0.push(format!(
+                "end {}/{}",
+                finish.lines_matched, finish.lines_searched
+            ));
+            Ok(())
+        }
+    }
+
+    fn events(searcher: &Searcher, pattern: &str, input: &[u8]) -> Vec<String> {
+        let regex = RegexNFA::new(pattern.to_string());
+        let mut sink = Events::default();
+        searcher
+            .search_reader(&regex, Cursor::new(input), &mut sink)
+            .unwrap();
+        sink.0
+    }
+
+    #[test]
+    fn test_matches() {
+        let searcher = Searcher::new();
+        assert_eq!(
+            events(&searcher, "a", b"ab\ncd\r\nxa\n"),
+            ["m1:ab", "m3:xa", "end 2/3"]
+        );
+        assert_eq!(events(&searcher, "z", b"ab"), ["end 0/1"]);
+        assert_eq!(events(&searcher, "z", b""), ["end 0/0"]);
+    }
+
+    #[test]
+    fn test_context() {
+        let searcher = Searcher::new().before_context(1).after_context(1);
+        let input = b"1\n2\nx3\n4\n5\n6\nx7\nx8\n9\n";
+        assert_eq!(
+            events(&searcher, "x", input),
+            ["b2:2", "m3:x3", "a4:4", "--", "b6:6", "m7:x7", "m8:x8", "a9:9", "end 3/9"]
+        );
+        // Context never repeats a line
+        let searcher = Searcher::new().before_context(3).after_context(2);
+        assert_eq!(
+            events(&searcher, "x", b"x1\n2\nx3\n"),
+            ["m1:x1", "a2:2", "m3:x3", "end 2/3"]
+        );
+        // Nor are there breaks without context
+        assert_eq!(
+            events(&Searcher::new(), "x", b"x1\n2\nx3\n"),
+            ["m1:x1", "m3:x3", "end 2/3"]
+        );
+    }
+
+    #[test]
+    fn test_binary_detection() {
+        let input = b"a\x00b\nab\n";
+        assert_eq!(
+            events(&Searcher::new(), "b", input),
+            ["m1:a\0b", "m2:ab", "end 2/2"]
+        );
+        let searcher = Searcher::new().binary_detection(BinaryDetection::Report);
+        assert_eq!(
+            events(&searcher, "b", input),
+            ["binary", "m1:a\0b", "m2:ab", "end 2/2"]
+        );
+        let searcher = Searcher::new().binary_detection(BinaryDetection::Quit);
+        assert_eq!(events(&searcher, "b", input), ["end 0/0"]);
+    }
+
+    #[test]
+    fn test_line_terminator() {
+        let searcher = Searcher::new().line_terminator(0);
+        assert_eq!(
+            events(&searcher, "a$", b"a\r\0b\0xa\0"),
+            ["m3:xa", "end 1/3"]
+        );
+        // NULs end lines here rather than mark binary input
+        let searcher = searcher.binary_detection(BinaryDetection::Quit);
+        assert_eq!(events(&searcher, "b", b"a\0b"), ["m2:b", "end 1/2"]);
+    }
+
+    #[test]
+    fn test_search_file_like_a_reader() {
+        let dir = TempDir::new("search-file");
+        let file_events = |searcher: &Searcher, name: &str, data: &[u8]| {
+            let path = dir.file(name, data);
+            let regex = RegexNFA::new("b".to_string());
+            let mut sink = Events::default();
+            searcher
+                .search_file(&regex, &path, &DecoderRegistry::new(), &mut sink)
+                .unwrap();
+            sink.0
+        };
+
+        let searcher = Searcher::new().line_terminator(0);
+        assert_eq!(
+            file_events(&searcher, "nul.txt", b"a\0b\0ab"),
+            ["m2:b", "m3:ab", "end 2/3"]
+        );
+        // Decoded first, so UTF-16 is split and checked as text
+        let mut wide = vec![0xFF, 0xFE];
+        wide.extend("a\0b".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(
+            file_events(&searcher, "wide.txt", &wide),
+            ["m2:b", "end 1/2"]
+        );
+        let mut wide = vec![0xFF, 0xFE];
+        wide.extend("ab\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let searcher = Searcher::new().binary_detection(BinaryDetection::Quit);
+        assert_eq!(
+            file_events(&searcher, "wide.txt", &wide),
+            ["m1:ab", "end 1/1"]
+        );
+        assert_eq!(file_events(&searcher, "blob.bin", b"a\0b\n"), ["end 0/0"]);
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let searcher = Searcher::new().max_line_length(Some(3)).before_context(1);
+        assert_eq!(
+            events(&searcher, "x", b"1\nlong x\nx3\n4\nx5\n"),
+            ["m3:x3", "b4:4", "m5:x5", "end 2/5"]
+        );
+    }
+
+    #[test]
+    fn test_invert_match() {
+        let searcher = Searcher::new().invert_match(true);
+        assert_eq!(
+            events(&searcher, "a", b"ab\ncd\nxa\n"),
+            ["m2:cd", "end 1/3"]
+        );
+        // The lines that match become the context
+        let searcher = searcher.after_context(1);
+        assert_eq!(
+            events(&searcher, "a", b"cd\nab\nxa\nef\n"),
+            ["m1:cd", "a2:ab", "--", "m4:ef", "end 2/4"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        assert_eq!(
+            events(&Searcher::new(), "caf.$", b"caf\xe9\n"),
+            ["m1:caf\u{FFFD}", "end 1/1"]
+        );
+        // The bytes read are still there
+        let regex = RegexNFA::new("caf.$".to_string());
+        let mut bytes = Vec::new();
+        Searcher::new()
+            .search_reader(
+                &regex,
+                Cursor::new(b"caf\xe9\r\n"),
+                &mut |found: &SinkMatch| {
+                    bytes.push(found.bytes().to_vec());
+                    Ok(true)
+                },
+            )
+            .unwrap();
+        assert_eq!(bytes, [b"caf\xe9"]);
+    }
+
+    #[test]
+    fn test_search_with() {
+        let regex = RegexNFA::new(r"\d".to_string());
+        let searcher = Searcher::new();
+        let mut input = Cursor::new("a\nb2\nc3\n");
+        let flow = searcher
+            .search_with(&regex, &mut input, |found| {
+                ControlFlow::Break(found.line_number())
+            })
+            .unwrap();
+        assert_eq!(flow, ControlFlow::Break(2));
+        // The search stopped right after the line it broke on
+        assert_eq!(input.position(), 5);
+
+        let mut seen = 0;
+        let flow = searcher
+            .search_with(&regex, Cursor::new("1\n2\n"), |_| {
+                seen += 1;
+                ControlFlow::<()>::Continue(())
+            })
+            .unwrap();
+        assert_eq!((flow, seen), (ControlFlow::Continue(()), 2));
+    }
+
+    #[test]
+    fn test_stopping_early() {
+        let regex = RegexNFA::new("a".to_string());
+        let mut seen = Vec::new();
+        Searcher::new()
+            .search_reader(
+                &regex,
+                Cursor::new("a1\na2\na3\n"),
+                &mut |found: &SinkMatch| {
+                    seen.push(found.line_number());
+                    Ok(seen.len() < 2)
+                },
+            )
+            .unwrap();
+        assert_eq!(seen, [1, 2]);
+    }
+}
@@ -0,0 +1,122 @@
+//! What a `Searcher` reports to: the `Sink` trait and its events.
+
+use std::io;
+
+/// Receives what a search finds, one event at a time. Every method but
+/// `matched` defaults to doing nothing; returning `Ok(false)` from one that
+/// returns a `bool` stops the search, and an error aborts it.
+pub trait Sink {
+    /// A line that matches.
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool>;
+
+    /// A line around a match, when the searcher is asked for context.
+    fn context(&mut self, _context: &SinkContext<'_>) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// A gap between the lines reported, which only comes between context
+    /// lines and matches that aren't next to each other, when the searcher
+    /// is asked for context.
+    fn context_break(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// The input looks binary, before any of its lines are reported. Only
+    /// when the searcher checks for binary input.
+    fn binary_data(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// The search is over, stopped early or not.
+    fn finish(&mut self, _finish: &SinkFinish) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A closure is a sink that only cares about matches.
+impl<F> Sink for F
+where
+    F: FnMut(&SinkMatch<'_>) -> io::Result<bool>,
+{
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+        self(found)
+    }
+}
+
+/// A matching line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkMatch<'a> {
+    pub(super) line_number: u64,
+    pub(super) line: &'a str,
+    pub(super) bytes: &'a [u8],
+}
+
+impl<'a> SinkMatch<'a> {
+    /// The line, without its terminator, and with bytes that aren't valid
+    /// UTF-8 replaced with U+FFFD.
+    pub fn line(&self) -> &'a str {
+        self.line
+    }
+
+    /// The line as it was read, without its terminator.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Where the line is in the input, counting from 1.
+    pub fn line_number(&self) -> u64 {
+        self.line_number
+    }
+}
+
+/// Whether a context line comes before or after the match it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextKind {
+    Before,
+    After,
+}
+
+/// A line around a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkContext<'a> {
+    pub(super) line_number: u64,
+    pub(super) line: &'a str,
+    pub(super) bytes: &'a [u8],
+    pub(super) kind: ContextKind,
+}
+
+impl<'a> SinkContext<'a> {
+    /// The line, without its terminator, and with bytes that aren't valid
+    /// UTF-8 replaced with U+FFFD.
+    pub fn line(&self) -> &'a str {
+        self.line
+    }
+
+    /// The line as it was read, without its terminator.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Where the line is in the input, counting from 1.
+    pub fn line_number(&self) -> u64 {
+        self.line_number
+    }
+
+    pub fn kind(&self) -> ContextKind {
+        self.kind
+    }
+}
+
+/// How a search went, for `Sink::finish`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinkFinish {
+    /// Bytes of the lines read, as they were searched: after decoding and
+    /// without their terminators.
+    pub bytes_searched: u64,
+    /// Lines read, including any context.
+    pub lines_searched: u64,
+    /// Lines that matched.
+    pub lines_matched: u64,
+    /// Whether the input looked binary.
+    pub binary: bool,
+}
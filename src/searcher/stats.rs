@@ -0,0 +1,176 @@
+//! `SearchSummary`: totals over any number of searches, for reporting how
+//! much was searched and found, as `--stats` does.
+//!
+//! A search only adds to a summary when its sink is wrapped with
+//! `SearchSummary::sink`, so searches nobody asked stats of don't pay for
+//! counting every match of their matching lines.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::matcher::{FindIter, Matcher};
+use crate::searcher::sink::{Sink, SinkContext, SinkFinish, SinkMatch};
+
+/// What a set of searches went through and found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchSummary {
+    /// Bytes of the lines read, as `SinkFinish::bytes_searched` counts them.
+    pub bytes_searched: u64,
+    /// Lines read, including any context.
+    pub lines_searched: u64,
+    /// Lines that matched.
+    pub lines_matched: u64,
+    /// Matches on the lines that matched, several per line where a line has
+    /// several.
+    pub matches: u64,
+    /// Inputs searched, files or not.
+    pub files_searched: u64,
+    /// Inputs with at least one matching line.
+    pub files_with_matches: u64,
+    /// Time spent in the searches, from creating each one's sink until it
+    /// finished.
+    pub elapsed: Duration,
+}
+
+impl SearchSummary {
+    pub fn new() -> Self {
+        SearchSummary::default()
+    }
+
+    /// Wraps `sink`, which still gets every event, so that the search it is
+    /// handed to adds to the summary when it finishes. `matcher` finds the
+    /// matches of each matching line to count them, and should be the one
+    /// searched with.
+    pub fn sink<'a, S>(&'a mut self, matcher: &'a dyn Matcher, sink: &'a mut S) -> StatsSink<'a, S>
+    where
+        S: Sink + ?Sized,
+    {
+        StatsSink {
+            summary: self,
+            matcher,
+            sink,
+            matches: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Adds the totals of `other`, such as those of searches run on another
+    /// thread.
+    pub fn merge(&mut self, other: &SearchSummary) {
+        self.bytes_searched += other.bytes_searched;
+        self.lines_searched += other.lines_searched;
+        self.lines_matched += other.lines_matched;
+        self.matches += other.matches;
+        self.files_searched += other.files_searched;
+        self.files_with_matches += other.files_with_matches;
+        self.elapsed += other.elapsed;
+    }
+}
+
+/// A sink adding its search to a `SearchSummary`, see `SearchSummary::sink`.
+pub struct StatsSink<'a, S: ?Sized> {
+    summary: &'a mut SearchSummary,
+    matcher: &'a dyn Matcher,
+    sink: &'a mut S,
+    matches: u64,
+    start: Instant,
+}
+
+impl<S: Sink + ?Sized> Sink for StatsSink<'_, S> {
+    fn matched(&mut self, found: &SinkMatch<'_>) -> io::Result<bool> {
+        self.matches += FindIter::new(self.matcher, found.bytes()).count() as u64;
+        self.sink.matched(found)
+    }
+
+    fn context(&mut self, context: &SinkContext<'_>) -> io::Result<bool> {
+        self.sink.context(context)
+    }
+
+    fn context_break(&mut self) -> io::Result<bool> {
+        self.sink.context_break()
+    }
+
+    fn binary_data(&mut self) -> io::Result<bool> {
+        self.sink.binary_data()
+    }
+
+    fn finish(&mut self, finish: &SinkFinish) -> io::Result<()> {
+        self.summary.merge(&SearchSummary {
+            bytes_searched: finish.bytes_searched,
+            lines_searched: finish.lines_searched,
+            lines_matched: finish.lines_matched,
+            matches: self.matches,
+            files_searched: 1,
+            files_with_matches: u64::from(finish.lines_matched > 0),
+            elapsed: self.start.elapsed(),
+        });
+        self.sink.finish(finish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::searcher::Searcher;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_summary() {
+        let regex = RegexNFA::new("a".to_string());
+        let mut summary = SearchSummary::new();
+        let mut lines = Vec::new();
+        for input in ["banana\r\nkiwi\n", "plum\n"] {
+            let mut collect = |found: &SinkMatch<'_>| {
+                lines.push(found.line().to_string());
+                Ok(true)
+            };
+            Searcher::new()
+                .search_reader(
+                    &regex,
+                    Cursor::new(input),
+                    &mut summary.sink(&regex, &mut collect),
+                )
+                .unwrap();
+        }
+        assert_eq!(lines, ["banana"]);
+        assert_eq!(
+            SearchSummary {
+                elapsed: Duration::ZERO,
+                ..summary
+            },
+            SearchSummary {
+                bytes_searched: 14,
+                lines_searched: 3,
+                lines_matched: 1,
+                matches: 3,
+                files_searched: 2,
+                files_with_matches: 1,
+                elapsed: Duration::ZERO,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stopped_search() {
+        // Only what was searched before the sink stopped counts
+        let regex = RegexNFA::new("a".to_string());
+        let mut summary = SearchSummary::new();
+        let mut stop = |_: &SinkMatch<'_>| Ok(false);
+        Searcher::new()
+            .search_reader(
+                &regex,
+                Cursor::new("xa\na\n"),
+                &mut summary.sink(&regex, &mut stop),
+            )
+            .unwrap();
+        assert_eq!(
+            (
+                summary.lines_searched,
+                summary.matches,
+                summary.bytes_searched
+            ),
+            (1, 1, 2)
+        );
+    }
+}
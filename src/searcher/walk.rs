@@ -0,0 +1,378 @@
+//! Recursive directory search that streams results one directory at a time.
+//!
+//! Instead of reporting every match as it is found, the walk collects the
+//! matches of all files directly inside a directory and hands them over as a
+//! single `DirectoryResults` before descending into its subdirectories. A
+//! frontend rendering a tree of results can therefore add one node per
+//! directory as the search proceeds, parents always arriving before children.
+
+use std::fs::read_dir;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::decoder::DecoderRegistry;
+use crate::error::{Error, Result};
+use crate::matcher::Matcher;
+use crate::messages::{self, Message};
+use crate::searcher::{SearchSummary, Searcher, SinkMatch};
+
+/// The matching lines of a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileResults {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+}
+
+/// The matches of the files directly inside `path` (not its subdirectories),
+/// and the errors reading them or `path` itself.
+#[derive(Debug)]
+pub struct DirectoryResults {
+    pub path: PathBuf,
+    pub files: Vec<FileResults>,
+    pub errors: Vec<Error>,
+}
+
+/// Searches `dir` recursively, calling `on_directory` once for every directory
+/// that contains at least one matching file or couldn't be read in full.
+/// Hidden subdirectories (starting with `.`) are skipped. Files are read
+/// through `decoders`; one that can't be read, or a subdirectory that can't
+/// be listed, is added to the `errors` of its directory and the walk goes on.
+/// Returns whether anything matched, and fails only if `dir` itself isn't a
+/// directory or can't be listed.
+pub fn walk_directory<M, F>(
+    dir: &Path,
+    matcher: &M,
+    decoders: &DecoderRegistry,
+    on_directory: &mut F,
+) -> Result<bool>
+where
+    M: Matcher + ?Sized,
+    F: FnMut(DirectoryResults),
+{
+    walk_directory_with_summary(dir, &Searcher::new(), matcher, decoders, None, on_directory)
+}
+
+/// Like `walk_directory`, searching every file with `searcher` and adding
+/// it to `summary` when there is one. Only the matching lines are
+/// collected, whatever context `searcher` asks for.
+pub fn walk_directory_with_summary<M, F>(
+    dir: &Path,
+    searcher: &Searcher,
+    matcher: &M,
+    decoders: &DecoderRegistry,
+    mut summary: Option<&mut SearchSummary>,
+    on_directory: &mut F,
+) -> Result<bool>
+where
+    M: Matcher + ?Sized,
+    F: FnMut(DirectoryResults),
+{
+    let (entries, subdirectories) = entries(dir)?;
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for entry in entries {
+        let summary = summary.as_deref_mut();
+        let (results, error) = search_file(&entry, searcher, matcher, decoders, summary);
+        files.extend(results);
+        errors.extend(error);
+    }
+
+    let mut found_match = !files.is_empty();
+    if found_match || !errors.is_empty() {
+        on_directory(DirectoryResults {
+            path: dir.to_path_buf(),
+            files,
+            errors,
+        });
+    }
+
+    for subdirectory in subdirectories {
+        let summary = summary.as_deref_mut();
+        match walk_directory_with_summary(
+            &subdirectory,
+            searcher,
+            matcher,
+            decoders,
+            summary,
+            on_directory,
+        ) {
+            Ok(found) => found_match |= found,
+            Err(error) => on_directory(DirectoryResults {
+                path: subdirectory,
+                files: Vec::new(),
+                errors: vec![error],
+            }),
+        }
+    }
+
+    Ok(found_match)
+}
+
+/// The files under `dir`, in the order `walk_directory` searches them: the
+/// files directly in a directory, then those under each of its
+/// subdirectories in turn. Hidden subdirectories are skipped; those that
+/// can't be read come up as an error in place of their files, and the walk
+/// goes on past them. Fails if `dir` isn't a directory or can't be read
+/// itself.
+pub fn walk_files(dir: &Path) -> Result<WalkFiles> {
+    let mut walk = WalkFiles {
+        pending: Vec::new(),
+    };
+    walk.enter(dir)?;
+    Ok(walk)
+}
+
+/// The files under a directory, see `walk_files`.
+#[derive(Debug)]
+pub struct WalkFiles {
+    // Files and directories still to go through, the next one last
+    pending: Vec<PathBuf>,
+}
+
+impl WalkFiles {
+    /// Queues the entries of `dir`.
+    fn enter(&mut self, dir: &Path) -> Result<()> {
+        let (files, subdirectories) = entries(dir)?;
+        self.pending.extend(subdirectories.into_iter().rev());
+        self.pending.extend(files.into_iter().rev());
+        Ok(())
+    }
+}
+
+impl Iterator for WalkFiles {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Result<PathBuf>> {
+        loop {
+            let path = self.pending.pop()?;
+            if !path.is_dir() {
+                return Some(Ok(path));
+            }
+            if let Err(error) = self.enter(&path) {
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// The files directly in `dir` and its subdirectories that aren't hidden.
+/// Symlinks to files count as files, but symlinks to directories are left
+/// out, as `grep -r` does: one pointing back up the tree would make the
+/// walk go round forever.
+pub(super) fn entries(dir: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    if !dir.is_dir() {
+        let source = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            messages::text(Message::NotADirectory),
+        );
+        return Err(Error::io(dir, source));
+    }
+    let mut files = Vec::new();
+    let mut subdirectories = Vec::new();
+    for entry in read_dir(dir).map_err(|error| Error::io(dir, error))? {
+        let entry = entry.map_err(|error| Error::io(dir, error))?;
+        let file_type = entry.file_type().map_err(|error| Error::io(dir, error))?;
+        let path = entry.path();
+        if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+            files.push(path);
+        } else if file_type.is_dir() && !is_hidden(&path) {
+            subdirectories.push(path);
+        }
+    }
+    Ok((files, subdirectories))
+}
+
+/// Whether `path` is hidden, its name starting with `.`, whether or not the
+/// rest of the name is valid UTF-8.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().first() == Some(&b'.'))
+}
+
+/// The matching lines of the file at `path`, if any, and the error that
+/// stopped reading it, if one did.
+fn search_file<M>(
+    path: &Path,
+    searcher: &Searcher,
+    matcher: &M,
+    decoders: &DecoderRegistry,
+    summary: Option<&mut SearchSummary>,
+) -> (Option<FileResults>, Option<Error>)
+where
+    M: Matcher + ?Sized,
+{
+    let mut lines = Vec::new();
+    let mut collect = |found: &SinkMatch| {
+        lines.push(found.line().to_string());
+        Ok(true)
+    };
+    // A file that stops being readable counts for what was read of it
+    let searched = match summary {
+        Some(summary) => {
+            let mut sink = summary.sink(&matcher, &mut collect);
+            searcher.search_file(matcher, path, decoders, &mut sink)
+        }
+        None => searcher.search_file(matcher, path, decoders, &mut collect),
+    };
+
+    let results = (!lines.is_empty()).then(|| FileResults {
+        path: path.to_path_buf(),
+        lines,
+    });
+    (results, searched.err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::RegexNFA;
+    use crate::test_util::TempDir;
+
+    fn fixture(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("walk-{}", name));
+        dir.file("a.txt", "apple\nbanana\n");
+        dir.file("b.txt", "cherry\n");
+        dir.file("sub/c.txt", "apricot\n");
+        dir.file("sub/deeper/d.txt", "avocado\nplum\n");
+        dir.file(".hidden/e.txt", "apple\n");
+        dir
+    }
+
+    #[test]
+    fn test_results_grouped_per_directory() {
+        let dir = fixture("grouped");
+        let root = dir.path();
+        let regex = RegexNFA::new("^a".to_string());
+        let mut groups = Vec::new();
+
+        let found = walk_directory(root, &regex, &DecoderRegistry::new(), &mut |group| {
+            groups.push(group)
+        })
+        .unwrap();
+
+        assert!(found);
+        let paths: Vec<PathBuf> = groups.iter().map(|g| g.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                root.to_path_buf(),
+                root.join("sub"),
+                root.join("sub/deeper")
+            ]
+        );
+        assert_eq!(groups[0].files.len(), 1);
+        assert_eq!(groups[0].files[0].path, root.join("a.txt"));
+        assert_eq!(groups[0].files[0].lines, vec!["apple".to_string()]);
+        assert_eq!(groups[2].files[0].lines, vec!["avocado".to_string()]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let dir = fixture("summary");
+        let root = dir.path();
+        let regex = RegexNFA::new("^a".to_string());
+        let mut summary = SearchSummary::new();
+        let decoders = DecoderRegistry::new();
+        walk_directory_with_summary(
+            root,
+            &Searcher::new(),
+            &regex,
+            &decoders,
+            Some(&mut summary),
+            &mut |_| {},
+        )
+        .unwrap();
+        // The hidden directory isn't searched
+        assert_eq!((summary.files_searched, summary.files_with_matches), (4, 3));
+        assert_eq!((summary.lines_searched, summary.matches), (6, 3));
+    }
+
+    #[test]
+    fn test_no_matches() {
+        let dir = fixture("none");
+        let root = dir.path();
+        let regex = RegexNFA::new("zebra".to_string());
+        let mut calls = 0;
+
+        let found =
+            walk_directory(root, &regex, &DecoderRegistry::new(), &mut |_| calls += 1).unwrap();
+
+        assert!(!found);
+        assert_eq!(calls, 0);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_unreadable_files_are_reported() {
+        let dir = fixture("errors");
+        let root = dir.path();
+        dir.file("sub/broken.gz", b"\x1f\x8bnot deflate");
+        let regex = RegexNFA::new("^a".to_string());
+        let mut decoders = DecoderRegistry::new();
+        decoders.register(Box::new(crate::decoder::GzipDecoder));
+        let mut groups = Vec::new();
+
+        let found =
+            walk_directory(root, &regex, &decoders, &mut |group| groups.push(group)).unwrap();
+
+        // The walk goes on past the broken file, reporting it with its
+        // directory
+        assert!(found);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[1].path, root.join("sub"));
+        assert_eq!(groups[1].files[0].lines, vec!["apricot".to_string()]);
+        assert_eq!(groups[1].errors.len(), 1);
+        assert_eq!(
+            groups[1].errors[0].path(),
+            Some(&*root.join("sub/broken.gz"))
+        );
+        assert!(groups[0].errors.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_are_not_hidden() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = fixture("non-utf8");
+        let root = dir.path();
+        let name = OsStr::from_bytes(b"caf\xe9");
+        std::fs::create_dir(root.join(name)).unwrap();
+        std::fs::write(root.join(name).join("f.txt"), "almond\n").unwrap();
+        std::fs::create_dir(root.join(OsStr::from_bytes(b".caf\xe9"))).unwrap();
+        std::fs::write(root.join(OsStr::from_bytes(b".caf\xe9/g.txt")), "apple\n").unwrap();
+
+        let files: Vec<PathBuf> = walk_files(root).unwrap().map(Result::unwrap).collect();
+        assert!(files.contains(&root.join(name).join("f.txt")));
+        assert_eq!(files.len(), 5);
+    }
+
+    #[test]
+    fn test_walk_files() {
+        let dir = fixture("files");
+        let root = dir.path();
+        let files: Vec<PathBuf> = walk_files(root).unwrap().map(Result::unwrap).collect();
+        // Each directory's own files come before those of its subdirectories
+        assert_eq!(files.len(), 4);
+        let position = |path: &str| files.iter().position(|f| *f == root.join(path)).unwrap();
+        assert!(position("a.txt") < position("sub/c.txt"));
+        assert!(position("b.txt") < position("sub/c.txt"));
+        assert!(position("sub/c.txt") < position("sub/deeper/d.txt"));
+        assert!(walk_files(&root.join("a.txt")).is_err());
+    }
+
+    #[test]
+    fn test_not_a_directory() {
+        let dir = fixture("file");
+        let root = dir.path();
+        let regex = RegexNFA::new("a".to_string());
+        assert!(walk_directory(
+            &root.join("a.txt"),
+            &regex,
+            &DecoderRegistry::new(),
+            &mut |_| {}
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! What `--version` reports: the build's version and capabilities, as text
+//! for people or as JSON for scripts and bug reports.
+
+use std::thread;
+
+use grep_clone::messages::{self, Message};
+
+/// The regex dialects `-E` understands.
+const DIALECTS: &[&str] = &["extended"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    pub dialects: Vec<&'static str>,
+    pub threads: usize,
+}
+
+impl VersionInfo {
+    /// Describes the running binary.
+    pub fn current() -> Self {
+        VersionInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            features: enabled_features(),
+            dialects: DIALECTS.to_vec(),
+            threads: available_threads(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let features = if self.features.is_empty() {
+            messages::text(Message::NoFeatures).to_string()
+        } else {
+            self.features.join(", ")
+        };
+        messages::format(
+            Message::Version,
+            &[
+                &self.name,
+                &self.version,
+                &features,
+                &self.dialects.join(", "),
+                &self.threads,
+            ],
+        )
+    }
+
+    /// A single-line JSON object. Field names and their meaning are stable.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":{},\"version\":{},\"features\":{},\"dialects\":{},\"threads\":{}}}",
+            json_string(self.name),
+            json_string(self.version),
+            json_array(&self.features),
+            json_array(&self.dialects),
+            self.threads
+        )
+    }
+}
+
+/// Cargo features compiled into this binary, in the order `Cargo.toml`
+/// declares them.
+fn enabled_features() -> Vec<&'static str> {
+    let features = [
+        ("std", cfg!(feature = "std")),
+        ("gzip", cfg!(feature = "gzip")),
+        ("cli", cfg!(feature = "cli")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("python", cfg!(feature = "python")),
+        ("serde", cfg!(feature = "serde")),
+        ("tokio", cfg!(feature = "tokio")),
+    ];
+    features
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// How many threads the machine can run at once for this process, or 1 if
+/// that can't be told.
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, usize::from)
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_array(values: &[&str]) -> String {
+    let items: Vec<String> = values.iter().map(|v| json_string(v)).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(features: Vec<&'static str>) -> VersionInfo {
+        VersionInfo {
+            name: "grep-clone",
+            version: "1.2.3",
+            features,
+            dialects: vec!["extended"],
+            threads: 1,
+        }
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            info(vec!["gzip"]).to_json(),
+            "{\"name\":\"grep-clone\",\"version\":\"1.2.3\",\"features\":[\"gzip\"],\
+             \"dialects\":[\"extended\"],\"threads\":1}"
+        );
+        assert!(info(Vec::new()).to_json().contains("\"features\":[]"));
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
+
+    #[test]
+    fn test_current() {
+        let current = VersionInfo::current();
+        assert_eq!(current.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(current.features.contains(&"gzip"), cfg!(feature = "gzip"));
+        assert_eq!(current.features.contains(&"tokio"), cfg!(feature = "tokio"));
+        assert!(current.features.contains(&"cli"));
+        assert_eq!(current.threads, available_threads());
+        assert!(current.threads >= 1);
+    }
+}
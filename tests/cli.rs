@@ -0,0 +1,1004 @@
+//! End-to-end tests of the binary: exit codes and everything it prints.
+
+mod common;
+
+#[cfg(target_os = "linux")]
+use common::run_in_terminal;
+use common::{run, Fixture};
+
+#[test]
+fn usage_without_arguments() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &[], b"");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("Usage: grep-clone [options] <pattern> [path...]"));
+
+    let output = run(fixture.root(), &["--help"], b"");
+    assert_eq!(output.code, 0);
+    assert!(output.stdout.starts_with("Usage: grep-clone"));
+}
+
+#[test]
+fn missing_pattern() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-r"], b"");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("error: no pattern given: pass one, or use -e or -f\n"));
+
+    let output = run(fixture.root(), &["-e"], b"");
+    assert_eq!(output.code, 2);
+    assert!(output.stderr.starts_with("error: a value is required"));
+}
+
+#[test]
+fn unknown_flag_exits_with_2() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["--colour", "x"], b"x\n");
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "");
+    assert!(output
+        .stderr
+        .starts_with("error: unexpected argument '--colour' found"));
+}
+
+#[test]
+fn pattern_as_positional_argument() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\nkiwi\n")
+        .file("dir/b.txt", "pineapple\n");
+    let output = run(fixture.root(), &["a+p", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "apple\n");
+
+    // Flags may come after the pattern and paths
+    let output = run(fixture.root(), &["kiwi", "a.txt", "-l"], b"");
+    assert_eq!(output.stdout, "a.txt\n");
+
+    // With -e, every positional argument is a path
+    let output = run(fixture.root(), &["-e", "apple", "a.txt", "dir/b.txt"], b"");
+    assert_eq!(output.stdout, "a.txt:apple\ndir/b.txt:pineapple\n");
+
+    // -r without a path searches the current directory
+    let output = run(fixture.root(), &["-r", "pine"], b"");
+    assert_eq!(output.stdout, "./dir/b.txt:pineapple\n");
+}
+
+#[test]
+fn invalid_pattern_exits_with_2() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "a|*"], b"a\n");
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "Invalid pattern: nothing to repeat at position 2\n"
+    );
+}
+
+#[test]
+fn oversized_pattern_exits_with_2() {
+    let fixture = Fixture::new();
+    let pattern = "\\w".repeat(2000);
+    let output = run(fixture.root(), &["-E", &pattern], b"a\n");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("Invalid pattern: pattern too large"));
+}
+
+#[test]
+fn malformed_pattern_exits_with_2() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "(a"], b"a\n");
+    assert_eq!(output.code, 2);
+    assert_eq!(
+        output.stderr,
+        "Invalid pattern: unsupported pattern: unbalanced parentheses\n"
+    );
+}
+
+#[test]
+fn empty_patterns_and_alternatives_match_everywhere() {
+    let fixture = Fixture::new();
+    let input = b"apple\n\nplum\n";
+    for args in [
+        &[""][..],
+        &["-E", "zz|"],
+        &["-E", "(|zz)p"],
+        &["-i", "-F", ""],
+    ] {
+        let output = run(fixture.root(), args, input);
+        assert_eq!(output.code, 0, "{:?}", args);
+    }
+    let output = run(fixture.root(), &["-c", ""], input);
+    assert_eq!(output.stdout, "3\n");
+    let output = run(fixture.root(), &["-c", "-E", "(|zz)p"], input);
+    assert_eq!(output.stdout, "2\n");
+    // Only the empty line is all empty string
+    let output = run(fixture.root(), &["-n", "-x", ""], input);
+    assert_eq!(output.stdout, "2:\n");
+    let output = run(fixture.root(), &["-n", "-x", "-F", ""], input);
+    assert_eq!(output.stdout, "2:\n");
+}
+
+#[test]
+fn unparsable_pattern_exits_with_2() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "x[z-a]"], b"x\n");
+    assert_eq!(output.code, 2);
+    assert_eq!(
+        output.stderr,
+        "Invalid pattern: invalid range z-a in the character class at position 1\n"
+    );
+}
+
+#[test]
+fn stdin_prints_matching_lines() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "a+b"], b"aab\ncd\nxab\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "aab\nxab\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn stdin_without_match() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "zebra"], b"apple\n");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn several_patterns_with_e_and_f() {
+    let fixture = Fixture::new();
+    fixture.file("patterns.txt", "^kiwi$\nb(an)+a\n");
+    let output = run(
+        fixture.root(),
+        &["-e", "apple", "-f", "patterns.txt"],
+        b"apple pie\nkiwi\nkiwis\nbanana\n",
+    );
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "apple pie\nkiwi\nbanana\n");
+
+    let output = run(fixture.root(), &["-e", "x", "-e", "a|*"], b"x\n");
+    assert_eq!(output.code, 2);
+    assert_eq!(
+        output.stderr,
+        "Invalid pattern: nothing to repeat at position 2\n"
+    );
+}
+
+#[test]
+fn fixed_strings() {
+    let fixture = Fixture::new();
+    let input = b"a.b\naxb\n(x|y)\n";
+    let output = run(fixture.root(), &["-F", "-E", "a.b"], input);
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "a.b\n");
+
+    let output = run(fixture.root(), &["-F", "-e", "(x|y)", "-e", "axb"], input);
+    assert_eq!(output.stdout, "axb\n(x|y)\n");
+
+    // Not a valid regex, but a fine string
+    let output = run(fixture.root(), &["-F", "-E", "a|*"], b"a|*\n");
+    assert_eq!(output.code, 0);
+}
+
+#[test]
+fn ignore_case() {
+    let fixture = Fixture::new();
+    let input = "École\nécole\nECOLE\n".as_bytes();
+    let output = run(fixture.root(), &["-i", "éCOLE"], input);
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "École\nécole\n");
+
+    let output = run(fixture.root(), &["--ignore-case", "[é]c"], input);
+    assert_eq!(output.stdout, "École\nécole\n");
+
+    // Fixed strings fold case too, beyond ASCII
+    let output = run(
+        fixture.root(),
+        &["-F", "-i", "É.c"],
+        "é.C\né-c\n".as_bytes(),
+    );
+    assert_eq!(output.stdout, "é.C\n");
+
+    // Folding goes beyond one lowercase and one uppercase form
+    let output = run(fixture.root(), &["-i", "kσ"], "\u{212A}ς\nkz\n".as_bytes());
+    assert_eq!(output.stdout, "\u{212A}ς\n");
+
+    let output = run(fixture.root(), &["ecole"], input);
+    assert_eq!(output.code, 1);
+}
+
+#[test]
+fn invert_match() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\nkiwi\n")
+        .file("dir/b.txt", "plum\npear\n");
+    let output = run(fixture.root(), &["-v", "a"], b"apple\nkiwi\nfig\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "kiwi\nfig\n");
+
+    let output = run(fixture.root(), &["--invert-match", "-r", "p", "."], b"");
+    assert_eq!(output.stdout, "./a.txt:kiwi\n");
+
+    // Every line matching leaves none to print
+    let output = run(fixture.root(), &["-v", "-l", "p", "dir/b.txt"], b"");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn line_numbers() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\nkiwi\napricot\n")
+        .file("dir/b.txt", "plum\navocado\n");
+    let output = run(fixture.root(), &["-n", "^a", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "1:apple\n3:apricot\n");
+
+    let output = run(
+        fixture.root(),
+        &["--line-number", "^a", "a.txt", "dir/b.txt"],
+        b"",
+    );
+    assert_eq!(
+        output.stdout,
+        "a.txt:1:apple\na.txt:3:apricot\ndir/b.txt:2:avocado\n"
+    );
+
+    let output = run(fixture.root(), &["-rn", "^a", "dir"], b"");
+    assert_eq!(output.stdout, "dir/b.txt:2:avocado\n");
+
+    // The lines that don't match keep their own numbers
+    let output = run(fixture.root(), &["-n", "-v", "i"], b"kiwi\nplum\n");
+    assert_eq!(output.stdout, "2:plum\n");
+}
+
+#[test]
+fn only_matching() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "id 12, id 345\nnone\nid 6\n");
+    let output = run(fixture.root(), &["-o", "[0-9]+", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "12\n345\n6\n");
+
+    let output = run(
+        fixture.root(),
+        &["-on", "--", "id [0-9]", "a.txt", "a.txt"],
+        b"",
+    );
+    assert_eq!(
+        output.stdout,
+        "a.txt:1:id 1\na.txt:1:id 3\na.txt:3:id 6\n".repeat(2)
+    );
+
+    // Empty matches print nothing, though the line still matches
+    let output = run(fixture.root(), &["--only-matching", "x*"], b"axxb\nc\n");
+    assert_eq!((output.code, output.stdout.as_str()), (0, "xx\n"));
+}
+
+#[test]
+fn word_regexp() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "cat\ncats\na cat.\nbobcat\nc.t x\n");
+    let output = run(fixture.root(), &["-w", "cat", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "cat\na cat.\n");
+
+    let output = run(fixture.root(), &["-wF", "c.t", "a.txt"], b"");
+    assert_eq!(output.stdout, "c.t x\n");
+
+    // The match is the whole word, even past a longer one on the line
+    let output = run(fixture.root(), &["-wio", "ca[a-z]"], b"cats CAT\n");
+    assert_eq!(output.stdout, "CAT\n");
+
+    let output = run(fixture.root(), &["--word-regexp", "cat"], b"concat\n");
+    assert_eq!(output.code, 1);
+}
+
+#[test]
+fn line_regexp() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "abc\nab\nxab\na.b\n");
+    let output = run(fixture.root(), &["-x", "a|ab", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "ab\n");
+
+    let output = run(fixture.root(), &["-xF", "a.b", "a.txt"], b"");
+    assert_eq!(output.stdout, "a.b\n");
+
+    let output = run(fixture.root(), &["-xvc", "ab", "a.txt"], b"");
+    assert_eq!(output.stdout, "3\n");
+
+    // Line endings aren't part of the line
+    let output = run(fixture.root(), &["--line-regexp", "ab"], b"ab\r\nabc\n");
+    assert_eq!(output.stdout, "ab\n");
+}
+
+#[test]
+fn context() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "1\n2\nx3\n4\n5\n6\nx7\nx8\n9\n")
+        .file("b.txt", "x\ny\n");
+    let output = run(fixture.root(), &["-C", "1", "x", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "2\nx3\n4\n--\n6\nx7\nx8\n9\n");
+
+    // Context lines take a `-`, and a `--` separates the inputs too
+    let output = run(fixture.root(), &["-n", "-A1", "x", "a.txt", "b.txt"], b"");
+    assert_eq!(
+        output.stdout,
+        "a.txt:3:x3\na.txt-4-4\n--\na.txt:7:x7\na.txt:8:x8\na.txt-9-9\n--\nb.txt:1:x\nb.txt-2-y\n"
+    );
+
+    // -B says how much comes before whatever -C says
+    let output = run(fixture.root(), &["-B2", "-C0", "x", "a.txt"], b"");
+    assert_eq!(output.stdout, "1\n2\nx3\n--\n5\n6\nx7\nx8\n");
+
+    let output = run(
+        fixture.root(),
+        &["--after-context", "1", "-v", "a"],
+        b"a\nb\nc\na\n",
+    );
+    assert_eq!(output.stdout, "b\nc\na\n");
+}
+
+#[test]
+fn count() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\nkiwi\napricot\n")
+        .file("b.txt", "plum\n");
+    let output = run(fixture.root(), &["-c", "^a", "a.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "2\n");
+
+    // Inputs without a match count too
+    let output = run(fixture.root(), &["--count", "^a", "a.txt", "b.txt"], b"");
+    assert_eq!(output.stdout, "a.txt:2\nb.txt:0\n");
+    assert_eq!(output.stderr, "");
+
+    let output = run(fixture.root(), &["-c", "-v", "^a", "a.txt", "b.txt"], b"");
+    assert_eq!(output.stdout, "a.txt:1\nb.txt:1\n");
+
+    let output = run(fixture.root(), &["-c", "x"], b"x\nxx\ny\n");
+    assert_eq!(output.stdout, "2\n");
+    let output = run(fixture.root(), &["-c", "z"], b"x\n");
+    assert_eq!((output.code, output.stdout.as_str()), (1, "0\n"));
+}
+
+#[test]
+fn engine() {
+    let fixture = Fixture::new();
+    let input = b"aab\nab\nb\n";
+    for engine in ["auto", "backtrack", "pikevm", "lazydfa"] {
+        let output = run(fixture.root(), &["--engine", engine, "-E", "a+b"], input);
+        assert_eq!(output.code, 0, "{}", engine);
+        assert_eq!(output.stdout, "aab\nab\n", "{}", engine);
+    }
+
+    // The Pike VM can't run backreferences
+    let output = run(
+        fixture.root(),
+        &["--engine", "pikevm", "-E", "(a)\\1"],
+        b"aa\n",
+    );
+    assert_eq!(output.code, 2);
+    assert!(output.stderr.starts_with("Invalid pattern: "));
+
+    let output = run(fixture.root(), &["--engine", "fast", "-E", "a"], b"a\n");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("error: invalid value 'fast' for '--engine <ENGINE>'"));
+}
+
+#[test]
+fn unreadable_pattern_file_exits_with_2() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-f", "missing.txt"], b"x\n");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("Error reading patterns from 'missing.txt': "));
+}
+
+#[test]
+fn quiet_prints_nothing() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "apple\n").file("b.txt", "banana\n");
+    let output = run(fixture.root(), &["-q", "-E", "an", "a.txt", "b.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!((output.stdout.as_str(), output.stderr.as_str()), ("", ""));
+
+    let output = run(fixture.root(), &["-q", "-E", "kiwi", "a.txt", "b.txt"], b"");
+    assert_eq!(output.code, 1);
+    assert_eq!((output.stdout.as_str(), output.stderr.as_str()), ("", ""));
+
+    let output = run(fixture.root(), &["-q", "-E", "x"], b"y\nx\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn files_with_matches() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\napricot\n")
+        .file("b.txt", "banana\n")
+        .file("dir/c.txt", "avocado\n");
+    let output = run(fixture.root(), &["-l", "-E", "^a", "a.txt", "b.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "a.txt\n");
+    assert_eq!(output.stderr, "");
+
+    let output = run(fixture.root(), &["-r", "-l", "-E", "^a", "dir"], b"");
+    assert_eq!(output.stdout, "dir/c.txt\n");
+
+    let output = run(fixture.root(), &["-l", "-E", "x"], b"x\n");
+    assert_eq!(output.stdout, "(standard input)\n");
+}
+
+#[test]
+fn stats_follow_the_results() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "banana\nkiwi\n")
+        .file("b.txt", "plum\n");
+    let output = run(
+        fixture.root(),
+        &["--stats", "-E", "a", "a.txt", "b.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 0);
+    let (lines, time) = output.stdout.trim_end().rsplit_once('\n').unwrap();
+    assert_eq!(
+        lines,
+        "a.txt:banana\n\n3 matches\n1 matched lines\n1 files contained matches\n\
+         2 files searched\n14 bytes searched"
+    );
+    assert!(time.ends_with(" seconds spent searching"));
+}
+
+#[test]
+fn files_without_match() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "// license: MIT\ncode\n")
+        .file("b.txt", "code\n")
+        .file("dir/c.txt", "more code\n");
+    let output = run(fixture.root(), &["-L", "license", "a.txt", "b.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "b.txt\n");
+    assert_eq!(output.stderr, "");
+
+    let output = run(
+        fixture.root(),
+        &["-r", "--files-without-match", "license", "dir"],
+        b"",
+    );
+    assert_eq!(output.stdout, "dir/c.txt\n");
+
+    // Every file matching leaves none to list
+    let output = run(fixture.root(), &["-L", "code", "a.txt", "b.txt"], b"");
+    assert_eq!((output.code, output.stdout.as_str()), (1, ""));
+
+    let output = run(fixture.root(), &["-L", "x"], b"y\n");
+    assert_eq!(output.stdout, "(standard input)\n");
+
+    let output = run(fixture.root(), &["-l", "-L", "x"], b"y\n");
+    assert_eq!(output.code, 2);
+}
+
+#[test]
+fn files_with_matches_stop_at_the_first_match() {
+    let fixture = Fixture::new();
+    fixture
+        .file("dir/a.txt", "apple\napricot\nbanana\n")
+        .file("dir/sub/b.txt", "kiwi\navocado\nfig\n");
+    let output = run(fixture.root(), &["--stats", "-r", "-l", "^a", "dir"], b"");
+    assert_eq!(output.code, 0);
+    // Only up to the first match of each file is read: `apple`, then
+    // `kiwi` and `avocado`
+    assert!(output
+        .stdout
+        .starts_with("dir/a.txt\ndir/sub/b.txt\n\n2 matches\n2 matched lines\n"));
+    assert!(output.stdout.contains("\n16 bytes searched\n"));
+}
+
+#[test]
+fn binary_stdin_piped_is_printed() {
+    // Only a terminal is protected from binary output
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "key"], b"\0\x01key\nother\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "\0\x01key\n");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn binary_files_on_a_terminal_are_noted() {
+    let fixture = Fixture::new();
+    fixture
+        .file("data.bin", b"\0\x01key\n")
+        .file("plain.txt", "key\n");
+    let Some(output) = run_in_terminal(fixture.root(), &["key", "data.bin", "plain.txt"]) else {
+        return;
+    };
+    assert_eq!(output.code, 0);
+    assert_eq!(
+        output.stdout,
+        "binary file data.bin matches\nplain.txt:key\n"
+    );
+
+    // A single file isn't in front of its lines, but still named
+    let output = run_in_terminal(fixture.root(), &["key", "data.bin"]).unwrap();
+    assert_eq!(output.stdout, "binary file data.bin matches\n");
+    let output = run_in_terminal(fixture.root(), &["-c", "key", "data.bin"]).unwrap();
+    assert_eq!(output.stdout, "1\n");
+
+    // Piped, or with `-a`, the lines are printed as they are
+    let output = run(fixture.root(), &["key", "data.bin"], b"");
+    assert_eq!(output.stdout, "\0\x01key\n");
+    let output = run_in_terminal(fixture.root(), &["-a", "key", "data.bin"]).unwrap();
+    assert_eq!(output.stdout, "\0\x01key\n");
+}
+
+#[test]
+fn single_file_has_no_prefix() {
+    let fixture = Fixture::new();
+    fixture.file("fruits.txt", "apple\nbanana\napricot\n");
+    let output = run(fixture.root(), &["-E", "^ap", "fruits.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "apple\napricot\n");
+}
+
+#[test]
+fn multiple_files_are_prefixed() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "apple\n")
+        .file("b.txt", "banana\n")
+        .file("c.txt", "avocado\n");
+    let output = run(
+        fixture.root(),
+        &["-E", "^a", "a.txt", "b.txt", "c.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "a.txt:apple\nc.txt:avocado\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn errors_are_reported_whatever_matched() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "apple\n");
+
+    let output = run(
+        fixture.root(),
+        &["-E", "apple", "a.txt", "missing.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "a.txt:apple\n");
+    assert!(output
+        .stderr
+        .starts_with("Error processing 'missing.txt': "));
+    assert_eq!(output.stderr.lines().count(), 1);
+
+    // An input without a match is no error
+    let output = run(
+        fixture.root(),
+        &["-E", "zebra", "a.txt", "missing.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "");
+    assert!(output
+        .stderr
+        .starts_with("Error processing 'missing.txt': "));
+    assert_eq!(output.stderr.lines().count(), 1);
+
+    let output = run(fixture.root(), &["-E", "zebra", "a.txt"], b"");
+    assert_eq!((output.code, output.stderr.as_str()), (1, ""));
+}
+
+#[test]
+fn recursive_walk_skips_hidden_directories() {
+    let fixture = Fixture::new();
+    fixture
+        .file("top.txt", "match here\n")
+        .file("sub/nested.txt", "no\nmatch again\n")
+        .file("sub/deeper/last.txt", "final match\n")
+        .file(".git/config", "match hidden\n")
+        .file("sub/.cache/entry", "match hidden\n");
+    let output = run(fixture.root(), &["-r", "-E", "match", "."], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(
+        output.stdout,
+        "./top.txt:match here\n\
+         ./sub/nested.txt:match again\n\
+         ./sub/deeper/last.txt:final match\n"
+    );
+}
+
+#[test]
+fn recursive_walk_searches_hidden_files() {
+    let fixture = Fixture::new();
+    fixture.file("dir/.env", "SECRET=1\n");
+    let output = run(fixture.root(), &["-r", "-E", "SECRET", "dir"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "dir/.env:SECRET=1\n");
+}
+
+#[test]
+fn recursive_walk_on_a_file() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "apple\n");
+    let output = run(fixture.root(), &["-r", "-E", "apple", "a.txt"], b"");
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "Error processing 'a.txt': Path is not a directory\n"
+    );
+}
+
+#[test]
+fn recursive_walk_without_match() {
+    let fixture = Fixture::new();
+    fixture.file("a/b.txt", "apple\n").dir("empty");
+    let output = run(fixture.root(), &["-r", "-E", "zebra", "."], b"");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+}
+
+#[cfg(unix)]
+#[test]
+fn recursive_walk_reports_unreadable_files() {
+    use std::fs::{self, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = Fixture::new();
+    fixture
+        .file("dir/a.txt", "key\n")
+        .file("dir/locked.txt", "key\n");
+    let locked = fixture.root().join("dir/locked.txt");
+    fs::set_permissions(&locked, Permissions::from_mode(0o000)).unwrap();
+    // Permissions don't stop root
+    if fs::read(&locked).is_ok() {
+        return;
+    }
+    let output = run(fixture.root(), &["-r", "key", "dir"], b"");
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "dir/a.txt:key\n");
+    assert!(output
+        .stderr
+        .starts_with("Error processing 'dir/locked.txt': "));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn recursive_walk_reports_corrupt_files() {
+    let fixture = Fixture::new();
+    fixture
+        .file("dir/a.txt", "key\n")
+        .file("dir/broken.gz", b"\x1f\x8b\x08\x00not deflate");
+    let output = run(fixture.root(), &["-Z", "-r", "key", "dir"], b"");
+    assert_eq!(output.code, 2);
+    assert_eq!(output.stdout, "dir/a.txt:key\n");
+    assert!(output
+        .stderr
+        .starts_with("Error processing 'dir/broken.gz': "));
+    assert_eq!(output.stderr.lines().count(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn recursive_walk_skips_directory_symlinks() {
+    let fixture = Fixture::new();
+    fixture
+        .file("data/real.txt", "linked match\n")
+        .dir("tree")
+        .symlink("data/real.txt", "tree/file-link.txt")
+        .symlink("data", "tree/dir-link")
+        .symlink("tree", "tree/loop");
+    // A link back up the tree doesn't send the walk round in circles
+    let output = run(fixture.root(), &["-r", "-E", "match", "tree"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "tree/file-link.txt:linked match\n");
+    assert_eq!(output.stderr, "");
+
+    // One given on the command line is followed
+    let output = run(fixture.root(), &["-r", "-E", "match", "tree/dir-link"], b"");
+    assert_eq!(output.stdout, "tree/dir-link/real.txt:linked match\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_file_names_are_printed_as_they_are() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let fixture = Fixture::new();
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    fixture.file(name, "espresso\n");
+    let output = run(fixture.root(), &["-r", "-E", "espresso", "."], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout_bytes, b"./caf\xe9.txt:espresso\n");
+    let output = run(fixture.root(), &["-r", "-l", "-E", "espresso", "."], b"");
+    assert_eq!(output.stdout_bytes, b"./caf\xe9.txt\n");
+}
+
+#[test]
+fn invalid_utf8_lines_are_printed_as_they_are() {
+    let fixture = Fixture::new();
+    fixture.file("latin1.txt", b"caf\xe9 latte\ntea\n");
+    let output = run(fixture.root(), &["-E", "latte", "latin1.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout_bytes, b"caf\xe9 latte\n");
+    assert_eq!(output.stderr, "");
+    // The invalid byte is matched as one char, and printed as it was read
+    let output = run(fixture.root(), &["-o", "-E", "f. l"], b"caf\xe9 latte\n");
+    assert_eq!(output.stdout_bytes, b"f\xe9 l\n");
+}
+
+#[test]
+fn utf16_files_are_decoded() {
+    let fixture = Fixture::new();
+    let mut data = vec![0xFF, 0xFE];
+    for unit in "first\r\nsecond\r\n".encode_utf16() {
+        data.extend(unit.to_le_bytes());
+    }
+    fixture.file("wide.txt", data);
+    let output = run(fixture.root(), &["-E", "sec", "wide.txt"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "second\n");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_files_need_decompress_flag() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"compressed line\nother\n").unwrap();
+    let fixture = Fixture::new();
+    fixture.file("log.gz", encoder.finish().unwrap());
+
+    let output = run(fixture.root(), &["-Z", "-E", "line", "log.gz"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "compressed line\n");
+
+    let output = run(fixture.root(), &["-E", "line", "log.gz"], b"");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stdout, "");
+}
+
+/// The features `--version` should list, in its order.
+fn expected_features() -> Vec<&'static str> {
+    [
+        ("std", cfg!(feature = "std")),
+        ("gzip", cfg!(feature = "gzip")),
+        ("cli", cfg!(feature = "cli")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("python", cfg!(feature = "python")),
+        ("serde", cfg!(feature = "serde")),
+        ("tokio", cfg!(feature = "tokio")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| name)
+    .collect()
+}
+
+fn expected_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+#[test]
+fn version_as_json() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["--version", "--json"], b"");
+    assert_eq!(output.code, 0);
+    let features: Vec<String> = expected_features()
+        .iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect();
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{{\"name\":\"grep-clone\",\"version\":\"{}\",\"features\":[{}],\
+             \"dialects\":[\"extended\"],\"threads\":{}}}\n",
+            env!("CARGO_PKG_VERSION"),
+            features.join(","),
+            expected_threads()
+        )
+    );
+}
+
+#[test]
+fn version_as_text() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["--version"], b"");
+    assert_eq!(output.code, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "grep-clone {}\nfeatures: {}\ndialects: extended\nthreads: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            expected_features().join(", "),
+            expected_threads()
+        )
+    );
+}
+
+#[test]
+fn show_spans_marks_groups() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["--show-spans", "-E", "(a)b"], b"xab\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "xab\n ^^ match\n ^  group 1\n");
+
+    // Carets line up under wide and multi-byte chars
+    let output = run(
+        fixture.root(),
+        &["--show-spans", "-E", "(字)ab"],
+        "漢字abc\n".as_bytes(),
+    );
+    assert_eq!(output.stdout, "漢字abc\n  ^^^^  match\n  ^^    group 1\n");
+}
+
+#[test]
+fn json_prints_events() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "one\ntwo\n").file("b.txt", "three\n");
+    let output = run(
+        fixture.root(),
+        &["--json", "-E", "o", "a.txt", "b.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 0);
+    assert_eq!(
+        output.stdout,
+        concat!(
+            "{\"type\":\"begin\",\"data\":{\"path\":\"a.txt\"}}\n",
+            "{\"type\":\"match\",\"data\":{\"path\":\"a.txt\",\"line_number\":1,\"line\":\"one\",",
+            "\"submatches\":[{\"match\":\"o\",\"start\":0,\"end\":1}]}}\n",
+            "{\"type\":\"match\",\"data\":{\"path\":\"a.txt\",\"line_number\":2,\"line\":\"two\",",
+            "\"submatches\":[{\"match\":\"o\",\"start\":2,\"end\":3}]}}\n",
+            "{\"type\":\"end\",\"data\":{\"path\":\"a.txt\",\"binary\":false,\"lines_searched\":2,\"lines_matched\":2}}\n",
+        )
+    );
+
+    let output = run(fixture.root(), &["--json", "-E", "x"], b"one\n");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn write_rewrites_files_with_backups() {
+    let fixture = Fixture::new();
+    fixture
+        .file("a.txt", "key=1\nother=2\n")
+        .file("dir/b.txt", "key=3\n")
+        .file("dir/c.txt", "none\n");
+    let read = |relative: &str| std::fs::read_to_string(fixture.root().join(relative)).unwrap();
+
+    let output = run(
+        fixture.root(),
+        &["--write", "$2=$1", "-E", "^(\\w+)=(\\d)", "a.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(read("a.txt"), "1=key\n2=other\n");
+
+    let output = run(
+        fixture.root(),
+        &[
+            "--write",
+            "k",
+            "--backup-suffix",
+            ".bak",
+            "-r",
+            "key",
+            "dir",
+        ],
+        b"",
+    );
+    assert_eq!(output.code, 0);
+    assert_eq!(read("dir/b.txt"), "k=3\n");
+    assert_eq!(read("dir/b.txt.bak"), "key=3\n");
+    // Files without a match are left alone, and not backed up
+    assert_eq!(read("dir/c.txt"), "none\n");
+    assert!(!fixture.root().join("dir/c.txt.bak").exists());
+
+    let output = run(fixture.root(), &["--write", "x", "nothing", "a.txt"], b"");
+    assert_eq!(output.code, 1);
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn write_usage_errors() {
+    let fixture = Fixture::new();
+    fixture.file("a.txt", "a\n");
+
+    let output = run(
+        fixture.root(),
+        &["--write", "x", "-e", "a", "-e", "b", "a.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("error: --write takes a single pattern\n"));
+
+    let output = run(fixture.root(), &["--write", "x", "-r", "a"], b"");
+    assert_eq!(output.code, 2);
+    assert!(output
+        .stderr
+        .starts_with("error: --write needs the files to rewrite\n"));
+
+    let output = run(
+        fixture.root(),
+        &["--backup-suffix", ".bak", "a", "a.txt"],
+        b"",
+    );
+    assert_eq!(output.code, 2);
+
+    let output = run(fixture.root(), &["--write", "x", "a", "missing.txt"], b"");
+    assert_eq!(output.code, 2);
+    assert!(output.stderr.starts_with("Error processing 'missing.txt'"));
+}
+
+#[test]
+fn trace_goes_to_stderr() {
+    let fixture = Fixture::new();
+    let output = run(fixture.root(), &["-E", "a+b"], b"aab\n");
+    assert_eq!(output.stdout, "aab\n");
+    assert_eq!(output.stderr, "");
+
+    let output = run(fixture.root(), &["--trace", "-E", "(a)|b"], b"b\n");
+    assert_eq!(output.code, 0);
+    assert_eq!(output.stdout, "b\n");
+    assert!(output
+        .stderr
+        .lines()
+        .all(|line| line.starts_with("trace: ")));
+    assert!(output.stderr.contains("trace: built group NFA: "));
+    assert!(output.stderr.contains("trace: built union NFA: "));
+}
+
+#[test]
+fn spanish_messages() {
+    let fixture = Fixture::new();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_grep-clone"))
+        .args(["-E", "x", "missing.txt"])
+        .current_dir(fixture.root())
+        .env_remove("LC_ALL")
+        .env_remove("LC_MESSAGES")
+        .env("LANG", "es_ES.UTF-8")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).starts_with("Error al procesar 'missing.txt': ")
+    );
+}
@@ -0,0 +1,120 @@
+//! Fixtures for running the built binary against throwaway directory trees.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_FIXTURE: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory tree, removed again when dropped.
+pub struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let root = env::temp_dir().join(format!(
+            "grep-cli-{}-{}",
+            std::process::id(),
+            NEXT_FIXTURE.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Fixture { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Writes `contents` to `relative`, creating parent directories.
+    pub fn file(&self, relative: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> &Self {
+        let path = self.root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    pub fn dir(&self, relative: &str) -> &Self {
+        fs::create_dir_all(self.root.join(relative)).unwrap();
+        self
+    }
+
+    /// Creates a symlink at `link` pointing to `target`, both relative to the
+    /// fixture root.
+    #[cfg(unix)]
+    pub fn symlink(&self, target: &str, link: &str) -> &Self {
+        std::os::unix::fs::symlink(self.root.join(target), self.root.join(link)).unwrap();
+        self
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// What a run of the binary printed and how it exited.
+#[derive(Debug)]
+pub struct Output {
+    /// Standard output, with bytes that aren't valid UTF-8 replaced.
+    pub stdout: String,
+    /// Standard output as the binary wrote it.
+    pub stdout_bytes: Vec<u8>,
+    pub stderr: String,
+    pub code: i32,
+}
+
+/// Runs the binary with `args` in `dir`, feeding it `stdin`. Messages are
+/// forced to English so assertions don't depend on the caller's locale.
+pub fn run(dir: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grep-clone"))
+        .args(args)
+        .current_dir(dir)
+        .env("LC_ALL", "C")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The binary may exit before reading everything, e.g. on a usage error
+    let _ = child.stdin.take().unwrap().write_all(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    Output {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stdout_bytes: output.stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code().unwrap(),
+    }
+}
+
+/// Like `run`, with standard output a terminal: the binary runs under
+/// `script`, which gives it a pseudo-terminal. `None` if `script` can't be
+/// run here. The terminal turns line breaks into `\r\n`, which are turned
+/// back.
+#[cfg(target_os = "linux")]
+pub fn run_in_terminal(dir: &Path, args: &[&str]) -> Option<Output> {
+    let quote = |arg: &str| format!("'{}'", arg.replace('\'', "'\\''"));
+    let command: Vec<String> = std::iter::once(env!("CARGO_BIN_EXE_grep-clone"))
+        .chain(args.iter().copied())
+        .map(quote)
+        .collect();
+    let output = Command::new("script")
+        .args(["-qec", &command.join(" "), "/dev/null"])
+        .current_dir(dir)
+        .env("LC_ALL", "C")
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    Some(Output {
+        stdout: String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n"),
+        stdout_bytes: output.stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code()?,
+    })
+}
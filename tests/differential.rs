@@ -0,0 +1,246 @@
+//! Differential tests of the regex engine against the `regex` crate.
+//!
+//! Random patterns and haystacks are generated within the syntax both engines
+//! read the same way, and every pair must agree on whether there is a match,
+//! where the leftmost one and its groups are and where all the others are.
+//! The alphabets are small so that matches, near misses and ambiguous paths
+//! are all common. Left out on purpose, because the engines differ there by
+//! design rather than by bug: line terminators in the haystack (`.` and `$`
+//! treat `\r` differently) and chars whose Unicode classes the engines draw
+//! differently. Groups inside a repetition that can match the empty string
+//! are not compared either: this engine picks the path a backtracking
+//! search would, the reference the one its NFA simulation reaches first,
+//! see `nested_lazy_groups`.
+//!
+//! A failing case is shrunk and stored in `differential.proptest-regressions`
+//! next to this file, which is checked in so the cases found so far run
+//! first everywhere. `PROPTEST_CASES` sets how many cases to run.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::test_runner::FileFailurePersistence;
+
+use grep_clone::regex::{parse, Ast, RegexNFA};
+
+/// Chars the haystacks are made of.
+const HAYSTACK_CHARS: [char; 14] = [
+    'a', 'b', 'c', 'é', 'A', 'É', '1', '_', ' ', '-', '\u{212A}', 'ſ', 'ς', 'σ',
+];
+
+/// Single chars, classes of a single char and nothing at all, making for
+/// empty groups and alternatives.
+fn atom() -> impl Strategy<Value = String> {
+    let atoms = vec![
+        "",
+        "a",
+        "b",
+        "c",
+        "é",
+        "A",
+        "k",
+        "s",
+        "Σ",
+        "1",
+        " ",
+        "-",
+        ".",
+        "[ab]",
+        "[^a]",
+        "[a-c]",
+        "[é1_]",
+        "[j-t]",
+        "[^ -]",
+        "[\\d]",
+        "[^\\w]",
+        "[[:alpha:]]",
+        "\\d",
+        "\\w",
+        "\\s",
+        "\\D",
+        "\\W",
+        "\\S",
+        "\\x41",
+        "\\-",
+        "^",
+        "$",
+        "\\b",
+        "\\B",
+    ];
+    select(atoms).prop_map(String::from)
+}
+
+/// Atoms combined by concatenation, alternation, groups and quantifiers.
+fn expression() -> impl Strategy<Value = String> {
+    let quantifiers = vec![
+        "*", "+", "?", "*?", "+?", "??", "{2}", "{1,3}", "{0,2}", "{2,}", "{1,3}?",
+    ];
+    atom().prop_recursive(4, 32, 4, move |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 2..4).prop_map(|parts| parts.concat()),
+            prop::collection::vec(inner.clone(), 2..4)
+                .prop_map(|parts| format!("(?:{})", parts.join("|"))),
+            inner.clone().prop_map(|inner| format!("({})", inner)),
+            (inner, select(quantifiers.clone()))
+                .prop_map(|(inner, quantifier)| format!("(?:{}){}", inner, quantifier)),
+        ]
+    })
+}
+
+/// An expression, maybe anchored at either end and maybe ignoring case.
+fn pattern() -> impl Strategy<Value = String> {
+    (any::<bool>(), any::<bool>(), any::<bool>(), expression()).prop_map(
+        |(ignore_case, start, end, expression)| {
+            format!(
+                "{}{}{}{}",
+                if ignore_case { "(?i)" } else { "" },
+                if start { "^" } else { "" },
+                expression,
+                if end { "$" } else { "" },
+            )
+        },
+    )
+}
+
+fn haystack() -> impl Strategy<Value = String> {
+    prop::collection::vec(select(HAYSTACK_CHARS.to_vec()), 0..12)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+/// The least number of chars `ast` matches.
+fn min_len(ast: &Ast) -> usize {
+    match ast {
+        Ast::Literal(..) | Ast::Dot(_) | Ast::ClassEscape(..) | Ast::Class(..) => 1,
+        Ast::Group { ast, .. } => min_len(ast),
+        Ast::Repetition { ast, min, .. } => min * min_len(ast),
+        Ast::Concat(parts) => parts.iter().map(min_len).sum(),
+        Ast::Alternation(alternatives) => alternatives.iter().map(min_len).min().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn has_group(ast: &Ast) -> bool {
+    match ast {
+        Ast::Group { .. } => true,
+        Ast::Repetition { ast, .. } => has_group(ast),
+        Ast::Concat(parts) | Ast::Alternation(parts) => parts.iter().any(has_group),
+        _ => false,
+    }
+}
+
+/// Whether a group of `ast` is inside a repetition that can match the empty
+/// string, where the engines may capture different iterations.
+fn groups_may_differ(ast: &Ast) -> bool {
+    match ast {
+        Ast::Repetition { ast, .. } if min_len(ast) == 0 && has_group(ast) => true,
+        Ast::Group { ast, .. } | Ast::Repetition { ast, .. } => groups_may_differ(ast),
+        Ast::Concat(parts) | Ast::Alternation(parts) => parts.iter().any(groups_may_differ),
+        _ => false,
+    }
+}
+
+/// The char index of byte offset `byte` into `haystack`.
+fn char_index(haystack: &str, byte: usize) -> usize {
+    haystack[..byte].chars().count()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        failure_persistence: Some(Box::new(FileFailurePersistence::WithSource("proptest-regressions"))),
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn agrees_with_regex_crate(pattern in pattern(), haystack in haystack()) {
+        let ours = RegexNFA::try_new(pattern.clone())
+            .unwrap_or_else(|error| panic!("{:?} doesn't compile: {}", pattern, error));
+        let reference = rust_regex::Regex::new(&pattern).expect("the reference compiles it");
+
+        prop_assert_eq!(
+            ours.matches(&haystack),
+            reference.is_match(&haystack),
+            "whether {:?} matches {:?}",
+            pattern,
+            haystack
+        );
+        let expected = reference
+            .find(&haystack)
+            .map(|found| (char_index(&haystack, found.start()), char_index(&haystack, found.end())));
+        let found = ours.match_spans(&haystack).and_then(|spans| spans[0]);
+        prop_assert_eq!(found, expected, "where {:?} matches {:?}", pattern, haystack);
+
+        type Groups = Vec<Option<std::ops::Range<usize>>>;
+        let ours_groups: Option<Groups> = ours
+            .captures(&haystack)
+            .map(|caps| caps.iter().map(|group| group.map(|m| m.range())).collect());
+        let expected_groups: Option<Groups> = reference
+            .captures(&haystack)
+            .map(|caps| caps.iter().map(|group| group.map(|m| m.range())).collect());
+        if !groups_may_differ(&parse(&pattern).unwrap()) {
+            prop_assert_eq!(ours_groups, expected_groups, "the groups of {:?} in {:?}", pattern, haystack);
+        }
+
+        let ours_all: Vec<_> = ours.find_iter(&haystack).map(|found| found.range()).collect();
+        let expected_all: Vec<_> = reference.find_iter(&haystack).map(|found| found.range()).collect();
+        prop_assert_eq!(ours_all, expected_all, "every match of {:?} in {:?}", pattern, haystack);
+
+        let ours_pieces: Vec<&str> = ours.split(&haystack).collect();
+        let expected_pieces: Vec<&str> = reference.split(&haystack).collect();
+        prop_assert_eq!(ours_pieces, expected_pieces, "{:?} splitting {:?}", pattern, haystack);
+    }
+}
+
+/// Cases the engines used to disagree on.
+#[test]
+fn former_divergences() {
+    for (pattern, haystack) in [
+        ("a|b$", "ax"),
+        ("a$|b", "ab"),
+        ("\\D+", "1ab"),
+        ("\\S+", " ab"),
+        ("\\W", "a-"),
+        ("[\\d]+", "a12"),
+        ("[^\\w]", "ab-"),
+        ("[[:alpha:]]+", "ab1"),
+        ("\\bfoo\\b", "a foo b"),
+        ("\\Boo", "a foo b"),
+        ("\\x41|\\x{e9}", "zAé"),
+        ("(?i)k", "kK\u{212A}"),
+        ("(?i)[a-z]+", "Sſs"),
+        ("(?i)σ+", "Σσς"),
+    ] {
+        let ours = RegexNFA::new(pattern.to_string());
+        let reference = rust_regex::Regex::new(pattern).unwrap();
+        let expected: Vec<_> = reference
+            .find_iter(haystack)
+            .map(|found| found.range())
+            .collect();
+        let found: Vec<_> = ours
+            .find_iter(haystack)
+            .map(|found| found.range())
+            .collect();
+        assert_eq!(
+            found, expected,
+            "every match of {:?} in {:?}",
+            pattern, haystack
+        );
+    }
+}
+
+/// A lazy repetition of a group that can match the empty string. Searching
+/// like a backtracker does, this engine ends up with the group's last
+/// iteration, `a` at 1..2; the reference's NFA simulation reaches the path
+/// that took both chars in one iteration first.
+#[test]
+#[ignore = "the engines pick different iterations, by design"]
+fn nested_lazy_groups() {
+    let pattern = "(?:((?:.)*?))*?$";
+    let ours = RegexNFA::new(pattern.to_string()).captures("aa").unwrap();
+    let reference = rust_regex::Regex::new(pattern)
+        .unwrap()
+        .captures("aa")
+        .unwrap();
+    assert_eq!(
+        ours.get(1).map(|m| m.range()),
+        reference.get(1).map(|m| m.range())
+    );
+}